@@ -29,6 +29,9 @@ pub fn get_gateway_status() -> Result<gateway::types::GatewayStatus, String> {
         avg_duration_ms: 0.0,
         total_input_tokens: 0,
         total_output_tokens: 0,
+        total_cache_read_tokens: 0,
+        total_cache_creation_tokens: 0,
+        total_cost_usd: 0.0,
     });
 
     let synced_accounts = accounts
@@ -36,6 +39,7 @@ pub fn get_gateway_status() -> Result<gateway::types::GatewayStatus, String> {
         .filter(|a| a.source.as_deref() == Some("synced"))
         .count();
     let platform_stats = crate::modules::gateway::account_pool_bridge::get_platform_account_stats();
+    let account_in_flight = gateway::concurrency::in_flight_snapshot();
 
     Ok(gateway::types::GatewayStatus {
         running: gateway::is_gateway_running(),
@@ -44,13 +48,16 @@ pub fn get_gateway_status() -> Result<gateway::types::GatewayStatus, String> {
         active_accounts,
         total_api_keys: api_keys.len(),
         total_requests: summary.total_requests,
-        uptime_seconds: None,
+        uptime_seconds: gateway::gateway_uptime_seconds(),
         synced_accounts,
         platform_stats: if platform_stats.is_empty() {
             None
         } else {
             Some(platform_stats)
         },
+        in_flight_requests: gateway::server::active_requests(),
+        account_in_flight,
+        draining: gateway::is_gateway_draining(),
     })
 }
 
@@ -59,9 +66,19 @@ pub fn get_gateway_config() -> Result<gateway::types::GatewayConfig, String> {
     Ok(gateway::config::get_gateway_config())
 }
 
+/// 先保存配置，运行中修改了端口再切换监听；切换失败时恢复旧配置，
+/// 保证已保存的端口与实际监听端口一致。其余配置在下一个请求时生效
 #[tauri::command]
-pub fn save_gateway_config(config: gateway::types::GatewayConfig) -> Result<(), String> {
-    gateway::config::save_gateway_config(&config)
+pub async fn save_gateway_config(config: gateway::types::GatewayConfig) -> Result<(), String> {
+    let previous = gateway::config::get_gateway_config();
+    gateway::config::save_gateway_config(&config)?;
+    if gateway::is_gateway_running() {
+        if let Err(e) = gateway::rebind_gateway(config.port).await {
+            gateway::config::save_gateway_config(&previous)?;
+            return Err(e);
+        }
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -94,7 +111,17 @@ pub fn add_gateway_account(
 
 #[tauri::command]
 pub fn delete_gateway_account(id: String) -> Result<(), String> {
-    gateway::db::delete_account(&id)
+    gateway::db::delete_account(&id)?;
+    gateway::token_refresh::forget_account(&id);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_account_events(
+    account_id: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<gateway::types::AccountEvent>, String> {
+    gateway::db::list_account_events(account_id.as_deref(), limit.unwrap_or(50))
 }
 
 #[tauri::command]
@@ -131,6 +158,44 @@ pub fn toggle_api_key(id: String, enabled: bool) -> Result<(), String> {
     gateway::db::toggle_api_key(&id, enabled)
 }
 
+#[tauri::command]
+pub fn update_api_key_limits(
+    id: String,
+    limits: gateway::types::ApiKeyLimits,
+) -> Result<(), String> {
+    gateway::db::update_api_key_limits(&id, &limits)
+}
+
+#[tauri::command]
+pub fn update_api_key_group(id: String, group_name: Option<String>) -> Result<(), String> {
+    gateway::db::update_api_key_group(&id, group_name.as_deref().filter(|g| !g.is_empty()))
+}
+
+#[tauri::command]
+pub fn rotate_api_key(
+    id: String,
+    overlap_seconds: i64,
+) -> Result<(String, gateway::types::GatewayApiKey), String> {
+    gateway::api_key::rotate_api_key(&id, overlap_seconds)
+}
+
+#[tauri::command]
+pub fn update_api_key_scopes(
+    id: String,
+    allowed_models: Option<Vec<String>>,
+    allowed_endpoints: Option<Vec<String>>,
+) -> Result<(), String> {
+    gateway::api_key::update_scopes(&id, allowed_models.as_ref(), allowed_endpoints.as_ref())
+}
+
+#[tauri::command]
+pub fn list_api_key_audit(
+    key_id: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<gateway::types::ApiKeyAuditEntry>, String> {
+    gateway::db::list_api_key_audit(key_id.as_deref(), limit.unwrap_or(100))
+}
+
 #[tauri::command]
 pub fn list_request_logs(
     query: gateway::types::RequestLogQuery,
@@ -138,6 +203,32 @@ pub fn list_request_logs(
     gateway::request_log::query_logs(&query)
 }
 
+#[tauri::command]
+pub fn get_request_capture(
+    trace_id: String,
+) -> Result<Option<gateway::types::RequestCapture>, String> {
+    gateway::db::get_request_capture(&trace_id)
+}
+
+#[tauri::command]
+pub async fn replay_request_capture(
+    trace_id: String,
+    account_id: Option<String>,
+    upstream_base_url: Option<String>,
+) -> Result<gateway::types::ReplayResult, String> {
+    gateway::capture::replay(
+        &trace_id,
+        account_id.as_deref(),
+        upstream_base_url.as_deref(),
+    )
+    .await
+}
+
+#[tauri::command]
+pub fn clear_request_captures() -> Result<(), String> {
+    gateway::db::clear_request_captures()
+}
+
 #[tauri::command]
 pub fn get_request_log_summary() -> Result<gateway::types::RequestLogSummary, String> {
     gateway::request_log::get_summary()
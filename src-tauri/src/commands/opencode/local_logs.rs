@@ -4,6 +4,7 @@
 
 use base64::Engine as _;
 use crate::modules::{cursor_account, logger, opencode_db::Database};
+use crate::modules::proxy::usage::budget;
 use crate::opencode_error::AppError;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
@@ -5230,6 +5231,12 @@ pub async fn import_local_logs(
 
     emit_local_log_progress(&window, "import", "done", total_sources, total_sources, "导入完成");
 
+    // 导入的记录可能使消费预算越过提醒阈值
+    drop(conn);
+    if imported > 0 {
+        budget::evaluate_budgets(&db, None);
+    }
+
     Ok(LocalLogImportResult {
         imported,
         skipped,
@@ -5356,6 +5363,11 @@ pub async fn auto_import_local_logs(db: State<'_, Arc<Database>>) -> Result<u32,
             }
         }
     }
+
+    drop(conn);
+    if imported > 0 {
+        budget::evaluate_budgets(&db, None);
+    }
     
     Ok(imported)
 }
@@ -1,8 +1,17 @@
 //! 代理服务器相关命令
 
-use crate::modules::opencode_db::schema::{ModelTrendData, ProjectStats, ProviderStats, UsageSummary, UsageTrend};
+use crate::modules::opencode_db::schema::{
+    ModelTrendData, ProjectStats, ProviderStats, TakeoverTarget, UsageBudgetDb, UsageSummary,
+    UsageTrend,
+};
 use crate::modules::opencode_db::Database;
-use crate::modules::proxy::{ProxyServerInfo, ProxyService, ProxyStatus, ProxyTakeoverStatus};
+use crate::modules::proxy::service::{RECOVERY_ASK, RECOVERY_RELAUNCH, RECOVERY_RESTORE};
+use crate::modules::proxy::usage::budget::{
+    evaluate_budgets, list_budget_status, validate_budget, BudgetStatus,
+};
+use crate::modules::proxy::{
+    OrphanedTakeover, ProxyServerInfo, ProxyService, ProxyStatus, ProxyTakeoverStatus,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tauri::State;
@@ -21,16 +30,35 @@ pub struct ProxyConfigResponse {
     pub takeover_claude: bool,
     pub takeover_codex: bool,
     pub takeover_gemini: bool,
+    #[serde(default)]
+    pub failover_claude: Vec<String>,
+    #[serde(default)]
+    pub failover_codex: Vec<String>,
+    #[serde(default)]
+    pub failover_gemini: Vec<String>,
+    #[serde(default)]
+    pub target_claude: Option<TakeoverTarget>,
+    #[serde(default)]
+    pub target_gemini: Option<TakeoverTarget>,
+    /// 启动时发现残留接管的处理方式：ask / restore / relaunch
+    #[serde(default = "default_takeover_recovery")]
+    pub takeover_recovery: String,
 }
 
-/// 初始化代理服务
+fn default_takeover_recovery() -> String {
+    RECOVERY_ASK.to_string()
+}
+
+/// 初始化代理服务；启动时的残留接管检查可能已创建服务并重新启动了代理，此时沿用
 #[tauri::command]
 pub async fn init_proxy_service(
     db: State<'_, Arc<Database>>,
     proxy_state: State<'_, ProxyServiceState>,
 ) -> Result<(), String> {
-    let service = ProxyService::new(db.inner().clone());
-    *proxy_state.0.write().await = Some(service);
+    let mut guard = proxy_state.0.write().await;
+    if guard.is_none() {
+        *guard = Some(ProxyService::new(db.inner().clone()));
+    }
     Ok(())
 }
 
@@ -107,16 +135,47 @@ pub async fn get_takeover_status(
     service.get_takeover_status().map_err(|e| e.to_string())
 }
 
-/// 为指定应用设置接管
+/// 为指定应用设置接管，`target` 指定时经协议转换转发到其他协议的服务商
 #[tauri::command]
 pub async fn set_takeover_for_app(
     app_type: String,
     enabled: bool,
+    target: Option<TakeoverTarget>,
+    proxy_state: State<'_, ProxyServiceState>,
+) -> Result<(), String> {
+    let guard = proxy_state.0.read().await;
+    let service = guard.as_ref().ok_or("代理服务未初始化")?;
+    service
+        .set_takeover_for_app(&app_type, enabled, target)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 获取残留接管：代理未运行但 CLI 配置仍指向本地代理的应用
+#[tauri::command]
+pub async fn get_orphaned_takeover(
+    proxy_state: State<'_, ProxyServiceState>,
+) -> Result<Vec<OrphanedTakeover>, String> {
+    let guard = proxy_state.0.read().await;
+    let service = guard.as_ref().ok_or("代理服务未初始化")?;
+    service
+        .get_orphaned_takeover()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 处理残留接管：`restore` 恢复 CLI 配置，`relaunch` 重新启动代理
+#[tauri::command]
+pub async fn recover_proxy_takeover(
+    action: String,
     proxy_state: State<'_, ProxyServiceState>,
 ) -> Result<(), String> {
     let guard = proxy_state.0.read().await;
     let service = guard.as_ref().ok_or("代理服务未初始化")?;
-    service.set_takeover_for_app(&app_type, enabled).await.map_err(|e| e.to_string())
+    service
+        .recover_takeover(&action)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 /// 获取代理配置
@@ -132,6 +191,12 @@ pub async fn get_proxy_config(
         takeover_claude: config.takeover_claude,
         takeover_codex: config.takeover_codex,
         takeover_gemini: config.takeover_gemini,
+        failover_claude: config.failover_claude,
+        failover_codex: config.failover_codex,
+        failover_gemini: config.failover_gemini,
+        target_claude: config.target_claude,
+        target_gemini: config.target_gemini,
+        takeover_recovery: config.takeover_recovery,
     })
 }
 
@@ -141,6 +206,14 @@ pub async fn update_proxy_config(
     config: ProxyConfigResponse,
     db: State<'_, Arc<Database>>,
 ) -> Result<(), String> {
+    if ![RECOVERY_ASK, RECOVERY_RESTORE, RECOVERY_RELAUNCH]
+        .contains(&config.takeover_recovery.as_str())
+    {
+        return Err(format!(
+            "未知的残留接管处理方式: {}",
+            config.takeover_recovery
+        ));
+    }
     let config_db = crate::modules::opencode_db::schema::ProxyConfigDb {
         proxy_enabled: config.proxy_enabled,
         listen_address: config.listen_address,
@@ -148,6 +221,12 @@ pub async fn update_proxy_config(
         takeover_claude: config.takeover_claude,
         takeover_codex: config.takeover_codex,
         takeover_gemini: config.takeover_gemini,
+        failover_claude: config.failover_claude,
+        failover_codex: config.failover_codex,
+        failover_gemini: config.failover_gemini,
+        target_claude: config.target_claude,
+        target_gemini: config.target_gemini,
+        takeover_recovery: config.takeover_recovery,
     };
     db.update_proxy_config(&config_db).map_err(|e| e.to_string())
 }
@@ -234,6 +313,49 @@ pub async fn clear_proxy_usage_stats(
     db.clear_usage_stats().map_err(|e| e.to_string())
 }
 
+// ==================== 消费预算命令 ====================
+
+/// 获取所有消费预算及其当前周期的消费
+#[tauri::command]
+pub async fn list_usage_budgets(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<BudgetStatus>, String> {
+    list_budget_status(&db).map_err(|e| e.to_string())
+}
+
+/// 新增或更新消费预算；修改后重新计算本周期的提醒状态
+#[tauri::command]
+pub async fn save_usage_budget(
+    mut budget: UsageBudgetDb,
+    db: State<'_, Arc<Database>>,
+) -> Result<UsageBudgetDb, String> {
+    validate_budget(&budget).map_err(|e| e.to_string())?;
+
+    let now = chrono::Utc::now().timestamp();
+    if budget.id.is_empty() {
+        budget.id = uuid::Uuid::new_v4().to_string();
+        budget.created_at = now;
+    }
+    budget.scope_value = budget.scope_value.trim().to_string();
+    budget.alerted_threshold = 0;
+    budget.alerted_period_start = None;
+    budget.updated_at = now;
+    db.save_usage_budget(&budget).map_err(|e| e.to_string())?;
+
+    // 已有的消费可能已越过新预算的阈值
+    evaluate_budgets(&db, None);
+    Ok(budget)
+}
+
+/// 删除消费预算
+#[tauri::command]
+pub async fn delete_usage_budget(
+    id: String,
+    db: State<'_, Arc<Database>>,
+) -> Result<(), String> {
+    db.delete_usage_budget(&id).map_err(|e| e.to_string())
+}
+
 // ==================== 辅助函数 ====================
 
 /// 计算时间范围
@@ -19,8 +19,6 @@ use tauri::{Emitter, Manager};
 use tokio::sync::RwLock;
 use tracing::info;
 
-pub struct ProxyServiceState(pub Arc<RwLock<Option<modules::proxy::ProxyService>>>);
-
 /// 全局 AppHandle 存储
 static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
 
@@ -79,7 +77,7 @@ pub fn run() {
         panic!("Database init failed: {}", e);
     });
     let db_arc = Arc::new(database);
-    let proxy_service_state = ProxyServiceState(Arc::new(RwLock::new(None)));
+    let proxy_service_state = commands::opencode::ProxyServiceState(Arc::new(RwLock::new(None)));
 
     let app = tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
@@ -182,6 +180,21 @@ pub fn run() {
                 }
             });
 
+            // 检查上次崩溃或被强制结束残留的代理接管，避免 CLI 一直指向已失效的本地端口
+            {
+                let db = app.state::<Arc<Database>>().inner().clone();
+                let proxy_state = app.state::<commands::opencode::ProxyServiceState>().0.clone();
+                tauri::async_runtime::spawn(async move {
+                    // 持有写锁直到检查完成，重新启动的代理由前端后续使用的同一服务管理
+                    let mut guard = proxy_state.write().await;
+                    let service =
+                        guard.get_or_insert_with(|| modules::proxy::ProxyService::new(db));
+                    if let Err(e) = service.reconcile_takeover().await {
+                        logger::log_error(&format!("[Proxy] 检查残留接管失败: {}", e));
+                    }
+                });
+            }
+
             // 启动网页查询服务（网络服务配置中的独立模块）
             tauri::async_runtime::spawn(async {
                 modules::web_report::start_server().await;
@@ -670,15 +683,24 @@ pub fn run() {
             commands::gateway::list_gateway_accounts,
             commands::gateway::add_gateway_account,
             commands::gateway::delete_gateway_account,
+            commands::gateway::list_account_events,
             commands::gateway::import_gateway_accounts,
             commands::gateway::export_gateway_accounts,
             commands::gateway::list_api_keys,
             commands::gateway::create_api_key,
             commands::gateway::delete_api_key,
             commands::gateway::toggle_api_key,
+            commands::gateway::update_api_key_limits,
+            commands::gateway::update_api_key_group,
+            commands::gateway::rotate_api_key,
+            commands::gateway::update_api_key_scopes,
+            commands::gateway::list_api_key_audit,
             commands::gateway::list_request_logs,
             commands::gateway::get_request_log_summary,
             commands::gateway::clear_request_logs,
+            commands::gateway::get_request_capture,
+            commands::gateway::replay_request_capture,
+            commands::gateway::clear_request_captures,
             commands::gateway::sync_accounts_to_gateway,
             commands::gateway::get_platform_account_stats,
             commands::gateway::sync_accounts_to_sub2api,
@@ -909,6 +931,8 @@ pub fn run() {
             commands::opencode::stop_proxy_with_restore,
             commands::opencode::get_takeover_status,
             commands::opencode::set_takeover_for_app,
+            commands::opencode::get_orphaned_takeover,
+            commands::opencode::recover_proxy_takeover,
             commands::opencode::get_proxy_config,
             commands::opencode::update_proxy_config,
             commands::opencode::get_proxy_usage_summary,
@@ -917,6 +941,9 @@ pub fn run() {
             commands::opencode::get_provider_stats,
             commands::opencode::get_project_stats,
             commands::opencode::clear_proxy_usage_stats,
+            commands::opencode::list_usage_budgets,
+            commands::opencode::save_usage_budget,
+            commands::opencode::delete_usage_budget,
             // === OpenCode Unified Config Commands ===
             commands::opencode::get_open_switch_providers,
             commands::opencode::get_open_switch_provider,
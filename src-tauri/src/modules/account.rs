@@ -1009,16 +1009,20 @@ pub fn emit_quota_alert(app_handle: &tauri::AppHandle, payload: &QuotaAlertPaylo
     let _ = app_handle.emit("quota:alert", payload);
 }
 
-#[cfg(not(target_os = "macos"))]
 pub fn send_quota_alert_native_notification(payload: &QuotaAlertPayload) {
+    let (title, body) = build_quota_alert_notification_text(payload);
+    send_native_notification(title, body);
+}
+
+/// 发送系统原生通知，配额预警与消费预算提醒共用
+#[cfg(not(target_os = "macos"))]
+pub fn send_native_notification(title: String, body: String) {
     let Some(app_handle) = crate::get_app_handle() else {
         return;
     };
 
     use tauri_plugin_notification::NotificationExt;
 
-    let (title, body) = build_quota_alert_notification_text(payload);
-
     if let Err(e) = app_handle
         .notification()
         .builder()
@@ -1026,17 +1030,17 @@ pub fn send_quota_alert_native_notification(payload: &QuotaAlertPayload) {
         .body(body)
         .show()
     {
-        modules::logger::log_warn(&format!("[QuotaAlert] 原生通知发送失败: {}", e));
+        modules::logger::log_warn(&format!("[Notification] 原生通知发送失败: {}", e));
     }
 }
 
+/// 发送系统原生通知，配额预警与消费预算提醒共用
 #[cfg(target_os = "macos")]
-pub fn send_quota_alert_native_notification(payload: &QuotaAlertPayload) {
+pub fn send_native_notification(title: String, body: String) {
     let Some(app_handle) = crate::get_app_handle() else {
         return;
     };
     let bundle_identifier = app_handle.config().identifier.to_string();
-    let (title, body) = build_quota_alert_notification_text(payload);
 
     std::thread::spawn(move || {
         let mut notification = mac_notification_sys::Notification::new();
@@ -1049,11 +1053,11 @@ pub fn send_quota_alert_native_notification(payload: &QuotaAlertPayload) {
             .asynchronous(true);
 
         if let Err(e) = mac_notification_sys::set_application(&bundle_identifier) {
-            modules::logger::log_warn(&format!("[QuotaAlert] 设置通知应用标识失败: {}", e));
+            modules::logger::log_warn(&format!("[Notification] 设置通知应用标识失败: {}", e));
         }
 
         if let Err(e) = notification.send() {
-            modules::logger::log_warn(&format!("[QuotaAlert] 原生通知发送失败: {}", e));
+            modules::logger::log_warn(&format!("[Notification] 原生通知发送失败: {}", e));
         }
     });
 }
@@ -812,7 +812,41 @@ pub fn is_token_expired(access_token: &str) -> bool {
     exp < now + 60
 }
 
+/// Token 刷新失败的详细信息，调用方据此区分 refresh_token 失效与临时故障
+#[derive(Debug, Clone)]
+pub struct TokenRefreshError {
+    /// 服务端返回的 HTTP 状态；请求未发出或响应无法解析时为 None
+    pub status: Option<u16>,
+    /// OAuth 错误响应中的 error 字段，如 invalid_grant
+    pub oauth_error: Option<String>,
+    pub message: String,
+}
+
+impl From<String> for TokenRefreshError {
+    fn from(message: String) -> Self {
+        Self {
+            status: None,
+            oauth_error: None,
+            message,
+        }
+    }
+}
+
+impl From<&str> for TokenRefreshError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
 pub async fn refresh_access_token(refresh_token: &str) -> Result<CodexTokens, String> {
+    refresh_access_token_detailed(refresh_token)
+        .await
+        .map_err(|e| e.message)
+}
+
+pub async fn refresh_access_token_detailed(
+    refresh_token: &str,
+) -> Result<CodexTokens, TokenRefreshError> {
     let client = reqwest::Client::new();
 
     let params = [
@@ -842,7 +876,14 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<CodexTokens, St
             status,
             &body[..body.len().min(200)]
         ));
-        return Err(format!("Token 刷新失败: {}", status));
+        let oauth_error = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("error")?.as_str().map(|s| s.to_string()));
+        return Err(TokenRefreshError {
+            status: Some(status.as_u16()),
+            oauth_error,
+            message: format!("Token 刷新失败: {}", status),
+        });
     }
 
     logger::log_info("Codex Token 刷新成功");
@@ -1,11 +1,44 @@
 use super::db;
-use super::account_pool_bridge;
-use super::types::{GatewayAccount, RouteStrategy};
+use super::account_pool_bridge::{self, Platform};
+use super::concurrency::{self, AccountPermit};
+use super::config;
+use super::protocol_adapter::ApiProtocol;
+use super::quota_lookup;
+use super::types::{AccountGroup, GatewayAccount, RouteStrategy};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static ROUND_ROBIN_INDEX: AtomicUsize = AtomicUsize::new(0);
 
-pub fn select_account(strategy: &RouteStrategy) -> Result<GatewayAccount, String> {
+pub enum SelectError {
+    /// 候选账号均已达到并发上限，可排队等待名额释放
+    Saturated,
+    Unavailable(String),
+}
+
+impl From<String> for SelectError {
+    fn from(message: String) -> Self {
+        SelectError::Unavailable(message)
+    }
+}
+
+/// 本次请求对候选账号的限制条件
+pub struct AccountFilter<'a> {
+    pub model: Option<&'a str>,
+    /// 模型映射指定的平台（或分组）；为空时按模型与协议自动筛选平台
+    pub platform: Option<&'a str>,
+    pub protocol: &'a ApiProtocol,
+    /// API Key 或请求头绑定的账号分组，分组的路由策略优先于全局策略
+    pub group: Option<&'a AccountGroup>,
+}
+
+/// 选择账号并占用一个并发名额，名额随返回的 permit 一起释放
+pub fn select_account(
+    strategy: &RouteStrategy,
+    filter: &AccountFilter,
+    exclude_ids: &[String],
+    max_concurrent: u32,
+) -> Result<(GatewayAccount, AccountPermit), SelectError> {
+    let (model, platform, protocol) = (filter.model, filter.platform, filter.protocol);
     let mut accounts = db::get_active_accounts()?;
 
     if accounts.is_empty() {
@@ -20,40 +53,237 @@ pub fn select_account(strategy: &RouteStrategy) -> Result<GatewayAccount, String
     }
 
     if accounts.is_empty() {
-        return Err("没有可用的账号（数据库和平台账号池均为空）".to_string());
+        return Err(SelectError::Unavailable(
+            "没有可用的账号（数据库和平台账号池均为空）".to_string(),
+        ));
     }
 
-    let account = match strategy {
-        RouteStrategy::RoundRobin => {
-            let idx = ROUND_ROBIN_INDEX.fetch_add(1, Ordering::Relaxed) % accounts.len();
-            accounts[idx].clone()
+    match platform {
+        Some(platform) => {
+            accounts.retain(|a| {
+                a.platform.as_deref() == Some(platform) || a.group_name.as_deref() == Some(platform)
+            });
+            if accounts.is_empty() {
+                return Err(SelectError::Unavailable(format!(
+                    "没有 {} 平台的可用账号",
+                    platform
+                )));
+            }
         }
-        RouteStrategy::LeastUsed | RouteStrategy::QuotaAware => {
-            accounts
-                .iter()
-                .min_by_key(|a| a.last_used_at.unwrap_or(0))
-                .unwrap()
-                .clone()
+        None => {
+            accounts.retain(|a| can_serve(a, model, protocol));
+            if accounts.is_empty() {
+                return Err(SelectError::Unavailable(format!(
+                    "没有能服务模型 {} 的平台账号",
+                    model.unwrap_or("(未指定)")
+                )));
+            }
         }
-        RouteStrategy::Random => {
-            use rand::Rng;
-            let idx = rand::thread_rng().gen_range(0..accounts.len());
-            accounts[idx].clone()
+    }
+
+    let strategy = match filter.group {
+        Some(group) => {
+            accounts = order_group_members(group, accounts);
+            if accounts.is_empty() {
+                return Err(SelectError::Unavailable(format!(
+                    "分组 {} 中没有可用的账号",
+                    group.name
+                )));
+            }
+            group.route_strategy.as_ref().unwrap_or(strategy)
         }
-        RouteStrategy::Priority => accounts[0].clone(),
+        None => strategy,
     };
 
+    accounts.retain(|a| !exclude_ids.contains(&a.id));
+    if accounts.is_empty() {
+        return Err(SelectError::Unavailable(
+            "没有其他可用的账号可供重试".to_string(),
+        ));
+    }
+
+    accounts.retain(|a| !concurrency::is_saturated(&a.id, max_concurrent));
+    if accounts.is_empty() {
+        return Err(SelectError::Saturated);
+    }
+
+    let (account, permit) = acquire_candidate(strategy, accounts, filter, max_concurrent)?;
     db::mark_account_used(&account.id)?;
-    Ok(account)
+    Ok((account, permit))
+}
+
+/// 按路由策略从候选账号中选择并占用名额。
+/// 并发请求可能在饱和检查之后抢先占满选中的账号，此时改选其余候选账号，全部占满才返回 Saturated
+fn acquire_candidate(
+    strategy: &RouteStrategy,
+    mut accounts: Vec<GatewayAccount>,
+    filter: &AccountFilter,
+    max_concurrent: u32,
+) -> Result<(GatewayAccount, AccountPermit), SelectError> {
+    while !accounts.is_empty() {
+        let account = match strategy {
+            RouteStrategy::RoundRobin => {
+                let idx = ROUND_ROBIN_INDEX.fetch_add(1, Ordering::Relaxed) % accounts.len();
+                accounts[idx].clone()
+            }
+            RouteStrategy::LeastUsed => least_used(&accounts).clone(),
+            RouteStrategy::QuotaAware => {
+                let threshold = config::get_gateway_config().quota_threshold_percent;
+                select_by_quota(&accounts, filter.model, threshold)?
+            }
+            RouteStrategy::Random => {
+                use rand::Rng;
+                let idx = rand::thread_rng().gen_range(0..accounts.len());
+                accounts[idx].clone()
+            }
+            RouteStrategy::Priority => accounts[0].clone(),
+            RouteStrategy::Weighted => weighted_random(&accounts, filter.group).clone(),
+        };
+
+        if let Some(permit) = concurrency::try_acquire(&account.id, max_concurrent) {
+            return Ok((account, permit));
+        }
+        accounts.retain(|a| a.id != account.id);
+    }
+    Err(SelectError::Saturated)
+}
+
+/// 账号所属平台能否服务该模型与协议；未标记平台或未知平台的账号不做限制
+fn can_serve(account: &GatewayAccount, model: Option<&str>, protocol: &ApiProtocol) -> bool {
+    let Some(platform) = account.platform.as_deref().and_then(Platform::from_id) else {
+        return true;
+    };
+    if !platform.supports_protocol(protocol) {
+        return false;
+    }
+    match model {
+        Some(model) => platform.supports_model(model),
+        None => true,
+    }
+}
+
+/// 只保留分组内的账号，并按 members 中的顺序排列（未列出的成员排在最后）
+fn order_group_members(group: &AccountGroup, accounts: Vec<GatewayAccount>) -> Vec<GatewayAccount> {
+    let mut members: Vec<(usize, GatewayAccount)> = accounts
+        .into_iter()
+        .filter_map(|account| {
+            let rank = group
+                .members
+                .iter()
+                .position(|m| m.account_id == account.id);
+            let in_group = account.group_name.as_deref() == Some(group.name.as_str())
+                || account
+                    .tags
+                    .as_deref()
+                    .is_some_and(|tags| tags.split(',').any(|t| t.trim() == group.name));
+            match rank {
+                Some(rank) => Some((rank, account)),
+                None if in_group => Some((usize::MAX, account)),
+                None => None,
+            }
+        })
+        .collect();
+    members.sort_by_key(|(rank, _)| *rank);
+    members.into_iter().map(|(_, account)| account).collect()
+}
+
+/// 按分组中配置的权重随机选择，未配置权重的账号权重为 1
+fn weighted_random<'a>(
+    accounts: &'a [GatewayAccount],
+    group: Option<&AccountGroup>,
+) -> &'a GatewayAccount {
+    use rand::Rng;
+    let weight_of = |account: &GatewayAccount| {
+        group
+            .and_then(|g| g.members.iter().find(|m| m.account_id == account.id))
+            .map_or(1, |m| m.weight) as u64
+    };
+
+    let total: u64 = accounts.iter().map(weight_of).sum();
+    if total == 0 {
+        return &accounts[0];
+    }
+    let mut point = rand::thread_rng().gen_range(0..total);
+    for account in accounts {
+        let weight = weight_of(account);
+        if point < weight {
+            return account;
+        }
+        point -= weight;
+    }
+    &accounts[accounts.len() - 1]
+}
+
+fn least_used(accounts: &[GatewayAccount]) -> &GatewayAccount {
+    accounts
+        .iter()
+        .min_by_key(|a| a.last_used_at.unwrap_or(0))
+        .unwrap()
+}
+
+/// 优先选择剩余配额最多的账号；低于阈值的账号跳过，配额未知的账号按最久未使用兜底
+fn select_by_quota(
+    accounts: &[GatewayAccount],
+    model: Option<&str>,
+    threshold: i32,
+) -> Result<GatewayAccount, String> {
+    let mut best: Option<(&GatewayAccount, i32)> = None;
+    let mut unknown: Vec<GatewayAccount> = Vec::new();
+    let mut skipped = 0;
+
+    for account in accounts {
+        match quota_lookup::remaining_percentage(account, model) {
+            Some(remaining) if remaining < threshold => skipped += 1,
+            Some(remaining) => {
+                let better = match best {
+                    None => true,
+                    Some((current, current_remaining)) => {
+                        remaining > current_remaining
+                            || (remaining == current_remaining
+                                && account.last_used_at.unwrap_or(0)
+                                    < current.last_used_at.unwrap_or(0))
+                    }
+                };
+                if better {
+                    best = Some((account, remaining));
+                }
+            }
+            None => unknown.push(account.clone()),
+        }
+    }
+
+    if let Some((account, remaining)) = best {
+        tracing::debug!(
+            "[AccountPool] 配额路由选择账号 {} (剩余 {}%)",
+            account.email,
+            remaining
+        );
+        return Ok(account.clone());
+    }
+
+    if !unknown.is_empty() {
+        return Ok(least_used(&unknown).clone());
+    }
+
+    Err(format!(
+        "所有账号剩余配额均低于阈值 {}%（已跳过 {} 个账号）",
+        threshold, skipped
+    ))
 }
 
 pub fn cooldown_account(id: &str, duration_seconds: u32) -> Result<(), String> {
     let cooldown_until = chrono::Utc::now().timestamp() + duration_seconds as i64;
     db::with_db(|conn| {
         conn.execute(
-            "UPDATE gateway_accounts SET cooldown_until = ?1, status = 'cooldown', updated_at = ?2 WHERE id = ?3",
+            "UPDATE gateway_accounts SET cooldown_until = ?1, updated_at = ?2 WHERE id = ?3",
             rusqlite::params![cooldown_until, chrono::Utc::now().timestamp(), id],
         )?;
+        db::set_account_status(
+            conn,
+            id,
+            "cooldown",
+            &format!("上游限流或异常，冷却 {} 秒", duration_seconds),
+        )?;
         Ok(())
     })
 }
@@ -72,9 +302,11 @@ pub fn report_account_error(id: &str) -> Result<(), String> {
         ).unwrap_or(0);
 
         if error_count >= 5 {
-            conn.execute(
-                "UPDATE gateway_accounts SET status = 'error' WHERE id = ?1",
-                rusqlite::params![id],
+            db::set_account_status(
+                conn,
+                id,
+                "error",
+                &format!("连续失败 {} 次", error_count),
             )?;
         }
 
@@ -83,11 +315,17 @@ pub fn report_account_error(id: &str) -> Result<(), String> {
 }
 
 pub fn reset_account_errors(id: &str) -> Result<(), String> {
+    restore_account(id, "请求成功")
+}
+
+/// 清空错误计数与冷却并恢复为可用状态
+pub fn restore_account(id: &str, reason: &str) -> Result<(), String> {
     db::with_db(|conn| {
         conn.execute(
-            "UPDATE gateway_accounts SET error_count = 0, status = 'active', cooldown_until = NULL, updated_at = ?1 WHERE id = ?2",
+            "UPDATE gateway_accounts SET error_count = 0, cooldown_until = NULL, updated_at = ?1 WHERE id = ?2",
             rusqlite::params![chrono::Utc::now().timestamp(), id],
         )?;
+        db::set_account_status(conn, id, "active", reason)?;
         Ok(())
     })
 }
@@ -115,3 +353,52 @@ pub fn import_accounts(accounts: Vec<super::types::AccountImportPayload>) -> Res
 pub fn export_accounts() -> Result<Vec<GatewayAccount>, String> {
     db::list_accounts()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::gateway::types::AccountStatus;
+
+    fn account(id: &str) -> GatewayAccount {
+        GatewayAccount {
+            id: id.to_string(),
+            email: format!("{}@example.com", id),
+            access_token: String::new(),
+            refresh_token: None,
+            token_expires_at: None,
+            status: AccountStatus::Active,
+            tags: None,
+            group_name: None,
+            proxy_url: None,
+            created_at: 0,
+            updated_at: 0,
+            last_used_at: None,
+            cooldown_until: None,
+            error_count: 0,
+            platform: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_acquire_candidate_skips_account_taken_after_check() {
+        let filter = AccountFilter {
+            model: None,
+            platform: None,
+            protocol: &ApiProtocol::OpenAIChat,
+            group: None,
+        };
+        let accounts = vec![account("pool-test-a"), account("pool-test-b")];
+        // 模拟 a 在饱和检查之后被其他请求占满
+        let _held = concurrency::try_acquire("pool-test-a", 1).unwrap();
+
+        let (selected, _permit) =
+            acquire_candidate(&RouteStrategy::Priority, accounts.clone(), &filter, 1)
+                .ok()
+                .unwrap();
+        assert_eq!(selected.id, "pool-test-b");
+
+        let result = acquire_candidate(&RouteStrategy::Priority, accounts, &filter, 1);
+        assert!(matches!(result, Err(SelectError::Saturated)));
+    }
+}
@@ -1,3 +1,4 @@
+use super::protocol_adapter::ApiProtocol;
 use super::types::{AccountStatus, GatewayAccount};
 use crate::modules::logger;
 use std::collections::HashMap;
@@ -71,6 +72,35 @@ impl Platform {
             Platform::Trae,
         ]
     }
+
+    pub fn from_id(id: &str) -> Option<Platform> {
+        Platform::all().into_iter().find(|p| p.to_string() == id)
+    }
+
+    /// 按模型系列粗略判断平台账号能否服务该模型；无法判断的平台不做限制
+    pub fn supports_model(&self, model: &str) -> bool {
+        let model = model.to_lowercase();
+        let is_openai = model.starts_with("gpt-")
+            || model.starts_with("codex")
+            || (model.starts_with('o') && model[1..].starts_with(|c: char| c.is_ascii_digit()));
+        match self {
+            Platform::Codex => is_openai,
+            Platform::Gemini => model.starts_with("gemini"),
+            Platform::Antigravity => model.starts_with("claude") || model.starts_with("gemini"),
+            Platform::GithubCopilot => {
+                is_openai || model.starts_with("claude") || model.starts_with("gemini")
+            }
+            _ => true,
+        }
+    }
+
+    /// Gemini 只提供 OpenAI 兼容的 Chat Completions 接口，无法承接转换后的 Responses 请求
+    pub fn supports_protocol(&self, protocol: &ApiProtocol) -> bool {
+        match self {
+            Platform::Gemini => matches!(protocol, ApiProtocol::OpenAIChat | ApiProtocol::Unknown),
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -235,6 +265,20 @@ fn load_single_account(
     })
 }
 
+/// 重新读取单个平台账号文件，平台模块刷新 Token 后用它取回新的凭据
+pub fn load_platform_account(
+    platform: &Platform,
+    account_id: &str,
+) -> Result<GatewayAccount, String> {
+    let data_dir = dirs::data_dir()
+        .ok_or("无法获取数据目录")?
+        .join("com.jlcodes.ai-switch");
+    let account_file = data_dir
+        .join(platform.to_string())
+        .join(format!("{}.json", account_id));
+    load_single_account(&account_file, account_id, platform).map(|pa| pa.account)
+}
+
 pub fn sync_platform_accounts_to_gateway() -> Result<SyncResult, String> {
     let platform_accounts = collect_platform_accounts();
     let existing = super::db::list_accounts().unwrap_or_default();
@@ -248,14 +292,24 @@ pub fn sync_platform_accounts_to_gateway() -> Result<SyncResult, String> {
 
         if let Some(existing_account) = existing_match {
             if existing_account.access_token != pa.account.access_token {
+                // 标签、分组与代理可能已在网关中调整过，刷新 token 时保留
                 super::db::insert_account(
                     &pa.account.id,
                     &pa.account.email,
                     &pa.account.access_token,
                     pa.account.refresh_token.as_deref(),
-                    pa.account.tags.as_deref(),
-                    pa.account.group_name.as_deref(),
-                    pa.account.proxy_url.as_deref(),
+                    existing_account
+                        .tags
+                        .as_deref()
+                        .or(pa.account.tags.as_deref()),
+                    existing_account
+                        .group_name
+                        .as_deref()
+                        .or(pa.account.group_name.as_deref()),
+                    existing_account
+                        .proxy_url
+                        .as_deref()
+                        .or(pa.account.proxy_url.as_deref()),
                     pa.account.platform.as_deref(),
                     pa.account.source.as_deref(),
                 )?;
@@ -279,6 +333,10 @@ pub fn sync_platform_accounts_to_gateway() -> Result<SyncResult, String> {
         }
     }
 
+    if added > 0 || updated > 0 {
+        super::model_catalog::invalidate_cache();
+    }
+
     logger::log_info(&format!(
         "[AccountBridge] 同步完成: 新增 {}, 更新 {}, 跳过 {}",
         added, updated, skipped
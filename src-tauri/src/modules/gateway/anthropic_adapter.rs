@@ -0,0 +1,703 @@
+use super::stream::{sse_event, SseTranslator};
+use serde_json::{json, Map, Value};
+
+/// Anthropic Messages 请求 → Responses 请求
+pub fn convert_request(body: &[u8]) -> Result<Vec<u8>, String> {
+    let json: Value = serde_json::from_slice(body).map_err(|e| format!("解析请求体失败: {}", e))?;
+    let converted = convert_request_value(&json);
+    serde_json::to_vec(&converted).map_err(|e| format!("序列化请求体失败: {}", e))
+}
+
+fn convert_request_value(json: &Value) -> Value {
+    let mut out = Map::new();
+
+    if let Some(model) = json.get("model") {
+        out.insert("model".to_string(), model.clone());
+    }
+
+    if let Some(instructions) = json.get("system").and_then(system_to_instructions) {
+        out.insert("instructions".to_string(), Value::String(instructions));
+    }
+
+    let messages = json
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.as_slice())
+        .unwrap_or(&[]);
+    out.insert("input".to_string(), Value::Array(convert_messages(messages)));
+
+    if let Some(tools) = json.get("tools").and_then(|t| t.as_array()) {
+        let converted: Vec<Value> = tools.iter().filter_map(convert_tool).collect();
+        if !converted.is_empty() {
+            out.insert("tools".to_string(), Value::Array(converted));
+        }
+    }
+
+    if let Some(tool_choice) = json.get("tool_choice") {
+        if let Some(choice) = convert_tool_choice(tool_choice) {
+            out.insert("tool_choice".to_string(), choice);
+        }
+        if tool_choice
+            .get("disable_parallel_tool_use")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            out.insert("parallel_tool_calls".to_string(), Value::Bool(false));
+        }
+    }
+
+    if let Some(max_tokens) = json.get("max_tokens") {
+        out.insert("max_output_tokens".to_string(), max_tokens.clone());
+    }
+    for key in ["temperature", "top_p", "stream"] {
+        if let Some(value) = json.get(key) {
+            out.insert(key.to_string(), value.clone());
+        }
+    }
+
+    if let Some(reasoning) = json.get("thinking").and_then(convert_thinking) {
+        out.insert("reasoning".to_string(), reasoning);
+    }
+
+    if let Some(user_id) = json
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(|v| v.as_str())
+    {
+        out.insert("user".to_string(), Value::String(user_id.to_string()));
+    }
+
+    Value::Object(out)
+}
+
+fn system_to_instructions(system: &Value) -> Option<String> {
+    let text = match system {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => return None,
+    };
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// 按原顺序展开消息：文本/图片合并为 message 条目，tool_use 与 tool_result 拆为独立的函数调用条目
+fn convert_messages(messages: &[Value]) -> Vec<Value> {
+    let mut input = Vec::new();
+
+    for msg in messages {
+        let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+        let is_assistant = role == "assistant";
+        let text_type = if is_assistant { "output_text" } else { "input_text" };
+
+        let blocks = match msg.get("content") {
+            Some(Value::String(text)) => vec![json!({"type": "text", "text": text})],
+            Some(Value::Array(blocks)) => blocks.clone(),
+            _ => Vec::new(),
+        };
+
+        let mut parts: Vec<Value> = Vec::new();
+        for block in &blocks {
+            match block.get("type").and_then(|t| t.as_str()).unwrap_or("text") {
+                "text" => {
+                    let text = block.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                    parts.push(json!({"type": text_type, "text": text}));
+                }
+                "image" => {
+                    if let Some(image) = convert_image(block) {
+                        parts.push(image);
+                    }
+                }
+                "tool_use" => {
+                    flush_message(&mut input, role, &mut parts);
+                    let arguments = block
+                        .get("input")
+                        .map(|i| i.to_string())
+                        .unwrap_or_else(|| "{}".to_string());
+                    input.push(json!({
+                        "type": "function_call",
+                        "call_id": block.get("id").cloned().unwrap_or(Value::Null),
+                        "name": block.get("name").cloned().unwrap_or(Value::Null),
+                        "arguments": arguments,
+                    }));
+                }
+                "tool_result" => {
+                    flush_message(&mut input, role, &mut parts);
+                    input.push(json!({
+                        "type": "function_call_output",
+                        "call_id": block.get("tool_use_id").cloned().unwrap_or(Value::Null),
+                        "output": tool_result_output(block),
+                    }));
+                }
+                // 上游无法校验 Anthropic 的思考签名，历史中的 thinking 块直接丢弃
+                _ => {}
+            }
+        }
+        flush_message(&mut input, role, &mut parts);
+    }
+
+    input
+}
+
+fn flush_message(input: &mut Vec<Value>, role: &str, parts: &mut Vec<Value>) {
+    if parts.is_empty() {
+        return;
+    }
+    input.push(json!({
+        "type": "message",
+        "role": role,
+        "content": std::mem::take(parts),
+    }));
+}
+
+fn convert_image(block: &Value) -> Option<Value> {
+    let source = block.get("source")?;
+    let url = match source.get("type").and_then(|t| t.as_str()) {
+        Some("base64") => format!(
+            "data:{};base64,{}",
+            source.get("media_type").and_then(|m| m.as_str()).unwrap_or("image/png"),
+            source.get("data").and_then(|d| d.as_str())?
+        ),
+        Some("url") => source.get("url").and_then(|u| u.as_str())?.to_string(),
+        _ => return None,
+    };
+    Some(json!({"type": "input_image", "image_url": url}))
+}
+
+fn tool_result_output(block: &Value) -> String {
+    let text = match block.get("content") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    if block.get("is_error").and_then(|v| v.as_bool()).unwrap_or(false) {
+        format!("[error] {}", text)
+    } else {
+        text
+    }
+}
+
+fn convert_tool(tool: &Value) -> Option<Value> {
+    // 仅转换自定义工具；web_search 等 Anthropic 服务端工具在上游没有对应实现
+    match tool.get("type").and_then(|t| t.as_str()) {
+        None | Some("custom") => {}
+        Some(_) => return None,
+    }
+    Some(json!({
+        "type": "function",
+        "name": tool.get("name")?,
+        "description": tool.get("description").cloned().unwrap_or(Value::Null),
+        "parameters": tool
+            .get("input_schema")
+            .cloned()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    }))
+}
+
+fn convert_tool_choice(choice: &Value) -> Option<Value> {
+    match choice.get("type").and_then(|t| t.as_str())? {
+        "auto" => Some(json!("auto")),
+        "any" => Some(json!("required")),
+        "none" => Some(json!("none")),
+        "tool" => Some(json!({"type": "function", "name": choice.get("name")?})),
+        _ => None,
+    }
+}
+
+/// Anthropic 以 budget_tokens 表示思考预算，映射到 Responses 的 reasoning effort 档位
+fn convert_thinking(thinking: &Value) -> Option<Value> {
+    if thinking.get("type").and_then(|t| t.as_str()) != Some("enabled") {
+        return None;
+    }
+    let budget = thinking
+        .get("budget_tokens")
+        .and_then(|b| b.as_u64())
+        .unwrap_or(0);
+    let effort = if budget < 4096 {
+        "low"
+    } else if budget < 16384 {
+        "medium"
+    } else {
+        "high"
+    };
+    Some(json!({"effort": effort, "summary": "auto"}))
+}
+
+/// Responses 非流式响应 → Anthropic Messages 响应，无法解析时返回 None
+pub fn convert_response(body: &[u8]) -> Option<Vec<u8>> {
+    let json: Value = serde_json::from_slice(body).ok()?;
+    json.get("output")?;
+    serde_json::to_vec(&convert_response_value(&json)).ok()
+}
+
+fn convert_response_value(resp: &Value) -> Value {
+    let mut content = Vec::new();
+    let mut has_tool_use = false;
+
+    for item in resp
+        .get("output")
+        .and_then(|o| o.as_array())
+        .map(|o| o.as_slice())
+        .unwrap_or(&[])
+    {
+        match item.get("type").and_then(|t| t.as_str()) {
+            Some("message") => {
+                for part in item
+                    .get("content")
+                    .and_then(|c| c.as_array())
+                    .map(|c| c.as_slice())
+                    .unwrap_or(&[])
+                {
+                    let text = match part.get("type").and_then(|t| t.as_str()) {
+                        Some("output_text") => part.get("text"),
+                        Some("refusal") => part.get("refusal"),
+                        _ => None,
+                    };
+                    if let Some(text) = text.and_then(|t| t.as_str()) {
+                        content.push(json!({"type": "text", "text": text}));
+                    }
+                }
+            }
+            Some("function_call") => {
+                has_tool_use = true;
+                content.push(json!({
+                    "type": "tool_use",
+                    "id": item.get("call_id").cloned().unwrap_or(Value::Null),
+                    "name": item.get("name").cloned().unwrap_or(Value::Null),
+                    "input": parse_arguments(item.get("arguments")),
+                }));
+            }
+            Some("reasoning") => {
+                let summary = item
+                    .get("summary")
+                    .and_then(|s| s.as_array())
+                    .map(|parts| {
+                        parts
+                            .iter()
+                            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                            .collect::<Vec<_>>()
+                            .join("\n\n")
+                    })
+                    .unwrap_or_default();
+                if !summary.is_empty() {
+                    content.push(json!({"type": "thinking", "thinking": summary, "signature": ""}));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    json!({
+        "id": resp.get("id").cloned().unwrap_or(Value::Null),
+        "type": "message",
+        "role": "assistant",
+        "model": resp.get("model").cloned().unwrap_or(Value::Null),
+        "content": content,
+        "stop_reason": stop_reason(resp, has_tool_use),
+        "stop_sequence": Value::Null,
+        "usage": convert_usage(resp.get("usage")),
+    })
+}
+
+fn parse_arguments(arguments: Option<&Value>) -> Value {
+    match arguments {
+        Some(Value::String(s)) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+        Some(v @ Value::Object(_)) => v.clone(),
+        _ => json!({}),
+    }
+}
+
+fn stop_reason(resp: &Value, has_tool_use: bool) -> &'static str {
+    let incomplete_reason = resp
+        .get("incomplete_details")
+        .and_then(|d| d.get("reason"))
+        .and_then(|r| r.as_str());
+    if incomplete_reason == Some("max_output_tokens") {
+        "max_tokens"
+    } else if has_tool_use {
+        "tool_use"
+    } else {
+        "end_turn"
+    }
+}
+
+/// Responses 的 input_tokens 包含缓存命中部分，Anthropic 则分开计数
+fn convert_usage(usage: Option<&Value>) -> Value {
+    let get = |v: Option<&Value>| v.and_then(|v| v.as_u64()).unwrap_or(0);
+    let input = get(usage.and_then(|u| u.get("input_tokens")));
+    let cached = get(usage
+        .and_then(|u| u.get("input_tokens_details"))
+        .and_then(|d| d.get("cached_tokens")));
+    json!({
+        "input_tokens": input.saturating_sub(cached),
+        "output_tokens": get(usage.and_then(|u| u.get("output_tokens"))),
+        "cache_read_input_tokens": cached,
+        "cache_creation_input_tokens": 0,
+    })
+}
+
+/// 上游错误响应 → Anthropic 错误格式
+pub fn convert_error(status: u16, body: &[u8]) -> Vec<u8> {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|json| {
+            json.get("error")
+                .and_then(|e| e.get("message").or(Some(e)))
+                .and_then(|m| m.as_str().map(|s| s.to_string()))
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).to_string());
+    let error_type = match status {
+        400 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        529 => "overloaded_error",
+        _ => "api_error",
+    };
+    serde_json::to_vec(&json!({
+        "type": "error",
+        "error": {"type": error_type, "message": message},
+    }))
+    .unwrap_or_default()
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum BlockKind {
+    Text,
+    Thinking,
+    ToolUse,
+}
+
+/// Responses SSE 事件 → Anthropic Messages SSE 事件
+#[derive(Default)]
+pub struct ResponsesToAnthropicStream {
+    message_started: bool,
+    finished: bool,
+    next_index: usize,
+    /// 当前打开的内容块: (上游 output_index, 块类型, Anthropic 块 index)
+    open_block: Option<(u64, BlockKind, usize)>,
+    has_tool_use: bool,
+}
+
+impl ResponsesToAnthropicStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn start_message(&mut self, response: Option<&Value>, out: &mut Vec<u8>) {
+        if self.message_started {
+            return;
+        }
+        self.message_started = true;
+        let field = |key: &str| {
+            response
+                .and_then(|r| r.get(key))
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+        out.extend(sse_event(
+            "message_start",
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": field("id"),
+                    "type": "message",
+                    "role": "assistant",
+                    "model": field("model"),
+                    "content": [],
+                    "stop_reason": Value::Null,
+                    "stop_sequence": Value::Null,
+                    "usage": {"input_tokens": 0, "output_tokens": 0},
+                }
+            }),
+        ));
+    }
+
+    fn close_block(&mut self, out: &mut Vec<u8>) {
+        if let Some((_, _, index)) = self.open_block.take() {
+            out.extend(sse_event(
+                "content_block_stop",
+                &json!({"type": "content_block_stop", "index": index}),
+            ));
+        }
+    }
+
+    /// 确保 output_index 对应类型的块已打开，返回其 Anthropic index
+    fn ensure_block(
+        &mut self,
+        output_index: u64,
+        kind: BlockKind,
+        content_block: Value,
+        out: &mut Vec<u8>,
+    ) -> usize {
+        if let Some((open_output, open_kind, index)) = self.open_block {
+            if open_output == output_index && open_kind == kind {
+                return index;
+            }
+        }
+        self.close_block(out);
+        let index = self.next_index;
+        self.next_index += 1;
+        self.open_block = Some((output_index, kind, index));
+        out.extend(sse_event(
+            "content_block_start",
+            &json!({"type": "content_block_start", "index": index, "content_block": content_block}),
+        ));
+        index
+    }
+
+    fn delta(&self, index: usize, delta: Value) -> Vec<u8> {
+        sse_event(
+            "content_block_delta",
+            &json!({"type": "content_block_delta", "index": index, "delta": delta}),
+        )
+    }
+
+    fn finish(&mut self, response: Option<&Value>, out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.start_message(response, out);
+        self.close_block(out);
+
+        let reason = response
+            .map(|r| stop_reason(r, self.has_tool_use))
+            .unwrap_or(if self.has_tool_use { "tool_use" } else { "end_turn" });
+        out.extend(sse_event(
+            "message_delta",
+            &json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": reason, "stop_sequence": Value::Null},
+                "usage": convert_usage(response.and_then(|r| r.get("usage"))),
+            }),
+        ));
+        out.extend(sse_event("message_stop", &json!({"type": "message_stop"})));
+    }
+
+    fn error(&mut self, message: &str, out: &mut Vec<u8>) {
+        self.finished = true;
+        self.close_block(out);
+        out.extend(sse_event(
+            "error",
+            &json!({"type": "error", "error": {"type": "api_error", "message": message}}),
+        ));
+    }
+}
+
+impl SseTranslator for ResponsesToAnthropicStream {
+    fn on_event(&mut self, _event: Option<&str>, data: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            return out;
+        };
+        let event_type = event.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let output_index = event
+            .get("output_index")
+            .and_then(|i| i.as_u64())
+            .unwrap_or(0);
+
+        match event_type {
+            "response.created" | "response.in_progress" => {
+                self.start_message(event.get("response"), &mut out);
+            }
+            "response.output_item.added" => {
+                self.start_message(None, &mut out);
+                let item = event.get("item");
+                if item.and_then(|i| i.get("type")).and_then(|t| t.as_str()) == Some("function_call") {
+                    self.has_tool_use = true;
+                    let field = |key: &str| {
+                        item.and_then(|i| i.get(key)).cloned().unwrap_or(Value::Null)
+                    };
+                    self.ensure_block(
+                        output_index,
+                        BlockKind::ToolUse,
+                        json!({"type": "tool_use", "id": field("call_id"), "name": field("name"), "input": {}}),
+                        &mut out,
+                    );
+                }
+            }
+            "response.output_text.delta" | "response.refusal.delta" => {
+                self.start_message(None, &mut out);
+                let index = self.ensure_block(
+                    output_index,
+                    BlockKind::Text,
+                    json!({"type": "text", "text": ""}),
+                    &mut out,
+                );
+                let text = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                out.extend(self.delta(index, json!({"type": "text_delta", "text": text})));
+            }
+            "response.reasoning_summary_text.delta" => {
+                self.start_message(None, &mut out);
+                let index = self.ensure_block(
+                    output_index,
+                    BlockKind::Thinking,
+                    json!({"type": "thinking", "thinking": "", "signature": ""}),
+                    &mut out,
+                );
+                let text = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                out.extend(self.delta(index, json!({"type": "thinking_delta", "thinking": text})));
+            }
+            "response.function_call_arguments.delta" => {
+                if let Some((open_output, BlockKind::ToolUse, index)) = self.open_block {
+                    if open_output == output_index {
+                        let partial = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                        out.extend(self.delta(
+                            index,
+                            json!({"type": "input_json_delta", "partial_json": partial}),
+                        ));
+                    }
+                }
+            }
+            "response.output_item.done" => {
+                if matches!(self.open_block, Some((open_output, _, _)) if open_output == output_index) {
+                    self.close_block(&mut out);
+                }
+            }
+            "response.completed" | "response.incomplete" => {
+                self.finish(event.get("response"), &mut out);
+            }
+            "response.failed" => {
+                let message = event
+                    .get("response")
+                    .and_then(|r| r.get("error"))
+                    .and_then(|e| e.get("message"))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("上游响应失败");
+                self.error(message, &mut out);
+            }
+            "error" => {
+                let message = event
+                    .get("message")
+                    .or_else(|| event.get("error").and_then(|e| e.get("message")))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("上游流返回错误");
+                self.error(message, &mut out);
+            }
+            _ => {}
+        }
+
+        out
+    }
+
+    fn on_end(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.finished {
+            self.error("上游流在响应完成前结束", &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_request_maps_system_tools_and_blocks() {
+        let request = json!({
+            "model": "claude-sonnet-4-20250514",
+            "system": [{"type": "text", "text": "be brief"}],
+            "max_tokens": 1024,
+            "thinking": {"type": "enabled", "budget_tokens": 8000},
+            "tools": [{"name": "read", "description": "read a file", "input_schema": {"type": "object"}}],
+            "tool_choice": {"type": "any"},
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "reading"},
+                    {"type": "tool_use", "id": "call_1", "name": "read", "input": {"path": "a"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "call_1", "content": "data"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAA"}}
+                ]}
+            ]
+        });
+
+        let converted = convert_request_value(&request);
+        assert_eq!(converted["instructions"], "be brief");
+        assert_eq!(converted["max_output_tokens"], 1024);
+        assert_eq!(converted["reasoning"]["effort"], "medium");
+        assert_eq!(converted["tool_choice"], "required");
+        assert_eq!(converted["tools"][0]["parameters"]["type"], "object");
+
+        let input = converted["input"].as_array().unwrap();
+        assert_eq!(input.len(), 5);
+        assert_eq!(input[0]["content"][0]["type"], "input_text");
+        assert_eq!(input[1]["content"][0]["type"], "output_text");
+        assert_eq!(input[2]["type"], "function_call");
+        assert_eq!(input[2]["arguments"], "{\"path\":\"a\"}");
+        assert_eq!(input[3]["type"], "function_call_output");
+        assert_eq!(input[3]["output"], "data");
+        assert_eq!(input[4]["content"][0]["image_url"], "data:image/png;base64,AAA");
+    }
+
+    #[test]
+    fn test_convert_response_with_tool_call() {
+        let response = json!({
+            "id": "resp_1",
+            "model": "gpt-5",
+            "output": [
+                {"type": "message", "content": [{"type": "output_text", "text": "ok"}]},
+                {"type": "function_call", "call_id": "call_1", "name": "read", "arguments": "{\"path\":\"a\"}"}
+            ],
+            "usage": {"input_tokens": 100, "output_tokens": 20, "input_tokens_details": {"cached_tokens": 40}}
+        });
+
+        let converted = convert_response_value(&response);
+        assert_eq!(converted["stop_reason"], "tool_use");
+        assert_eq!(converted["content"][0]["text"], "ok");
+        assert_eq!(converted["content"][1]["input"]["path"], "a");
+        assert_eq!(converted["usage"]["input_tokens"], 60);
+        assert_eq!(converted["usage"]["cache_read_input_tokens"], 40);
+    }
+
+    #[test]
+    fn test_stream_translation_emits_anthropic_events() {
+        let mut translator = ResponsesToAnthropicStream::new();
+        let mut out = Vec::new();
+        for event in [
+            json!({"type": "response.created", "response": {"id": "resp_1", "model": "gpt-5"}}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "Hel"}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "lo"}),
+            json!({"type": "response.output_item.done", "output_index": 0}),
+            json!({"type": "response.completed", "response": {"usage": {"input_tokens": 5, "output_tokens": 2}}}),
+        ] {
+            out.extend(translator.on_event(None, &event.to_string()));
+        }
+        out.extend(translator.on_end());
+
+        let text = String::from_utf8(out).unwrap();
+        let events: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert!(text.contains("\"stop_reason\":\"end_turn\""));
+    }
+}
@@ -1,27 +1,46 @@
 use super::db;
 use super::types::{ApiKeyCreatePayload, GatewayApiKey};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
 
 const KEY_PREFIX: &str = "sk-gw-";
 
-pub fn generate_api_key(payload: &ApiKeyCreatePayload) -> Result<(String, GatewayApiKey), String> {
-    let raw_key = format!(
+const RATE_WINDOW_MS: i64 = 60_000;
+
+/// 各 Key 最近 60 秒内放行请求的时间（毫秒），用于每分钟请求数的滑动窗口限流
+static REQUEST_WINDOWS: Mutex<Option<HashMap<String, VecDeque<i64>>>> = Mutex::new(None);
+
+fn new_raw_key() -> String {
+    format!(
         "{}{}",
         KEY_PREFIX,
         uuid::Uuid::new_v4().to_string().replace("-", "")
-    );
+    )
+}
 
+fn hash_key(raw_key: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(raw_key.as_bytes());
-    let key_hash = format!("{:x}", hasher.finalize());
+    format!("{:x}", hasher.finalize())
+}
+
+/// 空列表视为不限制，保存为 NULL
+fn scope_to_json(scope: Option<&Vec<String>>) -> Option<String> {
+    scope
+        .filter(|items| !items.is_empty())
+        .map(|items| serde_json::to_string(items).unwrap_or_default())
+}
+
+pub fn generate_api_key(payload: &ApiKeyCreatePayload) -> Result<(String, GatewayApiKey), String> {
+    let raw_key = new_raw_key();
+    let key_hash = hash_key(&raw_key);
 
     let key_prefix = &raw_key[..12];
     let id = uuid::Uuid::new_v4().to_string();
 
-    let allowed_models = payload
-        .allowed_models
-        .as_ref()
-        .map(|models| serde_json::to_string(models).unwrap_or_default());
+    let allowed_models = scope_to_json(payload.allowed_models.as_ref());
+    let allowed_endpoints = scope_to_json(payload.allowed_endpoints.as_ref());
 
     db::insert_api_key(
         &id,
@@ -29,6 +48,9 @@ pub fn generate_api_key(payload: &ApiKeyCreatePayload) -> Result<(String, Gatewa
         &key_hash,
         key_prefix,
         allowed_models.as_deref(),
+        allowed_endpoints.as_deref(),
+        &payload.limits,
+        payload.group_name.as_deref(),
     )?;
 
     let api_key = GatewayApiKey {
@@ -41,17 +63,51 @@ pub fn generate_api_key(payload: &ApiKeyCreatePayload) -> Result<(String, Gatewa
         created_at: chrono::Utc::now().timestamp(),
         last_used_at: None,
         usage_count: 0,
+        rpm_limit: payload.limits.rpm_limit,
+        daily_token_limit: payload.limits.daily_token_limit,
+        monthly_budget_usd: payload.limits.monthly_budget_usd,
+        expires_at: payload.limits.expires_at,
+        tokens_last_24h: 0,
+        cost_this_month_usd: 0.0,
+        group_name: payload.group_name.clone(),
+        allowed_endpoints,
+        last_used_ip: None,
+        previous_key_expires_at: None,
     };
 
     Ok((raw_key, api_key))
 }
 
-pub fn verify_api_key(raw_key: &str) -> Result<Option<GatewayApiKey>, String> {
-    let mut hasher = Sha256::new();
-    hasher.update(raw_key.as_bytes());
-    let key_hash = format!("{:x}", hasher.finalize());
+/// 为已有 Key 生成新的密钥，用量与限额保持不变。
+/// overlap_seconds 内旧 Key 仍可使用，为 0 时旧 Key 立即失效
+pub fn rotate_api_key(id: &str, overlap_seconds: i64) -> Result<(String, GatewayApiKey), String> {
+    let raw_key = new_raw_key();
+    let previous_expires_at =
+        (overlap_seconds > 0).then(|| chrono::Utc::now().timestamp() + overlap_seconds);
+
+    db::rotate_api_key(id, &hash_key(&raw_key), &raw_key[..12], previous_expires_at)?;
+
+    let api_key = db::list_api_keys()?
+        .into_iter()
+        .find(|k| k.id == id)
+        .ok_or("API Key 不存在")?;
+    Ok((raw_key, api_key))
+}
+
+pub fn update_scopes(
+    id: &str,
+    allowed_models: Option<&Vec<String>>,
+    allowed_endpoints: Option<&Vec<String>>,
+) -> Result<(), String> {
+    db::update_api_key_scopes(
+        id,
+        scope_to_json(allowed_models).as_deref(),
+        scope_to_json(allowed_endpoints).as_deref(),
+    )
+}
 
-    db::validate_api_key(&key_hash)
+pub fn verify_api_key(raw_key: &str) -> Result<Option<GatewayApiKey>, String> {
+    db::validate_api_key(&hash_key(raw_key))
 }
 
 pub fn is_model_allowed(api_key: &GatewayApiKey, model: &str) -> bool {
@@ -74,13 +130,210 @@ pub fn is_model_allowed(api_key: &GatewayApiKey, model: &str) -> bool {
     }
 }
 
-pub fn increment_usage(key_hash: &str) -> Result<(), String> {
+/// 端点按路径前缀匹配，例如 /v1/responses 同时允许 /v1/responses/{id}
+pub fn is_endpoint_allowed(api_key: &GatewayApiKey, path: &str) -> bool {
+    let Some(endpoints_json) = &api_key.allowed_endpoints else {
+        return true;
+    };
+    match serde_json::from_str::<Vec<String>>(endpoints_json) {
+        Ok(endpoints) => {
+            endpoints.is_empty()
+                || endpoints.iter().any(|e| {
+                    let e = e.trim_end_matches('/');
+                    e == "*"
+                        || path == e
+                        || path.strip_prefix(e).is_some_and(|rest| rest.starts_with('/'))
+                })
+        }
+        Err(_) => true,
+    }
+}
+
+/// API Key 超出限额或已过期
+pub enum KeyLimitError {
+    Expired,
+    RateLimited { limit: u32, retry_after: i64 },
+    DailyTokensExceeded { limit: i64 },
+    MonthlyBudgetExceeded { limit: f64 },
+}
+
+impl KeyLimitError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            KeyLimitError::Expired => 401,
+            _ => 429,
+        }
+    }
+
+    /// OpenAI 风格错误中的 (type, code)
+    pub fn error_kind(&self) -> (&'static str, &'static str) {
+        match self {
+            KeyLimitError::Expired => ("invalid_request_error", "api_key_expired"),
+            KeyLimitError::RateLimited { .. } => ("requests", "rate_limit_exceeded"),
+            KeyLimitError::DailyTokensExceeded { .. } => ("tokens", "rate_limit_exceeded"),
+            KeyLimitError::MonthlyBudgetExceeded { .. } => ("insufficient_quota", "insufficient_quota"),
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<i64> {
+        match self {
+            KeyLimitError::RateLimited { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            KeyLimitError::Expired => "API Key 已过期".to_string(),
+            KeyLimitError::RateLimited { limit, retry_after } => format!(
+                "API Key 超出每分钟请求数限制 ({} RPM)，请在 {} 秒后重试",
+                limit, retry_after
+            ),
+            KeyLimitError::DailyTokensExceeded { limit } => {
+                format!("API Key 最近 24 小时 token 用量已达上限 ({})", limit)
+            }
+            KeyLimitError::MonthlyBudgetExceeded { limit } => {
+                format!("API Key 本月费用已达预算上限 (${:.2})", limit)
+            }
+        }
+    }
+}
+
+/// 检查有效期、每日 token 与每月预算，通过后按每分钟请求数限制计入一次请求
+pub fn acquire_request(
+    api_key: &GatewayApiKey,
+    client_ip: Option<&str>,
+) -> Result<(), KeyLimitError> {
     let now = chrono::Utc::now().timestamp();
-    db::with_db(|conn| {
-        conn.execute(
-            "UPDATE gateway_api_keys SET usage_count = usage_count + 1, last_used_at = ?1 WHERE key_hash = ?2",
-            rusqlite::params![now, key_hash],
-        )?;
-        Ok(())
-    })
+    if api_key.expires_at.map(|t| t <= now).unwrap_or(false) {
+        return Err(KeyLimitError::Expired);
+    }
+    if let Some(limit) = api_key.daily_token_limit.filter(|l| *l > 0) {
+        if api_key.tokens_last_24h >= limit {
+            return Err(KeyLimitError::DailyTokensExceeded { limit });
+        }
+    }
+    if let Some(limit) = api_key.monthly_budget_usd.filter(|l| *l > 0.0) {
+        if api_key.cost_this_month_usd >= limit {
+            return Err(KeyLimitError::MonthlyBudgetExceeded { limit });
+        }
+    }
+
+    if let Some(limit) = api_key.rpm_limit.filter(|l| *l > 0) {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut guard = REQUEST_WINDOWS.lock().unwrap_or_else(|e| e.into_inner());
+        let windows = guard.get_or_insert_with(HashMap::new);
+        // 顺带清掉已滑出窗口的 Key，已删除或不再使用的 Key 不会一直占用内存
+        windows.retain(|_, w| w.back().is_some_and(|t| *t > now_ms - RATE_WINDOW_MS));
+        let window = windows.entry(api_key.id.clone()).or_default();
+        if let Err(retry_after) = admit_request(window, limit, now_ms) {
+            return Err(KeyLimitError::RateLimited { limit, retry_after });
+        }
+    }
+
+    if let Err(e) = db::register_api_key_request(&api_key.id, client_ip) {
+        tracing::warn!("[Gateway] 记录 API Key 请求失败: {}", e);
+    }
+    Ok(())
+}
+
+/// 滑动窗口：最近 60 秒内已放行 limit 个请求时拒绝，返回最早的请求滑出窗口还需等待的秒数
+fn admit_request(window: &mut VecDeque<i64>, limit: u32, now_ms: i64) -> Result<(), i64> {
+    while window
+        .front()
+        .is_some_and(|t| *t <= now_ms - RATE_WINDOW_MS)
+    {
+        window.pop_front();
+    }
+    if window.len() >= limit as usize {
+        let oldest = window.front().copied().unwrap_or(now_ms);
+        let wait_ms = oldest + RATE_WINDOW_MS - now_ms;
+        return Err(((wait_ms + 999) / 1000).max(1));
+    }
+    window.push_back(now_ms);
+    Ok(())
+}
+
+/// 请求完成后累加该 Key 的 token 用量与费用
+pub fn record_consumption(api_key_id: &str, tokens: i64, cost_usd: f64) -> Result<(), String> {
+    db::add_api_key_consumption(api_key_id, tokens, cost_usd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit_request_uses_sliding_window() {
+        let mut window = VecDeque::new();
+        assert!(admit_request(&mut window, 2, 59_000).is_ok());
+        assert!(admit_request(&mut window, 2, 59_500).is_ok());
+        // 跨过整分钟边界时仍计入前一分钟末尾的请求
+        assert_eq!(admit_request(&mut window, 2, 61_000), Err(58));
+        assert!(admit_request(&mut window, 2, 119_000).is_ok());
+        assert_eq!(admit_request(&mut window, 2, 119_200), Err(1));
+        assert!(admit_request(&mut window, 2, 119_500).is_ok());
+    }
+
+    fn key_with_endpoints(endpoints: Option<&[&str]>) -> GatewayApiKey {
+        GatewayApiKey {
+            id: "key".to_string(),
+            name: "test".to_string(),
+            key_hash: String::new(),
+            key_prefix: "sk-gw-test".to_string(),
+            allowed_models: None,
+            enabled: true,
+            created_at: 0,
+            last_used_at: None,
+            usage_count: 0,
+            rpm_limit: None,
+            daily_token_limit: None,
+            monthly_budget_usd: None,
+            expires_at: None,
+            tokens_last_24h: 0,
+            cost_this_month_usd: 0.0,
+            group_name: None,
+            allowed_endpoints: endpoints.map(|e| serde_json::to_string(e).unwrap()),
+            last_used_ip: None,
+            previous_key_expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_endpoint_scope_matches_path_segments() {
+        let key = key_with_endpoints(Some(&["/v1/responses", "/v1/chat/completions/"]));
+        assert!(is_endpoint_allowed(&key, "/v1/responses"));
+        assert!(is_endpoint_allowed(&key, "/v1/responses/resp_123"));
+        assert!(is_endpoint_allowed(&key, "/v1/chat/completions"));
+        // 只按完整路径段匹配，不能借前缀访问其他端点
+        assert!(!is_endpoint_allowed(&key, "/v1/responses_admin"));
+        assert!(!is_endpoint_allowed(&key, "/v1/messages"));
+        assert!(!is_endpoint_allowed(&key, "/v1"));
+    }
+
+    #[test]
+    fn test_endpoint_scope_unrestricted() {
+        assert!(is_endpoint_allowed(
+            &key_with_endpoints(None),
+            "/v1/messages"
+        ));
+        assert!(is_endpoint_allowed(
+            &key_with_endpoints(Some(&[])),
+            "/v1/messages"
+        ));
+        assert!(is_endpoint_allowed(
+            &key_with_endpoints(Some(&["*"])),
+            "/v1/messages"
+        ));
+    }
+
+    #[test]
+    fn test_empty_scope_is_stored_as_null() {
+        assert_eq!(scope_to_json(None), None);
+        assert_eq!(scope_to_json(Some(&Vec::new())), None);
+        assert_eq!(
+            scope_to_json(Some(&vec!["gpt-4.1".to_string()])).as_deref(),
+            Some(r#"["gpt-4.1"]"#)
+        );
+    }
 }
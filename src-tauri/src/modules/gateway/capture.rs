@@ -0,0 +1,247 @@
+use super::stream::ByteStream;
+use super::types::{GatewayConfig, ReplayResult, RequestCapture};
+use super::{config, db, protocol_adapter, proxy, token_refresh};
+use bytes::Bytes;
+use futures::StreamExt;
+use std::time::Instant;
+
+const REDACTED: &str = "[REDACTED]";
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+    "x-auth-token",
+    "cookie",
+    "set-cookie",
+];
+
+/// 单个请求的捕获上下文，capture_enabled 关闭时不创建
+pub struct CaptureContext {
+    capture: RequestCapture,
+    max_body_bytes: usize,
+    max_entries: u32,
+    retention_hours: u32,
+}
+
+impl CaptureContext {
+    pub fn new(
+        gw_config: &GatewayConfig,
+        trace_id: &str,
+        method: &str,
+        path: &str,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> Option<Self> {
+        if !gw_config.capture_enabled {
+            return None;
+        }
+        let max_body_bytes = gw_config.capture_max_body_kb as usize * 1024;
+        let (request_body, truncated) = truncate_body(body, max_body_bytes);
+        Some(Self {
+            capture: RequestCapture {
+                trace_id: trace_id.to_string(),
+                timestamp: chrono::Utc::now().timestamp(),
+                method: method.to_string(),
+                path: path.to_string(),
+                account_id: None,
+                upstream_url: None,
+                request_headers: redact_headers(headers),
+                request_body,
+                upstream_request_body: None,
+                status_code: 0,
+                response_headers: Vec::new(),
+                response_body: String::new(),
+                truncated,
+            },
+            max_body_bytes,
+            max_entries: gw_config.capture_max_entries,
+            retention_hours: gw_config.capture_retention_hours,
+        })
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// 记录本次尝试使用的账号与转换后的上游请求
+    pub fn set_attempt(&mut self, account_id: &str, upstream_url: &str, upstream_body: &[u8]) {
+        let (body, truncated) = truncate_body(upstream_body, self.max_body_bytes);
+        self.capture.account_id = Some(account_id.to_string());
+        self.capture.upstream_url = Some(upstream_url.to_string());
+        self.capture.upstream_request_body = Some(body);
+        self.capture.truncated |= truncated;
+    }
+
+    pub fn save(
+        &self,
+        status_code: u16,
+        response_headers: &[(String, String)],
+        response_body: &[u8],
+    ) {
+        let mut capture = self.capture.clone();
+        let (body, truncated) = truncate_body(response_body, self.max_body_bytes);
+        capture.status_code = status_code;
+        capture.response_headers = redact_headers(response_headers);
+        capture.response_body = body;
+        capture.truncated |= truncated;
+        if let Err(e) = db::save_request_capture(&capture, self.max_entries, self.retention_hours) {
+            tracing::warn!("[Gateway] 保存请求捕获失败: {}", e);
+        }
+    }
+}
+
+fn redact_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(key, value)| {
+            if SENSITIVE_HEADERS.contains(&key.to_lowercase().as_str()) {
+                (key.clone(), REDACTED.to_string())
+            } else {
+                (key.clone(), value.clone())
+            }
+        })
+        .collect()
+}
+
+fn truncate_body(body: &[u8], max_bytes: usize) -> (String, bool) {
+    let truncated = body.len() > max_bytes;
+    let body = &body[..body.len().min(max_bytes)];
+    (String::from_utf8_lossy(body).to_string(), truncated)
+}
+
+/// 用指定账号（默认为原账号）和上游（默认按账号平台解析）重新发送捕获的请求，
+/// 重新执行协议转换，返回上游原始响应与转换后的客户端响应
+pub async fn replay(
+    trace_id: &str,
+    account_id: Option<&str>,
+    upstream_base_url: Option<&str>,
+) -> Result<ReplayResult, String> {
+    let captured = db::get_request_capture(trace_id)?.ok_or("未找到该请求的捕获记录")?;
+    if captured.truncated {
+        return Err("捕获内容已被截断，无法重放".to_string());
+    }
+    let gw_config = config::get_gateway_config();
+
+    let account_id = account_id
+        .filter(|id| !id.is_empty())
+        .or(captured.account_id.as_deref())
+        .ok_or("请选择用于重放的账号")?;
+    let account = db::list_accounts()?
+        .into_iter()
+        .find(|a| a.id == account_id)
+        .ok_or("账号不存在")?;
+    let account = token_refresh::ensure_fresh_token(account).await?;
+
+    let base_url = match upstream_base_url.filter(|url| !url.is_empty()) {
+        Some(url) => url.to_string(),
+        None => protocol_adapter::resolve_upstream_for_platform(
+            account.platform.as_deref(),
+            &gw_config.platform_upstreams,
+            &gw_config.upstream_base_url,
+        ),
+    };
+    let request_body = captured.request_body.as_bytes();
+    let (upstream_url, upstream_body, adapter) = protocol_adapter::rewrite_request_for_upstream(
+        &captured.path,
+        request_body,
+        &base_url,
+        gw_config.is_responses_only(&base_url),
+    )?;
+    let is_stream = protocol_adapter::is_streaming_request(request_body);
+    // 脱敏过的头无法还原，重放时不再发送
+    let headers: Vec<(String, String)> = captured
+        .request_headers
+        .into_iter()
+        .filter(|(_, value)| value != REDACTED)
+        .collect();
+    let proxy_url = account
+        .proxy_url
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .or(gw_config.upstream_proxy_url.as_deref());
+
+    let start = Instant::now();
+    let response = proxy::send_upstream(
+        &captured.method,
+        &upstream_url,
+        &headers,
+        &account,
+        Bytes::from(upstream_body),
+        is_stream,
+        proxy_url,
+    )
+    .await?;
+    let status = response.status().as_u16();
+    let raw = response
+        .bytes()
+        .await
+        .map_err(|e| format!("读取响应失败: {}", e))?;
+    let duration_ms = start.elapsed().as_millis() as i64;
+
+    let client_body = if is_stream && status < 400 {
+        let upstream: ByteStream = Box::pin(futures::stream::iter([Ok(raw.clone())]));
+        let chunks: Vec<_> = protocol_adapter::adapt_response_stream(&adapter, upstream)
+            .collect()
+            .await;
+        chunks
+            .into_iter()
+            .filter_map(Result::ok)
+            .flat_map(|chunk| chunk.to_vec())
+            .collect()
+    } else {
+        protocol_adapter::adapt_response_body(&adapter, status, raw.clone()).to_vec()
+    };
+
+    Ok(ReplayResult {
+        status_code: status,
+        upstream_url,
+        duration_ms,
+        response_body: String::from_utf8_lossy(&raw).to_string(),
+        client_body: String::from_utf8_lossy(&client_body).to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_redact_headers_is_case_insensitive() {
+        let headers = vec![
+            header("Authorization", "Bearer sk-secret"),
+            header("X-Goog-Api-Key", "AIza-secret"),
+            header("Proxy-Authorization", "Basic secret"),
+            header("Cookie", "session=secret"),
+            header("Set-Cookie", "session=secret"),
+            header("Content-Type", "application/json"),
+        ];
+        let redacted = redact_headers(&headers);
+        for (key, value) in &redacted[..5] {
+            assert_eq!(value, REDACTED, "{} 未脱敏", key);
+        }
+        assert_eq!(redacted[5], header("Content-Type", "application/json"));
+        // 保留原始的头名称，便于排查
+        assert_eq!(redacted[1].0, "X-Goog-Api-Key");
+    }
+
+    #[test]
+    fn test_truncate_body_marks_truncation() {
+        assert_eq!(truncate_body(b"hello", 5), ("hello".to_string(), false));
+        assert_eq!(truncate_body(b"hello", 3), ("hel".to_string(), true));
+        assert_eq!(truncate_body(b"", 0), (String::new(), false));
+    }
+
+    #[test]
+    fn test_truncate_body_inside_multibyte_char() {
+        // "你" 占 3 个字节，截断在字符中间时不能 panic
+        let (body, truncated) = truncate_body("你好".as_bytes(), 4);
+        assert!(truncated);
+        assert!(body.starts_with('你'));
+    }
+}
@@ -0,0 +1,617 @@
+use super::stream::{sse_data, SseTranslator};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Chat Completions 请求 → Responses 请求
+pub fn convert_request(body: &[u8]) -> Result<Vec<u8>, String> {
+    let json: Value = serde_json::from_slice(body).map_err(|e| format!("解析请求体失败: {}", e))?;
+    let converted = convert_request_value(&json);
+    serde_json::to_vec(&converted).map_err(|e| format!("序列化请求体失败: {}", e))
+}
+
+/// 流式请求是否要求在末尾附带 usage 块
+pub fn wants_stream_usage(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|json| {
+            json.get("stream_options")?
+                .get("include_usage")?
+                .as_bool()
+        })
+        .unwrap_or(false)
+}
+
+fn convert_request_value(json: &Value) -> Value {
+    let mut out = Map::new();
+
+    if let Some(model) = json.get("model") {
+        out.insert("model".to_string(), model.clone());
+    }
+
+    let messages = json
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.as_slice())
+        .unwrap_or(&[]);
+    let (instructions, input) = convert_messages(messages);
+    if let Some(instructions) = instructions {
+        out.insert("instructions".to_string(), Value::String(instructions));
+    }
+    out.insert("input".to_string(), Value::Array(input));
+
+    if let Some(tools) = json.get("tools").and_then(|t| t.as_array()) {
+        let converted: Vec<Value> = tools.iter().filter_map(convert_tool).collect();
+        if !converted.is_empty() {
+            out.insert("tools".to_string(), Value::Array(converted));
+        }
+    }
+    if let Some(choice) = json.get("tool_choice").and_then(convert_tool_choice) {
+        out.insert("tool_choice".to_string(), choice);
+    }
+
+    if let Some(format) = json.get("response_format").and_then(convert_response_format) {
+        out.insert("text".to_string(), json!({"format": format}));
+    }
+
+    if let Some(max_tokens) = json
+        .get("max_completion_tokens")
+        .or_else(|| json.get("max_tokens"))
+    {
+        out.insert("max_output_tokens".to_string(), max_tokens.clone());
+    }
+    if let Some(effort) = json.get("reasoning_effort") {
+        out.insert("reasoning".to_string(), json!({"effort": effort}));
+    }
+    for key in [
+        "temperature",
+        "top_p",
+        "stream",
+        "parallel_tool_calls",
+        "user",
+        "metadata",
+        "store",
+    ] {
+        if let Some(value) = json.get(key) {
+            out.insert(key.to_string(), value.clone());
+        }
+    }
+
+    Value::Object(out)
+}
+
+/// system/developer 消息合并为 instructions，其余消息按顺序展开为 Responses 输入条目
+fn convert_messages(messages: &[Value]) -> (Option<String>, Vec<Value>) {
+    let mut instructions: Vec<String> = Vec::new();
+    let mut input = Vec::new();
+
+    for msg in messages {
+        let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+        match role {
+            "system" | "developer" => {
+                let text = content_text(msg.get("content"));
+                if !text.is_empty() {
+                    instructions.push(text);
+                }
+            }
+            "tool" => {
+                input.push(json!({
+                    "type": "function_call_output",
+                    "call_id": msg.get("tool_call_id").cloned().unwrap_or(Value::Null),
+                    "output": content_text(msg.get("content")),
+                }));
+            }
+            "assistant" => {
+                let text = content_text(msg.get("content"));
+                if !text.is_empty() {
+                    input.push(json!({
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [{"type": "output_text", "text": text}],
+                    }));
+                }
+                for call in msg
+                    .get("tool_calls")
+                    .and_then(|c| c.as_array())
+                    .map(|c| c.as_slice())
+                    .unwrap_or(&[])
+                {
+                    let function = call.get("function");
+                    input.push(json!({
+                        "type": "function_call",
+                        "call_id": call.get("id").cloned().unwrap_or(Value::Null),
+                        "name": function.and_then(|f| f.get("name")).cloned().unwrap_or(Value::Null),
+                        "arguments": function
+                            .and_then(|f| f.get("arguments"))
+                            .cloned()
+                            .unwrap_or_else(|| json!("{}")),
+                    }));
+                }
+            }
+            _ => {
+                let content = convert_user_content(msg.get("content"));
+                if !content.is_empty() {
+                    input.push(json!({"type": "message", "role": "user", "content": content}));
+                }
+            }
+        }
+    }
+
+    let instructions = if instructions.is_empty() {
+        None
+    } else {
+        Some(instructions.join("\n\n"))
+    };
+    (instructions, input)
+}
+
+fn content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn convert_user_content(content: Option<&Value>) -> Vec<Value> {
+    match content {
+        Some(Value::String(s)) => vec![json!({"type": "input_text", "text": s})],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(|t| t.as_str()) {
+                Some("text") => Some(json!({
+                    "type": "input_text",
+                    "text": part.get("text").cloned().unwrap_or_else(|| json!("")),
+                })),
+                Some("image_url") => {
+                    let image = part.get("image_url")?;
+                    let url = image.as_str().or_else(|| image.get("url")?.as_str())?;
+                    let mut converted = json!({"type": "input_image", "image_url": url});
+                    if let Some(detail) = image.get("detail") {
+                        converted["detail"] = detail.clone();
+                    }
+                    Some(converted)
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn convert_tool(tool: &Value) -> Option<Value> {
+    if tool.get("type").and_then(|t| t.as_str()) != Some("function") {
+        return None;
+    }
+    let function = tool.get("function")?;
+    let mut converted = json!({
+        "type": "function",
+        "name": function.get("name")?,
+        "parameters": function
+            .get("parameters")
+            .cloned()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    });
+    for key in ["description", "strict"] {
+        if let Some(value) = function.get(key) {
+            converted[key] = value.clone();
+        }
+    }
+    Some(converted)
+}
+
+fn convert_tool_choice(choice: &Value) -> Option<Value> {
+    match choice {
+        Value::String(_) => Some(choice.clone()),
+        Value::Object(_) => {
+            let name = choice.get("function")?.get("name")?;
+            Some(json!({"type": "function", "name": name}))
+        }
+        _ => None,
+    }
+}
+
+fn convert_response_format(format: &Value) -> Option<Value> {
+    match format.get("type").and_then(|t| t.as_str())? {
+        "json_schema" => {
+            let schema = format.get("json_schema")?;
+            let mut converted = json!({
+                "type": "json_schema",
+                "name": schema.get("name").cloned().unwrap_or_else(|| json!("response")),
+                "schema": schema.get("schema").cloned().unwrap_or_else(|| json!({})),
+            });
+            for key in ["description", "strict"] {
+                if let Some(value) = schema.get(key) {
+                    converted[key] = value.clone();
+                }
+            }
+            Some(converted)
+        }
+        "json_object" => Some(json!({"type": "json_object"})),
+        "text" => Some(json!({"type": "text"})),
+        _ => None,
+    }
+}
+
+/// Responses 非流式响应 → Chat Completions 响应，无法解析时返回 None
+pub fn convert_response(body: &[u8]) -> Option<Vec<u8>> {
+    let json: Value = serde_json::from_slice(body).ok()?;
+    json.get("output")?;
+    serde_json::to_vec(&convert_response_value(&json)).ok()
+}
+
+fn convert_response_value(resp: &Value) -> Value {
+    let mut text = String::new();
+    let mut refusal: Option<String> = None;
+    let mut tool_calls = Vec::new();
+
+    for item in resp
+        .get("output")
+        .and_then(|o| o.as_array())
+        .map(|o| o.as_slice())
+        .unwrap_or(&[])
+    {
+        match item.get("type").and_then(|t| t.as_str()) {
+            Some("message") => {
+                for part in item
+                    .get("content")
+                    .and_then(|c| c.as_array())
+                    .map(|c| c.as_slice())
+                    .unwrap_or(&[])
+                {
+                    match part.get("type").and_then(|t| t.as_str()) {
+                        Some("output_text") => {
+                            text.push_str(part.get("text").and_then(|t| t.as_str()).unwrap_or(""))
+                        }
+                        Some("refusal") => {
+                            refusal = part
+                                .get("refusal")
+                                .and_then(|r| r.as_str())
+                                .map(|r| r.to_string())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Some("function_call") => tool_calls.push(json!({
+                "id": item.get("call_id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": item.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": item.get("arguments").cloned().unwrap_or_else(|| json!("{}")),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let has_tool_calls = !tool_calls.is_empty();
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && has_tool_calls { Value::Null } else { Value::String(text) },
+    });
+    if has_tool_calls {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    if let Some(refusal) = refusal {
+        message["refusal"] = Value::String(refusal);
+    }
+
+    json!({
+        "id": resp.get("id").cloned().unwrap_or(Value::Null),
+        "object": "chat.completion",
+        "created": resp.get("created_at").cloned().unwrap_or_else(|| json!(chrono::Utc::now().timestamp())),
+        "model": resp.get("model").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(resp, has_tool_calls),
+        }],
+        "usage": convert_usage(resp.get("usage")),
+    })
+}
+
+fn finish_reason(resp: &Value, has_tool_calls: bool) -> &'static str {
+    match resp
+        .get("incomplete_details")
+        .and_then(|d| d.get("reason"))
+        .and_then(|r| r.as_str())
+    {
+        Some("max_output_tokens") => "length",
+        Some("content_filter") => "content_filter",
+        _ if has_tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+fn convert_usage(usage: Option<&Value>) -> Value {
+    let get = |v: Option<&Value>| v.and_then(|v| v.as_u64()).unwrap_or(0);
+    let input = get(usage.and_then(|u| u.get("input_tokens")));
+    let output = get(usage.and_then(|u| u.get("output_tokens")));
+    json!({
+        "prompt_tokens": input,
+        "completion_tokens": output,
+        "total_tokens": input + output,
+        "prompt_tokens_details": {
+            "cached_tokens": get(usage
+                .and_then(|u| u.get("input_tokens_details"))
+                .and_then(|d| d.get("cached_tokens"))),
+        },
+        "completion_tokens_details": {
+            "reasoning_tokens": get(usage
+                .and_then(|u| u.get("output_tokens_details"))
+                .and_then(|d| d.get("reasoning_tokens"))),
+        },
+    })
+}
+
+/// Responses SSE 事件 → Chat Completions chunk
+pub struct ResponsesToChatStream {
+    include_usage: bool,
+    id: Value,
+    model: Value,
+    created: i64,
+    role_sent: bool,
+    finished: bool,
+    /// 上游 output_index → tool_calls 中的 index
+    tool_indices: HashMap<u64, usize>,
+}
+
+impl ResponsesToChatStream {
+    pub fn new(include_usage: bool) -> Self {
+        Self {
+            include_usage,
+            id: Value::Null,
+            model: Value::Null,
+            created: chrono::Utc::now().timestamp(),
+            role_sent: false,
+            finished: false,
+            tool_indices: HashMap::new(),
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Vec<u8> {
+        sse_data(&json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        }))
+    }
+
+    fn ensure_role(&mut self, out: &mut Vec<u8>) {
+        if !self.role_sent {
+            self.role_sent = true;
+            out.extend(self.chunk(json!({"role": "assistant", "content": ""}), None));
+        }
+    }
+
+    fn finish(&mut self, response: Option<&Value>, out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.ensure_role(out);
+
+        let has_tool_calls = !self.tool_indices.is_empty();
+        let reason = response
+            .map(|r| finish_reason(r, has_tool_calls))
+            .unwrap_or(if has_tool_calls { "tool_calls" } else { "stop" });
+        out.extend(self.chunk(json!({}), Some(reason)));
+
+        if self.include_usage {
+            out.extend(sse_data(&json!({
+                "id": self.id,
+                "object": "chat.completion.chunk",
+                "created": self.created,
+                "model": self.model,
+                "choices": [],
+                "usage": convert_usage(response.and_then(|r| r.get("usage"))),
+            })));
+        }
+        out.extend_from_slice(b"data: [DONE]\n\n");
+    }
+
+    fn error(&mut self, message: &str, out: &mut Vec<u8>) {
+        self.finished = true;
+        out.extend(sse_data(&json!({
+            "error": {"message": message, "type": "upstream_error", "code": Value::Null},
+        })));
+        out.extend_from_slice(b"data: [DONE]\n\n");
+    }
+}
+
+impl SseTranslator for ResponsesToChatStream {
+    fn on_event(&mut self, _event: Option<&str>, data: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            return out;
+        };
+        let event_type = event.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let output_index = event
+            .get("output_index")
+            .and_then(|i| i.as_u64())
+            .unwrap_or(0);
+
+        match event_type {
+            "response.created" => {
+                if let Some(response) = event.get("response") {
+                    self.id = response.get("id").cloned().unwrap_or(Value::Null);
+                    self.model = response.get("model").cloned().unwrap_or(Value::Null);
+                    if let Some(created) = response.get("created_at").and_then(|c| c.as_i64()) {
+                        self.created = created;
+                    }
+                }
+                self.ensure_role(&mut out);
+            }
+            "response.output_text.delta" => {
+                self.ensure_role(&mut out);
+                let text = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                out.extend(self.chunk(json!({"content": text}), None));
+            }
+            "response.refusal.delta" => {
+                self.ensure_role(&mut out);
+                let text = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                out.extend(self.chunk(json!({"refusal": text}), None));
+            }
+            "response.output_item.added" => {
+                let Some(item) = event.get("item") else {
+                    return out;
+                };
+                if item.get("type").and_then(|t| t.as_str()) != Some("function_call") {
+                    return out;
+                }
+                self.ensure_role(&mut out);
+                let index = self.tool_indices.len();
+                self.tool_indices.insert(output_index, index);
+                out.extend(self.chunk(
+                    json!({"tool_calls": [{
+                        "index": index,
+                        "id": item.get("call_id").cloned().unwrap_or(Value::Null),
+                        "type": "function",
+                        "function": {
+                            "name": item.get("name").cloned().unwrap_or(Value::Null),
+                            "arguments": "",
+                        },
+                    }]}),
+                    None,
+                ));
+            }
+            "response.function_call_arguments.delta" => {
+                if let Some(&index) = self.tool_indices.get(&output_index) {
+                    let partial = event.get("delta").and_then(|d| d.as_str()).unwrap_or("");
+                    out.extend(self.chunk(
+                        json!({"tool_calls": [{"index": index, "function": {"arguments": partial}}]}),
+                        None,
+                    ));
+                }
+            }
+            "response.completed" | "response.incomplete" => {
+                self.finish(event.get("response"), &mut out);
+            }
+            "response.failed" => {
+                let message = event
+                    .get("response")
+                    .and_then(|r| r.get("error"))
+                    .and_then(|e| e.get("message"))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("上游响应失败");
+                self.error(message, &mut out);
+            }
+            "error" => {
+                let message = event
+                    .get("message")
+                    .or_else(|| event.get("error").and_then(|e| e.get("message")))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("上游流返回错误");
+                self.error(message, &mut out);
+            }
+            _ => {}
+        }
+
+        out
+    }
+
+    fn on_end(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.finished {
+            self.error("上游流在响应完成前结束", &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_request_maps_messages_tools_and_format() {
+        let request = json!({
+            "model": "gpt-5",
+            "max_tokens": 256,
+            "response_format": {"type": "json_schema", "json_schema": {"name": "out", "schema": {"type": "object"}, "strict": true}},
+            "tools": [{"type": "function", "function": {"name": "lookup", "parameters": {"type": "object"}}}],
+            "tool_choice": {"type": "function", "function": {"name": "lookup"}},
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this"},
+                    {"type": "image_url", "image_url": {"url": "https://x/img.png", "detail": "low"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "lookup", "arguments": "{\"q\":1}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "result"}
+            ]
+        });
+
+        let converted = convert_request_value(&request);
+        assert_eq!(converted["instructions"], "be brief");
+        assert_eq!(converted["max_output_tokens"], 256);
+        assert_eq!(converted["text"]["format"]["name"], "out");
+        assert_eq!(converted["text"]["format"]["strict"], true);
+        assert_eq!(converted["tools"][0]["name"], "lookup");
+        assert_eq!(converted["tool_choice"]["name"], "lookup");
+
+        let input = converted["input"].as_array().unwrap();
+        assert_eq!(input.len(), 3);
+        assert_eq!(input[0]["content"][1]["type"], "input_image");
+        assert_eq!(input[0]["content"][1]["detail"], "low");
+        assert_eq!(input[1]["type"], "function_call");
+        assert_eq!(input[1]["arguments"], "{\"q\":1}");
+        assert_eq!(input[2]["type"], "function_call_output");
+    }
+
+    #[test]
+    fn test_convert_response_to_chat_completion() {
+        let response = json!({
+            "id": "resp_1",
+            "created_at": 1700000000,
+            "model": "gpt-5",
+            "output": [
+                {"type": "function_call", "call_id": "call_1", "name": "lookup", "arguments": "{}"}
+            ],
+            "usage": {"input_tokens": 10, "output_tokens": 3}
+        });
+
+        let converted = convert_response_value(&response);
+        assert_eq!(converted["object"], "chat.completion");
+        assert_eq!(converted["choices"][0]["finish_reason"], "tool_calls");
+        assert!(converted["choices"][0]["message"]["content"].is_null());
+        assert_eq!(converted["choices"][0]["message"]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(converted["usage"]["total_tokens"], 13);
+    }
+
+    #[test]
+    fn test_stream_translation_emits_chunks_and_done() {
+        let mut translator = ResponsesToChatStream::new(true);
+        let mut out = Vec::new();
+        for event in [
+            json!({"type": "response.created", "response": {"id": "resp_1", "model": "gpt-5"}}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "Hi"}),
+            json!({"type": "response.completed", "response": {"usage": {"input_tokens": 4, "output_tokens": 1}}}),
+        ] {
+            out.extend(translator.on_event(None, &event.to_string()));
+        }
+        out.extend(translator.on_end());
+
+        let text = String::from_utf8(out).unwrap();
+        let payloads: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .collect();
+        assert_eq!(payloads.len(), 5);
+        assert!(payloads[1].contains("\"content\":\"Hi\""));
+        assert!(payloads[2].contains("\"finish_reason\":\"stop\""));
+        assert!(payloads[3].contains("\"total_tokens\":5"));
+        assert_eq!(payloads[4], "[DONE]");
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+static IN_FLIGHT: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+static SLOT_RELEASED: OnceLock<Notify> = OnceLock::new();
+
+fn slot_released() -> &'static Notify {
+    SLOT_RELEASED.get_or_init(Notify::new)
+}
+
+/// 账号的一个并发占位，持有期间计入在途请求数，释放时唤醒排队中的请求
+pub struct AccountPermit {
+    account_id: String,
+}
+
+impl Drop for AccountPermit {
+    fn drop(&mut self) {
+        if let Ok(mut guard) = IN_FLIGHT.lock() {
+            if let Some(map) = guard.as_mut() {
+                if let Some(count) = map.get_mut(&self.account_id) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        map.remove(&self.account_id);
+                    }
+                }
+            }
+        }
+        slot_released().notify_waiters();
+    }
+}
+
+/// 账号在途请求数未达上限时占用一个名额；limit 为 0 表示不限制
+pub fn try_acquire(account_id: &str, limit: u32) -> Option<AccountPermit> {
+    let mut guard = IN_FLIGHT.lock().ok()?;
+    let count = guard
+        .get_or_insert_with(HashMap::new)
+        .entry(account_id.to_string())
+        .or_insert(0);
+    if limit > 0 && *count >= limit as usize {
+        return None;
+    }
+    *count += 1;
+    Some(AccountPermit {
+        account_id: account_id.to_string(),
+    })
+}
+
+pub fn is_saturated(account_id: &str, limit: u32) -> bool {
+    limit > 0 && in_flight_count(account_id) >= limit as usize
+}
+
+pub fn in_flight_count(account_id: &str) -> usize {
+    IN_FLIGHT
+        .lock()
+        .ok()
+        .and_then(|guard| guard.as_ref().and_then(|map| map.get(account_id).copied()))
+        .unwrap_or(0)
+}
+
+/// 当前各账号的在途请求数（仅包含大于 0 的账号）
+pub fn in_flight_snapshot() -> HashMap<String, usize> {
+    IN_FLIGHT
+        .lock()
+        .ok()
+        .and_then(|guard| guard.clone())
+        .unwrap_or_default()
+}
+
+/// 等待任一账号释放名额，超时返回 false。
+/// 需在检查账号是否饱和之前调用 `slot_released_signal` 获取信号，避免错过释放通知。
+pub async fn wait_for_slot(signal: tokio::sync::futures::Notified<'_>, timeout: Duration) -> bool {
+    tokio::time::timeout(timeout, signal).await.is_ok()
+}
+
+pub fn slot_released_signal() -> tokio::sync::futures::Notified<'static> {
+    slot_released().notified()
+}
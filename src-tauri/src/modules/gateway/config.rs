@@ -44,6 +44,8 @@ pub fn save_gateway_config(config: &GatewayConfig) -> Result<(), String> {
 
     let mut guard = GATEWAY_CONFIG.lock().unwrap();
     *guard = Some(config.clone());
+    drop(guard);
 
+    super::model_catalog::invalidate_cache();
     Ok(())
 }
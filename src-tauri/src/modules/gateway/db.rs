@@ -1,4 +1,4 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -24,6 +24,14 @@ pub fn init_gateway_db() -> Result<(), String> {
     conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA foreign_keys=ON;")
         .map_err(|e| format!("设置 PRAGMA 失败: {}", e))?;
 
+    create_schema(&conn)?;
+
+    *guard = Some(conn);
+
+    Ok(())
+}
+
+fn create_schema(conn: &Connection) -> Result<(), String> {
     conn.execute_batch(
         "
         CREATE TABLE IF NOT EXISTS gateway_accounts (
@@ -54,7 +62,35 @@ pub fn init_gateway_db() -> Result<(), String> {
             enabled INTEGER NOT NULL DEFAULT 1,
             created_at INTEGER NOT NULL,
             last_used_at INTEGER,
-            usage_count INTEGER NOT NULL DEFAULT 0
+            usage_count INTEGER NOT NULL DEFAULT 0,
+            rpm_limit INTEGER,
+            daily_token_limit INTEGER,
+            monthly_budget_usd REAL,
+            expires_at INTEGER,
+            cost_month TEXT,
+            month_cost_usd REAL NOT NULL DEFAULT 0,
+            group_name TEXT,
+            allowed_endpoints TEXT,
+            last_used_ip TEXT,
+            previous_key_hash TEXT,
+            previous_key_expires_at INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS gateway_api_key_audit (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            key_id TEXT NOT NULL,
+            key_name TEXT NOT NULL,
+            key_prefix TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            action TEXT NOT NULL,
+            detail TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS gateway_api_key_token_usage (
+            key_id TEXT NOT NULL,
+            hour_start INTEGER NOT NULL,
+            tokens INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_id, hour_start)
         );
 
         CREATE TABLE IF NOT EXISTS gateway_request_logs (
@@ -70,19 +106,89 @@ pub fn init_gateway_db() -> Result<(), String> {
             input_tokens INTEGER,
             output_tokens INTEGER,
             error_message TEXT,
-            api_key_prefix TEXT
+            api_key_prefix TEXT,
+            cache_read_tokens INTEGER,
+            cache_creation_tokens INTEGER,
+            total_cost_usd TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS gateway_request_captures (
+            trace_id TEXT PRIMARY KEY,
+            timestamp INTEGER NOT NULL,
+            method TEXT NOT NULL,
+            path TEXT NOT NULL,
+            account_id TEXT,
+            upstream_url TEXT,
+            request_headers TEXT NOT NULL,
+            request_body TEXT NOT NULL,
+            upstream_request_body TEXT,
+            status_code INTEGER NOT NULL,
+            response_headers TEXT NOT NULL,
+            response_body TEXT NOT NULL,
+            truncated INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS gateway_account_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            reason TEXT
         );
 
         CREATE INDEX IF NOT EXISTS idx_request_logs_timestamp ON gateway_request_logs(timestamp);
         CREATE INDEX IF NOT EXISTS idx_request_logs_status ON gateway_request_logs(status_code);
         CREATE INDEX IF NOT EXISTS idx_accounts_status ON gateway_accounts(status);
         CREATE INDEX IF NOT EXISTS idx_api_keys_hash ON gateway_api_keys(key_hash);
+        CREATE INDEX IF NOT EXISTS idx_account_events_account ON gateway_account_events(account_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_api_key_audit_key ON gateway_api_key_audit(key_id, timestamp);
         ",
     )
     .map_err(|e| format!("创建表失败: {}", e))?;
 
-    *guard = Some(conn);
+    migrate_gateway_db(conn).map_err(|e| format!("迁移数据库失败: {}", e))
+}
+
+fn migrate_gateway_db(conn: &Connection) -> Result<(), rusqlite::Error> {
+    ensure_column(conn, "gateway_request_logs", "cache_read_tokens", "INTEGER")?;
+    ensure_column(conn, "gateway_request_logs", "cache_creation_tokens", "INTEGER")?;
+    ensure_column(conn, "gateway_request_logs", "total_cost_usd", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "rpm_limit", "INTEGER")?;
+    ensure_column(conn, "gateway_api_keys", "daily_token_limit", "INTEGER")?;
+    ensure_column(conn, "gateway_api_keys", "monthly_budget_usd", "REAL")?;
+    ensure_column(conn, "gateway_api_keys", "expires_at", "INTEGER")?;
+    ensure_column(conn, "gateway_api_keys", "cost_month", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "month_cost_usd", "REAL NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "gateway_api_keys", "group_name", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "allowed_endpoints", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "last_used_ip", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "previous_key_hash", "TEXT")?;
+    ensure_column(conn, "gateway_api_keys", "previous_key_expires_at", "INTEGER")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_api_keys_previous_hash ON gateway_api_keys(previous_key_hash)",
+        [],
+    )?;
+    Ok(())
+}
 
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
     Ok(())
 }
 
@@ -157,6 +263,92 @@ pub fn list_accounts() -> Result<Vec<super::types::GatewayAccount>, String> {
 pub fn delete_account(id: &str) -> Result<(), String> {
     with_db(|conn| {
         conn.execute("DELETE FROM gateway_accounts WHERE id = ?1", params![id])?;
+        conn.execute(
+            "DELETE FROM gateway_account_events WHERE account_id = ?1",
+            params![id],
+        )?;
+        Ok(())
+    })
+}
+
+/// 在同一连接内切换账号状态，状态实际发生变化时写入账号事件
+pub fn set_account_status(
+    conn: &Connection,
+    id: &str,
+    to_status: &str,
+    reason: &str,
+) -> Result<bool, rusqlite::Error> {
+    let from_status: Option<String> = conn
+        .query_row(
+            "SELECT status FROM gateway_accounts WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(from_status) = from_status else {
+        return Ok(false);
+    };
+    if from_status == to_status {
+        return Ok(false);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "UPDATE gateway_accounts SET status = ?1, updated_at = ?2 WHERE id = ?3",
+        params![to_status, now, id],
+    )?;
+    conn.execute(
+        "INSERT INTO gateway_account_events (account_id, timestamp, from_status, to_status, reason)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, now, from_status, to_status, reason],
+    )?;
+    tracing::info!(
+        "[AccountPool] 账号 {} 状态 {} -> {}: {}",
+        id,
+        from_status,
+        to_status,
+        reason
+    );
+    Ok(true)
+}
+
+pub fn list_account_events(
+    account_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<super::types::AccountEvent>, String> {
+    with_db(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, account_id, timestamp, from_status, to_status, reason
+             FROM gateway_account_events
+             WHERE ?1 IS NULL OR account_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![account_id, limit], |row| {
+            Ok(super::types::AccountEvent {
+                id: row.get(0)?,
+                account_id: row.get(1)?,
+                timestamp: row.get(2)?,
+                from_status: row.get(3)?,
+                to_status: row.get(4)?,
+                reason: row.get(5)?,
+            })
+        })?;
+        rows.collect::<Result<Vec<_>, _>>()
+    })
+}
+
+pub fn update_account_token(
+    id: &str,
+    access_token: &str,
+    refresh_token: Option<&str>,
+    token_expires_at: Option<i64>,
+) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp();
+    with_db(|conn| {
+        conn.execute(
+            "UPDATE gateway_accounts SET access_token = ?1, refresh_token = COALESCE(?2, refresh_token), token_expires_at = ?3, updated_at = ?4 WHERE id = ?5",
+            params![access_token, refresh_token, token_expires_at, now, id],
+        )?;
         Ok(())
     })
 }
@@ -216,75 +408,297 @@ pub fn get_active_accounts() -> Result<Vec<super::types::GatewayAccount>, String
     })
 }
 
+// token 用量按小时分桶，统计包含 24 小时前那一刻的小时桶，宁可略严也不让用量超出上限
+const API_KEY_COLUMNS: &str = "id, name, key_hash, key_prefix, allowed_models, enabled, created_at, last_used_at, usage_count, rpm_limit, daily_token_limit, monthly_budget_usd, expires_at,
+    (SELECT COALESCE(SUM(u.tokens), 0) FROM gateway_api_key_token_usage u
+     WHERE u.key_id = gateway_api_keys.id AND u.hour_start > CAST(strftime('%s', 'now') AS INTEGER) - 90000),
+    cost_month, month_cost_usd, group_name, allowed_endpoints, last_used_ip, previous_key_expires_at";
+
+const TOKEN_BUCKET_SECONDS: i64 = 3600;
+/// 超过该时间的小时桶不再参与统计，写入时顺带清理
+const TOKEN_USAGE_RETENTION_SECONDS: i64 = 90000;
+
+fn current_month() -> String {
+    chrono::Utc::now().format("%Y-%m").to_string()
+}
+
+fn map_api_key_row(row: &rusqlite::Row) -> Result<super::types::GatewayApiKey, rusqlite::Error> {
+    // 月度计数已过期时视为 0，下次累计时再重置
+    let cost_month: Option<String> = row.get(14)?;
+    Ok(super::types::GatewayApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        key_hash: row.get(2)?,
+        key_prefix: row.get(3)?,
+        allowed_models: row.get(4)?,
+        enabled: row.get::<_, i32>(5)? != 0,
+        created_at: row.get(6)?,
+        last_used_at: row.get(7)?,
+        usage_count: row.get(8)?,
+        rpm_limit: row.get(9)?,
+        daily_token_limit: row.get(10)?,
+        monthly_budget_usd: row.get(11)?,
+        expires_at: row.get(12)?,
+        tokens_last_24h: row.get(13)?,
+        cost_this_month_usd: if cost_month.as_deref() == Some(current_month().as_str()) {
+            row.get(15)?
+        } else {
+            0.0
+        },
+        group_name: row.get(16)?,
+        allowed_endpoints: row.get(17)?,
+        last_used_ip: row.get(18)?,
+        previous_key_expires_at: row.get(19)?,
+    })
+}
+
+/// 在同一连接内写入 API Key 审计记录，需在删除 Key 之前调用
+fn insert_api_key_audit(
+    conn: &Connection,
+    id: &str,
+    action: &str,
+    detail: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO gateway_api_key_audit (key_id, key_name, key_prefix, timestamp, action, detail)
+         SELECT id, name, key_prefix, ?2, ?3, ?4 FROM gateway_api_keys WHERE id = ?1",
+        params![id, now, action, detail],
+    )?;
+    tracing::info!("[Gateway] API Key {} {}", id, action);
+    Ok(())
+}
+
+pub fn list_api_key_audit(
+    key_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<super::types::ApiKeyAuditEntry>, String> {
+    with_db(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, key_id, key_name, key_prefix, timestamp, action, detail
+             FROM gateway_api_key_audit
+             WHERE ?1 IS NULL OR key_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![key_id, limit], |row| {
+            Ok(super::types::ApiKeyAuditEntry {
+                id: row.get(0)?,
+                key_id: row.get(1)?,
+                key_name: row.get(2)?,
+                key_prefix: row.get(3)?,
+                timestamp: row.get(4)?,
+                action: row.get(5)?,
+                detail: row.get(6)?,
+            })
+        })?;
+        rows.collect::<Result<Vec<_>, _>>()
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn insert_api_key(
     id: &str,
     name: &str,
     key_hash: &str,
     key_prefix: &str,
     allowed_models: Option<&str>,
+    allowed_endpoints: Option<&str>,
+    limits: &super::types::ApiKeyLimits,
+    group_name: Option<&str>,
 ) -> Result<(), String> {
     let now = chrono::Utc::now().timestamp();
     with_db(|conn| {
         conn.execute(
-            "INSERT INTO gateway_api_keys (id, name, key_hash, key_prefix, allowed_models, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![id, name, key_hash, key_prefix, allowed_models, now],
+            "INSERT INTO gateway_api_keys (id, name, key_hash, key_prefix, allowed_models, created_at, rpm_limit, daily_token_limit, monthly_budget_usd, expires_at, group_name, allowed_endpoints)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                id,
+                name,
+                key_hash,
+                key_prefix,
+                allowed_models,
+                now,
+                limits.rpm_limit,
+                limits.daily_token_limit,
+                limits.monthly_budget_usd,
+                limits.expires_at,
+                group_name,
+                allowed_endpoints,
+            ],
         )?;
-        Ok(())
+        insert_api_key_audit(conn, id, "create", None)
     })
 }
 
-pub fn list_api_keys() -> Result<Vec<super::types::GatewayApiKey>, String> {
+pub fn update_api_key_limits(id: &str, limits: &super::types::ApiKeyLimits) -> Result<(), String> {
     with_db(|conn| {
-        let mut stmt = conn.prepare(
-            "SELECT id, name, key_hash, key_prefix, allowed_models, enabled, created_at, last_used_at, usage_count
-             FROM gateway_api_keys ORDER BY created_at DESC",
+        conn.execute(
+            "UPDATE gateway_api_keys SET rpm_limit = ?1, daily_token_limit = ?2, monthly_budget_usd = ?3, expires_at = ?4 WHERE id = ?5",
+            params![
+                limits.rpm_limit,
+                limits.daily_token_limit,
+                limits.monthly_budget_usd,
+                limits.expires_at,
+                id,
+            ],
         )?;
-        let rows = stmt.query_map([], |row| {
-            Ok(super::types::GatewayApiKey {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                key_hash: row.get(2)?,
-                key_prefix: row.get(3)?,
-                allowed_models: row.get(4)?,
-                enabled: row.get::<_, i32>(5)? != 0,
-                created_at: row.get(6)?,
-                last_used_at: row.get(7)?,
-                usage_count: row.get(8)?,
-            })
-        })?;
+        insert_api_key_audit(conn, id, "update_limits", None)
+    })
+}
+
+pub fn update_api_key_group(id: &str, group_name: Option<&str>) -> Result<(), String> {
+    with_db(|conn| {
+        conn.execute(
+            "UPDATE gateway_api_keys SET group_name = ?1 WHERE id = ?2",
+            params![group_name, id],
+        )?;
+        insert_api_key_audit(conn, id, "update_group", group_name)
+    })
+}
+
+/// 更新可用模型与可访问端点（均为 JSON 数组，None 表示不限制）
+pub fn update_api_key_scopes(
+    id: &str,
+    allowed_models: Option<&str>,
+    allowed_endpoints: Option<&str>,
+) -> Result<(), String> {
+    with_db(|conn| {
+        conn.execute(
+            "UPDATE gateway_api_keys SET allowed_models = ?1, allowed_endpoints = ?2 WHERE id = ?3",
+            params![allowed_models, allowed_endpoints, id],
+        )?;
+        let detail = format!(
+            "models: {}, endpoints: {}",
+            allowed_models.unwrap_or("*"),
+            allowed_endpoints.unwrap_or("*")
+        );
+        insert_api_key_audit(conn, id, "update_scopes", Some(&detail))
+    })
+}
+
+/// 替换为新的 Key，旧 Key 在 previous_expires_at 之前仍然有效（None 表示立即失效）。
+/// 重叠期内再次轮换时，更早的旧 Key 立即失效
+pub fn rotate_api_key(
+    id: &str,
+    key_hash: &str,
+    key_prefix: &str,
+    previous_expires_at: Option<i64>,
+) -> Result<(), String> {
+    with_db(|conn| rotate_key_hash(conn, id, key_hash, key_prefix, previous_expires_at))
+}
+
+fn rotate_key_hash(
+    conn: &Connection,
+    id: &str,
+    key_hash: &str,
+    key_prefix: &str,
+    previous_expires_at: Option<i64>,
+) -> Result<(), rusqlite::Error> {
+    let old_prefix: String = conn.query_row(
+        "SELECT key_prefix FROM gateway_api_keys WHERE id = ?1",
+        params![id],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "UPDATE gateway_api_keys SET
+            previous_key_hash = CASE WHEN ?1 IS NULL THEN NULL ELSE key_hash END,
+            previous_key_expires_at = ?1,
+            key_hash = ?2,
+            key_prefix = ?3
+         WHERE id = ?4",
+        params![previous_expires_at, key_hash, key_prefix, id],
+    )?;
+    let detail = match previous_expires_at {
+        Some(deadline) => format!("{}... 有效至 {}", old_prefix, deadline),
+        None => format!("{}... 已失效", old_prefix),
+    };
+    insert_api_key_audit(conn, id, "rotate", Some(&detail))
+}
+
+pub fn list_api_keys() -> Result<Vec<super::types::GatewayApiKey>, String> {
+    with_db(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM gateway_api_keys ORDER BY created_at DESC",
+            API_KEY_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], map_api_key_row)?;
         rows.collect::<Result<Vec<_>, _>>()
     })
 }
 
 pub fn validate_api_key(key_hash: &str) -> Result<Option<super::types::GatewayApiKey>, String> {
+    let now = chrono::Utc::now().timestamp();
+    with_db(|conn| find_api_key_by_hash(conn, key_hash, now))
+}
+
+/// 当前 Key，或轮换重叠期（previous_key_expires_at 之前）内的旧 Key
+fn find_api_key_by_hash(
+    conn: &Connection,
+    key_hash: &str,
+    now: i64,
+) -> Result<Option<super::types::GatewayApiKey>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM gateway_api_keys
+         WHERE enabled = 1 AND (key_hash = ?1 OR (previous_key_hash = ?1 AND previous_key_expires_at > ?2))",
+        API_KEY_COLUMNS
+    ))?;
+    let mut rows = stmt.query(params![key_hash, now])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(map_api_key_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// 记录一次已放行的请求
+pub fn register_api_key_request(id: &str, client_ip: Option<&str>) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp();
     with_db(|conn| {
-        let mut stmt = conn.prepare(
-            "SELECT id, name, key_hash, key_prefix, allowed_models, enabled, created_at, last_used_at, usage_count
-             FROM gateway_api_keys WHERE key_hash = ?1 AND enabled = 1",
+        conn.execute(
+            "UPDATE gateway_api_keys SET
+                usage_count = usage_count + 1,
+                last_used_at = ?1,
+                last_used_ip = COALESCE(?3, last_used_ip)
+             WHERE id = ?2",
+            params![now, id, client_ip],
         )?;
-        let mut rows = stmt.query(params![key_hash])?;
-        if let Some(row) = rows.next()? {
-            Ok(Some(super::types::GatewayApiKey {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                key_hash: row.get(2)?,
-                key_prefix: row.get(3)?,
-                allowed_models: row.get(4)?,
-                enabled: true,
-                created_at: row.get(6)?,
-                last_used_at: row.get(7)?,
-                usage_count: row.get(8)?,
-            }))
-        } else {
-            Ok(None)
-        }
+        Ok(())
+    })
+}
+
+/// 将 token 用量计入当前小时桶，并累加当月费用（跨月时先清零）
+pub fn add_api_key_consumption(id: &str, tokens: i64, cost_usd: f64) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp();
+    let hour_start = now - now % TOKEN_BUCKET_SECONDS;
+    let month = current_month();
+    with_db(|conn| {
+        conn.execute(
+            "INSERT INTO gateway_api_key_token_usage (key_id, hour_start, tokens) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key_id, hour_start) DO UPDATE SET tokens = tokens + excluded.tokens",
+            params![id, hour_start, tokens],
+        )?;
+        conn.execute(
+            "DELETE FROM gateway_api_key_token_usage WHERE hour_start <= ?1",
+            params![now - TOKEN_USAGE_RETENTION_SECONDS],
+        )?;
+        conn.execute(
+            "UPDATE gateway_api_keys SET
+                month_cost_usd = CASE WHEN cost_month = ?2 THEN month_cost_usd + ?3 ELSE ?3 END,
+                cost_month = ?2
+             WHERE id = ?1",
+            params![id, month, cost_usd],
+        )?;
+        Ok(())
     })
 }
 
 pub fn delete_api_key(id: &str) -> Result<(), String> {
     with_db(|conn| {
+        insert_api_key_audit(conn, id, "delete", None)?;
         conn.execute("DELETE FROM gateway_api_keys WHERE id = ?1", params![id])?;
+        conn.execute(
+            "DELETE FROM gateway_api_key_token_usage WHERE key_id = ?1",
+            params![id],
+        )?;
         Ok(())
     })
 }
@@ -295,15 +709,15 @@ pub fn toggle_api_key(id: &str, enabled: bool) -> Result<(), String> {
             "UPDATE gateway_api_keys SET enabled = ?1 WHERE id = ?2",
             params![enabled as i32, id],
         )?;
-        Ok(())
+        insert_api_key_audit(conn, id, if enabled { "enable" } else { "disable" }, None)
     })
 }
 
 pub fn insert_request_log(entry: &super::types::RequestLogEntry) -> Result<(), String> {
     with_db(|conn| {
         conn.execute(
-            "INSERT INTO gateway_request_logs (trace_id, timestamp, method, path, status_code, duration_ms, account_email, model, input_tokens, output_tokens, error_message, api_key_prefix)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            "INSERT INTO gateway_request_logs (trace_id, timestamp, method, path, status_code, duration_ms, account_email, model, input_tokens, output_tokens, error_message, api_key_prefix, cache_read_tokens, cache_creation_tokens, total_cost_usd)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
             params![
                 entry.trace_id,
                 entry.timestamp,
@@ -317,6 +731,9 @@ pub fn insert_request_log(entry: &super::types::RequestLogEntry) -> Result<(), S
                 entry.output_tokens,
                 entry.error_message,
                 entry.api_key_prefix,
+                entry.cache_read_tokens,
+                entry.cache_creation_tokens,
+                entry.total_cost_usd,
             ],
         )?;
         Ok(())
@@ -331,7 +748,7 @@ pub fn query_request_logs(
         let offset = query.offset.unwrap_or(0);
 
         let mut sql = String::from(
-            "SELECT id, trace_id, timestamp, method, path, status_code, duration_ms, account_email, model, input_tokens, output_tokens, error_message, api_key_prefix
+            "SELECT id, trace_id, timestamp, method, path, status_code, duration_ms, account_email, model, input_tokens, output_tokens, error_message, api_key_prefix, cache_read_tokens, cache_creation_tokens, total_cost_usd
              FROM gateway_request_logs WHERE 1=1",
         );
         let mut param_values: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
@@ -382,6 +799,9 @@ pub fn query_request_logs(
                 output_tokens: row.get(10)?,
                 error_message: row.get(11)?,
                 api_key_prefix: row.get(12)?,
+                cache_read_tokens: row.get(13)?,
+                cache_creation_tokens: row.get(14)?,
+                total_cost_usd: row.get(15)?,
             })
         })?;
         rows.collect::<Result<Vec<_>, _>>()
@@ -393,7 +813,9 @@ pub fn get_request_log_summary() -> Result<super::types::RequestLogSummary, Stri
         let mut stmt = conn.prepare(
             "SELECT COUNT(*), SUM(CASE WHEN status_code < 400 THEN 1 ELSE 0 END),
                     SUM(CASE WHEN status_code >= 400 THEN 1 ELSE 0 END),
-                    AVG(duration_ms), COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0)
+                    AVG(duration_ms), COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0),
+                    COALESCE(SUM(cache_read_tokens), 0), COALESCE(SUM(cache_creation_tokens), 0),
+                    COALESCE(SUM(CAST(total_cost_usd AS REAL)), 0)
              FROM gateway_request_logs",
         )?;
         let summary = stmt.query_row([], |row| {
@@ -404,6 +826,9 @@ pub fn get_request_log_summary() -> Result<super::types::RequestLogSummary, Stri
                 avg_duration_ms: row.get::<_, f64>(3).unwrap_or(0.0),
                 total_input_tokens: row.get(4)?,
                 total_output_tokens: row.get(5)?,
+                total_cache_read_tokens: row.get(6)?,
+                total_cache_creation_tokens: row.get(7)?,
+                total_cost_usd: row.get(8)?,
             })
         })?;
         Ok(summary)
@@ -416,3 +841,134 @@ pub fn clear_request_logs() -> Result<(), String> {
         Ok(())
     })
 }
+
+/// 写入捕获记录，同一 trace_id 覆盖旧记录；超过 max_entries 时删除最早的记录
+/// 保存捕获后按条数与保留时长清理旧记录，retention_hours 为 0 时不按时长清理
+pub fn save_request_capture(
+    capture: &super::types::RequestCapture,
+    max_entries: u32,
+    retention_hours: u32,
+) -> Result<(), String> {
+    let request_headers = serde_json::to_string(&capture.request_headers).unwrap_or_default();
+    let response_headers = serde_json::to_string(&capture.response_headers).unwrap_or_default();
+    with_db(|conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO gateway_request_captures (trace_id, timestamp, method, path, account_id, upstream_url, request_headers, request_body, upstream_request_body, status_code, response_headers, response_body, truncated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                capture.trace_id,
+                capture.timestamp,
+                capture.method,
+                capture.path,
+                capture.account_id,
+                capture.upstream_url,
+                request_headers,
+                capture.request_body,
+                capture.upstream_request_body,
+                capture.status_code,
+                response_headers,
+                capture.response_body,
+                capture.truncated,
+            ],
+        )?;
+        conn.execute(
+            "DELETE FROM gateway_request_captures WHERE trace_id NOT IN (
+                SELECT trace_id FROM gateway_request_captures ORDER BY timestamp DESC LIMIT ?1
+            )",
+            params![max_entries],
+        )?;
+        if retention_hours > 0 {
+            let cutoff = chrono::Utc::now().timestamp() - retention_hours as i64 * 3600;
+            conn.execute(
+                "DELETE FROM gateway_request_captures WHERE timestamp < ?1",
+                params![cutoff],
+            )?;
+        }
+        Ok(())
+    })
+}
+
+pub fn get_request_capture(trace_id: &str) -> Result<Option<super::types::RequestCapture>, String> {
+    with_db(|conn| {
+        conn.query_row(
+            "SELECT trace_id, timestamp, method, path, account_id, upstream_url, request_headers, request_body, upstream_request_body, status_code, response_headers, response_body, truncated
+             FROM gateway_request_captures WHERE trace_id = ?1",
+            params![trace_id],
+            |row| {
+                let request_headers: String = row.get(6)?;
+                let response_headers: String = row.get(10)?;
+                Ok(super::types::RequestCapture {
+                    trace_id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    method: row.get(2)?,
+                    path: row.get(3)?,
+                    account_id: row.get(4)?,
+                    upstream_url: row.get(5)?,
+                    request_headers: serde_json::from_str(&request_headers).unwrap_or_default(),
+                    request_body: row.get(7)?,
+                    upstream_request_body: row.get(8)?,
+                    status_code: row.get(9)?,
+                    response_headers: serde_json::from_str(&response_headers).unwrap_or_default(),
+                    response_body: row.get(11)?,
+                    truncated: row.get(12)?,
+                })
+            },
+        )
+        .optional()
+    })
+}
+
+pub fn clear_request_captures() -> Result<(), String> {
+    with_db(|conn| {
+        conn.execute("DELETE FROM gateway_request_captures", [])?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO gateway_api_keys (id, name, key_hash, key_prefix, created_at)
+             VALUES ('key', 'test', 'old-hash', 'sk-gw-old', 0)",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn lookup(conn: &Connection, key_hash: &str, now: i64) -> Option<String> {
+        find_api_key_by_hash(conn, key_hash, now)
+            .unwrap()
+            .map(|key| key.id)
+    }
+
+    #[test]
+    fn test_rotated_key_keeps_old_hash_until_grace_deadline() {
+        let conn = test_conn();
+        rotate_key_hash(&conn, "key", "new-hash", "sk-gw-new", Some(1_000)).unwrap();
+
+        assert_eq!(lookup(&conn, "new-hash", 999).as_deref(), Some("key"));
+        assert_eq!(lookup(&conn, "old-hash", 999).as_deref(), Some("key"));
+        assert_eq!(lookup(&conn, "old-hash", 1_000), None);
+        assert_eq!(lookup(&conn, "new-hash", 1_000).as_deref(), Some("key"));
+    }
+
+    #[test]
+    fn test_rotation_without_overlap_revokes_old_hash() {
+        let conn = test_conn();
+        rotate_key_hash(&conn, "key", "new-hash", "sk-gw-new", None).unwrap();
+        assert_eq!(lookup(&conn, "old-hash", 0), None);
+
+        // 重叠期内再次轮换，更早的旧 Key 立即失效
+        rotate_key_hash(&conn, "key", "newer-hash", "sk-gw-newer", Some(1_000)).unwrap();
+        assert_eq!(lookup(&conn, "new-hash", 0).as_deref(), Some("key"));
+        rotate_key_hash(&conn, "key", "newest-hash", "sk-gw-newest", Some(1_000)).unwrap();
+        assert_eq!(lookup(&conn, "new-hash", 0), None);
+        assert_eq!(lookup(&conn, "newer-hash", 0).as_deref(), Some("key"));
+    }
+}
@@ -0,0 +1,142 @@
+use super::account_pool::restore_account;
+use super::types::{AccountStatus, GatewayAccount, GatewayConfig};
+use super::{config, db, protocol_adapter, proxy, token_refresh};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+static HEALTH_TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// 启动后台健康检查，网关启动时调用；已在运行时不重复启动
+pub fn start_health_monitor() {
+    let Ok(mut guard) = HEALTH_TASK.lock() else {
+        return;
+    };
+    if guard.as_ref().is_some_and(|task| !task.is_finished()) {
+        return;
+    }
+
+    *guard = Some(tokio::spawn(async {
+        loop {
+            // 每轮重新读取配置，间隔调整后无需重启网关
+            let interval = config::get_gateway_config().health_check_interval_seconds;
+            if interval > 0 {
+                run_health_check().await;
+            }
+            tokio::time::sleep(Duration::from_secs(interval.max(10))).await;
+        }
+    }));
+    tracing::info!("[Health] 账号健康检查已启动");
+}
+
+pub fn stop_health_monitor() {
+    if let Ok(mut guard) = HEALTH_TASK.lock() {
+        if let Some(task) = guard.take() {
+            task.abort();
+            tracing::info!("[Health] 账号健康检查已停止");
+        }
+    }
+}
+
+/// 单轮检查：解除到期冷却 → 刷新即将过期的 Token → 探测异常账号
+pub async fn run_health_check() {
+    expire_cooldowns();
+
+    let accounts = match db::list_accounts() {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            tracing::warn!("[Health] 读取账号失败: {}", e);
+            return;
+        }
+    };
+    let gw_config = config::get_gateway_config();
+
+    for account in &accounts {
+        match account.status {
+            AccountStatus::Inactive | AccountStatus::Cooldown => continue,
+            AccountStatus::Expired => refresh_and_restore(account).await,
+            AccountStatus::Active => {
+                if token_refresh::needs_refresh(account) {
+                    refresh_and_restore(account).await;
+                }
+            }
+            AccountStatus::Error => probe_account(&gw_config, account).await,
+        }
+    }
+}
+
+fn expire_cooldowns() {
+    let now = chrono::Utc::now().timestamp();
+    let expired: Vec<String> = db::list_accounts()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|a| a.status == AccountStatus::Cooldown)
+        .filter(|a| a.cooldown_until.is_none_or(|until| until <= now))
+        .map(|a| a.id)
+        .collect();
+
+    for id in expired {
+        if let Err(e) = restore_account(&id, "冷却结束") {
+            tracing::warn!("[Health] 解除账号 {} 冷却失败: {}", id, e);
+        }
+    }
+}
+
+/// 向上游发送轻量请求，判断异常账号是否已恢复
+async fn probe_account(gw_config: &GatewayConfig, account: &GatewayAccount) {
+    let base_url = protocol_adapter::resolve_upstream_for_platform(
+        account.platform.as_deref(),
+        &gw_config.platform_upstreams,
+        &gw_config.upstream_base_url,
+    );
+    let url = format!("{}/v1/models", base_url.trim_end_matches('/'));
+    let proxy_url = account
+        .proxy_url
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .or(gw_config.upstream_proxy_url.as_deref());
+
+    let client = match proxy::client_for_proxy(proxy_url) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("[Health] 探测账号 {} 失败: {}", account.id, e);
+            return;
+        }
+    };
+    let result = client
+        .get(&url)
+        .bearer_auth(&account.access_token)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) if response.status().is_success() => {
+            if let Err(e) = restore_account(&account.id, "探测成功") {
+                tracing::warn!("[Health] 恢复账号 {} 失败: {}", account.id, e);
+            }
+        }
+        Ok(response) if matches!(response.status().as_u16(), 401 | 403) => {
+            refresh_and_restore(account).await
+        }
+        Ok(response) => {
+            tracing::debug!(
+                "[Health] 账号 {} 探测返回 HTTP {}",
+                account.id,
+                response.status()
+            );
+        }
+        Err(e) => {
+            tracing::debug!("[Health] 账号 {} 探测失败: {}", account.id, e);
+        }
+    }
+}
+
+/// 刷新 Token，成功后恢复账号；失败时由 token_refresh 判断是否标记为过期
+async fn refresh_and_restore(account: &GatewayAccount) {
+    if let Err(e) = token_refresh::refresh_account(account).await {
+        tracing::debug!("[Health] 账号 {} 刷新未成功: {}", account.id, e);
+    }
+}
@@ -3,71 +3,173 @@ pub mod router;
 pub mod proxy;
 pub mod account_pool;
 pub mod account_pool_bridge;
+pub mod health;
+pub mod token_refresh;
+pub mod quota_lookup;
 pub mod api_key;
 pub mod request_log;
+pub mod capture;
 pub mod protocol_adapter;
+pub mod anthropic_adapter;
+pub mod chat_adapter;
+pub mod model_catalog;
+pub mod model_mapping;
+pub mod stream;
+pub mod concurrency;
 pub mod db;
 pub mod config;
 pub mod types;
 
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::OnceLock;
-use tokio::sync::Mutex;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// 强制关闭后等待连接退出的时间
+const FORCE_CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+static GATEWAY_SERVER: OnceLock<Mutex<Option<ServerHandle>>> = OnceLock::new();
+static GATEWAY_DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// 正在监听的服务实例；热更新端口时会短暂存在新旧两个实例
+struct ServerHandle {
+    port: u16,
+    started_at: Instant,
+    shutdown_tx: oneshot::Sender<()>,
+    force_close_tx: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
 
-static GATEWAY_RUNNING: AtomicBool = AtomicBool::new(false);
-static GATEWAY_SHUTDOWN: OnceLock<Mutex<Option<tokio::sync::oneshot::Sender<()>>>> =
-    OnceLock::new();
+fn server_slot() -> &'static Mutex<Option<ServerHandle>> {
+    GATEWAY_SERVER.get_or_init(|| Mutex::new(None))
+}
 
 pub fn is_gateway_running() -> bool {
-    GATEWAY_RUNNING.load(Ordering::Relaxed)
+    server_slot()
+        .lock()
+        .map(|slot| slot.as_ref().is_some_and(|s| !s.task.is_finished()))
+        .unwrap_or(false)
+}
+
+/// 网关正在停止，等待在途请求完成
+pub fn is_gateway_draining() -> bool {
+    GATEWAY_DRAINING.load(Ordering::Relaxed)
+}
+
+pub fn gateway_uptime_seconds() -> Option<u64> {
+    let slot = server_slot().lock().ok()?;
+    slot.as_ref()
+        .filter(|s| !s.task.is_finished())
+        .map(|s| s.started_at.elapsed().as_secs())
+}
+
+fn spawn_server(listener: tokio::net::TcpListener, port: u16, started_at: Instant) -> ServerHandle {
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let (force_close_tx, force_close_rx) = watch::channel(false);
+
+    let task = tokio::spawn(async move {
+        if let Err(e) = server::run_gateway_server(listener, shutdown_rx, force_close_rx).await {
+            tracing::error!("[Gateway] 服务器异常退出: {}", e);
+        }
+    });
+
+    ServerHandle {
+        port,
+        started_at,
+        shutdown_tx,
+        force_close_tx,
+        task,
+    }
 }
 
 pub async fn start_gateway(port: u16) -> Result<(), String> {
-    if GATEWAY_RUNNING.load(Ordering::Relaxed) {
+    if is_gateway_running() {
         return Err("网关已在运行".to_string());
     }
+    if is_gateway_draining() {
+        return Err("网关正在停止，请稍后再试".to_string());
+    }
 
     db::init_gateway_db().map_err(|e| format!("初始化网关数据库失败: {}", e))?;
 
-    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
-
-    let shutdown_mutex = GATEWAY_SHUTDOWN.get_or_init(|| Mutex::new(None));
-    *shutdown_mutex.lock().await = Some(shutdown_tx);
+    let listener = server::bind(port).await?;
+    let mut slot = server_slot().lock().map_err(|e| e.to_string())?;
+    if slot.as_ref().is_some_and(|s| !s.task.is_finished()) {
+        return Err("网关已在运行".to_string());
+    }
+    *slot = Some(spawn_server(listener, port, Instant::now()));
+    health::start_health_monitor();
 
-    GATEWAY_RUNNING.store(true, Ordering::Relaxed);
+    tracing::info!("[Gateway] 网关服务已启动，监听端口: {}", port);
+    Ok(())
+}
 
-    let server_handle = tokio::spawn(async move {
-        if let Err(e) = server::run_gateway_server(port, shutdown_rx).await {
-            tracing::error!("[Gateway] 服务器异常退出: {}", e);
+/// 切换监听端口：新端口就绪后再排空旧实例，进行中的请求（包括流式响应）不受影响
+pub async fn rebind_gateway(port: u16) -> Result<(), String> {
+    let started_at = {
+        let slot = server_slot().lock().map_err(|e| e.to_string())?;
+        match slot.as_ref() {
+            Some(s) if s.port != port && !s.task.is_finished() => s.started_at,
+            _ => return Ok(()),
         }
-        GATEWAY_RUNNING.store(false, Ordering::Relaxed);
-    });
-
-    tracing::info!("[Gateway] 网关服务已启动，监听端口: {}", port);
+    };
 
-    tokio::spawn(async move {
-        let _ = server_handle.await;
-        tracing::info!("[Gateway] 网关服务已停止");
-    });
+    let listener = server::bind(port).await?;
+    let old = server_slot()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .replace(spawn_server(listener, port, started_at));
 
+    if let Some(old) = old {
+        tracing::info!("[Gateway] 监听端口 {} -> {}，旧端口排空中", old.port, port);
+        tokio::spawn(drain(old, drain_timeout()));
+    }
     Ok(())
 }
 
+/// 停止接受新连接，等待在途请求完成后再返回；超时后强制关闭剩余连接
 pub async fn stop_gateway() -> Result<(), String> {
-    if !GATEWAY_RUNNING.load(Ordering::Relaxed) {
-        return Err("网关未在运行".to_string());
-    }
+    let server = server_slot()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .take()
+        .ok_or("网关未在运行")?;
+
+    health::stop_health_monitor();
+    GATEWAY_DRAINING.store(true, Ordering::Relaxed);
+    tracing::info!(
+        "[Gateway] 网关服务正在关闭，在途请求: {}",
+        server::active_requests()
+    );
+    drain(server, drain_timeout()).await;
+    GATEWAY_DRAINING.store(false, Ordering::Relaxed);
+
+    tracing::info!("[Gateway] 网关服务已停止");
+    Ok(())
+}
 
-    let shutdown_mutex = GATEWAY_SHUTDOWN
-        .get()
-        .ok_or("网关未初始化")?;
+fn drain_timeout() -> Duration {
+    Duration::from_secs(config::get_gateway_config().drain_timeout_seconds)
+}
 
-    let sender = shutdown_mutex.lock().await.take();
-    if let Some(tx) = sender {
-        let _ = tx.send(());
+async fn drain(server: ServerHandle, timeout: Duration) {
+    let _ = server.shutdown_tx.send(());
+    let mut task = server.task;
+    if tokio::time::timeout(timeout, &mut task).await.is_ok() {
+        return;
     }
 
-    GATEWAY_RUNNING.store(false, Ordering::Relaxed);
-    tracing::info!("[Gateway] 网关服务正在关闭");
-    Ok(())
+    tracing::warn!(
+        "[Gateway] 端口 {} 排空超时，强制关闭剩余 {} 个请求",
+        server.port,
+        server::active_requests()
+    );
+    let _ = server.force_close_tx.send(true);
+    if tokio::time::timeout(FORCE_CLOSE_GRACE, &mut task)
+        .await
+        .is_err()
+    {
+        task.abort();
+    }
 }
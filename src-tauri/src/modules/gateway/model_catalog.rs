@@ -0,0 +1,237 @@
+use super::account_pool_bridge::Platform;
+use super::types::{GatewayAccount, GatewayApiKey, GatewayConfig};
+use super::{api_key, config, db, protocol_adapter, proxy};
+use crate::modules::account;
+use crate::modules::opencode_config::opencode_manager::OpenCodeConfigManager;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const UPSTREAM_MODELS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 未标记平台的账号（手动导入）走默认上游，模型归属记为 openai
+const DEFAULT_OWNER: &str = "openai";
+
+static MODEL_CACHE: Mutex<Option<CachedModels>> = Mutex::new(None);
+
+/// 各平台上次成功获取的上游模型列表，上游暂时不可用时沿用
+static UPSTREAM_MODELS: Mutex<Option<HashMap<String, Vec<String>>>> = Mutex::new(None);
+
+struct CachedModels {
+    fetched_at: i64,
+    models: Vec<CatalogModel>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatalogModel {
+    pub id: String,
+    pub owned_by: String,
+}
+
+/// 构建 `/v1/models` 响应，按调用方 API Key 的 allowed_models 过滤
+pub async fn build_models_response(api_key_info: Option<&GatewayApiKey>) -> Value {
+    let models = get_models().await;
+    let data: Vec<Value> = models
+        .iter()
+        .filter(|m| match api_key_info {
+            Some(key) => api_key::is_model_allowed(key, &m.id),
+            None => true,
+        })
+        .map(|m| {
+            serde_json::json!({
+                "id": m.id,
+                "object": "model",
+                "owned_by": m.owned_by,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "object": "list",
+        "data": data,
+    })
+}
+
+/// 汇总后的模型列表，在 models_cache_seconds 内复用缓存
+pub async fn get_models() -> Vec<CatalogModel> {
+    let gw_config = config::get_gateway_config();
+    let now = chrono::Utc::now().timestamp();
+
+    if let Ok(guard) = MODEL_CACHE.lock() {
+        if let Some(cached) = guard.as_ref() {
+            if now - cached.fetched_at < gw_config.models_cache_seconds as i64 {
+                return cached.models.clone();
+            }
+        }
+    }
+
+    let models = collect_models(&gw_config).await;
+
+    if let Ok(mut guard) = MODEL_CACHE.lock() {
+        *guard = Some(CachedModels {
+            fetched_at: now,
+            models: models.clone(),
+        });
+    }
+
+    models
+}
+
+/// 丢弃缓存，下次请求时重新汇总（账号同步或配置变更后调用）
+pub fn invalidate_cache() {
+    if let Ok(mut guard) = MODEL_CACHE.lock() {
+        *guard = None;
+    }
+}
+
+async fn collect_models(gw_config: &GatewayConfig) -> Vec<CatalogModel> {
+    let mut catalog = Catalog::default();
+    let accounts = db::get_active_accounts().unwrap_or_default();
+
+    // 每个平台取一个账号，向该平台实际转发的上游查询模型列表
+    let mut owners: Vec<(String, &GatewayAccount)> = Vec::new();
+    for account in &accounts {
+        let owner = account.platform.as_deref().unwrap_or(DEFAULT_OWNER);
+        if !owners.iter().any(|(o, _)| o == owner) {
+            owners.push((owner.to_string(), account));
+        }
+    }
+
+    for (owner, account) in &owners {
+        for id in upstream_models(gw_config, owner, account).await {
+            catalog.add(&id, owner);
+        }
+        if owner == &Platform::Antigravity.to_string() {
+            for name in antigravity_quota_models() {
+                catalog.add(&name, owner);
+            }
+        }
+    }
+
+    for (id, owner) in opencode_provider_models() {
+        catalog.add(&id, &owner);
+    }
+
+    catalog.models
+}
+
+/// 查询失败时返回该平台上次成功获取的列表，从未成功过时为空
+async fn upstream_models(
+    gw_config: &GatewayConfig,
+    owner: &str,
+    account: &GatewayAccount,
+) -> Vec<String> {
+    let base_url = protocol_adapter::resolve_upstream_for_platform(
+        account.platform.as_deref(),
+        &gw_config.platform_upstreams,
+        &gw_config.upstream_base_url,
+    );
+    // 与请求转发相同：账号单独配置的代理优先于全局代理
+    let proxy_url = account
+        .proxy_url
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .or(gw_config.upstream_proxy_url.as_deref());
+
+    match fetch_upstream_models(&base_url, &account.access_token, proxy_url).await {
+        Ok(ids) => {
+            if let Ok(mut guard) = UPSTREAM_MODELS.lock() {
+                guard
+                    .get_or_insert_with(HashMap::new)
+                    .insert(owner.to_string(), ids.clone());
+            }
+            ids
+        }
+        Err(e) => {
+            tracing::warn!("[Gateway] 获取 {} 上游模型列表失败: {}", owner, e);
+            UPSTREAM_MODELS
+                .lock()
+                .ok()
+                .and_then(|guard| guard.as_ref()?.get(owner).cloned())
+                .unwrap_or_default()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Catalog {
+    seen: HashSet<String>,
+    models: Vec<CatalogModel>,
+}
+
+impl Catalog {
+    fn add(&mut self, id: &str, owned_by: &str) {
+        if id.is_empty() || !self.seen.insert(id.to_string()) {
+            return;
+        }
+        self.models.push(CatalogModel {
+            id: id.to_string(),
+            owned_by: owned_by.to_string(),
+        });
+    }
+}
+
+fn antigravity_quota_models() -> Vec<String> {
+    account::list_accounts()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|a| a.quota.as_ref())
+        .flat_map(|q| q.models.iter().map(|m| m.name.clone()))
+        .collect()
+}
+
+fn opencode_provider_models() -> Vec<(String, String)> {
+    let providers = match OpenCodeConfigManager::new(std::path::PathBuf::new())
+        .map_err(|e| e.to_string())
+        .and_then(|manager| manager.get_all_providers())
+    {
+        Ok(providers) => providers,
+        Err(e) => {
+            tracing::debug!("[Gateway] 读取 OpenCode Provider 失败: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut models: Vec<(String, String)> = providers
+        .into_iter()
+        .filter(|(_, provider)| provider.enabled)
+        .flat_map(|(name, provider)| {
+            provider
+                .models
+                .into_keys()
+                .map(move |id| (id, name.clone()))
+        })
+        .collect();
+    models.sort();
+    models
+}
+
+async fn fetch_upstream_models(
+    base_url: &str,
+    access_token: &str,
+    proxy_url: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let url = format!("{}/v1/models", base_url.trim_end_matches('/'));
+    let response = proxy::client_for_proxy(proxy_url)?
+        .get(&url)
+        .bearer_auth(access_token)
+        .timeout(UPSTREAM_MODELS_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let json: Value = response.json().await.map_err(|e| e.to_string())?;
+    Ok(json
+        .get("data")
+        .and_then(|d| d.as_array())
+        .map(|data| {
+            data.iter()
+                .filter_map(|m| m.get("id").and_then(|id| id.as_str()))
+                .map(|id| id.to_string())
+                .collect()
+        })
+        .unwrap_or_default())
+}
@@ -0,0 +1,132 @@
+use super::types::ModelMapping;
+use bytes::Bytes;
+use serde_json::Value;
+
+/// 模型映射的结果：上游实际使用的模型与限定的账号平台/分组
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedModel {
+    pub model: Option<String>,
+    pub platform: Option<String>,
+}
+
+/// 按顺序匹配映射规则，第一条命中的规则生效；`*` 规则可作为兜底放在最后。
+/// 请求未携带模型时只有 `*` 规则能命中
+pub fn resolve(model: Option<&str>, rules: &[ModelMapping]) -> ResolvedModel {
+    let requested = model.unwrap_or("");
+    let matched = rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .find(|rule| wildcard_match(&rule.pattern, requested));
+
+    match matched {
+        Some(rule) => ResolvedModel {
+            model: rule
+                .target_model
+                .as_ref()
+                .filter(|m| !m.is_empty())
+                .cloned()
+                .or_else(|| model.map(|m| m.to_string())),
+            platform: rule.target_platform.clone().filter(|p| !p.is_empty()),
+        },
+        None => ResolvedModel {
+            model: model.map(|m| m.to_string()),
+            platform: None,
+        },
+    }
+}
+
+/// 大小写不敏感的通配符匹配，`*` 匹配任意长度字符，`?` 匹配单个字符
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 将请求体中的 model 字段替换为映射后的模型，非 JSON 请求体原样返回
+pub fn rewrite_model_in_body(body: Bytes, model: &str) -> Bytes {
+    let Ok(mut json) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    match json.as_object_mut() {
+        Some(obj) => {
+            obj.insert("model".to_string(), Value::String(model.to_string()));
+            serde_json::to_vec(&json).map(Bytes::from).unwrap_or(body)
+        }
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(
+        pattern: &str,
+        target_model: Option<&str>,
+        target_platform: Option<&str>,
+    ) -> ModelMapping {
+        ModelMapping {
+            pattern: pattern.to_string(),
+            target_model: target_model.map(|s| s.to_string()),
+            target_platform: target_platform.map(|s| s.to_string()),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("gpt-4.1", "GPT-4.1"));
+        assert!(wildcard_match("claude-*-latest", "claude-sonnet-latest"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("o?-mini", "o4-mini"));
+        assert!(!wildcard_match("claude-*", "gpt-4.1"));
+        assert!(!wildcard_match("gpt-4.1", "gpt-4.1-mini"));
+    }
+
+    #[test]
+    fn test_resolve_uses_first_match_and_fallback() {
+        let rules = vec![
+            rule(
+                "claude-sonnet-latest",
+                Some("claude-sonnet-4-20250514"),
+                Some("antigravity"),
+            ),
+            rule("gpt-4.1*", None, Some("codex")),
+            rule("*", Some("gpt-5"), None),
+        ];
+
+        let resolved = resolve(Some("claude-sonnet-latest"), &rules);
+        assert_eq!(resolved.model.as_deref(), Some("claude-sonnet-4-20250514"));
+        assert_eq!(resolved.platform.as_deref(), Some("antigravity"));
+
+        let resolved = resolve(Some("gpt-4.1-mini"), &rules);
+        assert_eq!(resolved.model.as_deref(), Some("gpt-4.1-mini"));
+        assert_eq!(resolved.platform.as_deref(), Some("codex"));
+
+        let resolved = resolve(Some("unknown"), &rules);
+        assert_eq!(resolved.model.as_deref(), Some("gpt-5"));
+        assert_eq!(resolved.platform, None);
+
+        assert_eq!(resolve(Some("o3"), &[]).model.as_deref(), Some("o3"));
+    }
+}
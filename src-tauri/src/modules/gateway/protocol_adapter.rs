@@ -1,3 +1,7 @@
+use super::anthropic_adapter;
+use super::chat_adapter;
+use super::stream::{ByteStream, TranslatedStream};
+use bytes::Bytes;
 use serde_json::Value;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -48,13 +52,26 @@ pub fn resolve_upstream_for_platform(
     default_upstream.to_string()
 }
 
+/// 上游响应需要如何转换回客户端协议
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseAdapter {
+    Passthrough,
+    Anthropic,
+    ChatCompletions { include_usage: bool },
+}
+
+/// 生成上游地址与请求体；`responses_only` 表示上游只支持 /v1/responses，
+/// 此时 Chat Completions 请求会转换为 Responses 协议
 pub fn rewrite_request_for_upstream(
     path: &str,
     body: &[u8],
     upstream_base_url: &str,
-) -> Result<(String, Vec<u8>), String> {
+    responses_only: bool,
+) -> Result<(String, Vec<u8>, ResponseAdapter), String> {
     let protocol = detect_protocol(path);
+    let bridge_chat = protocol == ApiProtocol::OpenAIChat && responses_only;
     let upstream_path = match protocol {
+        ApiProtocol::OpenAIChat if bridge_chat => "/v1/responses".to_string(),
         ApiProtocol::OpenAIChat => "/v1/chat/completions".to_string(),
         ApiProtocol::CodexResponses => {
             if path.contains("/compact") {
@@ -69,72 +86,50 @@ pub fn rewrite_request_for_upstream(
 
     let upstream_url = format!("{}{}", upstream_base_url.trim_end_matches('/'), upstream_path);
 
-    let rewritten_body = if protocol == ApiProtocol::AnthropicMessages {
-        convert_anthropic_to_codex(body)?
+    let (rewritten_body, adapter) = if protocol == ApiProtocol::AnthropicMessages {
+        (anthropic_adapter::convert_request(body)?, ResponseAdapter::Anthropic)
+    } else if bridge_chat {
+        (
+            chat_adapter::convert_request(body)?,
+            ResponseAdapter::ChatCompletions {
+                include_usage: chat_adapter::wants_stream_usage(body),
+            },
+        )
     } else {
-        body.to_vec()
+        (body.to_vec(), ResponseAdapter::Passthrough)
     };
 
-    Ok((upstream_url, rewritten_body))
+    Ok((upstream_url, rewritten_body, adapter))
 }
 
-fn convert_anthropic_to_codex(body: &[u8]) -> Result<Vec<u8>, String> {
-    let mut json: Value =
-        serde_json::from_slice(body).map_err(|e| format!("解析请求体失败: {}", e))?;
-
-    if let Some(messages) = json.get("messages").cloned() {
-        let input = convert_anthropic_messages_to_codex_input(&messages);
-        json["input"] = input;
-        json.as_object_mut().map(|o| o.remove("messages"));
-    }
-
-    if let Some(max_tokens) = json.get("max_tokens").cloned() {
-        json["max_output_tokens"] = max_tokens;
-        json.as_object_mut().map(|o| o.remove("max_tokens"));
+/// 将上游（Responses 格式）的非流式响应体转换为客户端协议
+pub fn adapt_response_body(adapter: &ResponseAdapter, status: u16, body: Bytes) -> Bytes {
+    match adapter {
+        ResponseAdapter::Anthropic if status < 400 => anthropic_adapter::convert_response(&body)
+            .map(Bytes::from)
+            .unwrap_or(body),
+        ResponseAdapter::Anthropic => Bytes::from(anthropic_adapter::convert_error(status, &body)),
+        // Responses 的错误格式与 Chat Completions 相同，无需转换
+        ResponseAdapter::ChatCompletions { .. } if status < 400 => {
+            chat_adapter::convert_response(&body)
+                .map(Bytes::from)
+                .unwrap_or(body)
+        }
+        _ => body,
     }
-
-    serde_json::to_vec(&json).map_err(|e| format!("序列化请求体失败: {}", e))
 }
 
-fn convert_anthropic_messages_to_codex_input(messages: &Value) -> Value {
-    if let Some(arr) = messages.as_array() {
-        let converted: Vec<Value> = arr
-            .iter()
-            .map(|msg| {
-                let role = msg
-                    .get("role")
-                    .and_then(|r| r.as_str())
-                    .unwrap_or("user");
-                let content = msg
-                    .get("content")
-                    .cloned()
-                    .unwrap_or(Value::String(String::new()));
-
-                serde_json::json!({
-                    "role": role,
-                    "content": content,
-                })
-            })
-            .collect();
-        Value::Array(converted)
-    } else {
-        messages.clone()
+/// 将上游（Responses 格式）的 SSE 流转换为客户端协议
+pub fn adapt_response_stream(adapter: &ResponseAdapter, stream: ByteStream) -> ByteStream {
+    match adapter {
+        ResponseAdapter::Anthropic => Box::pin(TranslatedStream::new(
+            stream,
+            anthropic_adapter::ResponsesToAnthropicStream::new(),
+        )),
+        ResponseAdapter::ChatCompletions { include_usage } => Box::pin(TranslatedStream::new(
+            stream,
+            chat_adapter::ResponsesToChatStream::new(*include_usage),
+        )),
+        ResponseAdapter::Passthrough => stream,
     }
 }
-
-pub fn build_models_response() -> Value {
-    serde_json::json!({
-        "object": "list",
-        "data": [
-            {"id": "codex-mini-latest", "object": "model", "owned_by": "openai"},
-            {"id": "o4-mini", "object": "model", "owned_by": "openai"},
-            {"id": "o3", "object": "model", "owned_by": "openai"},
-            {"id": "o3-pro", "object": "model", "owned_by": "openai"},
-            {"id": "gpt-4.1", "object": "model", "owned_by": "openai"},
-            {"id": "gpt-4.1-mini", "object": "model", "owned_by": "openai"},
-            {"id": "gpt-4.1-nano", "object": "model", "owned_by": "openai"},
-            {"id": "claude-sonnet-4-20250514", "object": "model", "owned_by": "anthropic"},
-            {"id": "claude-4-opus-20250514", "object": "model", "owned_by": "anthropic"},
-        ]
-    })
-}
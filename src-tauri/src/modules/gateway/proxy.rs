@@ -1,21 +1,104 @@
-use super::{account_pool, api_key, config, protocol_adapter, request_log, types};
+use super::account_pool::SelectError;
+use super::concurrency::{self, AccountPermit};
+use super::stream::{ByteStream, RelayStream, StreamOutcome};
+use super::{
+    account_pool, api_key, capture, config, model_mapping, protocol_adapter, request_log,
+    token_refresh, types,
+};
+use crate::modules::metrics;
 use bytes::Bytes;
+use futures::StreamExt;
 use reqwest::Client;
-use std::sync::OnceLock;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+static PROXY_CLIENTS: Mutex<Option<HashMap<String, Client>>> = Mutex::new(None);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+// 流式响应按空闲时间判定超时，非流式请求仍限制整体耗时
+const READ_TIMEOUT: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+/// 未绑定分组的 API Key 可通过该请求头选择账号分组
+const GROUP_HEADER: &str = "x-gateway-group";
 
 fn get_client() -> &'static Client {
     HTTP_CLIENT.get_or_init(|| {
         Client::builder()
-            .timeout(std::time::Duration::from_secs(300))
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
             .pool_max_idle_per_host(20)
             .build()
             .expect("创建 HTTP 客户端失败")
     })
 }
 
+/// 按代理地址复用客户端；账号可配置独立代理，避免每次请求都重建连接池
+fn get_proxy_client(proxy_url: &str) -> Result<Client, String> {
+    let mut guard = PROXY_CLIENTS.lock().map_err(|e| e.to_string())?;
+    let clients = guard.get_or_insert_with(HashMap::new);
+    if let Some(client) = clients.get(proxy_url) {
+        return Ok(client.clone());
+    }
+
+    let proxy = match reqwest::Proxy::all(proxy_url) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            tracing::warn!("[Gateway] 代理地址无效 {}，改为直连: {}", proxy_url, e);
+            return Ok(get_client().clone());
+        }
+    };
+    let client = Client::builder()
+        .proxy(proxy)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .pool_max_idle_per_host(20)
+        .build()
+        .map_err(|e| format!("创建代理客户端失败: {}", e))?;
+    clients.insert(proxy_url.to_string(), client.clone());
+    Ok(client)
+}
+
+/// 按代理选择上游客户端，未配置代理时使用共享的直连客户端
+pub fn client_for_proxy(proxy_url: Option<&str>) -> Result<Client, String> {
+    match proxy_url.filter(|url| !url.is_empty()) {
+        Some(proxy_url) => get_proxy_client(proxy_url),
+        None => Ok(get_client().clone()),
+    }
+}
+
+/// 单次上游尝试失败的结果，重试耗尽或无账号可切换时原样返回给客户端
+enum AttemptFailure {
+    Upstream {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Bytes,
+        adapter: protocol_adapter::ResponseAdapter,
+    },
+    Transport(String),
+}
+
+impl AttemptFailure {
+    fn into_result(self) -> Result<ProxyResponse, String> {
+        match self {
+            AttemptFailure::Upstream {
+                status,
+                headers,
+                body,
+                adapter,
+            } => Ok(ProxyResponse {
+                status,
+                headers,
+                body: ProxyBody::Buffered(protocol_adapter::adapt_response_body(
+                    &adapter, status, body,
+                )),
+            }),
+            AttemptFailure::Transport(message) => Err(message),
+        }
+    }
+}
+
 pub async fn handle_proxy_request(
     method: &str,
     path: &str,
@@ -27,144 +110,515 @@ pub async fn handle_proxy_request(
     let trace_id = uuid::Uuid::new_v4().to_string();
     let gw_config = config::get_gateway_config();
 
-    let model = protocol_adapter::extract_model_from_body(&body);
-    if let (Some(ref key_info), Some(ref model_name)) = (api_key_info, &model) {
-        if !api_key::is_model_allowed(key_info, model_name) {
-            return Err(format!("API Key 不允许使用模型: {}", model_name));
+    let requested_model = protocol_adapter::extract_model_from_body(&body);
+    let resolved = model_mapping::resolve(requested_model.as_deref(), &gw_config.model_mappings);
+    let model = resolved.model;
+
+    // 请求的模型与映射后的实际模型都必须在 API Key 允许范围内，避免借映射绕过限制
+    if let Some(key_info) = api_key_info {
+        for model_name in [requested_model.as_deref(), model.as_deref()]
+            .into_iter()
+            .flatten()
+        {
+            if !api_key::is_model_allowed(key_info, model_name) {
+                return Err(format!("API Key 不允许使用模型: {}", model_name));
+            }
         }
     }
-
-    let account =
-        account_pool::select_account(&gw_config.route_strategy)?;
-
-    let (upstream_url, rewritten_body) =
-        protocol_adapter::rewrite_request_for_upstream(path, &body, &gw_config.upstream_base_url)?;
+    let body = match model.as_deref() {
+        Some(target) if requested_model.as_deref() != Some(target) => {
+            tracing::debug!(
+                "[Gateway] 模型映射: {} -> {}",
+                requested_model.as_deref().unwrap_or("(未指定)"),
+                target
+            );
+            model_mapping::rewrite_model_in_body(body, target)
+        }
+        _ => body,
+    };
 
     let is_stream = protocol_adapter::is_streaming_request(&body);
-    let client = get_client();
+    let retry_policy = &gw_config.retry_policy;
+    let max_attempts = retry_policy.max_attempts.max(1);
+    let api_key_prefix = api_key_info.map(|k| k.key_prefix.clone());
+    let api_key_id = api_key_info.map(|k| k.id.clone());
+    let mut capture =
+        capture::CaptureContext::new(&gw_config, &trace_id, method, path, headers, &body);
 
-    let mut req_builder = match method.to_uppercase().as_str() {
-        "POST" => client.post(&upstream_url),
-        "GET" => client.get(&upstream_url),
-        "PUT" => client.put(&upstream_url),
-        "DELETE" => client.delete(&upstream_url),
-        "PATCH" => client.patch(&upstream_url),
-        _ => client.post(&upstream_url),
+    // API Key 绑定的分组优先，避免客户端通过请求头切换到其他分组
+    let key_group = api_key_info
+        .and_then(|k| k.group_name.as_deref())
+        .filter(|g| !g.is_empty());
+    let header_group = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(GROUP_HEADER))
+        .map(|(_, v)| v.trim())
+        .filter(|g| !g.is_empty());
+    let find_group = |name: &str| gw_config.account_groups.iter().find(|g| g.name == name);
+    let group = match (key_group, header_group) {
+        // 分组被删除后 Key 仍指向它，属于 Key 配置问题，不应回退到全部账号
+        (Some(name), _) => match find_group(name) {
+            Some(group) => Some(group),
+            None => {
+                return Ok(gateway_error_response(
+                    403,
+                    &format!("API Key 绑定的账号分组已不存在: {}", name),
+                ))
+            }
+        },
+        (None, Some(name)) => match find_group(name) {
+            Some(group) => Some(group),
+            None => {
+                return Ok(gateway_error_response(
+                    400,
+                    &format!("{} 指定的账号分组不存在: {}", GROUP_HEADER, name),
+                ))
+            }
+        },
+        (None, None) => None,
     };
 
-    for (key, value) in headers {
-        let lower_key = key.to_lowercase();
-        if lower_key == "host" || lower_key == "content-length" || lower_key == "authorization" {
-            continue;
-        }
-        req_builder = req_builder.header(key.as_str(), value.as_str());
-    }
+    let protocol = protocol_adapter::detect_protocol(path);
+    let filter = account_pool::AccountFilter {
+        model: model.as_deref(),
+        platform: resolved.platform.as_deref(),
+        protocol: &protocol,
+        group,
+    };
+    let mut tried_accounts: Vec<String> = Vec::new();
+    let mut last_failure: Option<AttemptFailure> = None;
+    // 最后一次失败尝试的日志，last_failure 直接返回给客户端时据此计入指标
+    let mut last_attempt_log: Option<types::RequestLogEntry> = None;
+    let mut attempt: u32 = 0;
 
-    req_builder = req_builder
-        .header("Authorization", format!("Bearer {}", account.access_token))
-        .header("Content-Type", "application/json");
+    loop {
+        attempt += 1;
+        let attempt_start = Instant::now();
 
-    if let Some(ref proxy_url) = gw_config.upstream_proxy_url {
-        if !proxy_url.is_empty() {
-            if let Ok(proxy) = reqwest::Proxy::all(proxy_url) {
-                let proxy_client = Client::builder()
-                    .proxy(proxy)
-                    .timeout(std::time::Duration::from_secs(300))
-                    .build()
-                    .map_err(|e| format!("创建代理客户端失败: {}", e))?;
-
-                req_builder = match method.to_uppercase().as_str() {
-                    "POST" => proxy_client.post(&upstream_url),
-                    "GET" => proxy_client.get(&upstream_url),
-                    _ => proxy_client.post(&upstream_url),
+        let selected = acquire_account(&gw_config, &filter, &tried_accounts).await;
+        let (account, permit) = match selected {
+            Ok(selected) => selected,
+            Err(SelectError::Saturated) => {
+                if let Some(failure) = last_failure.take() {
+                    if let Some(entry) = last_attempt_log.as_ref() {
+                        request_log::record_metrics(entry);
+                    }
+                    return failure.into_result();
+                }
+                let log_entry = request_log::create_log_entry(
+                    trace_id,
+                    method.to_string(),
+                    path.to_string(),
+                    429,
+                    start.elapsed().as_millis() as i64,
+                    None,
+                    model,
+                    Some("所有账号并发已满，排队超时".to_string()),
+                    api_key_prefix,
+                );
+                request_log::log_request(&log_entry).ok();
+                return Ok(saturated_response());
+            }
+            Err(SelectError::Unavailable(e)) => {
+                if let Some(entry) = last_attempt_log.as_ref() {
+                    request_log::record_metrics(entry);
+                }
+                return match last_failure.take() {
+                    Some(failure) => failure.into_result(),
+                    None => Err(e),
                 };
+            }
+        };
+        tried_accounts.push(account.id.clone());
+        let can_retry = attempt < max_attempts;
 
-                for (key, value) in headers {
-                    let lower_key = key.to_lowercase();
-                    if lower_key == "host"
-                        || lower_key == "content-length"
-                        || lower_key == "authorization"
-                    {
-                        continue;
-                    }
-                    req_builder = req_builder.header(key.as_str(), value.as_str());
+        // Token 即将过期时先刷新，同一账号的并发请求共用一次刷新
+        let account_email = account.email.clone();
+        let account = match token_refresh::ensure_fresh_token(account).await {
+            Ok(account) => account,
+            Err(e) => {
+                let message = format!("账号 Token 刷新失败: {}", e);
+                let log_entry = request_log::create_log_entry(
+                    trace_id.clone(),
+                    method.to_string(),
+                    path.to_string(),
+                    502,
+                    attempt_start.elapsed().as_millis() as i64,
+                    Some(account_email),
+                    model.clone(),
+                    Some(format!("第 {} 次尝试: {}", attempt, message)),
+                    api_key_prefix.clone(),
+                );
+                if !can_retry {
+                    request_log::log_request(&log_entry).ok();
+                    // 之前的尝试已拿到上游响应时，返回该响应比刷新错误更有用
+                    return match last_failure.take() {
+                        Some(failure) => failure.into_result(),
+                        None => Err(message),
+                    };
                 }
+                request_log::log_attempt(&log_entry).ok();
+                last_attempt_log = Some(log_entry);
+                tracing::warn!("[Gateway] {}，切换账号重试", message);
+                last_failure = Some(AttemptFailure::Transport(message));
+                drop(permit);
+                continue;
+            }
+        };
+
+        // 不同平台的账号可能走不同的上游，协议转换方式随上游而定
+        let upstream_base_url = protocol_adapter::resolve_upstream_for_platform(
+            account.platform.as_deref(),
+            &gw_config.platform_upstreams,
+            &gw_config.upstream_base_url,
+        );
+        let (upstream_url, rewritten_body, adapter) = protocol_adapter::rewrite_request_for_upstream(
+            path,
+            &body,
+            &upstream_base_url,
+            gw_config.is_responses_only(&upstream_base_url),
+        )?;
+        if let Some(capture) = capture.as_mut() {
+            capture.set_attempt(&account.id, &upstream_url, &rewritten_body);
+        }
+        // 账号单独配置的代理优先于全局代理
+        let upstream_proxy_url = account
+            .proxy_url
+            .as_deref()
+            .filter(|url| !url.is_empty())
+            .or(gw_config.upstream_proxy_url.as_deref());
+
+        let response = match send_upstream(
+            method,
+            &upstream_url,
+            headers,
+            &account,
+            Bytes::from(rewritten_body),
+            is_stream,
+            upstream_proxy_url,
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                account_pool::report_account_error(&account.id).ok();
+                let message = format!("上游请求失败: {}", e);
+                if let Some(capture) = capture.as_ref() {
+                    capture.save(502, &[], message.as_bytes());
+                }
+                let log_entry = request_log::create_log_entry(
+                    trace_id.clone(),
+                    method.to_string(),
+                    path.to_string(),
+                    502,
+                    attempt_start.elapsed().as_millis() as i64,
+                    Some(account.email.clone()),
+                    model.clone(),
+                    Some(format!("第 {} 次尝试: {}", attempt, message)),
+                    api_key_prefix.clone(),
+                );
+
+                if !can_retry {
+                    request_log::log_request(&log_entry).ok();
+                    return Err(message);
+                }
+                request_log::log_attempt(&log_entry).ok();
+                last_attempt_log = Some(log_entry);
+                tracing::warn!(
+                    "[Gateway] 账号 {} 请求失败，切换账号重试 ({}/{}): {}",
+                    account.email,
+                    attempt,
+                    max_attempts,
+                    e
+                );
+                last_failure = Some(AttemptFailure::Transport(message));
+                drop(permit);
+                tokio::time::sleep(retry_policy.backoff_delay(attempt)).await;
+                continue;
+            }
+        };
+
+        let status = response.status().as_u16();
+
+        let resp_headers: Vec<(String, String)> = response
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+            .collect();
+
+        if status >= 429 {
+            account_pool::cooldown_account(&account.id, gw_config.cooldown_seconds).ok();
+        } else if status >= 400 {
+            account_pool::report_account_error(&account.id).ok();
+        } else {
+            account_pool::reset_account_errors(&account.id).ok();
+        }
 
-                req_builder = req_builder
-                    .header("Authorization", format!("Bearer {}", account.access_token))
-                    .header("Content-Type", "application/json");
+        if can_retry && retry_policy.is_retryable(status) {
+            let failed_body = response.bytes().await.unwrap_or_default();
+            if let Some(capture) = capture.as_ref() {
+                capture.save(status, &resp_headers, &failed_body);
             }
+            let log_entry = request_log::create_log_entry(
+                trace_id.clone(),
+                method.to_string(),
+                path.to_string(),
+                status,
+                attempt_start.elapsed().as_millis() as i64,
+                Some(account.email.clone()),
+                model.clone(),
+                Some(format!(
+                    "第 {} 次尝试: {}",
+                    attempt,
+                    String::from_utf8_lossy(&failed_body[..failed_body.len().min(500)])
+                )),
+                api_key_prefix.clone(),
+            );
+            request_log::log_attempt(&log_entry).ok();
+            last_attempt_log = Some(log_entry);
+
+            tracing::warn!(
+                "[Gateway] 账号 {} 返回 {}，切换账号重试 ({}/{})",
+                account.email,
+                status,
+                attempt,
+                max_attempts
+            );
+            last_failure = Some(AttemptFailure::Upstream {
+                status,
+                headers: resp_headers,
+                body: failed_body,
+                adapter,
+            });
+            drop(permit);
+            tokio::time::sleep(retry_policy.backoff_delay(attempt)).await;
+            continue;
         }
+
+        let method = method.to_string();
+        let path = path.to_string();
+        let account_email = Some(account.email.clone());
+
+        if is_stream && status < 400 {
+            let upstream = response.bytes_stream().boxed();
+            let transcript_limit = capture.as_ref().map(|c| c.max_body_bytes());
+            let capture_headers = resp_headers.clone();
+            let relay = RelayStream::new(upstream, move |outcome: StreamOutcome| {
+                // 并发名额一直占用到流结束
+                drop(permit);
+                let usage = request_log::usage_from_stream_events(&outcome.usage_events);
+                if let (Some(capture), Some(transcript)) = (capture, outcome.transcript.as_ref()) {
+                    capture.save(status, &capture_headers, transcript);
+                }
+                let first_token = outcome.first_chunk_at.map(|at| at.duration_since(start));
+                if let Some(first_token) = first_token {
+                    let model_label = model.clone().unwrap_or_else(|| "unknown".to_string());
+                    metrics::gateway().observe_ttft(vec![("model", model_label)], first_token);
+                }
+                if let Some(ref err) = outcome.error {
+                    tracing::warn!(
+                        "[Gateway] 流式响应未正常结束 ({} 字节已转发): {}",
+                        outcome.bytes_sent,
+                        err
+                    );
+                }
+                let log_entry = request_log::create_log_entry(
+                    trace_id,
+                    method,
+                    path,
+                    status,
+                    start.elapsed().as_millis() as i64,
+                    account_email,
+                    model,
+                    outcome.error,
+                    api_key_prefix,
+                );
+                request_log::log_request_with_usage(
+                    log_entry,
+                    usage,
+                    api_key_id.as_deref(),
+                    true,
+                    first_token.map(|d| d.as_millis() as u64),
+                )
+                .ok();
+            });
+            let relay = match transcript_limit {
+                Some(limit) => relay.with_transcript(limit),
+                None => relay,
+            };
+
+            return Ok(ProxyResponse {
+                status,
+                headers: resp_headers,
+                body: ProxyBody::Stream(protocol_adapter::adapt_response_stream(
+                    &adapter,
+                    Box::pin(relay),
+                )),
+            });
+        }
+
+        let resp_body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("读取响应失败: {}", e))?;
+        if let Some(capture) = capture.as_ref() {
+            capture.save(status, &resp_headers, &resp_body);
+        }
+
+        let log_entry = request_log::create_log_entry(
+            trace_id,
+            method,
+            path,
+            status,
+            start.elapsed().as_millis() as i64,
+            account_email,
+            model,
+            if status >= 400 {
+                Some(String::from_utf8_lossy(&resp_body[..resp_body.len().min(500)]).to_string())
+            } else {
+                None
+            },
+            api_key_prefix,
+        );
+        let usage = if status < 400 {
+            request_log::usage_from_response_body(&resp_body)
+        } else {
+            None
+        };
+        request_log::log_request_with_usage(log_entry, usage, api_key_id.as_deref(), false, None)
+            .ok();
+        drop(permit);
+
+        return Ok(ProxyResponse {
+            status,
+            headers: resp_headers,
+            body: ProxyBody::Buffered(protocol_adapter::adapt_response_body(
+                &adapter, status, resp_body,
+            )),
+        });
     }
+}
 
-    if method.to_uppercase() != "GET" {
-        req_builder = req_builder.body(rewritten_body);
+/// 选择账号并占用并发名额；所有候选账号都已满时排队等待，超过 queue_timeout_seconds 返回 Saturated
+async fn acquire_account(
+    gw_config: &types::GatewayConfig,
+    filter: &account_pool::AccountFilter<'_>,
+    exclude_ids: &[String],
+) -> Result<(types::GatewayAccount, AccountPermit), SelectError> {
+    let deadline = Instant::now() + Duration::from_secs(gw_config.queue_timeout_seconds as u64);
+
+    loop {
+        let signal = concurrency::slot_released_signal();
+        match account_pool::select_account(
+            &gw_config.route_strategy,
+            filter,
+            exclude_ids,
+            gw_config.max_concurrent_per_account,
+        ) {
+            Err(SelectError::Saturated) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() || !concurrency::wait_for_slot(signal, remaining).await {
+                    tracing::warn!(
+                        "[Gateway] 所有账号并发已满 (上限 {})，排队超时",
+                        gw_config.max_concurrent_per_account
+                    );
+                    return Err(SelectError::Saturated);
+                }
+            }
+            result => return result,
+        }
     }
+}
 
-    let response = req_builder
-        .send()
-        .await
-        .map_err(|e| {
-            account_pool::report_account_error(&account.id).ok();
-            format!("上游请求失败: {}", e)
-        })?;
+/// 网关自身拒绝请求时返回的 JSON 错误，格式与上游错误一致
+fn gateway_error_response(status: u16, message: &str) -> ProxyResponse {
+    let body = serde_json::json!({
+        "error": {
+            "message": message,
+            "type": "gateway_error",
+            "code": status
+        }
+    });
 
-    let status = response.status().as_u16();
-    let duration_ms = start.elapsed().as_millis() as i64;
+    ProxyResponse {
+        status,
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body: ProxyBody::Buffered(Bytes::from(serde_json::to_vec(&body).unwrap_or_default())),
+    }
+}
 
-    let resp_headers: Vec<(String, String)> = response
-        .headers()
-        .iter()
-        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-        .collect();
-
-    if status >= 429 {
-        account_pool::cooldown_account(&account.id, gw_config.cooldown_seconds).ok();
-    } else if status >= 400 {
-        account_pool::report_account_error(&account.id).ok();
-    } else {
-        account_pool::reset_account_errors(&account.id).ok();
+fn saturated_response() -> ProxyResponse {
+    let body = serde_json::json!({
+        "error": {
+            "message": "所有账号并发请求已满，请稍后重试",
+            "type": "gateway_error",
+            "code": 429
+        }
+    });
+
+    ProxyResponse {
+        status: 429,
+        headers: vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Retry-After".to_string(), "1".to_string()),
+        ],
+        body: ProxyBody::Buffered(Bytes::from(serde_json::to_vec(&body).unwrap_or_default())),
     }
+}
+
+pub async fn send_upstream(
+    method: &str,
+    upstream_url: &str,
+    headers: &[(String, String)],
+    account: &types::GatewayAccount,
+    body: Bytes,
+    is_stream: bool,
+    upstream_proxy_url: Option<&str>,
+) -> Result<reqwest::Response, String> {
+    let client = client_for_proxy(upstream_proxy_url)?;
 
-    if let Some(ref key_info) = api_key_info {
-        api_key::increment_usage(&key_info.key_hash).ok();
+    let mut req_builder = match method.to_uppercase().as_str() {
+        "POST" => client.post(upstream_url),
+        "GET" => client.get(upstream_url),
+        "PUT" => client.put(upstream_url),
+        "DELETE" => client.delete(upstream_url),
+        "PATCH" => client.patch(upstream_url),
+        _ => client.post(upstream_url),
+    };
+
+    for (key, value) in headers {
+        let lower_key = key.to_lowercase();
+        if lower_key == "host"
+            || lower_key == "content-length"
+            || lower_key == "authorization"
+            || lower_key == GROUP_HEADER
+        {
+            continue;
+        }
+        req_builder = req_builder.header(key.as_str(), value.as_str());
     }
 
-    let resp_body = response
-        .bytes()
-        .await
-        .map_err(|e| format!("读取响应失败: {}", e))?;
+    req_builder = req_builder
+        .header("Authorization", format!("Bearer {}", account.access_token))
+        .header("Content-Type", "application/json");
 
-    let log_entry = request_log::create_log_entry(
-        trace_id,
-        method.to_string(),
-        path.to_string(),
-        status,
-        duration_ms,
-        Some(account.email.clone()),
-        model,
-        None,
-        None,
-        if status >= 400 {
-            Some(String::from_utf8_lossy(&resp_body[..resp_body.len().min(500)]).to_string())
-        } else {
-            None
-        },
-        api_key_info.map(|k| k.key_prefix.clone()),
-    );
-    request_log::log_request(&log_entry).ok();
+    if method.to_uppercase() != "GET" {
+        req_builder = req_builder.body(body);
+    }
+    if !is_stream {
+        req_builder = req_builder.timeout(REQUEST_TIMEOUT);
+    }
 
-    Ok(ProxyResponse {
-        status,
-        headers: resp_headers,
-        body: resp_body,
-        is_stream,
-    })
+    req_builder.send().await.map_err(|e| e.to_string())
+}
+
+pub enum ProxyBody {
+    Buffered(Bytes),
+    Stream(ByteStream),
 }
 
 pub struct ProxyResponse {
     pub status: u16,
     pub headers: Vec<(String, String)>,
-    pub body: Bytes,
-    pub is_stream: bool,
+    pub body: ProxyBody,
 }
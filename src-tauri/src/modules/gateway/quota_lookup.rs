@@ -0,0 +1,152 @@
+use super::account_pool_bridge::Platform;
+use super::types::GatewayAccount;
+use crate::models::codex::CodexAccount;
+use crate::models::Account;
+use crate::modules::{account, codex_account, quota_cache};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 配额信息读取自账号文件，短时间内缓存以免每次路由都扫描磁盘
+const QUOTA_CACHE_TTL_SECONDS: i64 = 30;
+
+static QUOTA_CACHE: Mutex<Option<HashMap<String, CachedQuota>>> = Mutex::new(None);
+
+#[derive(Clone)]
+struct CachedQuota {
+    fetched_at: i64,
+    remaining: Option<i32>,
+}
+
+/// 网关账号对应的平台账号
+enum PlatformRef {
+    Codex(CodexAccount),
+    Antigravity(Account),
+}
+
+/// 查询网关账号剩余配额百分比 (0-100)，无法确定时返回 None
+pub fn remaining_percentage(account: &GatewayAccount, model: Option<&str>) -> Option<i32> {
+    let cache_key = format!("{}:{}", account.id, model.unwrap_or(""));
+    let now = chrono::Utc::now().timestamp();
+
+    if let Ok(guard) = QUOTA_CACHE.lock() {
+        if let Some(cached) = guard.as_ref().and_then(|cache| cache.get(&cache_key)) {
+            if now - cached.fetched_at < QUOTA_CACHE_TTL_SECONDS {
+                return cached.remaining;
+            }
+        }
+    }
+
+    let remaining = resolve_platform_account(account).and_then(|platform_account| {
+        match platform_account {
+            PlatformRef::Codex(codex) => codex_remaining(&codex, now),
+            PlatformRef::Antigravity(antigravity) => antigravity_remaining(&antigravity, model),
+        }
+    });
+
+    if let Ok(mut guard) = QUOTA_CACHE.lock() {
+        guard.get_or_insert_with(HashMap::new).insert(
+            cache_key,
+            CachedQuota {
+                fetched_at: now,
+                remaining,
+            },
+        );
+    }
+
+    remaining
+}
+
+fn resolve_platform_account(account: &GatewayAccount) -> Option<PlatformRef> {
+    let platform = account.platform.as_deref();
+
+    if let Some(platform_name) = platform {
+        let platform_id = account
+            .id
+            .strip_prefix(&format!("bridge_{}_", platform_name));
+
+        if platform_name == Platform::Codex.to_string() {
+            return platform_id
+                .and_then(codex_account::load_account)
+                .or_else(|| find_codex_by_email(&account.email))
+                .map(PlatformRef::Codex);
+        }
+        if platform_name == Platform::Antigravity.to_string() {
+            return platform_id
+                .and_then(|id| account::load_account(id).ok())
+                .map(|mut loaded| {
+                    let _ = quota_cache::apply_cached_quota(&mut loaded, "authorized");
+                    loaded
+                })
+                .or_else(|| find_antigravity_by_email(&account.email))
+                .map(PlatformRef::Antigravity);
+        }
+        return None;
+    }
+
+    // 手动导入的账号没有平台信息，按邮箱匹配已有的平台账号
+    find_codex_by_email(&account.email)
+        .map(PlatformRef::Codex)
+        .or_else(|| find_antigravity_by_email(&account.email).map(PlatformRef::Antigravity))
+}
+
+fn find_codex_by_email(email: &str) -> Option<CodexAccount> {
+    codex_account::list_accounts()
+        .into_iter()
+        .find(|a| a.email.eq_ignore_ascii_case(email))
+}
+
+fn find_antigravity_by_email(email: &str) -> Option<Account> {
+    account::list_accounts()
+        .ok()?
+        .into_iter()
+        .find(|a| a.email.eq_ignore_ascii_case(email))
+}
+
+/// Codex 取 5 小时窗口和周窗口中较小的剩余值，已过重置时间的窗口视为满额
+fn codex_remaining(codex: &CodexAccount, now: i64) -> Option<i32> {
+    let quota = codex.quota.as_ref()?;
+    let has_presence =
+        quota.hourly_window_present.is_some() || quota.weekly_window_present.is_some();
+
+    let window = |percentage: i32, reset_time: Option<i64>| {
+        if reset_time.map(|t| t <= now).unwrap_or(false) {
+            100
+        } else {
+            percentage.clamp(0, 100)
+        }
+    };
+
+    let mut windows = Vec::new();
+    if !has_presence || quota.hourly_window_present.unwrap_or(false) {
+        windows.push(window(quota.hourly_percentage, quota.hourly_reset_time));
+    }
+    if !has_presence || quota.weekly_window_present.unwrap_or(false) {
+        windows.push(window(quota.weekly_percentage, quota.weekly_reset_time));
+    }
+
+    windows.into_iter().min()
+}
+
+/// Antigravity 按请求模型匹配对应的模型配额，未匹配时取各模型平均值
+fn antigravity_remaining(antigravity: &Account, model: Option<&str>) -> Option<i32> {
+    let quota = antigravity.quota.as_ref()?;
+    if quota.is_forbidden {
+        return Some(0);
+    }
+    if quota.models.is_empty() {
+        return None;
+    }
+
+    if let Some(model) = model.map(|m| m.to_lowercase()) {
+        let matched = quota.models.iter().find(|q| {
+            let name = q.name.to_lowercase();
+            name == model || model.starts_with(&name) || name.starts_with(&model)
+        });
+        if let Some(model_quota) = matched {
+            return Some(model_quota.percentage.clamp(0, 100));
+        }
+    }
+
+    let sum: i32 = quota.models.iter().map(|q| q.percentage.clamp(0, 100)).sum();
+    Some(sum / quota.models.len() as i32)
+}
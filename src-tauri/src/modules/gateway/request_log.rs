@@ -1,10 +1,130 @@
+use super::api_key;
 use super::db;
 use super::types::{RequestLogEntry, RequestLogQuery, RequestLogSummary};
+use crate::modules::metrics;
+use crate::modules::opencode_db::Database;
+use crate::modules::proxy::usage::{estimate_cost, log_usage, TokenUsage};
+use crate::modules::proxy::AppType;
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Manager;
 
+const GATEWAY_PROVIDER_ID: &str = "gateway";
+const GATEWAY_PROVIDER_NAME: &str = "API Gateway";
+
+/// 写入客户端请求的最终结果并计入 `/metrics`，每个客户端请求只调用一次
 pub fn log_request(entry: &RequestLogEntry) -> Result<(), String> {
+    record_metrics(entry);
     db::insert_request_log(entry)
 }
 
+/// 写入随后会换账号重试的单次尝试，不计入 `/metrics`
+pub fn log_attempt(entry: &RequestLogEntry) -> Result<(), String> {
+    db::insert_request_log(entry)
+}
+
+/// 按客户端请求计数；重试途中没有账号可换而直接返回上次失败时，用最后一次尝试的日志计数
+pub fn record_metrics(entry: &RequestLogEntry) {
+    let gateway_metrics = metrics::gateway();
+    let model = entry.model.clone().unwrap_or_else(|| "unknown".to_string());
+    gateway_metrics.inc_requests(vec![
+        ("status", entry.status_code.to_string()),
+        ("model", model.clone()),
+        ("account", entry.account_email.clone().unwrap_or_default()),
+        ("api_key", entry.api_key_prefix.clone().unwrap_or_default()),
+    ]);
+    gateway_metrics.observe_duration(
+        vec![("model", model.clone())],
+        Duration::from_millis(entry.duration_ms.max(0) as u64),
+    );
+    for (kind, tokens) in [
+        ("input", entry.input_tokens),
+        ("output", entry.output_tokens),
+        ("cache_read", entry.cache_read_tokens),
+        ("cache_creation", entry.cache_creation_tokens),
+    ] {
+        gateway_metrics.add_tokens(
+            vec![("model", model.clone()), ("type", kind.to_string())],
+            tokens.unwrap_or(0).max(0) as u64,
+        );
+    }
+}
+
+/// 写入请求日志；带有 usage 时同时按 model_pricing 计算费用，同步到代理使用统计，
+/// 并计入对应 API Key 的每日 token 与每月费用；流式响应额外记录首 token 耗时
+pub fn log_request_with_usage(
+    mut entry: RequestLogEntry,
+    usage: Option<TokenUsage>,
+    api_key_id: Option<&str>,
+    is_streaming: bool,
+    first_token_ms: Option<u64>,
+) -> Result<(), String> {
+    if let Some(usage) = usage {
+        entry.input_tokens = Some(usage.input_tokens as i64);
+        entry.output_tokens = Some(usage.output_tokens as i64);
+        entry.cache_read_tokens = Some(usage.cache_read_tokens as i64);
+        entry.cache_creation_tokens = Some(usage.cache_creation_tokens as i64);
+        let total_tokens = usage.input_tokens as i64 + usage.output_tokens as i64;
+        let mut cost_usd = 0.0;
+
+        if let Some(usage_db) = usage_db() {
+            let model = usage
+                .model
+                .clone()
+                .or_else(|| entry.model.clone())
+                .unwrap_or_else(|| "unknown".to_string());
+            match estimate_cost(&usage_db, &model, &usage) {
+                Ok(cost) => {
+                    cost_usd = cost.total_cost.to_f64().unwrap_or(0.0);
+                    entry.total_cost_usd = Some(cost.total_cost.to_string());
+                }
+                Err(e) => tracing::warn!("[Gateway] 计算请求费用失败: {}", e),
+            }
+            if let Err(e) = log_usage(
+                &usage_db,
+                GATEWAY_PROVIDER_ID,
+                Some(GATEWAY_PROVIDER_NAME),
+                None,
+                AppType::Gateway,
+                &model,
+                usage,
+                entry.duration_ms.max(0) as u64,
+                first_token_ms,
+                is_streaming,
+                entry.status_code,
+            ) {
+                tracing::warn!("[Gateway] 同步使用统计失败: {}", e);
+            }
+        }
+
+        if let Some(key_id) = api_key_id {
+            if let Err(e) = api_key::record_consumption(key_id, total_tokens, cost_usd) {
+                tracing::warn!("[Gateway] 记录 API Key 用量失败: {}", e);
+            }
+        }
+    }
+
+    log_request(&entry)
+}
+
+pub fn usage_from_response_body(body: &[u8]) -> Option<TokenUsage> {
+    let json: Value = serde_json::from_slice(body).ok()?;
+    TokenUsage::from_openai_response(&json).or_else(|| TokenUsage::from_codex_response(&json))
+}
+
+pub fn usage_from_stream_events(events: &[Value]) -> Option<TokenUsage> {
+    TokenUsage::from_claude_stream_events(events)
+        .or_else(|| TokenUsage::from_codex_stream_events(events))
+        .or_else(|| TokenUsage::from_openai_stream_events(events))
+}
+
+fn usage_db() -> Option<Arc<Database>> {
+    let app = crate::get_app_handle()?;
+    app.try_state::<Arc<Database>>().map(|db| db.inner().clone())
+}
+
 pub fn query_logs(query: &RequestLogQuery) -> Result<Vec<RequestLogEntry>, String> {
     db::query_request_logs(query)
 }
@@ -25,8 +145,6 @@ pub fn create_log_entry(
     duration_ms: i64,
     account_email: Option<String>,
     model: Option<String>,
-    input_tokens: Option<i64>,
-    output_tokens: Option<i64>,
     error_message: Option<String>,
     api_key_prefix: Option<String>,
 ) -> RequestLogEntry {
@@ -40,9 +158,12 @@ pub fn create_log_entry(
         duration_ms,
         account_email,
         model,
-        input_tokens,
-        output_tokens,
+        input_tokens: None,
+        output_tokens: None,
         error_message,
         api_key_prefix,
+        cache_read_tokens: None,
+        cache_creation_tokens: None,
+        total_cost_usd: None,
     }
 }
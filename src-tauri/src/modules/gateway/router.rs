@@ -1,12 +1,16 @@
-use super::{api_key, protocol_adapter, proxy};
+use super::types::AccountStatus;
+use super::{api_key, config, db, model_catalog, proxy, server};
+use crate::modules::metrics;
 use axum::{
     body::Body,
-    extract::Request,
-    http::{HeaderMap, Method, Response, StatusCode},
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    middleware::{self, Next},
     routing::{any, get},
     Router,
 };
 use bytes::Bytes;
+use std::net::SocketAddr;
 
 pub fn create_router() -> Router {
     Router::new()
@@ -16,23 +20,114 @@ pub fn create_router() -> Router {
         .route("/v1/messages", any(gateway_handler))
         .route("/v1/models", get(models_handler))
         .route("/health", get(health_handler))
+        .route("/metrics", get(metrics_handler))
         .fallback(any(gateway_handler))
+        .layer(middleware::from_fn(cors_middleware))
+}
+
+/// 每次请求读取 cors_enabled，修改配置后无需重启网关即可生效
+async fn cors_middleware(req: Request, next: Next) -> Response<Body> {
+    let cors_enabled = config::get_gateway_config().cors_enabled;
+    if cors_enabled && req.method() == Method::OPTIONS {
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Access-Control-Allow-Origin", "*")
+            .header(
+                "Access-Control-Allow-Methods",
+                "GET, POST, PUT, DELETE, OPTIONS",
+            )
+            .header(
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type, X-Request-Id, X-Gateway-Group",
+            )
+            .header("Access-Control-Max-Age", "86400")
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let mut response = next.run(req).await;
+    if cors_enabled {
+        response.headers_mut().insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+    } else {
+        response
+            .headers_mut()
+            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    }
+    response
 }
 
 async fn health_handler() -> &'static str {
     "ok"
 }
 
-async fn models_handler(headers: HeaderMap) -> Response<Body> {
-    if let Err(resp) = verify_auth(&headers) {
+/// Prometheus 抓取端点，与其他接口一样需要网关 API Key
+async fn metrics_handler(headers: HeaderMap) -> Response<Body> {
+    if let Err(resp) = verify_auth_and_get_key(&headers, "/metrics") {
         return resp;
     }
 
-    let models = protocol_adapter::build_models_response();
+    let accounts = db::list_accounts().unwrap_or_default();
+    let statuses = [
+        AccountStatus::Active,
+        AccountStatus::Inactive,
+        AccountStatus::Cooldown,
+        AccountStatus::Error,
+        AccountStatus::Expired,
+    ];
+    let gauges = [
+        metrics::Gauge {
+            name: "in_flight_requests",
+            help: "正在处理的请求数",
+            samples: vec![(Vec::new(), server::active_requests() as f64)],
+        },
+        metrics::Gauge {
+            name: "accounts",
+            help: "各状态的账号数",
+            samples: statuses
+                .iter()
+                .map(|status| {
+                    let count = accounts.iter().filter(|a| &a.status == status).count();
+                    (vec![("status", status.to_string())], count as f64)
+                })
+                .collect(),
+        },
+        metrics::Gauge {
+            name: "account_status",
+            help: "账号当前状态，取值恒为 1",
+            samples: accounts
+                .iter()
+                .map(|a| {
+                    let labels = vec![
+                        ("account", a.email.clone()),
+                        ("platform", a.platform.clone().unwrap_or_default()),
+                        ("status", a.status.to_string()),
+                    ];
+                    (labels, 1.0)
+                })
+                .collect(),
+        },
+    ];
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", metrics::CONTENT_TYPE)
+        .body(Body::from(metrics::gateway().render(&gauges)))
+        .unwrap()
+}
+
+async fn models_handler(headers: HeaderMap) -> Response<Body> {
+    let api_key_info = match verify_auth_and_get_key(&headers, "/v1/models") {
+        Ok(key) => key,
+        Err(resp) => return resp,
+    };
+
+    let models = model_catalog::build_models_response(api_key_info.as_ref()).await;
     Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
         .body(Body::from(serde_json::to_vec(&models).unwrap_or_default()))
         .unwrap_or_else(|_| {
             Response::builder()
@@ -43,26 +138,24 @@ async fn models_handler(headers: HeaderMap) -> Response<Body> {
 }
 
 async fn gateway_handler(req: Request) -> Response<Body> {
-    if req.method() == Method::OPTIONS {
-        return Response::builder()
-            .status(StatusCode::NO_CONTENT)
-            .header("Access-Control-Allow-Origin", "*")
-            .header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
-            .header("Access-Control-Allow-Headers", "Authorization, Content-Type, X-Request-Id")
-            .header("Access-Control-Max-Age", "86400")
-            .body(Body::empty())
-            .unwrap();
-    }
-
     let headers = req.headers().clone();
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
 
-    let api_key_info = match verify_auth_and_get_key(&headers) {
+    let api_key_info = match verify_auth_and_get_key(&headers, &path) {
         Ok(key) => key,
         Err(resp) => return resp,
     };
 
-    let method = req.method().to_string();
-    let path = req.uri().path().to_string();
+    if let Some(ref key) = api_key_info {
+        let client_ip = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        if let Err(limit_err) = api_key::acquire_request(key, client_ip.as_deref()) {
+            return key_limit_response(&limit_err);
+        }
+    }
 
     let header_pairs: Vec<(String, String)> = headers
         .iter()
@@ -99,11 +192,16 @@ async fn gateway_handler(req: Request) -> Response<Body> {
                 resp_builder = resp_builder.header(key.as_str(), value.as_str());
             }
 
-            resp_builder = resp_builder
-                .header("Access-Control-Allow-Origin", "*");
+            let body = match proxy_resp.body {
+                proxy::ProxyBody::Buffered(bytes) => Body::from(bytes),
+                proxy::ProxyBody::Stream(stream) => {
+                    resp_builder = resp_builder.header("X-Accel-Buffering", "no");
+                    Body::from_stream(stream)
+                }
+            };
 
             resp_builder
-                .body(Body::from(proxy_resp.body))
+                .body(body)
                 .unwrap_or_else(|_| {
                     Response::builder()
                         .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -123,26 +221,9 @@ fn extract_bearer_token(headers: &HeaderMap) -> Option<String> {
         .map(|s| s.to_string())
 }
 
-fn verify_auth(headers: &HeaderMap) -> Result<(), Response<Body>> {
-    let token = extract_bearer_token(headers).ok_or_else(|| {
-        json_error_response(StatusCode::UNAUTHORIZED, "缺少 Authorization 头")
-    })?;
-
-    match api_key::verify_api_key(&token) {
-        Ok(Some(_)) => Ok(()),
-        Ok(None) => Err(json_error_response(
-            StatusCode::UNAUTHORIZED,
-            "无效的 API Key",
-        )),
-        Err(e) => Err(json_error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            &format!("验证 API Key 失败: {}", e),
-        )),
-    }
-}
-
 fn verify_auth_and_get_key(
     headers: &HeaderMap,
+    path: &str,
 ) -> Result<Option<super::types::GatewayApiKey>, Response<Body>> {
     let token = match extract_bearer_token(headers) {
         Some(t) => t,
@@ -156,11 +237,17 @@ fn verify_auth_and_get_key(
 
     match api_key::verify_api_key(&token) {
         Ok(key) => {
-            if key.is_none() {
+            let Some(api_key) = &key else {
                 return Err(json_error_response(
                     StatusCode::UNAUTHORIZED,
                     "无效的 API Key",
                 ));
+            };
+            if !api_key::is_endpoint_allowed(api_key, path) {
+                return Err(json_error_response(
+                    StatusCode::FORBIDDEN,
+                    &format!("API Key 无权访问 {}", path),
+                ));
             }
             Ok(key)
         }
@@ -171,6 +258,35 @@ fn verify_auth_and_get_key(
     }
 }
 
+fn key_limit_response(err: &api_key::KeyLimitError) -> Response<Body> {
+    let status = StatusCode::from_u16(err.status_code()).unwrap_or(StatusCode::TOO_MANY_REQUESTS);
+    let (error_type, code) = err.error_kind();
+    let body = serde_json::json!({
+        "error": {
+            "message": err.message(),
+            "type": error_type,
+            "param": null,
+            "code": code
+        }
+    });
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json");
+    if let Some(retry_after) = err.retry_after() {
+        builder = builder.header("Retry-After", retry_after.max(1).to_string());
+    }
+
+    builder
+        .body(Body::from(serde_json::to_vec(&body).unwrap_or_default()))
+        .unwrap_or_else(|_| {
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        })
+}
+
 fn json_error_response(status: StatusCode, message: &str) -> Response<Body> {
     let body = serde_json::json!({
         "error": {
@@ -183,7 +299,6 @@ fn json_error_response(status: StatusCode, message: &str) -> Response<Body> {
     Response::builder()
         .status(status)
         .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
         .body(Body::from(serde_json::to_vec(&body).unwrap_or_default()))
         .unwrap_or_else(|_| {
             Response::builder()
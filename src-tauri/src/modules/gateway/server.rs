@@ -1,28 +1,96 @@
 use super::router;
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+};
+use futures::StreamExt;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::net::TcpListener;
+use tokio::sync::watch;
 
-pub async fn run_gateway_server(
-    port: u16,
-    shutdown_rx: tokio::sync::oneshot::Receiver<()>,
-) -> Result<(), String> {
-    let app = router::create_router();
+static ACTIVE_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 正在处理的请求数，包括排队等待账号与仍在输出的流式响应
+pub fn active_requests() -> usize {
+    ACTIVE_REQUESTS.load(Ordering::Relaxed)
+}
+
+struct RequestGuard;
+
+impl RequestGuard {
+    fn new() -> Self {
+        ACTIVE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        RequestGuard
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        ACTIVE_REQUESTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
+/// 计数持续到响应体发送完毕；收到强制关闭信号时截断仍在输出的流式响应
+async fn track_in_flight(
+    State(force_close): State<watch::Receiver<bool>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let guard = RequestGuard::new();
+    let response = next.run(req).await;
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let mut force_close = force_close;
+    let stream = body
+        .into_data_stream()
+        .take_until(async move {
+            let _ = force_close.wait_for(|closed| *closed).await;
+        })
+        .map(move |chunk| {
+            let _in_flight = &guard;
+            chunk
+        });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 先绑定端口再切换服务，端口被占用时不影响正在运行的实例
+pub async fn bind(port: u16) -> Result<TcpListener, String> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = TcpListener::bind(addr)
+    TcpListener::bind(addr)
         .await
-        .map_err(|e| format!("绑定端口 {} 失败: {}", port, e))?;
+        .map_err(|e| format!("绑定端口 {} 失败: {}", port, e))
+}
+
+/// 收到 shutdown 信号后停止接受新连接，并等待已有连接处理完毕
+pub async fn run_gateway_server(
+    listener: TcpListener,
+    shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+    force_close: watch::Receiver<bool>,
+) -> Result<(), String> {
+    let app =
+        router::create_router().layer(middleware::from_fn_with_state(force_close, track_in_flight));
 
+    let addr = listener
+        .local_addr()
+        .map_err(|e| format!("读取监听地址失败: {}", e))?;
     tracing::info!("[Gateway] HTTP 服务器启动于 {}", addr);
 
+    // 保留客户端地址，用于记录 API Key 的最近使用 IP
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
     axum::serve(listener, app)
-        .with_graceful_shutdown(async {
+        .with_graceful_shutdown(async move {
             let _ = shutdown_rx.await;
-            tracing::info!("[Gateway] 收到关闭信号，正在优雅停止");
+            tracing::info!("[Gateway] {} 停止接受新连接，等待在途请求完成", addr);
         })
         .await
         .map_err(|e| format!("服务器错误: {}", e))?;
 
-    tracing::info!("[Gateway] HTTP 服务器已停止");
+    tracing::info!("[Gateway] HTTP 服务器 {} 已停止", addr);
     Ok(())
 }
@@ -0,0 +1,301 @@
+use bytes::Bytes;
+use futures::Stream;
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+type Finalizer = Box<dyn FnOnce(StreamOutcome) + Send>;
+
+const MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+pub struct StreamOutcome {
+    pub usage_events: Vec<Value>,
+    pub bytes_sent: usize,
+    pub error: Option<String>,
+    /// 首个数据块转发给客户端的时间，用于统计首 token 延迟
+    pub first_chunk_at: Option<Instant>,
+    /// 开启请求捕获时保存的上游原始字节
+    pub transcript: Option<Vec<u8>>,
+}
+
+/// 将上游字节流原样转发给客户端，同时增量解析 SSE 事件以捕获 usage。
+/// 流正常结束、出错或被客户端提前丢弃时都会调用一次 `on_finish`。
+pub struct RelayStream {
+    inner: ByteStream,
+    line_buf: Vec<u8>,
+    usage_events: Vec<Value>,
+    bytes_sent: usize,
+    first_chunk_at: Option<Instant>,
+    transcript: Option<Vec<u8>>,
+    transcript_limit: usize,
+    completed: bool,
+    error: Option<String>,
+    on_finish: Option<Finalizer>,
+}
+
+impl RelayStream {
+    pub fn new<F>(inner: ByteStream, on_finish: F) -> Self
+    where
+        F: FnOnce(StreamOutcome) + Send + 'static,
+    {
+        Self {
+            inner,
+            line_buf: Vec::new(),
+            usage_events: Vec::new(),
+            bytes_sent: 0,
+            first_chunk_at: None,
+            transcript: None,
+            transcript_limit: 0,
+            completed: false,
+            error: None,
+            on_finish: Some(Box::new(on_finish)),
+        }
+    }
+
+    /// 额外保存最多 `max_bytes` 字节的原始流，多保留 1 字节用于判断是否截断
+    pub fn with_transcript(mut self, max_bytes: usize) -> Self {
+        self.transcript = Some(Vec::new());
+        self.transcript_limit = max_bytes.saturating_add(1);
+        self
+    }
+
+    fn observe_chunk(&mut self, chunk: &[u8]) {
+        if let Some(transcript) = self.transcript.as_mut() {
+            let room = self.transcript_limit.saturating_sub(transcript.len());
+            transcript.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        }
+        if self.first_chunk_at.is_none() && !chunk.is_empty() {
+            self.first_chunk_at = Some(Instant::now());
+        }
+        self.bytes_sent += chunk.len();
+        for &b in chunk {
+            if b == b'\n' {
+                let line = std::mem::take(&mut self.line_buf);
+                self.observe_line(&line);
+            } else if self.line_buf.len() < MAX_LINE_BYTES {
+                self.line_buf.push(b);
+            }
+        }
+    }
+
+    fn observe_line(&mut self, line: &[u8]) {
+        let Ok(text) = std::str::from_utf8(line) else {
+            return;
+        };
+        let Some(data) = text.trim_end_matches('\r').strip_prefix("data:") else {
+            return;
+        };
+        let data = data.trim();
+        if data.is_empty() || data == "[DONE]" {
+            return;
+        }
+        if let Ok(event) = serde_json::from_str::<Value>(data) {
+            if is_usage_event(&event) {
+                self.usage_events.push(event);
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(on_finish) = self.on_finish.take() {
+            if !self.line_buf.is_empty() {
+                let line = std::mem::take(&mut self.line_buf);
+                self.observe_line(&line);
+            }
+            let error = if self.completed {
+                self.error.take()
+            } else {
+                Some(
+                    self.error
+                        .take()
+                        .unwrap_or_else(|| "客户端在流结束前断开连接".to_string()),
+                )
+            };
+            on_finish(StreamOutcome {
+                usage_events: std::mem::take(&mut self.usage_events),
+                bytes_sent: self.bytes_sent,
+                error,
+                first_chunk_at: self.first_chunk_at,
+                transcript: self.transcript.take(),
+            });
+        }
+    }
+}
+
+impl Stream for RelayStream {
+    type Item = Result<Bytes, reqwest::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        match polled {
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.observe_chunk(&chunk);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                self.error = Some(format!("上游流中断: {}", e));
+                self.finish();
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => {
+                self.completed = true;
+                self.finish();
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for RelayStream {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn is_usage_event(event: &Value) -> bool {
+    if let Some(event_type) = event.get("type").and_then(|v| v.as_str()) {
+        if matches!(
+            event_type,
+            "message_start" | "message_delta" | "response.completed" | "response.done"
+        ) {
+            return true;
+        }
+    }
+    event.get("usage").map(|u| !u.is_null()).unwrap_or(false)
+        || event.get("usageMetadata").is_some()
+}
+
+/// 将一种协议的 SSE 事件转换为另一种协议的 SSE 字节
+pub trait SseTranslator: Send {
+    /// 处理一个完整的上游事件；`event` 为 `event:` 行的值（若有）
+    fn on_event(&mut self, event: Option<&str>, data: &str) -> Vec<u8>;
+    /// 上游流结束（正常或异常）时调用，输出收尾事件
+    fn on_end(&mut self) -> Vec<u8>;
+}
+
+/// 按 SSE 帧（空行分隔）缓冲上游字节，逐帧交给 translator 转换后输出
+pub struct TranslatedStream<T: SseTranslator> {
+    inner: ByteStream,
+    translator: T,
+    frame_buf: Vec<u8>,
+    pending_error: Option<reqwest::Error>,
+    ended: bool,
+}
+
+impl<T: SseTranslator> TranslatedStream<T> {
+    pub fn new(inner: ByteStream, translator: T) -> Self {
+        Self {
+            inner,
+            translator,
+            frame_buf: Vec::new(),
+            pending_error: None,
+            ended: false,
+        }
+    }
+
+    fn drain_frames(&mut self, flush: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let boundary = find_frame_boundary(&self.frame_buf);
+            let frame = match boundary {
+                Some((end, sep_len)) => {
+                    let frame: Vec<u8> = self.frame_buf.drain(..end + sep_len).collect();
+                    frame[..end].to_vec()
+                }
+                None if flush && !self.frame_buf.is_empty() => std::mem::take(&mut self.frame_buf),
+                None => break,
+            };
+            self.translate_frame(&frame, &mut out);
+        }
+        out
+    }
+
+    fn translate_frame(&mut self, frame: &[u8], out: &mut Vec<u8>) {
+        let text = String::from_utf8_lossy(frame);
+        let mut event: Option<String> = None;
+        let mut data_lines: Vec<&str> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if let Some(value) = line.strip_prefix("event:") {
+                event = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data_lines.push(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+        if data_lines.is_empty() {
+            return;
+        }
+        let data = data_lines.join("\n");
+        out.extend(self.translator.on_event(event.as_deref(), &data));
+    }
+}
+
+fn find_frame_boundary(buf: &[u8]) -> Option<(usize, usize)> {
+    for i in 0..buf.len() {
+        if buf[i..].starts_with(b"\n\n") {
+            return Some((i, 2));
+        }
+        if buf[i..].starts_with(b"\r\n\r\n") {
+            return Some((i, 4));
+        }
+    }
+    None
+}
+
+impl<T: SseTranslator + Unpin> Stream for TranslatedStream<T> {
+    type Item = Result<Bytes, reqwest::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(e) = self.pending_error.take() {
+                return Poll::Ready(Some(Err(e)));
+            }
+            if self.ended {
+                return Poll::Ready(None);
+            }
+
+            match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.frame_buf.extend_from_slice(&chunk);
+                    let out = self.drain_frames(false);
+                    if !out.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(out))));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    // 先输出收尾事件，让客户端拿到完整的错误信息，再传递上游错误
+                    self.ended = true;
+                    let mut out = self.drain_frames(true);
+                    out.extend(self.translator.on_end());
+                    self.pending_error = Some(e);
+                    if !out.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(out))));
+                    }
+                }
+                Poll::Ready(None) => {
+                    self.ended = true;
+                    let mut out = self.drain_frames(true);
+                    out.extend(self.translator.on_end());
+                    if !out.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(out))));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// 生成一条 SSE 事件
+pub fn sse_event(event: &str, data: &Value) -> Vec<u8> {
+    format!("event: {}\ndata: {}\n\n", event, data).into_bytes()
+}
+
+/// 生成一条仅含 data 行的 SSE 事件（OpenAI Chat Completions 流格式）
+pub fn sse_data(data: &Value) -> Vec<u8> {
+    format!("data: {}\n\n", data).into_bytes()
+}
@@ -0,0 +1,357 @@
+use super::account_pool::restore_account;
+use super::account_pool_bridge::{self, Platform};
+use super::db;
+use super::types::{AccountStatus, GatewayAccount};
+use crate::modules::{
+    account, codebuddy_account, codebuddy_cn_account, codex_account, codex_oauth, cursor_account,
+    gemini_account, github_copilot_account, kiro_account, qoder_oauth, trae_account,
+    windsurf_account, workbuddy_account,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Token 在该时间内过期即提前刷新
+const REFRESH_AHEAD_SECONDS: i64 = 300;
+
+/// 每个账号一把刷新锁，并发请求只触发一次刷新
+static REFRESH_LOCKS: Mutex<Option<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    Mutex::new(None);
+
+fn refresh_lock(account_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+    let mut guard = REFRESH_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    guard
+        .get_or_insert_with(HashMap::new)
+        .entry(account_id.to_string())
+        .or_default()
+        .clone()
+}
+
+/// 账号删除后释放其刷新锁
+pub fn forget_account(account_id: &str) {
+    let mut guard = REFRESH_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(locks) = guard.as_mut() {
+        locks.remove(account_id);
+    }
+}
+
+/// 刷新失败的原因。Rejected 表示 OAuth 服务端明确拒绝了 refresh_token，重试也不会成功
+#[derive(Debug)]
+enum RefreshError {
+    Rejected(String),
+    Failed(String),
+}
+
+impl RefreshError {
+    fn into_message(self) -> String {
+        match self {
+            RefreshError::Rejected(message) | RefreshError::Failed(message) => message,
+        }
+    }
+}
+
+impl From<String> for RefreshError {
+    fn from(message: String) -> Self {
+        RefreshError::Failed(message)
+    }
+}
+
+impl From<&str> for RefreshError {
+    fn from(message: &str) -> Self {
+        RefreshError::Failed(message.to_string())
+    }
+}
+
+/// 优先使用记录的过期时间，否则尝试从 JWT 的 exp 中解析；无法判断时返回 None
+fn token_expires_at(account: &GatewayAccount) -> Option<i64> {
+    account
+        .token_expires_at
+        .or_else(|| jwt_expires_at(&account.access_token))
+}
+
+pub fn needs_refresh(account: &GatewayAccount) -> bool {
+    let now = chrono::Utc::now().timestamp();
+    token_expires_at(account).is_some_and(|exp| exp - now < REFRESH_AHEAD_SECONDS)
+}
+
+fn is_token_expired(account: &GatewayAccount) -> bool {
+    let now = chrono::Utc::now().timestamp();
+    token_expires_at(account).is_some_and(|exp| exp <= now)
+}
+
+/// 刷新失败后是否将账号标记为 Expired：access_token 仍有效时继续使用到过期为止；
+/// 无法判断过期时间时，只有 refresh_token 被明确拒绝才标记
+fn should_mark_expired(rejected: bool, expires_at: Option<i64>, now: i64) -> bool {
+    match expires_at {
+        Some(exp) => exp <= now,
+        None => rejected,
+    }
+}
+
+/// 转发前调用：Token 即将过期时先刷新。刷新暂时失败但旧 Token 仍未过期时继续使用旧 Token
+pub async fn ensure_fresh_token(account: GatewayAccount) -> Result<GatewayAccount, String> {
+    if !needs_refresh(&account) {
+        return Ok(account);
+    }
+    match refresh_account(&account).await {
+        Ok(updated) => Ok(updated),
+        Err(_) if !is_token_expired(&account) => Ok(account),
+        Err(e) => Err(e),
+    }
+}
+
+/// 刷新账号 Token 并写回网关数据库（平台账号同时写回平台账号文件）。
+/// 刷新被上游拒绝或 Token 已过期且无法刷新时，将账号标记为 Expired
+pub async fn refresh_account(account: &GatewayAccount) -> Result<GatewayAccount, String> {
+    let lock = refresh_lock(&account.id);
+    let _guard = lock.lock().await;
+
+    // 等锁期间其他请求可能已经刷新过
+    let current = match db::list_accounts()?
+        .into_iter()
+        .find(|a| a.id == account.id)
+    {
+        Some(current) => current,
+        None => {
+            forget_account(&account.id);
+            return Err("账号不存在".to_string());
+        }
+    };
+    if current.access_token != account.access_token && !needs_refresh(&current) {
+        return Ok(current);
+    }
+
+    match refresh_tokens(&current).await {
+        Ok(updated) => {
+            tracing::info!("[TokenRefresh] 账号 {} Token 已刷新", current.email);
+            if current.status != AccountStatus::Active {
+                restore_account(&current.id, "Token 已刷新")?;
+            }
+            Ok(updated)
+        }
+        Err(e) => {
+            let rejected = matches!(e, RefreshError::Rejected(_));
+            let e = e.into_message();
+            tracing::warn!(
+                "[TokenRefresh] 账号 {} Token 刷新失败: {}",
+                current.email,
+                e
+            );
+            let now = chrono::Utc::now().timestamp();
+            if should_mark_expired(rejected, token_expires_at(&current), now) {
+                let reason = format!("Token 刷新失败: {}", e);
+                db::with_db(|conn| {
+                    db::set_account_status(conn, &current.id, "expired", &reason).map(|_| ())
+                })?;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// 根据 OAuth 错误响应判断 refresh_token 是否已失效或被吊销。
+/// 有 error 字段时以其为准，否则只把 401/403 视为拒绝
+fn is_rejected_response(status: Option<u16>, oauth_error: Option<&str>) -> bool {
+    match oauth_error {
+        Some(error) => matches!(
+            error,
+            "invalid_grant" | "invalid_client" | "unauthorized_client"
+        ),
+        None => matches!(status, Some(401 | 403)),
+    }
+}
+
+async fn refresh_tokens(account: &GatewayAccount) -> Result<GatewayAccount, RefreshError> {
+    let (access_token, refresh_token, expires_at) = if account.source.as_deref() == Some("synced") {
+        let refreshed = refresh_platform_account(account).await?;
+        (
+            refreshed.access_token,
+            refreshed.refresh_token,
+            refreshed.token_expires_at,
+        )
+    } else {
+        refresh_manual_account(account).await?
+    };
+    let expires_at = expires_at.or_else(|| jwt_expires_at(&access_token));
+
+    db::update_account_token(
+        &account.id,
+        &access_token,
+        refresh_token.as_deref(),
+        expires_at,
+    )?;
+
+    let mut updated = account.clone();
+    updated.access_token = access_token;
+    updated.refresh_token = refresh_token.or(updated.refresh_token);
+    updated.token_expires_at = expires_at;
+    Ok(updated)
+}
+
+/// 手动添加的账号按 Codex OAuth 刷新
+async fn refresh_manual_account(
+    account: &GatewayAccount,
+) -> Result<(String, Option<String>, Option<i64>), RefreshError> {
+    match account.platform.as_deref() {
+        None | Some("codex") => {}
+        Some(platform) => {
+            return Err(format!("不支持刷新 {} 平台的手动账号", platform).into());
+        }
+    }
+    let refresh_token = account
+        .refresh_token
+        .as_deref()
+        .filter(|t| !t.is_empty())
+        .ok_or("账号未保存 refresh_token")?;
+    let tokens = codex_oauth::refresh_access_token_detailed(refresh_token)
+        .await
+        .map_err(|e| {
+            if is_rejected_response(e.status, e.oauth_error.as_deref()) {
+                RefreshError::Rejected(e.message)
+            } else {
+                RefreshError::Failed(e.message)
+            }
+        })?;
+    Ok((tokens.access_token, tokens.refresh_token, None))
+}
+
+/// 平台账号交给各自的模块刷新，由平台模块写回账号文件后再读取新的 Token。
+/// 平台模块只返回错误文本，无法区分拒绝与临时故障，统一按临时故障处理
+async fn refresh_platform_account(account: &GatewayAccount) -> Result<GatewayAccount, String> {
+    let platform = account
+        .platform
+        .as_deref()
+        .and_then(Platform::from_id)
+        .ok_or("未知的账号平台")?;
+    let platform_id = account
+        .id
+        .strip_prefix(&format!("bridge_{}_", platform))
+        .ok_or("无法解析平台账号 ID")?;
+
+    match platform {
+        Platform::Antigravity => {
+            account::prepare_account_for_injection(platform_id).await?;
+        }
+        Platform::Codex => {
+            codex_account::prepare_account_for_injection(platform_id).await?;
+        }
+        Platform::GithubCopilot => {
+            github_copilot_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::Windsurf => {
+            windsurf_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::Kiro => {
+            kiro_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::Gemini => {
+            gemini_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::CodeBuddy => {
+            codebuddy_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::CodeBuddyCn => {
+            codebuddy_cn_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::WorkBuddy => {
+            workbuddy_account::refresh_account_token(platform_id).await?;
+        }
+        Platform::Cursor => {
+            cursor_account::refresh_account_async(platform_id).await?;
+        }
+        Platform::Trae => {
+            trae_account::refresh_account_async(platform_id).await?;
+        }
+        Platform::Qoder => {
+            qoder_oauth::refresh_account_from_openapi(platform_id).await?;
+        }
+    }
+
+    account_pool_bridge::load_platform_account(&platform, platform_id)
+}
+
+fn jwt_expires_at(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    json.get("exp").and_then(|exp| exp.as_i64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(access_token: &str, token_expires_at: Option<i64>) -> GatewayAccount {
+        GatewayAccount {
+            id: "acc".to_string(),
+            email: "a@example.com".to_string(),
+            access_token: access_token.to_string(),
+            refresh_token: Some("rt".to_string()),
+            token_expires_at,
+            status: AccountStatus::Active,
+            tags: None,
+            group_name: None,
+            proxy_url: None,
+            created_at: 0,
+            updated_at: 0,
+            last_used_at: None,
+            cooldown_until: None,
+            error_count: 0,
+            platform: None,
+            source: None,
+        }
+    }
+
+    fn jwt_with_exp(exp: i64) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(format!(r#"{{"exp":{}}}"#, exp));
+        format!("header.{}.signature", payload)
+    }
+
+    #[test]
+    fn test_rejection_uses_oauth_error_before_status() {
+        assert!(is_rejected_response(Some(400), Some("invalid_grant")));
+        assert!(is_rejected_response(Some(401), Some("invalid_client")));
+        assert!(!is_rejected_response(Some(400), Some("invalid_request")));
+        assert!(!is_rejected_response(
+            Some(503),
+            Some("temporarily_unavailable")
+        ));
+        assert!(is_rejected_response(Some(401), None));
+        assert!(is_rejected_response(Some(403), None));
+        assert!(!is_rejected_response(Some(400), None));
+        assert!(!is_rejected_response(Some(500), None));
+        assert!(!is_rejected_response(None, None));
+    }
+
+    #[test]
+    fn test_still_valid_token_is_never_marked_expired() {
+        let now = 1_000_000;
+        assert!(!should_mark_expired(true, Some(now + 60), now));
+        assert!(!should_mark_expired(false, Some(now + 60), now));
+        assert!(should_mark_expired(false, Some(now), now));
+        assert!(should_mark_expired(true, Some(now - 1), now));
+        assert!(should_mark_expired(true, None, now));
+        assert!(!should_mark_expired(false, None, now));
+    }
+
+    #[test]
+    fn test_needs_refresh_uses_recorded_expiry_then_jwt() {
+        let now = chrono::Utc::now().timestamp();
+        assert!(needs_refresh(&account("opaque", Some(now + 60))));
+        assert!(!needs_refresh(&account("opaque", Some(now + 3600))));
+        assert!(!needs_refresh(&account("opaque", None)));
+        assert!(needs_refresh(&account(&jwt_with_exp(now + 60), None)));
+        assert!(!needs_refresh(&account(&jwt_with_exp(now + 3600), None)));
+        assert!(is_token_expired(&account(&jwt_with_exp(now - 1), None)));
+    }
+
+    #[test]
+    fn test_forget_account_releases_lock() {
+        let _ = refresh_lock("deleted");
+        forget_account("deleted");
+        let guard = REFRESH_LOCKS.lock().unwrap();
+        assert!(!guard
+            .as_ref()
+            .is_some_and(|locks| locks.contains_key("deleted")));
+    }
+}
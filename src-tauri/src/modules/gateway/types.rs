@@ -53,6 +53,55 @@ pub struct GatewayApiKey {
     pub created_at: i64,
     pub last_used_at: Option<i64>,
     pub usage_count: i64,
+    /// 每分钟请求数上限
+    #[serde(default)]
+    pub rpm_limit: Option<u32>,
+    /// 每日 token 上限（输入 + 输出，按最近 24 小时滚动计算）
+    #[serde(default)]
+    pub daily_token_limit: Option<i64>,
+    /// 每月费用预算 (USD)，按 model_pricing 计算
+    #[serde(default)]
+    pub monthly_budget_usd: Option<f64>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub tokens_last_24h: i64,
+    #[serde(default)]
+    pub cost_this_month_usd: f64,
+    /// 绑定的账号分组，为空时可由请求头 X-Gateway-Group 指定
+    #[serde(default)]
+    pub group_name: Option<String>,
+    /// 允许访问的端点（JSON 数组），为空时不限制
+    #[serde(default)]
+    pub allowed_endpoints: Option<String>,
+    #[serde(default)]
+    pub last_used_ip: Option<String>,
+    /// 轮换后旧 Key 的失效时间，在此之前新旧 Key 均可使用
+    #[serde(default)]
+    pub previous_key_expires_at: Option<i64>,
+}
+
+/// API Key 操作审计记录，Key 删除后仍保留
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyAuditEntry {
+    pub id: i64,
+    pub key_id: String,
+    pub key_name: String,
+    pub key_prefix: String,
+    pub timestamp: i64,
+    pub action: String,
+    pub detail: Option<String>,
+}
+
+/// 账号状态变化记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountEvent {
+    pub id: i64,
+    pub account_id: String,
+    pub timestamp: i64,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +119,12 @@ pub struct RequestLogEntry {
     pub output_tokens: Option<i64>,
     pub error_message: Option<String>,
     pub api_key_prefix: Option<String>,
+    #[serde(default)]
+    pub cache_read_tokens: Option<i64>,
+    #[serde(default)]
+    pub cache_creation_tokens: Option<i64>,
+    #[serde(default)]
+    pub total_cost_usd: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,6 +138,12 @@ pub struct GatewayStatus {
     pub uptime_seconds: Option<u64>,
     pub synced_accounts: usize,
     pub platform_stats: Option<std::collections::HashMap<String, usize>>,
+    /// 网关正在处理的请求数，包括排队等待账号与仍在输出的流式响应
+    pub in_flight_requests: usize,
+    /// 各账号 ID 对应的在途请求数
+    pub account_in_flight: std::collections::HashMap<String, usize>,
+    /// 正在停止，等待在途请求完成
+    pub draining: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,6 +159,116 @@ pub struct GatewayConfig {
     pub cooldown_seconds: u32,
     pub platform_upstreams: Option<std::collections::HashMap<String, String>>,
     pub enable_account_bridge: bool,
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    /// QuotaAware 策略下剩余配额低于该百分比的账号不参与路由
+    #[serde(default = "default_quota_threshold_percent")]
+    pub quota_threshold_percent: i32,
+    /// 所有账号并发已满时请求排队等待的最长时间，超时返回 429
+    #[serde(default = "default_queue_timeout_seconds")]
+    pub queue_timeout_seconds: u32,
+    /// 仅支持 /v1/responses 的上游地址，发往这些上游的 Chat Completions 请求会转换为 Responses 协议
+    #[serde(default)]
+    pub responses_only_upstreams: Vec<String>,
+    /// `/v1/models` 汇总结果的缓存时间
+    #[serde(default = "default_models_cache_seconds")]
+    pub models_cache_seconds: u64,
+    /// 模型映射规则，按顺序匹配
+    #[serde(default)]
+    pub model_mappings: Vec<ModelMapping>,
+    #[serde(default)]
+    pub account_groups: Vec<AccountGroup>,
+    /// 停止网关或切换端口时等待在途请求完成的最长时间，超时后强制断开
+    #[serde(default = "default_drain_timeout_seconds")]
+    pub drain_timeout_seconds: u64,
+    /// 后台健康检查间隔：解除到期冷却、刷新即将过期的 Token、探测异常账号；0 表示关闭
+    #[serde(default = "default_health_check_interval_seconds")]
+    pub health_check_interval_seconds: u64,
+    /// 调试用：完整保存请求与上游响应（含 SSE 原文），鉴权头会被脱敏
+    #[serde(default)]
+    pub capture_enabled: bool,
+    /// 最多保留的捕获条数，超出后删除最早的记录
+    #[serde(default = "default_capture_max_entries")]
+    pub capture_max_entries: u32,
+    /// 单个请求体/响应体最多保存的字节数（KB），超出部分截断
+    #[serde(default = "default_capture_max_body_kb")]
+    pub capture_max_body_kb: u32,
+    /// 捕获记录最长保留的小时数，超过后删除；0 表示只按条数清理
+    #[serde(default = "default_capture_retention_hours")]
+    pub capture_retention_hours: u32,
+}
+
+/// 账号分组：group_name 或标签与分组名相同的账号，以及 members 中列出的账号。
+/// members 的顺序即 Priority 策略下的优先级，weight 用于 Weighted 策略
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountGroup {
+    pub name: String,
+    /// 为空时沿用全局路由策略
+    #[serde(default)]
+    pub route_strategy: Option<RouteStrategy>,
+    #[serde(default)]
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub account_id: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// 将客户端请求的模型（支持 `*`/`?` 通配符）映射到上游模型与账号平台
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelMapping {
+    pub pattern: String,
+    /// 为空时保留客户端请求的模型
+    #[serde(default)]
+    pub target_model: Option<String>,
+    /// 限定使用该平台（或分组）的账号
+    #[serde(default)]
+    pub target_platform: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_quota_threshold_percent() -> i32 {
+    5
+}
+
+fn default_queue_timeout_seconds() -> u32 {
+    30
+}
+
+fn default_models_cache_seconds() -> u64 {
+    300
+}
+
+fn default_drain_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_health_check_interval_seconds() -> u64 {
+    60
+}
+
+fn default_capture_max_entries() -> u32 {
+    200
+}
+
+fn default_capture_max_body_kb() -> u32 {
+    1024
+}
+
+fn default_capture_retention_hours() -> u32 {
+    24
 }
 
 impl Default for GatewayConfig {
@@ -114,10 +285,68 @@ impl Default for GatewayConfig {
             cooldown_seconds: 300,
             platform_upstreams: None,
             enable_account_bridge: false,
+            retry_policy: RetryPolicy::default(),
+            quota_threshold_percent: default_quota_threshold_percent(),
+            queue_timeout_seconds: default_queue_timeout_seconds(),
+            responses_only_upstreams: Vec::new(),
+            models_cache_seconds: default_models_cache_seconds(),
+            model_mappings: Vec::new(),
+            account_groups: Vec::new(),
+            drain_timeout_seconds: default_drain_timeout_seconds(),
+            health_check_interval_seconds: default_health_check_interval_seconds(),
+            capture_enabled: false,
+            capture_max_entries: default_capture_max_entries(),
+            capture_max_body_kb: default_capture_max_body_kb(),
+            capture_retention_hours: default_capture_retention_hours(),
         }
     }
 }
 
+impl GatewayConfig {
+    pub fn is_responses_only(&self, upstream_base_url: &str) -> bool {
+        let target = upstream_base_url.trim_end_matches('/');
+        self.responses_only_upstreams
+            .iter()
+            .any(|u| u.trim_end_matches('/').eq_ignore_ascii_case(target))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub retryable_status_codes: Vec<u16>,
+    pub backoff_base_ms: u64,
+    pub backoff_max_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retryable_status_codes: vec![429, 500, 502, 503, 504],
+            backoff_base_ms: 500,
+            backoff_max_ms: 5000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn is_retryable(&self, status: u16) -> bool {
+        self.retryable_status_codes.contains(&status)
+    }
+
+    /// 第 n 次失败后的等待时间（指数退避，受 backoff_max_ms 限制）
+    pub fn backoff_delay(&self, failed_attempts: u32) -> std::time::Duration {
+        let factor = 1u64 << failed_attempts.saturating_sub(1).min(16);
+        let ms = self
+            .backoff_base_ms
+            .saturating_mul(factor)
+            .min(self.backoff_max_ms);
+        std::time::Duration::from_millis(ms)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum RouteStrategy {
@@ -126,6 +355,7 @@ pub enum RouteStrategy {
     Random,
     Priority,
     QuotaAware,
+    Weighted,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,6 +382,52 @@ pub struct AccountImportPayload {
 pub struct ApiKeyCreatePayload {
     pub name: String,
     pub allowed_models: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub limits: ApiKeyLimits,
+    #[serde(default)]
+    pub group_name: Option<String>,
+    #[serde(default)]
+    pub allowed_endpoints: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiKeyLimits {
+    pub rpm_limit: Option<u32>,
+    pub daily_token_limit: Option<i64>,
+    pub monthly_budget_usd: Option<f64>,
+    pub expires_at: Option<i64>,
+}
+
+/// 按 trace_id 保存的完整请求/响应，重试时保留最后一次尝试
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestCapture {
+    pub trace_id: String,
+    pub timestamp: i64,
+    pub method: String,
+    pub path: String,
+    pub account_id: Option<String>,
+    pub upstream_url: Option<String>,
+    pub request_headers: Vec<(String, String)>,
+    /// 客户端请求体（已应用模型映射）
+    pub request_body: String,
+    /// 协议转换后实际发往上游的请求体
+    pub upstream_request_body: Option<String>,
+    pub status_code: u16,
+    pub response_headers: Vec<(String, String)>,
+    /// 上游原始响应，流式请求为完整的 SSE 文本
+    pub response_body: String,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub status_code: u16,
+    pub upstream_url: String,
+    pub duration_ms: i64,
+    /// 上游原始响应
+    pub response_body: String,
+    /// 经过协议转换后返回给客户端的内容
+    pub client_body: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,4 +448,10 @@ pub struct RequestLogSummary {
     pub avg_duration_ms: f64,
     pub total_input_tokens: i64,
     pub total_output_tokens: i64,
+    #[serde(default)]
+    pub total_cache_read_tokens: i64,
+    #[serde(default)]
+    pub total_cache_creation_tokens: i64,
+    #[serde(default)]
+    pub total_cost_usd: f64,
 }
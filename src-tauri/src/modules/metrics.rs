@@ -0,0 +1,253 @@
+//! Prometheus 指标
+//!
+//! 网关与本地代理各持有一份指标，`/metrics` 端点以 Prometheus 文本格式输出
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 耗时类直方图的分桶（秒）
+const LATENCY_BUCKETS: &[f64] = &[
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+pub type Labels = Vec<(&'static str, String)>;
+
+/// 抓取时才计算的瞬时值，例如在途请求数、各状态账号数
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub samples: Vec<(Labels, f64)>,
+}
+
+#[derive(Clone, Default)]
+struct Histogram {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                self.counts[i] += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Families {
+    requests: BTreeMap<Labels, u64>,
+    tokens: BTreeMap<Labels, u64>,
+    duration: BTreeMap<Labels, Histogram>,
+    ttft: BTreeMap<Labels, Histogram>,
+}
+
+pub struct Metrics {
+    namespace: &'static str,
+    families: Mutex<Families>,
+    in_flight: AtomicI64,
+}
+
+/// 在途请求计数，随 guard 释放而减少
+pub struct InFlightGuard(&'static Metrics);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn gateway() -> &'static Metrics {
+    static GATEWAY: OnceLock<Metrics> = OnceLock::new();
+    GATEWAY.get_or_init(|| Metrics::new("gateway"))
+}
+
+pub fn proxy() -> &'static Metrics {
+    static PROXY: OnceLock<Metrics> = OnceLock::new();
+    PROXY.get_or_init(|| Metrics::new("proxy"))
+}
+
+impl Metrics {
+    fn new(namespace: &'static str) -> Self {
+        Self {
+            namespace,
+            families: Mutex::new(Families::default()),
+            in_flight: AtomicI64::new(0),
+        }
+    }
+
+    fn with_families(&self, f: impl FnOnce(&mut Families)) {
+        let mut families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut families);
+    }
+
+    pub fn inc_requests(&self, labels: Labels) {
+        self.with_families(|f| *f.requests.entry(labels).or_default() += 1);
+    }
+
+    pub fn add_tokens(&self, labels: Labels, tokens: u64) {
+        if tokens == 0 {
+            return;
+        }
+        self.with_families(|f| *f.tokens.entry(labels).or_default() += tokens);
+    }
+
+    pub fn observe_duration(&self, labels: Labels, duration: Duration) {
+        self.with_families(|f| {
+            f.duration
+                .entry(labels)
+                .or_default()
+                .observe(duration.as_secs_f64())
+        });
+    }
+
+    pub fn observe_ttft(&self, labels: Labels, duration: Duration) {
+        self.with_families(|f| {
+            f.ttft
+                .entry(labels)
+                .or_default()
+                .observe(duration.as_secs_f64())
+        });
+    }
+
+    pub fn track_in_flight(&'static self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(self)
+    }
+
+    pub fn in_flight(&self) -> i64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// 输出全部指标；`gauges` 由调用方在抓取时现算
+    pub fn render(&self, gauges: &[Gauge]) -> String {
+        let families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let ns = self.namespace;
+        let mut out = String::new();
+
+        write_header(&mut out, ns, "requests_total", "counter", "已处理的请求数");
+        for (labels, value) in &families.requests {
+            write_sample(&mut out, ns, "requests_total", labels, None, *value as f64);
+        }
+
+        write_header(&mut out, ns, "tokens_total", "counter", "上游返回的 token 用量");
+        for (labels, value) in &families.tokens {
+            write_sample(&mut out, ns, "tokens_total", labels, None, *value as f64);
+        }
+
+        write_histogram(
+            &mut out,
+            ns,
+            "request_duration_seconds",
+            "请求总耗时",
+            &families.duration,
+        );
+        write_histogram(
+            &mut out,
+            ns,
+            "time_to_first_token_seconds",
+            "流式响应首个数据块的到达时间",
+            &families.ttft,
+        );
+
+        for gauge in gauges {
+            write_header(&mut out, ns, gauge.name, "gauge", gauge.help);
+            for (labels, value) in &gauge.samples {
+                write_sample(&mut out, ns, gauge.name, labels, None, *value);
+            }
+        }
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, ns: &str, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {}_{} {}", ns, name, help);
+    let _ = writeln!(out, "# TYPE {}_{} {}", ns, name, kind);
+}
+
+fn write_sample(
+    out: &mut String,
+    ns: &str,
+    name: &str,
+    labels: &Labels,
+    le: Option<&str>,
+    value: f64,
+) {
+    let _ = write!(out, "{}_{}", ns, name);
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if !pairs.is_empty() {
+        let _ = write!(out, "{{{}}}", pairs.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn write_histogram(
+    out: &mut String,
+    ns: &str,
+    name: &str,
+    help: &str,
+    series: &BTreeMap<Labels, Histogram>,
+) {
+    write_header(out, ns, name, "histogram", help);
+    let bucket = format!("{}_bucket", name);
+    for (labels, histogram) in series {
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.counts) {
+            write_sample(out, ns, &bucket, labels, Some(&bound.to_string()), *count as f64);
+        }
+        let count = histogram.count as f64;
+        write_sample(out, ns, &bucket, labels, Some("+Inf"), count);
+        write_sample(out, ns, &format!("{}_sum", name), labels, None, histogram.sum);
+        write_sample(out, ns, &format!("{}_count", name), labels, None, count);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counters_and_histograms() {
+        let metrics = Metrics::new("test");
+        metrics.inc_requests(vec![("status", "200".into()), ("model", "gpt-\"4\"".into())]);
+        metrics.observe_duration(vec![("model", "o3".into())], Duration::from_millis(300));
+
+        let text = metrics.render(&[Gauge {
+            name: "in_flight_requests",
+            help: "在途请求数",
+            samples: vec![(Vec::new(), 2.0)],
+        }]);
+
+        assert!(text.contains("test_requests_total{status=\"200\",model=\"gpt-\\\"4\\\"\"} 1\n"));
+        assert!(text.contains("test_request_duration_seconds_bucket{model=\"o3\",le=\"0.25\"} 0\n"));
+        assert!(text.contains("test_request_duration_seconds_bucket{model=\"o3\",le=\"0.5\"} 1\n"));
+        assert!(text.contains("test_request_duration_seconds_bucket{model=\"o3\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("test_request_duration_seconds_count{model=\"o3\"} 1\n"));
+        assert!(text.contains("# TYPE test_in_flight_requests gauge\ntest_in_flight_requests 2\n"));
+    }
+}
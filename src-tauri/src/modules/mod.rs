@@ -74,6 +74,7 @@ pub mod session_manager;
 pub mod opencode_config;
 pub mod opencode_db;
 pub mod proxy;
+pub mod metrics;
 
 // 重新导出常用函数
 pub use account::*;
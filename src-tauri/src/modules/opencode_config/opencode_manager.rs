@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const SYNC_THEME: &str = "tokyonight";
 
@@ -71,6 +71,14 @@ impl OpenCodeConfigManager {
         })
     }
 
+    /// 服务商配置所在的文件（opencode.json 与元数据文件），用于判断配置是否变化
+    pub fn config_paths(&self) -> [&Path; 2] {
+        [
+            self.opencode_config_json.as_path(),
+            self.metadata_json.as_path(),
+        ]
+    }
+
     /// 读取元数据存储
     fn read_metadata(&self) -> Result<HashMap<String, ProviderMetadataStorage>, String> {
         if !self.metadata_json.exists() {
@@ -40,6 +40,8 @@ impl Database {
                 error_message TEXT,
                 is_streaming INTEGER NOT NULL DEFAULT 0,
                 cost_multiplier TEXT NOT NULL DEFAULT '1.0',
+                upstream_url TEXT,
+                failover_hop INTEGER NOT NULL DEFAULT 0,
                 created_at INTEGER NOT NULL
             )",
             [],
@@ -121,6 +123,12 @@ impl Database {
                 takeover_claude INTEGER NOT NULL DEFAULT 0,
                 takeover_codex INTEGER NOT NULL DEFAULT 0,
                 takeover_gemini INTEGER NOT NULL DEFAULT 0,
+                failover_claude TEXT NOT NULL DEFAULT '[]',
+                failover_codex TEXT NOT NULL DEFAULT '[]',
+                failover_gemini TEXT NOT NULL DEFAULT '[]',
+                target_claude TEXT,
+                target_gemini TEXT,
+                takeover_recovery TEXT NOT NULL DEFAULT 'ask',
                 created_at TEXT NOT NULL DEFAULT (datetime('now')),
                 updated_at TEXT NOT NULL DEFAULT (datetime('now'))
             )",
@@ -210,6 +218,43 @@ impl Database {
         )
         .map_err(|e| AppError::Database(format!("创建 tool_calls tool_name 索引失败: {e}")))?;
 
+        // 7. 上游熔断状态表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS proxy_circuit_state (
+                upstream_key TEXT PRIMARY KEY,
+                provider_id TEXT NOT NULL,
+                base_url TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'closed',
+                consecutive_failures INTEGER NOT NULL DEFAULT 0,
+                window_requests INTEGER NOT NULL DEFAULT 0,
+                window_failures INTEGER NOT NULL DEFAULT 0,
+                avg_latency_ms INTEGER NOT NULL DEFAULT 0,
+                open_until INTEGER,
+                updated_at INTEGER NOT NULL
+            )",
+            [],
+        )
+        .map_err(|e| AppError::Database(format!("创建 proxy_circuit_state 表失败: {e}")))?;
+
+        // 8. 消费预算表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS usage_budgets (
+                id TEXT PRIMARY KEY,
+                scope TEXT NOT NULL,
+                scope_value TEXT NOT NULL,
+                period TEXT NOT NULL,
+                limit_usd REAL NOT NULL,
+                hard_cap INTEGER NOT NULL DEFAULT 0,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                alerted_threshold INTEGER NOT NULL DEFAULT 0,
+                alerted_period_start INTEGER,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+            [],
+        )
+        .map_err(|e| AppError::Database(format!("创建 usage_budgets 表失败: {e}")))?;
+
         Ok(())
     }
 
@@ -229,6 +274,18 @@ impl Database {
             Self::migrate_to_v2_add_project_name(&conn)?;
         }
 
+        if version < 3 {
+            Self::migrate_to_v3_add_failover(&conn)?;
+        }
+
+        if version < 4 {
+            Self::migrate_to_v4_add_takeover_target(&conn)?;
+        }
+
+        if version < 5 {
+            Self::migrate_to_v5_add_takeover_recovery(&conn)?;
+        }
+
         if version < SCHEMA_VERSION {
             Self::set_user_version(&conn, SCHEMA_VERSION)?;
         }
@@ -376,6 +433,47 @@ impl Database {
 
         Ok(())
     }
+
+    fn migrate_to_v3_add_failover(conn: &Connection) -> Result<(), AppError> {
+        let columns = [
+            ("proxy_config", "failover_claude", "TEXT NOT NULL DEFAULT '[]'"),
+            ("proxy_config", "failover_codex", "TEXT NOT NULL DEFAULT '[]'"),
+            ("proxy_config", "failover_gemini", "TEXT NOT NULL DEFAULT '[]'"),
+            ("proxy_request_logs", "upstream_url", "TEXT"),
+            ("proxy_request_logs", "failover_hop", "INTEGER NOT NULL DEFAULT 0"),
+        ];
+        for (table, column, definition) in columns {
+            if !Self::column_exists(conn, table, column)? {
+                conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), [])
+                    .map_err(|e| AppError::Database(format!("新增 {column} 列失败: {e}")))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn migrate_to_v4_add_takeover_target(conn: &Connection) -> Result<(), AppError> {
+        for column in ["target_claude", "target_gemini"] {
+            if !Self::column_exists(conn, "proxy_config", column)? {
+                conn.execute(&format!("ALTER TABLE proxy_config ADD COLUMN {column} TEXT"), [])
+                    .map_err(|e| AppError::Database(format!("新增 {column} 列失败: {e}")))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn migrate_to_v5_add_takeover_recovery(conn: &Connection) -> Result<(), AppError> {
+        if !Self::column_exists(conn, "proxy_config", "takeover_recovery")? {
+            conn.execute(
+                "ALTER TABLE proxy_config ADD COLUMN takeover_recovery TEXT NOT NULL DEFAULT 'ask'",
+                [],
+            )
+            .map_err(|e| AppError::Database(format!("新增 takeover_recovery 列失败: {e}")))?;
+        }
+
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -492,6 +590,75 @@ pub struct ProxyConfigDb {
     pub takeover_claude: bool,
     pub takeover_codex: bool,
     pub takeover_gemini: bool,
+    /// 各应用的备用服务商 ID，当前服务商不可用时按顺序尝试
+    pub failover_claude: Vec<String>,
+    pub failover_codex: Vec<String>,
+    pub failover_gemini: Vec<String>,
+    /// 协议转换接管的目标，为空时按原生协议转发
+    pub target_claude: Option<TakeoverTarget>,
+    pub target_gemini: Option<TakeoverTarget>,
+    /// 启动时发现残留接管的处理方式：ask / restore / relaunch
+    pub takeover_recovery: String,
+}
+
+/// 协议转换接管的目标上游
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoverTarget {
+    /// 上游协议：openai / gemini
+    pub protocol: String,
+    /// 上游服务商 ID，为空时使用该协议对应应用的当前服务商
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    /// 发往上游的模型名，为空时沿用客户端请求中的模型
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// 上游熔断状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitStateDb {
+    pub upstream_key: String,
+    pub provider_id: String,
+    pub base_url: String,
+    /// closed / open / half_open
+    pub state: String,
+    pub consecutive_failures: u32,
+    /// 统计窗口内的请求数与失败数
+    pub window_requests: u32,
+    pub window_failures: u32,
+    pub avg_latency_ms: u64,
+    /// 熔断（或半开探测）截止时间，Unix 秒
+    pub open_until: Option<i64>,
+    pub updated_at: i64,
+}
+
+/// 消费预算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageBudgetDb {
+    #[serde(default)]
+    pub id: String,
+    /// 预算范围：app_type / provider / project
+    pub scope: String,
+    /// 范围取值：应用类型、服务商 ID 或项目名，与 proxy_request_logs 中的取值一致
+    pub scope_value: String,
+    /// 统计周期：daily / weekly / monthly
+    pub period: String,
+    pub limit_usd: f64,
+    /// 达到上限后本地代理拒绝该范围的请求；project 范围无法在代理中识别，保存时拒绝
+    pub hard_cap: bool,
+    pub enabled: bool,
+    /// 当前周期已提醒到的阈值（0 / 80 / 100）及该周期的起点，Unix 秒
+    #[serde(default)]
+    pub alerted_threshold: u32,
+    #[serde(default)]
+    pub alerted_period_start: Option<i64>,
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
 }
 
 /// 会话统计汇总
@@ -528,10 +695,25 @@ impl Default for ProxyConfigDb {
             takeover_claude: false,
             takeover_codex: false,
             takeover_gemini: false,
+            failover_claude: Vec::new(),
+            failover_codex: Vec::new(),
+            failover_gemini: Vec::new(),
+            target_claude: None,
+            target_gemini: None,
+            takeover_recovery: "ask".to_string(),
         }
     }
 }
 
+/// 备用服务商列表以 JSON 数组保存，内容无法解析时视为未配置
+fn parse_id_list(raw: &str) -> Vec<String> {
+    serde_json::from_str(raw).unwrap_or_default()
+}
+
+fn parse_target(raw: Option<String>) -> Option<TakeoverTarget> {
+    serde_json::from_str(&raw?).ok()
+}
+
 impl Database {
     /// 获取使用量汇总
     pub fn get_usage_summary(
@@ -1076,7 +1258,9 @@ impl Database {
         let conn = lock_conn!(self.conn);
 
         conn.query_row(
-            "SELECT proxy_enabled, listen_address, listen_port, takeover_claude, takeover_codex, takeover_gemini
+            "SELECT proxy_enabled, listen_address, listen_port, takeover_claude, takeover_codex, takeover_gemini,
+                    failover_claude, failover_codex, failover_gemini, target_claude, target_gemini,
+                    takeover_recovery
              FROM proxy_config WHERE id = 1",
             [],
             |row| {
@@ -1087,6 +1271,12 @@ impl Database {
                     takeover_claude: row.get::<_, i32>(3)? != 0,
                     takeover_codex: row.get::<_, i32>(4)? != 0,
                     takeover_gemini: row.get::<_, i32>(5)? != 0,
+                    failover_claude: parse_id_list(&row.get::<_, String>(6)?),
+                    failover_codex: parse_id_list(&row.get::<_, String>(7)?),
+                    failover_gemini: parse_id_list(&row.get::<_, String>(8)?),
+                    target_claude: parse_target(row.get(9)?),
+                    target_gemini: parse_target(row.get(10)?),
+                    takeover_recovery: row.get(11)?,
                 })
             },
         )
@@ -1105,6 +1295,12 @@ impl Database {
                 takeover_claude = ?4,
                 takeover_codex = ?5,
                 takeover_gemini = ?6,
+                failover_claude = ?7,
+                failover_codex = ?8,
+                failover_gemini = ?9,
+                target_claude = ?10,
+                target_gemini = ?11,
+                takeover_recovery = ?12,
                 updated_at = datetime('now')
              WHERE id = 1",
             rusqlite::params![
@@ -1114,6 +1310,12 @@ impl Database {
                 if config.takeover_claude { 1 } else { 0 },
                 if config.takeover_codex { 1 } else { 0 },
                 if config.takeover_gemini { 1 } else { 0 },
+                serde_json::to_string(&config.failover_claude).unwrap_or_default(),
+                serde_json::to_string(&config.failover_codex).unwrap_or_default(),
+                serde_json::to_string(&config.failover_gemini).unwrap_or_default(),
+                config.target_claude.as_ref().and_then(|t| serde_json::to_string(t).ok()),
+                config.target_gemini.as_ref().and_then(|t| serde_json::to_string(t).ok()),
+                config.takeover_recovery,
             ],
         )
         .map_err(|e| AppError::Database(format!("更新代理配置失败: {e}")))?;
@@ -1121,6 +1323,65 @@ impl Database {
         Ok(())
     }
 
+    /// 获取所有上游的熔断状态
+    pub fn list_circuit_states(&self) -> Result<Vec<CircuitStateDb>, AppError> {
+        let conn = lock_conn!(self.conn);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT upstream_key, provider_id, base_url, state, consecutive_failures,
+                        window_requests, window_failures, avg_latency_ms, open_until, updated_at
+                 FROM proxy_circuit_state ORDER BY provider_id, base_url",
+            )
+            .map_err(|e| AppError::Database(format!("查询熔断状态失败: {e}")))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(CircuitStateDb {
+                    upstream_key: row.get(0)?,
+                    provider_id: row.get(1)?,
+                    base_url: row.get(2)?,
+                    state: row.get(3)?,
+                    consecutive_failures: row.get::<_, i64>(4)? as u32,
+                    window_requests: row.get::<_, i64>(5)? as u32,
+                    window_failures: row.get::<_, i64>(6)? as u32,
+                    avg_latency_ms: row.get::<_, i64>(7)? as u64,
+                    open_until: row.get(8)?,
+                    updated_at: row.get(9)?,
+                })
+            })
+            .map_err(|e| AppError::Database(format!("查询熔断状态失败: {e}")))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AppError::Database(format!("读取熔断状态失败: {e}")))
+    }
+
+    /// 保存单个上游的熔断状态
+    pub fn save_circuit_state(&self, state: &CircuitStateDb) -> Result<(), AppError> {
+        let conn = lock_conn!(self.conn);
+
+        conn.execute(
+            "INSERT OR REPLACE INTO proxy_circuit_state (
+                upstream_key, provider_id, base_url, state, consecutive_failures,
+                window_requests, window_failures, avg_latency_ms, open_until, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                state.upstream_key,
+                state.provider_id,
+                state.base_url,
+                state.state,
+                state.consecutive_failures as i64,
+                state.window_requests as i64,
+                state.window_failures as i64,
+                state.avg_latency_ms as i64,
+                state.open_until,
+                state.updated_at,
+            ],
+        )
+        .map_err(|e| AppError::Database(format!("保存熔断状态失败: {e}")))?;
+
+        Ok(())
+    }
+
     /// 保存配置备份
     pub fn save_live_backup(&self, app_type: &str, original_config: &str) -> Result<(), AppError> {
         let conn = lock_conn!(self.conn);
@@ -1165,6 +1426,121 @@ impl Database {
         Ok(())
     }
 
+    /// 获取所有消费预算
+    pub fn list_usage_budgets(&self) -> Result<Vec<UsageBudgetDb>, AppError> {
+        let conn = lock_conn!(self.conn);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, scope, scope_value, period, limit_usd, hard_cap, enabled,
+                        alerted_threshold, alerted_period_start, created_at, updated_at
+                 FROM usage_budgets ORDER BY created_at",
+            )
+            .map_err(|e| AppError::Database(format!("查询消费预算失败: {e}")))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(UsageBudgetDb {
+                    id: row.get(0)?,
+                    scope: row.get(1)?,
+                    scope_value: row.get(2)?,
+                    period: row.get(3)?,
+                    limit_usd: row.get(4)?,
+                    hard_cap: row.get::<_, i32>(5)? != 0,
+                    enabled: row.get::<_, i32>(6)? != 0,
+                    alerted_threshold: row.get::<_, i64>(7)? as u32,
+                    alerted_period_start: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                })
+            })
+            .map_err(|e| AppError::Database(format!("查询消费预算失败: {e}")))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AppError::Database(format!("读取消费预算失败: {e}")))
+    }
+
+    /// 新增或更新消费预算
+    pub fn save_usage_budget(&self, budget: &UsageBudgetDb) -> Result<(), AppError> {
+        let conn = lock_conn!(self.conn);
+
+        conn.execute(
+            "INSERT OR REPLACE INTO usage_budgets (
+                id, scope, scope_value, period, limit_usd, hard_cap, enabled,
+                alerted_threshold, alerted_period_start, created_at, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params![
+                budget.id,
+                budget.scope,
+                budget.scope_value,
+                budget.period,
+                budget.limit_usd,
+                if budget.hard_cap { 1 } else { 0 },
+                if budget.enabled { 1 } else { 0 },
+                budget.alerted_threshold as i64,
+                budget.alerted_period_start,
+                budget.created_at,
+                budget.updated_at,
+            ],
+        )
+        .map_err(|e| AppError::Database(format!("保存消费预算失败: {e}")))?;
+
+        Ok(())
+    }
+
+    /// 删除消费预算
+    pub fn delete_usage_budget(&self, id: &str) -> Result<(), AppError> {
+        let conn = lock_conn!(self.conn);
+
+        conn.execute("DELETE FROM usage_budgets WHERE id = ?1", [id])
+            .map_err(|e| AppError::Database(format!("删除消费预算失败: {e}")))?;
+
+        Ok(())
+    }
+
+    /// 记录预算在当前周期已提醒到的阈值
+    pub fn update_budget_alert(
+        &self,
+        id: &str,
+        threshold: u32,
+        period_start: i64,
+    ) -> Result<(), AppError> {
+        let conn = lock_conn!(self.conn);
+
+        conn.execute(
+            "UPDATE usage_budgets SET alerted_threshold = ?1, alerted_period_start = ?2 WHERE id = ?3",
+            rusqlite::params![threshold as i64, period_start, id],
+        )
+        .map_err(|e| AppError::Database(format!("更新预算提醒状态失败: {e}")))?;
+
+        Ok(())
+    }
+
+    /// 统计某个范围自 `since` 起的消费（美元）
+    pub fn sum_usage_cost(
+        &self,
+        scope: &str,
+        scope_value: &str,
+        since: i64,
+    ) -> Result<f64, AppError> {
+        let column = match scope {
+            "app_type" => "app_type",
+            "provider" => "provider_id",
+            "project" => "project_name",
+            _ => return Err(AppError::Database(format!("未知的预算范围: {scope}"))),
+        };
+        let conn = lock_conn!(self.conn);
+
+        conn.query_row(
+            &format!(
+                "SELECT COALESCE(SUM(CAST(total_cost_usd AS REAL)), 0)
+                 FROM proxy_request_logs WHERE {column} = ?1 AND created_at >= ?2"
+            ),
+            rusqlite::params![scope_value, since],
+            |row| row.get(0),
+        )
+        .map_err(|e| AppError::Database(format!("统计消费失败: {e}")))
+    }
+
     /// 清除所有使用统计
     pub fn clear_usage_stats(&self) -> Result<(), AppError> {
         let conn = lock_conn!(self.conn);
@@ -0,0 +1,314 @@
+//! 上游熔断
+//!
+//! 按上游统计最近请求的错误率、连续失败次数与延迟。连续失败或错误率过高时熔断，
+//! 冷却期内故障转移直接跳过该上游；冷却结束后放行一个探测请求（半开），成功即恢复。
+//! 状态变化写入数据库，代理重启后沿用
+
+use super::provider::UpstreamProvider;
+use super::types::UpstreamCircuitStatus;
+use crate::modules::opencode_db::schema::CircuitStateDb;
+use crate::modules::opencode_db::Database;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 统计窗口保留的最近请求数
+const WINDOW_SIZE: usize = 20;
+/// 窗口内请求数达到该值后才按错误率判断
+const MIN_SAMPLES: usize = 10;
+const ERROR_RATE_THRESHOLD: f64 = 0.5;
+const CONSECUTIVE_FAILURE_THRESHOLD: u32 = 5;
+/// 熔断冷却时间，同时作为半开探测的超时时间
+const OPEN_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "open" => CircuitState::Open,
+            "half_open" => CircuitState::HalfOpen,
+            _ => CircuitState::Closed,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UpstreamHealth {
+    provider_id: String,
+    base_url: String,
+    state: CircuitState,
+    /// 最近请求的结果与耗时（毫秒）
+    window: VecDeque<(bool, u64)>,
+    consecutive_failures: u32,
+    open_until: Option<i64>,
+    probe_in_flight: bool,
+}
+
+impl UpstreamHealth {
+    fn new(provider_id: &str, base_url: &str) -> Self {
+        Self {
+            provider_id: provider_id.to_string(),
+            base_url: base_url.to_string(),
+            state: CircuitState::Closed,
+            window: VecDeque::with_capacity(WINDOW_SIZE),
+            consecutive_failures: 0,
+            open_until: None,
+            probe_in_flight: false,
+        }
+    }
+
+    /// 数据库只保存窗口汇总，按汇总值还原窗口以保留错误率
+    fn from_db(row: CircuitStateDb) -> Self {
+        let failures = row.window_failures.min(row.window_requests) as usize;
+        let successes = row.window_requests as usize - failures;
+        let window = std::iter::repeat_n((false, row.avg_latency_ms), failures)
+            .chain(std::iter::repeat_n((true, row.avg_latency_ms), successes))
+            .collect();
+        Self {
+            provider_id: row.provider_id,
+            base_url: row.base_url,
+            state: CircuitState::parse(&row.state),
+            window,
+            consecutive_failures: row.consecutive_failures,
+            open_until: row.open_until,
+            probe_in_flight: false,
+        }
+    }
+
+    /// 是否放行本次请求；冷却结束的熔断转为半开，且同一时间只放行一个探测请求
+    fn try_acquire(&mut self, now: i64) -> bool {
+        // 半开状态下 open_until 为探测超时时间，探测请求未返回时允许重新探测
+        let expired = self.open_until.is_none_or(|until| until <= now);
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen if !self.probe_in_flight || expired => {
+                self.start_probe(now);
+                true
+            }
+            CircuitState::Open if expired => {
+                self.start_probe(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn start_probe(&mut self, now: i64) {
+        self.state = CircuitState::HalfOpen;
+        self.open_until = Some(now + OPEN_SECONDS);
+        self.probe_in_flight = true;
+    }
+
+    fn record(&mut self, success: bool, latency_ms: u64, now: i64) {
+        if self.state == CircuitState::HalfOpen && success {
+            // 探测成功，旧的失败记录不再参与判断
+            self.window.clear();
+        }
+        self.window.push_back((success, latency_ms));
+        if self.window.len() > WINDOW_SIZE {
+            self.window.pop_front();
+        }
+        self.consecutive_failures = if success {
+            0
+        } else {
+            self.consecutive_failures + 1
+        };
+
+        match self.state {
+            CircuitState::HalfOpen if success => {
+                self.state = CircuitState::Closed;
+                self.open_until = None;
+                self.probe_in_flight = false;
+            }
+            CircuitState::HalfOpen => self.open(now),
+            CircuitState::Closed if self.should_trip() => self.open(now),
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, now: i64) {
+        self.state = CircuitState::Open;
+        self.open_until = Some(now + OPEN_SECONDS);
+        self.probe_in_flight = false;
+    }
+
+    fn should_trip(&self) -> bool {
+        self.consecutive_failures >= CONSECUTIVE_FAILURE_THRESHOLD
+            || (self.window.len() >= MIN_SAMPLES && self.error_rate() >= ERROR_RATE_THRESHOLD)
+    }
+
+    fn failures(&self) -> usize {
+        self.window.iter().filter(|(success, _)| !success).count()
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.window.is_empty() {
+            return 0.0;
+        }
+        self.failures() as f64 / self.window.len() as f64
+    }
+
+    fn avg_latency_ms(&self) -> u64 {
+        if self.window.is_empty() {
+            return 0;
+        }
+        self.window.iter().map(|(_, ms)| ms).sum::<u64>() / self.window.len() as u64
+    }
+
+    fn to_db(&self, upstream_key: &str, now: i64) -> CircuitStateDb {
+        CircuitStateDb {
+            upstream_key: upstream_key.to_string(),
+            provider_id: self.provider_id.clone(),
+            base_url: self.base_url.clone(),
+            state: self.state.as_str().to_string(),
+            consecutive_failures: self.consecutive_failures,
+            window_requests: self.window.len() as u32,
+            window_failures: self.failures() as u32,
+            avg_latency_ms: self.avg_latency_ms(),
+            open_until: self.open_until,
+            updated_at: now,
+        }
+    }
+
+    fn status(&self) -> UpstreamCircuitStatus {
+        UpstreamCircuitStatus {
+            provider_id: self.provider_id.clone(),
+            base_url: self.base_url.clone(),
+            state: self.state.as_str().to_string(),
+            window_requests: self.window.len() as u32,
+            error_rate: self.error_rate(),
+            consecutive_failures: self.consecutive_failures,
+            avg_latency_ms: self.avg_latency_ms(),
+            open_until: self.open_until,
+        }
+    }
+}
+
+/// 代理内所有上游的熔断器
+pub struct CircuitBreakers {
+    upstreams: Mutex<HashMap<String, UpstreamHealth>>,
+}
+
+impl CircuitBreakers {
+    /// 从数据库恢复上次保存的熔断状态
+    pub fn load(db: &Database) -> Self {
+        let rows = db.list_circuit_states().unwrap_or_else(|e| {
+            tracing::warn!("[Proxy] 读取熔断状态失败: {}", e);
+            Vec::new()
+        });
+        let upstreams = rows
+            .into_iter()
+            .map(|row| (row.upstream_key.clone(), UpstreamHealth::from_db(row)))
+            .collect();
+        Self {
+            upstreams: Mutex::new(upstreams),
+        }
+    }
+
+    /// 熔断中的上游返回 false，调用方应跳过该上游
+    pub fn try_acquire(&self, upstream: &UpstreamProvider) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        let mut upstreams = self.upstreams.lock().unwrap_or_else(|e| e.into_inner());
+        match upstreams.get_mut(&upstream_key(upstream)) {
+            Some(health) => health.try_acquire(now),
+            None => true,
+        }
+    }
+
+    /// 记录一次请求结果，并将最新状态写入数据库
+    pub fn record(
+        &self,
+        db: &Database,
+        upstream: &UpstreamProvider,
+        success: bool,
+        latency: Duration,
+    ) {
+        let now = chrono::Utc::now().timestamp();
+        let key = upstream_key(upstream);
+        let row = {
+            let mut upstreams = self.upstreams.lock().unwrap_or_else(|e| e.into_inner());
+            let health = upstreams
+                .entry(key.clone())
+                .or_insert_with(|| UpstreamHealth::new(&upstream.id, &upstream.base_url));
+            let previous = health.state;
+            health.record(success, latency.as_millis() as u64, now);
+            if health.state != previous {
+                tracing::warn!(
+                    "[Proxy] 上游 {} ({}) 熔断状态: {} -> {}",
+                    upstream.id,
+                    upstream.base_url,
+                    previous.as_str(),
+                    health.state.as_str()
+                );
+            }
+            health.to_db(&key, now)
+        };
+        if let Err(e) = db.save_circuit_state(&row) {
+            tracing::warn!("[Proxy] 保存熔断状态失败: {}", e);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<UpstreamCircuitStatus> {
+        let upstreams = self.upstreams.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statuses: Vec<_> = upstreams.values().map(UpstreamHealth::status).collect();
+        statuses.sort_by(|a, b| (&a.provider_id, &a.base_url).cmp(&(&b.provider_id, &b.base_url)));
+        statuses
+    }
+}
+
+fn upstream_key(upstream: &UpstreamProvider) -> String {
+    format!("{}|{}", upstream.id, upstream.base_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_half_open_and_recover() {
+        let mut health = UpstreamHealth::new("relay", "https://relay.example.com");
+        for _ in 0..CONSECUTIVE_FAILURE_THRESHOLD {
+            assert!(health.try_acquire(0));
+            health.record(false, 100, 0);
+        }
+        assert_eq!(health.state, CircuitState::Open);
+        assert!(!health.try_acquire(OPEN_SECONDS - 1));
+
+        // 冷却结束只放行一个探测请求
+        assert!(health.try_acquire(OPEN_SECONDS));
+        assert_eq!(health.state, CircuitState::HalfOpen);
+        assert!(!health.try_acquire(OPEN_SECONDS));
+
+        health.record(false, 100, OPEN_SECONDS);
+        assert_eq!(health.state, CircuitState::Open);
+
+        assert!(health.try_acquire(OPEN_SECONDS * 2));
+        health.record(true, 80, OPEN_SECONDS * 2);
+        assert_eq!(health.state, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.error_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_error_rate_trips_after_min_samples() {
+        let mut health = UpstreamHealth::new("relay", "https://relay.example.com");
+        for i in 0..MIN_SAMPLES {
+            health.record(i % 2 == 0, 100, 0);
+        }
+        assert_eq!(health.state, CircuitState::Open);
+    }
+}
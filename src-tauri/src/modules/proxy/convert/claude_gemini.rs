@@ -0,0 +1,763 @@
+//! Anthropic Messages ⇄ Gemini generateContent
+
+use super::{
+    anthropic_blocks, anthropic_system_text, anthropic_tool_result_text, is_custom_tool,
+    parse_arguments,
+};
+use crate::modules::gateway::stream::{sse_event, SseTranslator};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Anthropic 历史中没有 Gemini 的思考签名，按 Gemini 文档为历史中的函数调用填入跳过校验的占位签名
+const SKIP_THOUGHT_SIGNATURE: &str = "skip_thought_signature_validator";
+
+/// Gemini 函数声明不接受的 JSON Schema 关键字
+const UNSUPPORTED_SCHEMA_KEYS: &[&str] = &[
+    "$schema",
+    "$id",
+    "additionalProperties",
+    "default",
+    "examples",
+    "propertyNames",
+];
+
+/// Anthropic Messages 请求 → generateContent 请求（模型名在请求路径中）
+pub fn convert_request(json: &Value) -> Value {
+    let mut out = Map::new();
+
+    if let Some(system) = json.get("system").and_then(anthropic_system_text) {
+        out.insert(
+            "systemInstruction".to_string(),
+            json!({"parts": [{"text": system}]}),
+        );
+    }
+
+    let messages = json
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.as_slice())
+        .unwrap_or(&[]);
+    out.insert(
+        "contents".to_string(),
+        Value::Array(convert_messages(messages)),
+    );
+
+    if let Some(tools) = json.get("tools").and_then(|t| t.as_array()) {
+        let declarations: Vec<Value> = tools.iter().filter_map(convert_tool).collect();
+        if !declarations.is_empty() {
+            out.insert(
+                "tools".to_string(),
+                json!([{"functionDeclarations": declarations}]),
+            );
+        }
+    }
+    if let Some(config) = json.get("tool_choice").and_then(convert_tool_choice) {
+        out.insert(
+            "toolConfig".to_string(),
+            json!({"functionCallingConfig": config}),
+        );
+    }
+
+    let mut generation = Map::new();
+    for (from, to) in [
+        ("max_tokens", "maxOutputTokens"),
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("top_k", "topK"),
+        ("stop_sequences", "stopSequences"),
+    ] {
+        if let Some(value) = json.get(from) {
+            generation.insert(to.to_string(), value.clone());
+        }
+    }
+    if let Some(thinking) = json.get("thinking") {
+        if thinking.get("type").and_then(|t| t.as_str()) == Some("enabled") {
+            let mut config = json!({"includeThoughts": true});
+            if let Some(budget) = thinking.get("budget_tokens") {
+                config["thinkingBudget"] = budget.clone();
+            }
+            generation.insert("thinkingConfig".to_string(), config);
+        }
+    }
+    if !generation.is_empty() {
+        out.insert("generationConfig".to_string(), Value::Object(generation));
+    }
+
+    Value::Object(out)
+}
+
+/// Gemini 的 functionResponse 需要函数名，按 tool_use_id 回查对应 tool_use 的名称
+fn convert_messages(messages: &[Value]) -> Vec<Value> {
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+    let mut contents = Vec::new();
+
+    for msg in messages {
+        let is_assistant = msg.get("role").and_then(|r| r.as_str()) == Some("assistant");
+        let mut parts = Vec::new();
+        for block in anthropic_blocks(msg) {
+            match block.get("type").and_then(|t| t.as_str()).unwrap_or("text") {
+                "text" => {
+                    let text = block.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                    if !text.is_empty() {
+                        parts.push(json!({"text": text}));
+                    }
+                }
+                "image" => {
+                    if let Some(image) = convert_image(&block) {
+                        parts.push(image);
+                    }
+                }
+                "tool_use" => {
+                    let name = block.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                    if let Some(id) = block.get("id").and_then(|i| i.as_str()) {
+                        tool_names.insert(id.to_string(), name.to_string());
+                    }
+                    parts.push(json!({
+                        "functionCall": {
+                            "name": name,
+                            "args": block.get("input").cloned().unwrap_or_else(|| json!({})),
+                        },
+                        "thoughtSignature": SKIP_THOUGHT_SIGNATURE,
+                    }));
+                }
+                "tool_result" => {
+                    let id = block
+                        .get("tool_use_id")
+                        .and_then(|i| i.as_str())
+                        .unwrap_or("");
+                    let name = tool_names.get(id).cloned().unwrap_or_default();
+                    parts.push(json!({
+                        "functionResponse": {
+                            "name": name,
+                            "response": {"content": anthropic_tool_result_text(&block)},
+                        }
+                    }));
+                }
+                // 上游无法校验 Anthropic 的思考签名，历史中的 thinking 块直接丢弃
+                _ => {}
+            }
+        }
+        if !parts.is_empty() {
+            let role = if is_assistant { "model" } else { "user" };
+            contents.push(json!({"role": role, "parts": parts}));
+        }
+    }
+
+    contents
+}
+
+/// Gemini 只能引用已上传文件的 fileUri，外链图片无法直接传递，直接丢弃
+fn convert_image(block: &Value) -> Option<Value> {
+    let source = block.get("source")?;
+    if source.get("type").and_then(|t| t.as_str()) != Some("base64") {
+        return None;
+    }
+    Some(json!({
+        "inlineData": {
+            "mimeType": source.get("media_type").and_then(|m| m.as_str()).unwrap_or("image/png"),
+            "data": source.get("data")?,
+        }
+    }))
+}
+
+fn convert_tool(tool: &Value) -> Option<Value> {
+    if !is_custom_tool(tool) {
+        return None;
+    }
+    let mut declaration = json!({
+        "name": tool.get("name")?,
+        "description": tool.get("description").cloned().unwrap_or(Value::Null),
+    });
+    if let Some(schema) = tool.get("input_schema") {
+        declaration["parameters"] = clean_schema(schema);
+    }
+    Some(declaration)
+}
+
+fn clean_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !UNSUPPORTED_SCHEMA_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| match (key.as_str(), value) {
+                    // properties 的键是字段名而非关键字，只清理各字段的 schema
+                    ("properties", Value::Object(properties)) => (
+                        key.clone(),
+                        Value::Object(
+                            properties
+                                .iter()
+                                .map(|(name, property)| (name.clone(), clean_schema(property)))
+                                .collect(),
+                        ),
+                    ),
+                    _ => (key.clone(), clean_schema(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(clean_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn convert_tool_choice(choice: &Value) -> Option<Value> {
+    match choice.get("type").and_then(|t| t.as_str())? {
+        "auto" => Some(json!({"mode": "AUTO"})),
+        "any" => Some(json!({"mode": "ANY"})),
+        "none" => Some(json!({"mode": "NONE"})),
+        "tool" => Some(json!({"mode": "ANY", "allowedFunctionNames": [choice.get("name")?]})),
+        _ => None,
+    }
+}
+
+/// generateContent 非流式响应 → Anthropic Messages 响应，`model` 为客户端请求的模型名
+pub fn convert_response(resp: &Value, model: &str) -> Value {
+    let candidate = resp
+        .get("candidates")
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first());
+
+    let mut content: Vec<Value> = Vec::new();
+    let mut has_tool_use = false;
+    for part in candidate_parts(candidate) {
+        if let Some(call) = part.get("functionCall") {
+            has_tool_use = true;
+            content.push(json!({
+                "type": "tool_use",
+                "id": tool_use_id(call),
+                "name": call.get("name").cloned().unwrap_or(Value::Null),
+                "input": parse_arguments(call.get("args")),
+            }));
+            continue;
+        }
+        let Some(text) = part.get("text").and_then(|t| t.as_str()) else {
+            continue;
+        };
+        let (kind, key) = if is_thought(part) {
+            ("thinking", "thinking")
+        } else {
+            ("text", "text")
+        };
+        // 相邻的同类文本片段合并为一个块
+        match content.last_mut() {
+            Some(last) if last["type"] == kind => {
+                let merged = format!("{}{}", last[key].as_str().unwrap_or(""), text);
+                last[key] = Value::String(merged);
+            }
+            _ if kind == "thinking" => {
+                content.push(json!({"type": "thinking", "thinking": text, "signature": ""}))
+            }
+            _ => content.push(json!({"type": "text", "text": text})),
+        }
+    }
+
+    let finish_reason = candidate
+        .and_then(|c| c.get("finishReason"))
+        .and_then(|r| r.as_str());
+    let blocked = candidate.is_none()
+        && resp
+            .get("promptFeedback")
+            .and_then(|f| f.get("blockReason"))
+            .is_some();
+    json!({
+        "id": resp.get("responseId").cloned().unwrap_or(Value::Null),
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": if blocked { "refusal" } else { stop_reason(finish_reason, has_tool_use) },
+        "stop_sequence": Value::Null,
+        "usage": convert_usage(resp.get("usageMetadata")),
+    })
+}
+
+fn candidate_parts(candidate: Option<&Value>) -> &[Value] {
+    candidate
+        .and_then(|c| c.get("content"))
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.as_array())
+        .map(|p| p.as_slice())
+        .unwrap_or(&[])
+}
+
+fn is_thought(part: &Value) -> bool {
+    part.get("thought")
+        .and_then(|t| t.as_bool())
+        .unwrap_or(false)
+}
+
+/// Gemini 的函数调用不一定带 id，缺失时生成一个供客户端回传 tool_result
+fn tool_use_id(call: &Value) -> String {
+    call.get("id")
+        .and_then(|i| i.as_str())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| format!("toolu_{}", uuid::Uuid::new_v4().simple()))
+}
+
+fn stop_reason(finish_reason: Option<&str>, has_tool_use: bool) -> &'static str {
+    match finish_reason {
+        Some("MAX_TOKENS") => "max_tokens",
+        Some("SAFETY")
+        | Some("RECITATION")
+        | Some("BLOCKLIST")
+        | Some("PROHIBITED_CONTENT")
+        | Some("SPII") => "refusal",
+        _ if has_tool_use => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+/// Gemini 的 promptTokenCount 包含缓存命中部分，思考 token 单独计数，均需折算为 Anthropic 口径
+fn convert_usage(usage: Option<&Value>) -> Value {
+    let get = |key: &str| {
+        usage
+            .and_then(|u| u.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+    let cached = get("cachedContentTokenCount");
+    json!({
+        "input_tokens": get("promptTokenCount").saturating_sub(cached),
+        "output_tokens": get("candidatesTokenCount") + get("thoughtsTokenCount"),
+        "cache_read_input_tokens": cached,
+        "cache_creation_input_tokens": 0,
+    })
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum BlockKind {
+    Text,
+    Thinking,
+}
+
+/// generateContent SSE 数据块 → Anthropic Messages SSE 事件
+pub struct GeminiToAnthropicStream {
+    model: String,
+    message_started: bool,
+    finished: bool,
+    next_index: usize,
+    /// 当前打开的文本或思考块: (块类型, Anthropic 块 index)；函数调用整块到达，不保持打开
+    open_block: Option<(BlockKind, usize)>,
+    has_tool_use: bool,
+    finish_reason: Option<String>,
+    usage: Option<Value>,
+}
+
+impl GeminiToAnthropicStream {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            message_started: false,
+            finished: false,
+            next_index: 0,
+            open_block: None,
+            has_tool_use: false,
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn start_message(&mut self, chunk: &Value, out: &mut Vec<u8>) {
+        if self.message_started {
+            return;
+        }
+        self.message_started = true;
+        out.extend(sse_event(
+            "message_start",
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": chunk.get("responseId").cloned().unwrap_or(Value::Null),
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [],
+                    "stop_reason": Value::Null,
+                    "stop_sequence": Value::Null,
+                    "usage": {"input_tokens": 0, "output_tokens": 0},
+                }
+            }),
+        ));
+    }
+
+    fn close_block(&mut self, out: &mut Vec<u8>) {
+        if let Some((_, index)) = self.open_block.take() {
+            out.extend(sse_event(
+                "content_block_stop",
+                &json!({"type": "content_block_stop", "index": index}),
+            ));
+        }
+    }
+
+    fn start_block(&mut self, content_block: Value, out: &mut Vec<u8>) -> usize {
+        self.close_block(out);
+        let index = self.next_index;
+        self.next_index += 1;
+        out.extend(sse_event(
+            "content_block_start",
+            &json!({"type": "content_block_start", "index": index, "content_block": content_block}),
+        ));
+        index
+    }
+
+    fn ensure_block(&mut self, kind: BlockKind, out: &mut Vec<u8>) -> usize {
+        if let Some((open_kind, index)) = self.open_block {
+            if open_kind == kind {
+                return index;
+            }
+        }
+        let content_block = match kind {
+            BlockKind::Text => json!({"type": "text", "text": ""}),
+            BlockKind::Thinking => json!({"type": "thinking", "thinking": "", "signature": ""}),
+        };
+        let index = self.start_block(content_block, out);
+        self.open_block = Some((kind, index));
+        index
+    }
+
+    fn delta(&self, index: usize, delta: Value) -> Vec<u8> {
+        sse_event(
+            "content_block_delta",
+            &json!({"type": "content_block_delta", "index": index, "delta": delta}),
+        )
+    }
+
+    fn on_part(&mut self, part: &Value, out: &mut Vec<u8>) {
+        if let Some(call) = part.get("functionCall") {
+            self.has_tool_use = true;
+            let index = self.start_block(
+                json!({
+                    "type": "tool_use",
+                    "id": tool_use_id(call),
+                    "name": call.get("name").cloned().unwrap_or(Value::Null),
+                    "input": {},
+                }),
+                out,
+            );
+            let arguments = parse_arguments(call.get("args")).to_string();
+            out.extend(self.delta(
+                index,
+                json!({"type": "input_json_delta", "partial_json": arguments}),
+            ));
+            out.extend(sse_event(
+                "content_block_stop",
+                &json!({"type": "content_block_stop", "index": index}),
+            ));
+            return;
+        }
+        let Some(text) = part.get("text").and_then(|t| t.as_str()) else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        if is_thought(part) {
+            let index = self.ensure_block(BlockKind::Thinking, out);
+            out.extend(self.delta(index, json!({"type": "thinking_delta", "thinking": text})));
+        } else {
+            let index = self.ensure_block(BlockKind::Text, out);
+            out.extend(self.delta(index, json!({"type": "text_delta", "text": text})));
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        self.finished = true;
+        self.start_message(&Value::Null, out);
+        self.close_block(out);
+        out.extend(sse_event(
+            "message_delta",
+            &json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": stop_reason(self.finish_reason.as_deref(), self.has_tool_use),
+                    "stop_sequence": Value::Null,
+                },
+                "usage": convert_usage(self.usage.as_ref()),
+            }),
+        ));
+        out.extend(sse_event("message_stop", &json!({"type": "message_stop"})));
+    }
+
+    fn error(&mut self, message: &str, out: &mut Vec<u8>) {
+        self.finished = true;
+        self.close_block(out);
+        out.extend(sse_event(
+            "error",
+            &json!({"type": "error", "error": {"type": "api_error", "message": message}}),
+        ));
+    }
+}
+
+impl SseTranslator for GeminiToAnthropicStream {
+    fn on_event(&mut self, _event: Option<&str>, data: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return out;
+        };
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("上游流返回错误");
+            self.error(message, &mut out);
+            return out;
+        }
+
+        self.start_message(&chunk, &mut out);
+        if let Some(usage) = chunk.get("usageMetadata") {
+            self.usage = Some(usage.clone());
+        }
+        let candidate = chunk
+            .get("candidates")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.first());
+        for part in candidate_parts(candidate) {
+            self.on_part(part, &mut out);
+        }
+        if let Some(reason) = candidate
+            .and_then(|c| c.get("finishReason"))
+            .and_then(|r| r.as_str())
+        {
+            self.finish_reason = Some(reason.to_string());
+        }
+        out
+    }
+
+    /// Gemini 流没有结束标记，用量可能在 finishReason 之后的数据块中到达，因此在流结束时收尾
+    fn on_end(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        if self.finish_reason.is_some() {
+            self.finish(&mut out);
+        } else {
+            self.error("上游流在响应完成前结束", &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次送入数据块并结束流，返回 (事件名, 数据) 列表
+    fn translate(chunks: &[Value]) -> Vec<(String, Value)> {
+        let mut translator = GeminiToAnthropicStream::new("claude-sonnet-4");
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend(translator.on_event(None, &chunk.to_string()));
+        }
+        out.extend(translator.on_end());
+
+        let text = String::from_utf8(out).unwrap();
+        text.split("\n\n")
+            .filter_map(|event| {
+                let (name, data) = event.split_once('\n')?;
+                Some((
+                    name.strip_prefix("event: ")?.to_string(),
+                    serde_json::from_str(data.strip_prefix("data: ")?).unwrap(),
+                ))
+            })
+            .collect()
+    }
+
+    fn response_with(parts: Value, finish_reason: &str) -> Value {
+        json!({"candidates": [{"content": {"role": "model", "parts": parts}, "finishReason": finish_reason}]})
+    }
+
+    #[test]
+    fn test_tool_use_round_trip_resolves_function_names() {
+        let request = json!({
+            "tools": [
+                {
+                    "name": "read",
+                    "input_schema": {
+                        "$schema": "http://json-schema.org/draft-07/schema#",
+                        "type": "object",
+                        "properties": {"default": {"type": "string", "default": "a"}},
+                        "additionalProperties": false
+                    }
+                },
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "tool_choice": {"type": "tool", "name": "read"},
+            "messages": [
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "...", "signature": "sig"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {"path": "a"}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "list", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_2", "content": "a\nb"},
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "denied", "is_error": true}
+                ]}
+            ]
+        });
+
+        let converted = convert_request(&request);
+        let declarations = converted["tools"][0]["functionDeclarations"]
+            .as_array()
+            .unwrap();
+        assert_eq!(declarations.len(), 1);
+        // 名为 default 的字段保留，default 关键字移除
+        assert_eq!(
+            declarations[0]["parameters"],
+            json!({"type": "object", "properties": {"default": {"type": "string"}}})
+        );
+        assert_eq!(
+            converted["toolConfig"]["functionCallingConfig"],
+            json!({"mode": "ANY", "allowedFunctionNames": ["read"]})
+        );
+
+        let contents = converted["contents"].as_array().unwrap();
+        let calls = contents[0]["parts"].as_array().unwrap();
+        // thinking 块被丢弃，历史函数调用带跳过校验的签名
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0]["thoughtSignature"], SKIP_THOUGHT_SIGNATURE);
+        let responses = contents[1]["parts"].as_array().unwrap();
+        assert_eq!(responses[0]["functionResponse"]["name"], "list");
+        assert_eq!(responses[1]["functionResponse"]["name"], "read");
+        assert_eq!(
+            responses[1]["functionResponse"]["response"]["content"],
+            "[error] denied"
+        );
+
+        let response = response_with(
+            json!([
+                {"functionCall": {"id": "fc_1", "name": "read", "args": {"path": "b"}}},
+                {"functionCall": {"name": "read", "args": {"path": "c"}}}
+            ]),
+            "STOP",
+        );
+        let content = convert_response(&response, "claude-sonnet-4")["content"].clone();
+        assert_eq!(content[0]["id"], "fc_1");
+        assert_eq!(content[0]["input"], json!({"path": "b"}));
+        // 缺少 id 的函数调用生成新 id，供客户端回传 tool_result
+        let generated = content[1]["id"].as_str().unwrap();
+        assert!(generated.starts_with("toolu_"));
+        assert_ne!(generated, "fc_1");
+    }
+
+    #[test]
+    fn test_system_prompt_moves_to_system_instruction() {
+        let request = json!({
+            "system": [{"type": "text", "text": "rule one"}, {"type": "text", "text": "rule two"}],
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let converted = convert_request(&request);
+        assert_eq!(
+            converted["systemInstruction"],
+            json!({"parts": [{"text": "rule one\n\nrule two"}]})
+        );
+        let contents = converted["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 1);
+        assert_eq!(
+            contents[0],
+            json!({"role": "user", "parts": [{"text": "hi"}]})
+        );
+
+        let request = json!({"system": [], "messages": [{"role": "user", "content": "hi"}]});
+        assert!(convert_request(&request).get("systemInstruction").is_none());
+    }
+
+    #[test]
+    fn test_images_become_inline_data_and_url_images_are_dropped() {
+        let request = json!({
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "AAAA"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+                ]}
+            ]
+        });
+
+        let contents = convert_request(&request)["contents"].clone();
+        assert_eq!(contents.as_array().unwrap().len(), 1);
+        assert_eq!(
+            contents[0]["parts"][1],
+            json!({"inlineData": {"mimeType": "image/jpeg", "data": "AAAA"}})
+        );
+    }
+
+    #[test]
+    fn test_finish_reason_maps_to_stop_reason() {
+        let text = json!([{"text": "ok"}]);
+        let call = json!([{"functionCall": {"name": "read", "args": {}}}]);
+        for (parts, finish_reason, expected) in [
+            (&text, "STOP", "end_turn"),
+            (&text, "MAX_TOKENS", "max_tokens"),
+            (&text, "SAFETY", "refusal"),
+            (&text, "RECITATION", "refusal"),
+            (&text, "OTHER", "end_turn"),
+            // Gemini 返回函数调用时 finishReason 仍为 STOP
+            (&call, "STOP", "tool_use"),
+            (&call, "MAX_TOKENS", "max_tokens"),
+        ] {
+            let response = response_with(parts.clone(), finish_reason);
+            assert_eq!(
+                convert_response(&response, "claude-sonnet-4")["stop_reason"],
+                expected,
+                "finishReason {}",
+                finish_reason
+            );
+        }
+
+        let blocked = json!({"promptFeedback": {"blockReason": "SAFETY"}});
+        let converted = convert_response(&blocked, "claude-sonnet-4");
+        assert_eq!(converted["stop_reason"], "refusal");
+        assert_eq!(converted["content"], json!([]));
+    }
+
+    #[test]
+    fn test_stream_usage_from_chunk_after_finish_reason() {
+        let events = translate(&[
+            json!({"responseId": "r1", "candidates": [{"content": {"parts": [{"text": "plan", "thought": true}]}}],
+                   "usageMetadata": {"promptTokenCount": 50}}),
+            json!({"candidates": [{"content": {"parts": [{"text": "Hi"}]}, "finishReason": "MAX_TOKENS"}]}),
+            json!({"candidates": [{"content": {"parts": []}}],
+                   "usageMetadata": {"promptTokenCount": 50, "candidatesTokenCount": 7, "thoughtsTokenCount": 3, "cachedContentTokenCount": 20}}),
+        ]);
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[1].1["content_block"]["type"], "thinking");
+        let delta = &events[7].1;
+        assert_eq!(delta["delta"]["stop_reason"], "max_tokens");
+        // 思考 token 计入输出，缓存命中从输入中扣除
+        assert_eq!(
+            delta["usage"],
+            json!({"input_tokens": 30, "output_tokens": 10, "cache_read_input_tokens": 20, "cache_creation_input_tokens": 0})
+        );
+    }
+
+    #[test]
+    fn test_stream_without_finish_reason_ends_with_error() {
+        let events = translate(&[
+            json!({"responseId": "r1", "candidates": [{"content": {"parts": [{"text": "Hi"}]}}]}),
+        ]);
+        let (name, error) = events.last().unwrap();
+        assert_eq!(name, "error");
+        assert_eq!(error["error"]["type"], "api_error");
+        assert!(!events.iter().any(|(name, _)| name == "message_stop"));
+    }
+}
@@ -0,0 +1,709 @@
+//! Anthropic Messages ⇄ OpenAI Chat Completions
+
+use super::{
+    anthropic_blocks, anthropic_system_text, anthropic_tool_result_text, is_custom_tool,
+    parse_arguments,
+};
+use crate::modules::gateway::stream::{sse_event, SseTranslator};
+use serde_json::{json, Map, Value};
+
+/// Anthropic Messages 请求 → Chat Completions 请求，`model` 为发往上游的模型名
+pub fn convert_request(json: &Value, model: &str) -> Value {
+    let mut out = Map::new();
+    out.insert("model".to_string(), Value::String(model.to_string()));
+
+    let mut messages = Vec::new();
+    if let Some(system) = json.get("system").and_then(anthropic_system_text) {
+        messages.push(json!({"role": "system", "content": system}));
+    }
+    for msg in json
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.as_slice())
+        .unwrap_or(&[])
+    {
+        convert_message(msg, &mut messages);
+    }
+    out.insert("messages".to_string(), Value::Array(messages));
+
+    if let Some(tools) = json.get("tools").and_then(|t| t.as_array()) {
+        let converted: Vec<Value> = tools.iter().filter_map(convert_tool).collect();
+        if !converted.is_empty() {
+            out.insert("tools".to_string(), Value::Array(converted));
+        }
+    }
+
+    if let Some(tool_choice) = json.get("tool_choice") {
+        if let Some(choice) = convert_tool_choice(tool_choice) {
+            out.insert("tool_choice".to_string(), choice);
+        }
+        if tool_choice
+            .get("disable_parallel_tool_use")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            out.insert("parallel_tool_calls".to_string(), Value::Bool(false));
+        }
+    }
+
+    // thinking 不做映射：OpenAI 兼容服务商对 reasoning_effort 的支持不一，部分会直接拒绝请求
+    for (from, to) in [
+        ("max_tokens", "max_tokens"),
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("stop_sequences", "stop"),
+    ] {
+        if let Some(value) = json.get(from) {
+            out.insert(to.to_string(), value.clone());
+        }
+    }
+
+    if let Some(user_id) = json
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(|v| v.as_str())
+    {
+        out.insert("user".to_string(), Value::String(user_id.to_string()));
+    }
+
+    Value::Object(out)
+}
+
+/// assistant 消息的 tool_use 合并为 tool_calls；user 消息的 tool_result 拆为 tool 消息，
+/// 且排在同一条消息的其余内容之前，保证紧跟在对应的 tool_calls 之后
+fn convert_message(msg: &Value, messages: &mut Vec<Value>) {
+    let blocks = anthropic_blocks(msg);
+
+    if msg.get("role").and_then(|r| r.as_str()) == Some("assistant") {
+        let mut text = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in &blocks {
+            match block.get("type").and_then(|t| t.as_str()).unwrap_or("text") {
+                "text" => text.push(block.get("text").and_then(|t| t.as_str()).unwrap_or("")),
+                "tool_use" => tool_calls.push(json!({
+                    "id": block.get("id").cloned().unwrap_or(Value::Null),
+                    "type": "function",
+                    "function": {
+                        "name": block.get("name").cloned().unwrap_or(Value::Null),
+                        "arguments": block
+                            .get("input")
+                            .map(|i| i.to_string())
+                            .unwrap_or_else(|| "{}".to_string()),
+                    },
+                })),
+                // 上游无法校验 Anthropic 的思考签名，历史中的 thinking 块直接丢弃
+                _ => {}
+            }
+        }
+        if text.is_empty() && tool_calls.is_empty() {
+            return;
+        }
+        let mut message = json!({"role": "assistant", "content": text.join("")});
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        messages.push(message);
+        return;
+    }
+
+    let mut parts = Vec::new();
+    let mut has_image = false;
+    for block in &blocks {
+        match block.get("type").and_then(|t| t.as_str()).unwrap_or("text") {
+            "text" => {
+                let text = block.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                parts.push(json!({"type": "text", "text": text}));
+            }
+            "image" => {
+                if let Some(image) = convert_image(block) {
+                    has_image = true;
+                    parts.push(image);
+                }
+            }
+            "tool_result" => messages.push(json!({
+                "role": "tool",
+                "tool_call_id": block.get("tool_use_id").cloned().unwrap_or(Value::Null),
+                "content": anthropic_tool_result_text(block),
+            })),
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        return;
+    }
+    // 部分 OpenAI 兼容服务商不接受数组形式的 content，纯文本时合并为字符串
+    let content = if has_image {
+        Value::Array(parts)
+    } else {
+        Value::String(
+            parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    };
+    messages.push(json!({"role": "user", "content": content}));
+}
+
+fn convert_image(block: &Value) -> Option<Value> {
+    let source = block.get("source")?;
+    let url = match source.get("type").and_then(|t| t.as_str()) {
+        Some("base64") => format!(
+            "data:{};base64,{}",
+            source
+                .get("media_type")
+                .and_then(|m| m.as_str())
+                .unwrap_or("image/png"),
+            source.get("data").and_then(|d| d.as_str())?
+        ),
+        Some("url") => source.get("url").and_then(|u| u.as_str())?.to_string(),
+        _ => return None,
+    };
+    Some(json!({"type": "image_url", "image_url": {"url": url}}))
+}
+
+fn convert_tool(tool: &Value) -> Option<Value> {
+    if !is_custom_tool(tool) {
+        return None;
+    }
+    Some(json!({
+        "type": "function",
+        "function": {
+            "name": tool.get("name")?,
+            "description": tool.get("description").cloned().unwrap_or(Value::Null),
+            "parameters": tool
+                .get("input_schema")
+                .cloned()
+                .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+        },
+    }))
+}
+
+fn convert_tool_choice(choice: &Value) -> Option<Value> {
+    match choice.get("type").and_then(|t| t.as_str())? {
+        "auto" => Some(json!("auto")),
+        "any" => Some(json!("required")),
+        "none" => Some(json!("none")),
+        "tool" => Some(json!({"type": "function", "function": {"name": choice.get("name")?}})),
+        _ => None,
+    }
+}
+
+/// Chat Completions 非流式响应 → Anthropic Messages 响应，`model` 为客户端请求的模型名
+pub fn convert_response(resp: &Value, model: &str) -> Value {
+    let choice = resp
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first());
+    let message = choice.and_then(|c| c.get("message"));
+    let field = |key: &str| message.and_then(|m| m.get(key));
+
+    let mut content = Vec::new();
+    // DeepSeek 等服务商以 reasoning_content 返回思考过程
+    if let Some(thinking) = field("reasoning_content").and_then(|r| r.as_str()) {
+        if !thinking.is_empty() {
+            content.push(json!({"type": "thinking", "thinking": thinking, "signature": ""}));
+        }
+    }
+    if let Some(text) = field("content").and_then(|c| c.as_str()) {
+        if !text.is_empty() {
+            content.push(json!({"type": "text", "text": text}));
+        }
+    }
+    let mut has_tool_use = false;
+    for call in field("tool_calls")
+        .and_then(|t| t.as_array())
+        .map(|t| t.as_slice())
+        .unwrap_or(&[])
+    {
+        has_tool_use = true;
+        let function = call.get("function");
+        content.push(json!({
+            "type": "tool_use",
+            "id": call.get("id").cloned().unwrap_or(Value::Null),
+            "name": function.and_then(|f| f.get("name")).cloned().unwrap_or(Value::Null),
+            "input": parse_arguments(function.and_then(|f| f.get("arguments"))),
+        }));
+    }
+
+    let finish_reason = choice
+        .and_then(|c| c.get("finish_reason"))
+        .and_then(|r| r.as_str());
+    json!({
+        "id": resp.get("id").cloned().unwrap_or(Value::Null),
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason(finish_reason, has_tool_use),
+        "stop_sequence": Value::Null,
+        "usage": convert_usage(resp.get("usage")),
+    })
+}
+
+fn stop_reason(finish_reason: Option<&str>, has_tool_use: bool) -> &'static str {
+    match finish_reason {
+        Some("length") => "max_tokens",
+        Some("tool_calls") | Some("function_call") => "tool_use",
+        Some("content_filter") => "refusal",
+        _ if has_tool_use => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+/// Chat Completions 的 prompt_tokens 包含缓存命中部分，Anthropic 则分开计数
+fn convert_usage(usage: Option<&Value>) -> Value {
+    let get = |v: Option<&Value>| v.and_then(|v| v.as_u64()).unwrap_or(0);
+    let prompt = get(usage.and_then(|u| u.get("prompt_tokens")));
+    let cached = get(usage
+        .and_then(|u| u.get("prompt_tokens_details"))
+        .and_then(|d| d.get("cached_tokens")));
+    json!({
+        "input_tokens": prompt.saturating_sub(cached),
+        "output_tokens": get(usage.and_then(|u| u.get("completion_tokens"))),
+        "cache_read_input_tokens": cached,
+        "cache_creation_input_tokens": 0,
+    })
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum BlockKind {
+    Text,
+    Thinking,
+    /// 上游 tool_calls 中的 index
+    ToolUse(u64),
+}
+
+/// Chat Completions SSE 数据块 → Anthropic Messages SSE 事件
+pub struct ChatToAnthropicStream {
+    model: String,
+    message_started: bool,
+    finished: bool,
+    next_index: usize,
+    /// 当前打开的内容块: (块类型, Anthropic 块 index)
+    open_block: Option<(BlockKind, usize)>,
+    has_tool_use: bool,
+    finish_reason: Option<String>,
+    /// stream_options.include_usage 开启时，用量在 finish_reason 之后的数据块中返回
+    usage: Option<Value>,
+}
+
+impl ChatToAnthropicStream {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            message_started: false,
+            finished: false,
+            next_index: 0,
+            open_block: None,
+            has_tool_use: false,
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn start_message(&mut self, chunk: &Value, out: &mut Vec<u8>) {
+        if self.message_started {
+            return;
+        }
+        self.message_started = true;
+        out.extend(sse_event(
+            "message_start",
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": chunk.get("id").cloned().unwrap_or(Value::Null),
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [],
+                    "stop_reason": Value::Null,
+                    "stop_sequence": Value::Null,
+                    "usage": {"input_tokens": 0, "output_tokens": 0},
+                }
+            }),
+        ));
+    }
+
+    fn close_block(&mut self, out: &mut Vec<u8>) {
+        if let Some((_, index)) = self.open_block.take() {
+            out.extend(sse_event(
+                "content_block_stop",
+                &json!({"type": "content_block_stop", "index": index}),
+            ));
+        }
+    }
+
+    /// 确保对应类型的块已打开，返回其 Anthropic index
+    fn ensure_block(&mut self, kind: BlockKind, content_block: Value, out: &mut Vec<u8>) -> usize {
+        if let Some((open_kind, index)) = self.open_block {
+            if open_kind == kind {
+                return index;
+            }
+        }
+        self.close_block(out);
+        let index = self.next_index;
+        self.next_index += 1;
+        self.open_block = Some((kind, index));
+        out.extend(sse_event(
+            "content_block_start",
+            &json!({"type": "content_block_start", "index": index, "content_block": content_block}),
+        ));
+        index
+    }
+
+    fn delta(&self, index: usize, delta: Value) -> Vec<u8> {
+        sse_event(
+            "content_block_delta",
+            &json!({"type": "content_block_delta", "index": index, "delta": delta}),
+        )
+    }
+
+    fn on_tool_call(&mut self, call: &Value, out: &mut Vec<u8>) {
+        let call_index = call.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+        let function = call.get("function");
+        // 带 id 的数据块开始一个新的调用，部分服务商会在后续数据块中重复 id
+        if let Some(id) = call.get("id").filter(|id| !id.is_null()) {
+            self.has_tool_use = true;
+            self.ensure_block(
+                BlockKind::ToolUse(call_index),
+                json!({
+                    "type": "tool_use",
+                    "id": id,
+                    "name": function.and_then(|f| f.get("name")).cloned().unwrap_or(Value::Null),
+                    "input": {},
+                }),
+                out,
+            );
+        }
+        let partial = function
+            .and_then(|f| f.get("arguments"))
+            .and_then(|a| a.as_str())
+            .unwrap_or("");
+        if let Some((BlockKind::ToolUse(open_call), index)) = self.open_block {
+            if open_call == call_index && !partial.is_empty() {
+                out.extend(self.delta(
+                    index,
+                    json!({"type": "input_json_delta", "partial_json": partial}),
+                ));
+            }
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.start_message(&Value::Null, out);
+        self.close_block(out);
+        out.extend(sse_event(
+            "message_delta",
+            &json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": stop_reason(self.finish_reason.as_deref(), self.has_tool_use),
+                    "stop_sequence": Value::Null,
+                },
+                "usage": convert_usage(self.usage.as_ref()),
+            }),
+        ));
+        out.extend(sse_event("message_stop", &json!({"type": "message_stop"})));
+    }
+
+    fn error(&mut self, message: &str, out: &mut Vec<u8>) {
+        self.finished = true;
+        self.close_block(out);
+        out.extend(sse_event(
+            "error",
+            &json!({"type": "error", "error": {"type": "api_error", "message": message}}),
+        ));
+    }
+}
+
+impl SseTranslator for ChatToAnthropicStream {
+    fn on_event(&mut self, _event: Option<&str>, data: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        if data.trim() == "[DONE]" {
+            self.finish(&mut out);
+            return out;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return out;
+        };
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("上游流返回错误");
+            self.error(message, &mut out);
+            return out;
+        }
+
+        self.start_message(&chunk, &mut out);
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = Some(usage.clone());
+        }
+        let Some(choice) = chunk
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.first())
+        else {
+            return out;
+        };
+
+        let delta = choice.get("delta");
+        let text_of = |key: &str| {
+            delta
+                .and_then(|d| d.get(key))
+                .and_then(|t| t.as_str())
+                .filter(|t| !t.is_empty())
+        };
+        if let Some(thinking) = text_of("reasoning_content") {
+            let index = self.ensure_block(
+                BlockKind::Thinking,
+                json!({"type": "thinking", "thinking": "", "signature": ""}),
+                &mut out,
+            );
+            out.extend(self.delta(
+                index,
+                json!({"type": "thinking_delta", "thinking": thinking}),
+            ));
+        }
+        if let Some(text) = text_of("content") {
+            let index = self.ensure_block(
+                BlockKind::Text,
+                json!({"type": "text", "text": ""}),
+                &mut out,
+            );
+            out.extend(self.delta(index, json!({"type": "text_delta", "text": text})));
+        }
+        for call in delta
+            .and_then(|d| d.get("tool_calls"))
+            .and_then(|t| t.as_array())
+            .map(|t| t.as_slice())
+            .unwrap_or(&[])
+        {
+            self.on_tool_call(call, &mut out);
+        }
+
+        if let Some(reason) = choice.get("finish_reason").and_then(|r| r.as_str()) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        out
+    }
+
+    fn on_end(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        // 部分服务商不发送 [DONE]，收到 finish_reason 即视为正常结束
+        if self.finish_reason.is_some() {
+            self.finish(&mut out);
+        } else {
+            self.error("上游流在响应完成前结束", &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次送入数据块，返回 (事件名, 数据) 列表
+    fn translate(chunks: &[Value], done: bool) -> Vec<(String, Value)> {
+        let mut translator = ChatToAnthropicStream::new("claude-sonnet-4");
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend(translator.on_event(None, &chunk.to_string()));
+        }
+        if done {
+            out.extend(translator.on_event(None, "[DONE]"));
+        }
+        out.extend(translator.on_end());
+
+        let text = String::from_utf8(out).unwrap();
+        text.split("\n\n")
+            .filter_map(|event| {
+                let (name, data) = event.split_once('\n')?;
+                Some((
+                    name.strip_prefix("event: ")?.to_string(),
+                    serde_json::from_str(data.strip_prefix("data: ")?).unwrap(),
+                ))
+            })
+            .collect()
+    }
+
+    fn response_with(message: Value, finish_reason: Value) -> Value {
+        json!({"id": "chatcmpl-1", "choices": [{"message": message, "finish_reason": finish_reason}]})
+    }
+
+    #[test]
+    fn test_tool_use_round_trip_keeps_ids_and_orders_tool_results_first() {
+        let request = json!({
+            "tools": [
+                {"name": "read", "input_schema": {"type": "object"}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "tool_choice": {"type": "any", "disable_parallel_tool_use": true},
+            "messages": [
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "call_1", "name": "read", "input": {"path": "a"}},
+                    {"type": "tool_use", "id": "call_2", "name": "read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "both done"},
+                    {"type": "tool_result", "tool_use_id": "call_1", "content": [{"type": "text", "text": "data"}]},
+                    {"type": "tool_result", "tool_use_id": "call_2", "content": "missing", "is_error": true}
+                ]}
+            ]
+        });
+
+        let converted = convert_request(&request, "gpt-4o");
+        // 服务端工具在 OpenAI 上游没有对应实现
+        assert_eq!(converted["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(converted["tool_choice"], "required");
+        assert_eq!(converted["parallel_tool_calls"], false);
+        let messages = converted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        // 只有 tool_use 的 assistant 消息仍需 content 字段
+        assert_eq!(messages[0]["content"], "");
+        assert_eq!(messages[0]["tool_calls"][1]["id"], "call_2");
+        assert_eq!(messages[0]["tool_calls"][1]["function"]["arguments"], "{}");
+        // tool 消息紧跟 tool_calls，排在同一条消息的文本之前
+        assert_eq!(messages[1]["tool_call_id"], "call_1");
+        assert_eq!(messages[1]["content"], "data");
+        assert_eq!(messages[2]["tool_call_id"], "call_2");
+        assert_eq!(messages[2]["content"], "[error] missing");
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(messages[3]["content"], "both done");
+
+        let response = response_with(
+            json!({"content": null, "tool_calls": [
+                {"id": "call_3", "type": "function", "function": {"name": "read", "arguments": "{\"path\":\"b\"}"}},
+                {"id": "call_4", "type": "function", "function": {"name": "read", "arguments": "not json"}}
+            ]}),
+            json!("tool_calls"),
+        );
+        let content = convert_response(&response, "claude-sonnet-4")["content"].clone();
+        assert_eq!(content.as_array().unwrap().len(), 2);
+        assert_eq!(content[0]["id"], "call_3");
+        assert_eq!(content[0]["input"], json!({"path": "b"}));
+        assert_eq!(content[1]["input"], json!({}));
+    }
+
+    #[test]
+    fn test_system_prompt_becomes_leading_system_message() {
+        let request = json!({
+            "system": [{"type": "text", "text": "rule one"}, {"type": "text", "text": "rule two"}],
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "rule one\n\nrule two");
+        assert_eq!(messages[1]["role"], "user");
+
+        let request = json!({"system": "", "messages": [{"role": "user", "content": "hi"}]});
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        assert_eq!(messages.as_array().unwrap().len(), 1);
+        assert_eq!(messages[0]["role"], "user");
+    }
+
+    #[test]
+    fn test_images_switch_content_to_parts() {
+        let request = json!({
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "AAAA"}},
+                    {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "and this"},
+                    {"type": "image", "source": {"type": "file", "file_id": "file_1"}}
+                ]}
+            ]
+        });
+
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        let parts = messages[0]["content"].as_array().unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1]["image_url"]["url"], "data:image/jpeg;base64,AAAA");
+        assert_eq!(parts[2]["image_url"]["url"], "https://example.com/a.png");
+        // 无法转换的图片被丢弃后只剩文本，content 回到字符串形式
+        assert_eq!(messages[1]["content"], "and this");
+    }
+
+    #[test]
+    fn test_finish_reason_maps_to_stop_reason() {
+        let text = json!({"content": "ok"});
+        let tool_call = json!({"content": "", "tool_calls": [
+            {"id": "call_1", "type": "function", "function": {"name": "read", "arguments": "{}"}}
+        ]});
+        for (message, finish_reason, expected) in [
+            (&text, json!("stop"), "end_turn"),
+            (&text, json!("length"), "max_tokens"),
+            (&text, json!("content_filter"), "refusal"),
+            (&text, Value::Null, "end_turn"),
+            (&tool_call, json!("tool_calls"), "tool_use"),
+            // 部分服务商返回工具调用时 finish_reason 仍为 stop
+            (&tool_call, json!("stop"), "tool_use"),
+            (&tool_call, json!("length"), "max_tokens"),
+        ] {
+            let response = response_with(message.clone(), finish_reason.clone());
+            assert_eq!(
+                convert_response(&response, "claude-sonnet-4")["stop_reason"],
+                expected,
+                "finish_reason {}",
+                finish_reason
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_usage_from_chunk_after_finish_reason() {
+        let events = translate(
+            &[
+                json!({"id": "c1", "choices": [{"index": 0, "delta": {"content": "Hi"}}], "usage": null}),
+                json!({"id": "c1", "choices": [{"index": 0, "delta": {}, "finish_reason": "length"}], "usage": null}),
+                json!({"id": "c1", "choices": [], "usage": {"prompt_tokens": 50, "completion_tokens": 7, "prompt_tokens_details": {"cached_tokens": 20}}}),
+            ],
+            // 上游不发送 [DONE]，流结束时按 finish_reason 收尾
+            false,
+        );
+
+        let (name, delta) = &events[events.len() - 2];
+        assert_eq!(name, "message_delta");
+        assert_eq!(delta["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(
+            delta["usage"],
+            json!({"input_tokens": 30, "output_tokens": 7, "cache_read_input_tokens": 20, "cache_creation_input_tokens": 0})
+        );
+        assert_eq!(events.last().unwrap().0, "message_stop");
+    }
+
+    #[test]
+    fn test_stream_without_finish_reason_ends_with_error() {
+        let events = translate(
+            &[json!({"id": "c1", "choices": [{"index": 0, "delta": {"content": "Hi"}}]})],
+            false,
+        );
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "error",
+            ]
+        );
+    }
+}
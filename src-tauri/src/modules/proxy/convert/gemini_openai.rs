@@ -0,0 +1,757 @@
+//! Gemini generateContent ⇄ OpenAI Chat Completions
+
+use super::parse_arguments;
+use crate::modules::gateway::stream::{sse_data, SseTranslator};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// generateContent 请求 → Chat Completions 请求，`model` 为发往上游的模型名
+pub fn convert_request(json: &Value, model: &str) -> Value {
+    let mut out = Map::new();
+    out.insert("model".to_string(), Value::String(model.to_string()));
+
+    let mut messages = Vec::new();
+    let system = json
+        .get("systemInstruction")
+        .map(|s| text_of_parts(parts_of(s)))
+        .unwrap_or_default();
+    if !system.is_empty() {
+        messages.push(json!({"role": "system", "content": system}));
+    }
+    let contents = json
+        .get("contents")
+        .and_then(|c| c.as_array())
+        .map(|c| c.as_slice())
+        .unwrap_or(&[]);
+    messages.extend(convert_contents(contents));
+    out.insert("messages".to_string(), Value::Array(messages));
+
+    let tools: Vec<Value> = json
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .map(|t| t.as_slice())
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|tool| tool.get("functionDeclarations").and_then(|d| d.as_array()))
+        .flatten()
+        .filter_map(convert_declaration)
+        .collect();
+    if !tools.is_empty() {
+        out.insert("tools".to_string(), Value::Array(tools));
+    }
+    if let Some(choice) = json
+        .get("toolConfig")
+        .and_then(|c| c.get("functionCallingConfig"))
+        .and_then(convert_tool_choice)
+    {
+        out.insert("tool_choice".to_string(), choice);
+    }
+
+    if let Some(generation) = json.get("generationConfig") {
+        for (from, to) in [
+            ("maxOutputTokens", "max_tokens"),
+            ("temperature", "temperature"),
+            ("topP", "top_p"),
+            ("stopSequences", "stop"),
+            ("seed", "seed"),
+        ] {
+            if let Some(value) = generation.get(from) {
+                out.insert(to.to_string(), value.clone());
+            }
+        }
+    }
+
+    Value::Object(out)
+}
+
+fn parts_of(content: &Value) -> &[Value] {
+    content
+        .get("parts")
+        .and_then(|p| p.as_array())
+        .map(|p| p.as_slice())
+        .unwrap_or(&[])
+}
+
+/// 拼接非思考的文本片段
+fn text_of_parts(parts: &[Value]) -> String {
+    parts
+        .iter()
+        .filter(|p| !p.get("thought").and_then(|t| t.as_bool()).unwrap_or(false))
+        .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+/// functionCall 与 functionResponse 不一定带 id，按函数名依次配对并生成 tool_call_id
+fn convert_contents(contents: &[Value]) -> Vec<Value> {
+    let mut pending: HashMap<String, VecDeque<String>> = HashMap::new();
+    let mut next_call = 0;
+    let mut messages = Vec::new();
+
+    for content in contents {
+        let parts = parts_of(content);
+        if content.get("role").and_then(|r| r.as_str()) == Some("model") {
+            let mut tool_calls = Vec::new();
+            for call in parts.iter().filter_map(|p| p.get("functionCall")) {
+                let name = call.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                let id = match call.get("id").and_then(|i| i.as_str()) {
+                    Some(id) => id.to_string(),
+                    None => {
+                        next_call += 1;
+                        format!("call_{}", next_call)
+                    }
+                };
+                pending
+                    .entry(name.to_string())
+                    .or_default()
+                    .push_back(id.clone());
+                tool_calls.push(json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {
+                        "name": name,
+                        "arguments": call.get("args").cloned().unwrap_or_else(|| json!({})).to_string(),
+                    },
+                }));
+            }
+            let text = text_of_parts(parts);
+            if text.is_empty() && tool_calls.is_empty() {
+                continue;
+            }
+            let mut message = json!({"role": "assistant", "content": text});
+            if !tool_calls.is_empty() {
+                message["tool_calls"] = Value::Array(tool_calls);
+            }
+            messages.push(message);
+            continue;
+        }
+
+        let mut user_parts = Vec::new();
+        let mut has_image = false;
+        for part in parts {
+            if let Some(response) = part.get("functionResponse") {
+                let name = response.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                let id = response
+                    .get("id")
+                    .and_then(|i| i.as_str())
+                    .map(|id| id.to_string())
+                    .or_else(|| pending.get_mut(name).and_then(|ids| ids.pop_front()))
+                    .unwrap_or_else(|| format!("call_{}", name));
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": id,
+                    "content": function_response_text(response.get("response")),
+                }));
+            } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                user_parts.push(json!({"type": "text", "text": text}));
+            } else if let Some(data) = part.get("inlineData") {
+                has_image = true;
+                user_parts.push(json!({
+                    "type": "image_url",
+                    "image_url": {"url": format!(
+                        "data:{};base64,{}",
+                        data.get("mimeType").and_then(|m| m.as_str()).unwrap_or("image/png"),
+                        data.get("data").and_then(|d| d.as_str()).unwrap_or("")
+                    )},
+                }));
+            }
+        }
+        if user_parts.is_empty() {
+            continue;
+        }
+        // 部分 OpenAI 兼容服务商不接受数组形式的 content，纯文本时合并为字符串
+        let content = if has_image {
+            Value::Array(user_parts)
+        } else {
+            Value::String(text_of_parts(parts))
+        };
+        messages.push(json!({"role": "user", "content": content}));
+    }
+
+    messages
+}
+
+/// Gemini CLI 的工具结果通常为 {"output": "..."}，取出文本，其余结构原样序列化
+fn function_response_text(response: Option<&Value>) -> String {
+    match response {
+        Some(Value::Object(map)) => match map.get("output").or_else(|| map.get("content")) {
+            Some(Value::String(text)) if map.len() == 1 => text.clone(),
+            _ => Value::Object(map.clone()).to_string(),
+        },
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+    }
+}
+
+fn convert_declaration(declaration: &Value) -> Option<Value> {
+    let parameters = declaration
+        .get("parametersJsonSchema")
+        .or_else(|| declaration.get("parameters"))
+        .map(lowercase_types)
+        .unwrap_or_else(|| json!({"type": "object", "properties": {}}));
+    Some(json!({
+        "type": "function",
+        "function": {
+            "name": declaration.get("name")?,
+            "description": declaration.get("description").cloned().unwrap_or(Value::Null),
+            "parameters": parameters,
+        },
+    }))
+}
+
+/// Gemini 的 Schema 类型名为大写（OBJECT、STRING），JSON Schema 要求小写
+fn lowercase_types(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| match (key.as_str(), value) {
+                    ("type", Value::String(t)) => (key.clone(), Value::String(t.to_lowercase())),
+                    _ => (key.clone(), lowercase_types(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(lowercase_types).collect()),
+        other => other.clone(),
+    }
+}
+
+fn convert_tool_choice(config: &Value) -> Option<Value> {
+    let allowed = config
+        .get("allowedFunctionNames")
+        .and_then(|n| n.as_array())
+        .filter(|n| n.len() == 1)
+        .and_then(|n| n.first());
+    match config.get("mode").and_then(|m| m.as_str())? {
+        "AUTO" => Some(json!("auto")),
+        "NONE" => Some(json!("none")),
+        "ANY" => match allowed {
+            Some(name) => Some(json!({"type": "function", "function": {"name": name}})),
+            None => Some(json!("required")),
+        },
+        _ => None,
+    }
+}
+
+/// Chat Completions 非流式响应 → generateContent 响应，`model` 为客户端请求的模型名
+pub fn convert_response(resp: &Value, model: &str) -> Value {
+    let choice = resp
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first());
+    let message = choice.and_then(|c| c.get("message"));
+    let field = |key: &str| {
+        message
+            .and_then(|m| m.get(key))
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut parts = Vec::new();
+    if let Some(thinking) = field("reasoning_content") {
+        parts.push(json!({"text": thinking, "thought": true}));
+    }
+    if let Some(text) = field("content") {
+        parts.push(json!({"text": text}));
+    }
+    for call in message
+        .and_then(|m| m.get("tool_calls"))
+        .and_then(|t| t.as_array())
+        .map(|t| t.as_slice())
+        .unwrap_or(&[])
+    {
+        let function = call.get("function");
+        parts.push(function_call_part(
+            call.get("id").and_then(|i| i.as_str()),
+            function.and_then(|f| f.get("name")),
+            function.and_then(|f| f.get("arguments")),
+        ));
+    }
+    if parts.is_empty() {
+        parts.push(json!({"text": ""}));
+    }
+
+    let finish_reason = choice
+        .and_then(|c| c.get("finish_reason"))
+        .and_then(|r| r.as_str());
+    json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": parts},
+            "finishReason": finish_reason_of(finish_reason),
+            "index": 0,
+        }],
+        "usageMetadata": convert_usage(resp.get("usage")),
+        "modelVersion": model,
+        "responseId": resp.get("id").cloned().unwrap_or(Value::Null),
+    })
+}
+
+fn function_call_part(id: Option<&str>, name: Option<&Value>, arguments: Option<&Value>) -> Value {
+    let mut call = json!({
+        "name": name.cloned().unwrap_or(Value::Null),
+        "args": parse_arguments(arguments),
+    });
+    // 带上 id，客户端回传 functionResponse 时可据此配对 tool_call_id
+    if let Some(id) = id {
+        call["id"] = Value::String(id.to_string());
+    }
+    json!({"functionCall": call})
+}
+
+fn finish_reason_of(finish_reason: Option<&str>) -> &'static str {
+    match finish_reason {
+        Some("length") => "MAX_TOKENS",
+        Some("content_filter") => "SAFETY",
+        _ => "STOP",
+    }
+}
+
+/// Chat Completions 的 completion_tokens 包含推理 token，Gemini 将其单独计为 thoughtsTokenCount
+fn convert_usage(usage: Option<&Value>) -> Value {
+    let get = |v: Option<&Value>| v.and_then(|v| v.as_u64()).unwrap_or(0);
+    let prompt = get(usage.and_then(|u| u.get("prompt_tokens")));
+    let completion = get(usage.and_then(|u| u.get("completion_tokens")));
+    let reasoning = get(usage
+        .and_then(|u| u.get("completion_tokens_details"))
+        .and_then(|d| d.get("reasoning_tokens")));
+    let cached = get(usage
+        .and_then(|u| u.get("prompt_tokens_details"))
+        .and_then(|d| d.get("cached_tokens")));
+    let mut metadata = json!({
+        "promptTokenCount": prompt,
+        "candidatesTokenCount": completion.saturating_sub(reasoning),
+        "totalTokenCount": prompt + completion,
+    });
+    if reasoning > 0 {
+        metadata["thoughtsTokenCount"] = json!(reasoning);
+    }
+    if cached > 0 {
+        metadata["cachedContentTokenCount"] = json!(cached);
+    }
+    metadata
+}
+
+/// 上游错误响应 → Gemini 错误格式
+pub fn convert_error(status: u16, body: &[u8]) -> Vec<u8> {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|json| {
+            json.get("error")
+                .and_then(|e| e.get("message").or(Some(e)))
+                .and_then(|m| m.as_str().map(|s| s.to_string()))
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).to_string());
+    serde_json::to_vec(&error_body(status, &message)).unwrap_or_default()
+}
+
+fn error_body(status: u16, message: &str) -> Value {
+    let status_name = match status {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        503 => "UNAVAILABLE",
+        _ => "INTERNAL",
+    };
+    json!({"error": {"code": status, "message": message, "status": status_name}})
+}
+
+/// OpenAI 兼容服务商没有 countTokens 接口，按约 4 字符一个 token 估算
+pub fn estimate_tokens(json: &Value) -> Value {
+    let chars = json
+        .get("contents")
+        .unwrap_or(json)
+        .to_string()
+        .chars()
+        .count();
+    json!({"totalTokens": chars.div_ceil(4)})
+}
+
+/// Chat Completions SSE 数据块 → generateContent SSE 数据块
+pub struct ChatToGeminiStream {
+    model: String,
+    response_id: Value,
+    finished: bool,
+    /// 工具调用参数分片到达，按上游 index 累积到结束时整块输出: (id, 函数名, 参数)
+    tool_calls: BTreeMap<u64, (Option<String>, Value, String)>,
+    finish_reason: Option<String>,
+    usage: Option<Value>,
+}
+
+impl ChatToGeminiStream {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            response_id: Value::Null,
+            finished: false,
+            tool_calls: BTreeMap::new(),
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn chunk(&self, parts: Vec<Value>, finish_reason: Option<&str>) -> Vec<u8> {
+        let mut candidate = json!({
+            "content": {"role": "model", "parts": parts},
+            "index": 0,
+        });
+        if let Some(reason) = finish_reason {
+            candidate["finishReason"] = json!(reason);
+        }
+        let mut chunk = json!({
+            "candidates": [candidate],
+            "modelVersion": self.model,
+            "responseId": self.response_id,
+        });
+        if finish_reason.is_some() {
+            chunk["usageMetadata"] = convert_usage(self.usage.as_ref());
+        }
+        sse_data(&chunk)
+    }
+
+    fn on_tool_call(&mut self, call: &Value) {
+        let index = call.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+        let entry = self
+            .tool_calls
+            .entry(index)
+            .or_insert_with(|| (None, Value::Null, String::new()));
+        if let Some(id) = call.get("id").and_then(|i| i.as_str()) {
+            entry.0 = Some(id.to_string());
+        }
+        let function = call.get("function");
+        if let Some(name) = function
+            .and_then(|f| f.get("name"))
+            .filter(|n| !n.is_null())
+        {
+            entry.1 = name.clone();
+        }
+        if let Some(arguments) = function
+            .and_then(|f| f.get("arguments"))
+            .and_then(|a| a.as_str())
+        {
+            entry.2.push_str(arguments);
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        self.finished = true;
+        let mut parts: Vec<Value> = std::mem::take(&mut self.tool_calls)
+            .into_values()
+            .map(|(id, name, arguments)| {
+                function_call_part(id.as_deref(), Some(&name), Some(&Value::String(arguments)))
+            })
+            .collect();
+        if parts.is_empty() {
+            parts.push(json!({"text": ""}));
+        }
+        let reason = finish_reason_of(self.finish_reason.as_deref());
+        out.extend(self.chunk(parts, Some(reason)));
+    }
+
+    fn error(&mut self, status: u16, message: &str, out: &mut Vec<u8>) {
+        self.finished = true;
+        out.extend(sse_data(&error_body(status, message)));
+    }
+}
+
+impl SseTranslator for ChatToGeminiStream {
+    fn on_event(&mut self, _event: Option<&str>, data: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        if data.trim() == "[DONE]" {
+            self.finish(&mut out);
+            return out;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return out;
+        };
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("上游流返回错误");
+            self.error(500, message, &mut out);
+            return out;
+        }
+
+        if self.response_id.is_null() {
+            self.response_id = chunk.get("id").cloned().unwrap_or(Value::Null);
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = Some(usage.clone());
+        }
+        let Some(choice) = chunk
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.first())
+        else {
+            return out;
+        };
+
+        let delta = choice.get("delta");
+        let text_of = |key: &str| {
+            delta
+                .and_then(|d| d.get(key))
+                .and_then(|t| t.as_str())
+                .filter(|t| !t.is_empty())
+        };
+        let mut parts = Vec::new();
+        if let Some(thinking) = text_of("reasoning_content") {
+            parts.push(json!({"text": thinking, "thought": true}));
+        }
+        if let Some(text) = text_of("content") {
+            parts.push(json!({"text": text}));
+        }
+        if !parts.is_empty() {
+            out.extend(self.chunk(parts, None));
+        }
+        for call in delta
+            .and_then(|d| d.get("tool_calls"))
+            .and_then(|t| t.as_array())
+            .map(|t| t.as_slice())
+            .unwrap_or(&[])
+        {
+            self.on_tool_call(call);
+        }
+
+        if let Some(reason) = choice.get("finish_reason").and_then(|r| r.as_str()) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        out
+    }
+
+    fn on_end(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        // 部分服务商不发送 [DONE]，收到 finish_reason 即视为正常结束
+        if self.finish_reason.is_some() {
+            self.finish(&mut out);
+        } else {
+            self.error(502, "上游流在响应完成前结束", &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次送入数据块，返回输出的 generateContent 数据块
+    fn translate(chunks: &[Value], done: bool) -> Vec<Value> {
+        let mut translator = ChatToGeminiStream::new("gemini-2.5-pro");
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend(translator.on_event(None, &chunk.to_string()));
+        }
+        if done {
+            out.extend(translator.on_event(None, "[DONE]"));
+        }
+        out.extend(translator.on_end());
+
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .map(|d| serde_json::from_str(d).unwrap())
+            .collect()
+    }
+
+    fn response_with(message: Value, finish_reason: Value) -> Value {
+        json!({"id": "chatcmpl-1", "choices": [{"message": message, "finish_reason": finish_reason}]})
+    }
+
+    #[test]
+    fn test_function_calls_without_ids_pair_by_name_in_order() {
+        let request = json!({
+            "tools": [{"functionDeclarations": [{
+                "name": "read_file",
+                "parameters": {"type": "OBJECT", "properties": {"path": {"type": "STRING"}}}
+            }]}],
+            "toolConfig": {"functionCallingConfig": {"mode": "ANY", "allowedFunctionNames": ["read_file"]}},
+            "contents": [
+                {"role": "model", "parts": [
+                    {"functionCall": {"name": "read_file", "args": {"path": "a"}}},
+                    {"functionCall": {"name": "read_file", "args": {"path": "b"}}},
+                    {"functionCall": {"id": "fc_9", "name": "list", "args": {}}}
+                ]},
+                {"role": "user", "parts": [
+                    {"functionResponse": {"name": "list", "response": {"output": "x"}}},
+                    {"functionResponse": {"name": "read_file", "response": {"output": "A"}}},
+                    {"functionResponse": {"name": "read_file", "response": {"error": "denied"}}}
+                ]}
+            ]
+        });
+
+        let converted = convert_request(&request, "gpt-4o");
+        assert_eq!(
+            converted["tools"][0]["function"]["parameters"],
+            json!({"type": "object", "properties": {"path": {"type": "string"}}})
+        );
+        assert_eq!(converted["tool_choice"]["function"]["name"], "read_file");
+
+        let messages = converted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        let calls = messages[0]["tool_calls"].as_array().unwrap();
+        assert_eq!(calls[0]["id"], "call_1");
+        assert_eq!(calls[1]["id"], "call_2");
+        assert_eq!(calls[2]["id"], "fc_9");
+        assert_eq!(messages[1]["tool_call_id"], "fc_9");
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[2]["content"], "A");
+        assert_eq!(messages[3]["tool_call_id"], "call_2");
+        // 非 output 结构的结果原样序列化
+        assert_eq!(messages[3]["content"], "{\"error\":\"denied\"}");
+
+        let response = response_with(
+            json!({"content": null, "tool_calls": [
+                {"id": "call_7", "type": "function", "function": {"name": "read_file", "arguments": "{\"path\":\"c\"}"}}
+            ]}),
+            json!("tool_calls"),
+        );
+        let parts = convert_response(&response, "gemini-2.5-pro")["candidates"][0]["content"]
+            ["parts"]
+            .clone();
+        // id 随 functionCall 返回，客户端回传 functionResponse 时可直接配对
+        assert_eq!(
+            parts,
+            json!([{"functionCall": {"id": "call_7", "name": "read_file", "args": {"path": "c"}}}])
+        );
+    }
+
+    #[test]
+    fn test_system_instruction_becomes_leading_system_message() {
+        let request = json!({
+            "systemInstruction": {"parts": [
+                {"text": "internal", "thought": true},
+                {"text": "rule one. "},
+                {"text": "rule two."}
+            ]},
+            "contents": [{"role": "user", "parts": [{"text": "hi"}]}]
+        });
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        assert_eq!(
+            messages[0],
+            json!({"role": "system", "content": "rule one. rule two."})
+        );
+        assert_eq!(messages[1], json!({"role": "user", "content": "hi"}));
+
+        let request = json!({"contents": [{"role": "user", "parts": [{"text": "hi"}]}]});
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        assert_eq!(messages.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_inline_images_switch_content_to_parts() {
+        let request = json!({
+            "contents": [{"role": "user", "parts": [
+                {"text": "what is this"},
+                {"inlineData": {"mimeType": "image/webp", "data": "AAAA"}}
+            ]}]
+        });
+        let messages = convert_request(&request, "gpt-4o")["messages"].clone();
+        assert_eq!(
+            messages[0]["content"],
+            json!([
+                {"type": "text", "text": "what is this"},
+                {"type": "image_url", "image_url": {"url": "data:image/webp;base64,AAAA"}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_finish_reason_maps_to_gemini() {
+        for (finish_reason, expected) in [
+            (json!("stop"), "STOP"),
+            (json!("length"), "MAX_TOKENS"),
+            (json!("content_filter"), "SAFETY"),
+            (json!("tool_calls"), "STOP"),
+            (Value::Null, "STOP"),
+        ] {
+            let response = response_with(json!({"content": "ok"}), finish_reason.clone());
+            assert_eq!(
+                convert_response(&response, "gemini-2.5-pro")["candidates"][0]["finishReason"],
+                expected,
+                "finish_reason {}",
+                finish_reason
+            );
+        }
+
+        // 空响应仍需至少一个 part
+        let empty = response_with(json!({"content": ""}), json!("stop"));
+        assert_eq!(
+            convert_response(&empty, "gemini-2.5-pro")["candidates"][0]["content"]["parts"],
+            json!([{"text": ""}])
+        );
+    }
+
+    #[test]
+    fn test_stream_usage_from_chunk_after_finish_reason() {
+        let chunks = translate(
+            &[
+                json!({"id": "c1", "choices": [{"index": 0, "delta": {"reasoning_content": "plan", "content": "Hi"}}]}),
+                json!({"id": "c1", "choices": [{"index": 0, "delta": {"tool_calls": [
+                    {"index": 1, "id": "call_2", "function": {"name": "list", "arguments": "{}"}},
+                    {"index": 0, "id": "call_1", "function": {"name": "read_file", "arguments": "{\"pa"}}
+                ]}}]}),
+                json!({"id": "c1", "choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "th\":\"a\"}"}}]}, "finish_reason": "length"}]}),
+                json!({"id": "c1", "choices": [], "usage": {
+                    "prompt_tokens": 50, "completion_tokens": 12,
+                    "completion_tokens_details": {"reasoning_tokens": 4},
+                    "prompt_tokens_details": {"cached_tokens": 20}
+                }}),
+            ],
+            // 上游不发送 [DONE]，流结束时按 finish_reason 收尾
+            false,
+        );
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0]["candidates"][0]["content"]["parts"],
+            json!([{"text": "plan", "thought": true}, {"text": "Hi"}])
+        );
+        assert!(chunks[0].get("usageMetadata").is_none());
+
+        let last = &chunks[1];
+        let parts = last["candidates"][0]["content"]["parts"]
+            .as_array()
+            .unwrap();
+        // 工具调用按上游 index 排序输出
+        assert_eq!(parts[0]["functionCall"]["args"], json!({"path": "a"}));
+        assert_eq!(parts[1]["functionCall"]["name"], "list");
+        assert_eq!(last["candidates"][0]["finishReason"], "MAX_TOKENS");
+        assert_eq!(
+            last["usageMetadata"],
+            json!({
+                "promptTokenCount": 50,
+                "candidatesTokenCount": 8,
+                "totalTokenCount": 62,
+                "thoughtsTokenCount": 4,
+                "cachedContentTokenCount": 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_without_finish_reason_ends_with_error() {
+        let chunks = translate(
+            &[json!({"id": "c1", "choices": [{"index": 0, "delta": {"content": "Hi"}}]})],
+            false,
+        );
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1]["error"]["code"], 502);
+        assert_eq!(chunks[1]["error"]["status"], "INTERNAL");
+
+        // 收到 [DONE] 后即使没有 finish_reason 也正常收尾
+        let chunks = translate(
+            &[json!({"id": "c1", "choices": [{"index": 0, "delta": {"content": "Hi"}}]})],
+            true,
+        );
+        assert_eq!(chunks[1]["candidates"][0]["finishReason"], "STOP");
+    }
+}
@@ -0,0 +1,94 @@
+//! 协议转换
+//!
+//! 接管时可为 CLI 指定与其协议不同的上游：Claude Code 使用任意 OpenAI 兼容或 Gemini 服务商，
+//! Gemini CLI 使用 OpenAI 兼容服务商。请求、非流式响应、流式事件、工具调用与用量均在此转换
+//!
+//! - `claude_openai`: Anthropic Messages ⇄ OpenAI Chat Completions
+//! - `claude_gemini`: Anthropic Messages ⇄ Gemini generateContent
+//! - `gemini_openai`: Gemini generateContent ⇄ OpenAI Chat Completions
+
+pub mod claude_gemini;
+pub mod claude_openai;
+pub mod gemini_openai;
+
+use serde_json::{json, Value};
+
+/// 上游协议：OpenAI Chat Completions
+pub const PROTOCOL_OPENAI: &str = "openai";
+/// 上游协议：Gemini generateContent
+pub const PROTOCOL_GEMINI: &str = "gemini";
+
+/// 协议转换路由的路径前缀，客户端协议与上游协议的组合不受支持时返回 None
+pub fn route_prefix(app_type: &str, protocol: &str) -> Option<&'static str> {
+    match (app_type, protocol) {
+        ("claude", PROTOCOL_OPENAI) => Some("/claude-openai"),
+        ("claude", PROTOCOL_GEMINI) => Some("/claude-gemini"),
+        ("gemini", PROTOCOL_OPENAI) => Some("/gemini-openai"),
+        _ => None,
+    }
+}
+
+/// 工具参数可能是 JSON 字符串或对象，解析失败时按空对象处理
+fn parse_arguments(arguments: Option<&Value>) -> Value {
+    match arguments {
+        Some(Value::String(s)) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+        Some(v @ Value::Object(_)) => v.clone(),
+        _ => json!({}),
+    }
+}
+
+/// Anthropic 的 system 可以是字符串或文本块数组
+fn anthropic_system_text(system: &Value) -> Option<String> {
+    let text = match system {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => return None,
+    };
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Anthropic 消息的 content 可以是字符串或内容块数组，统一展开为内容块
+fn anthropic_blocks(msg: &Value) -> Vec<Value> {
+    match msg.get("content") {
+        Some(Value::String(text)) => vec![json!({"type": "text", "text": text})],
+        Some(Value::Array(blocks)) => blocks.clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn anthropic_tool_result_text(block: &Value) -> String {
+    let text = match block.get("content") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    if block
+        .get("is_error")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+    {
+        format!("[error] {}", text)
+    } else {
+        text
+    }
+}
+
+/// 仅转换自定义工具；web_search 等 Anthropic 服务端工具在其他上游没有对应实现
+fn is_custom_tool(tool: &Value) -> bool {
+    matches!(
+        tool.get("type").and_then(|t| t.as_str()),
+        None | Some("custom")
+    )
+}
@@ -2,19 +2,31 @@
 //!
 //! 处理各种 API 端点的 HTTP 请求
 
+use super::convert::{claude_gemini, claude_openai, gemini_openai};
+use super::provider::{self, UpstreamProvider};
 use super::server::ProxyState;
 use super::types::*;
-use super::usage::{log_usage, TokenUsage};
+use super::usage::{budget, log_usage, TokenUsage};
 use axum::{
     body::Body,
     extract::{Path, State},
-    http::{HeaderMap, HeaderValue, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::IntoResponse,
     Json,
 };
-use futures::StreamExt;
+use crate::modules::gateway::anthropic_adapter;
+use crate::modules::gateway::stream::{ByteStream, RelayStream, TranslatedStream};
+use crate::modules::opencode_db::schema::TakeoverTarget;
+use crate::modules::metrics;
+use futures::{Stream, StreamExt};
 use serde_json::{json, Value};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// 连接上游的超时
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 每一跳等待响应头的时限：流式响应会先返回响应头，非流式响应要等上游生成完毕才返回
+const STREAM_RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+const BUFFERED_RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
 
 /// 健康检查
 pub async fn health_check() -> (StatusCode, Json<Value>) {
@@ -27,6 +39,19 @@ pub async fn health_check() -> (StatusCode, Json<Value>) {
     )
 }
 
+/// Prometheus 指标
+pub async fn get_metrics() -> impl IntoResponse {
+    let gauges = [metrics::Gauge {
+        name: "in_flight_requests",
+        help: "正在处理的请求数",
+        samples: vec![(Vec::new(), metrics::proxy().in_flight() as f64)],
+    }];
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        metrics::proxy().render(&gauges),
+    )
+}
+
 /// 获取服务状态
 pub async fn get_status(State(state): State<ProxyState>) -> Result<Json<ProxyStatus>, StatusCode> {
     let mut status = state.status.read().await.clone();
@@ -34,6 +59,7 @@ pub async fn get_status(State(state): State<ProxyState>) -> Result<Json<ProxySta
     if let Some(start) = *state.start_time.read().await {
         status.uptime_seconds = start.elapsed().as_secs();
     }
+    status.upstreams = state.circuits.snapshot();
     
     Ok(Json(status))
 }
@@ -48,62 +74,41 @@ pub async fn handle_claude(
     let is_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
     let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
 
-    // 获取 API Key 和 Base URL
-    let (api_key, base_url) = get_claude_config(&headers);
+    // 获取上游服务商及备用链
+    let upstreams = get_claude_config(&state, &headers);
     
-    if api_key.is_empty() {
+    if upstreams.iter().all(|u| u.api_key.is_empty()) {
         return Err((StatusCode::UNAUTHORIZED, "缺少 API Key".to_string()));
     }
 
-    // 构建转发请求
-    let client = reqwest::Client::new();
-    let target_url = format!("{}/v1/messages", base_url);
-    
-    let mut req_builder = client.post(&target_url)
-        .header("Content-Type", "application/json")
-        .header("x-api-key", &api_key)
-        .header("anthropic-version", "2023-06-01")
-        .json(&body);
-
-    // 复制其他头部
-    for (key, value) in headers.iter() {
-        let key_str = key.as_str().to_lowercase();
-        if !["host", "content-length", "x-api-key", "authorization"].contains(&key_str.as_str()) {
-            if let Ok(v) = value.to_str() {
-                req_builder = req_builder.header(key.as_str(), v);
-            }
-        }
-    }
+    // 构建并发送转发请求，上游不可用时切换到下一跳
+    let (response, upstream) = send_with_failover(&state, AppType::Claude, &upstreams, response_timeout(is_stream), |client, upstream| {
+        let req_builder = client.post(format!("{}/v1/messages", upstream.base_url))
+            .header("Content-Type", "application/json")
+            .header("x-api-key", &upstream.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .json(&body);
 
-    // 发送请求
-    let response = req_builder.send().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
+        // 复制其他头部
+        forward_headers(req_builder, &headers, &["host", "content-length", "x-api-key", "authorization", "x-base-url"])
+    }).await?;
 
     let status_code = response.status();
     
     // 更新统计
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::Claude, &model, status_code).await;
 
     if is_stream {
         // 流式响应处理
-        let stream = response.bytes_stream();
-        let _db = state.db.clone();
-        let _model_clone = model.clone();
-        
-        let mapped_stream = stream.map(move |chunk| {
-            chunk.map(|bytes| bytes.to_vec())
-        });
-
-        // 创建响应
-        let body = Body::from_stream(mapped_stream);
+        let body = Body::from_stream(relay_stream(
+            &state,
+            response,
+            AppType::Claude,
+            &model,
+            upstream,
+            start_time,
+            TokenUsage::from_claude_stream_events,
+        ));
         let mut response_headers = HeaderMap::new();
         response_headers.insert("Content-Type", HeaderValue::from_static("text/event-stream"));
         response_headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
@@ -117,15 +122,19 @@ pub async fn handle_claude(
         // 解析并记录使用量
         if let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) {
             if let Some(usage) = TokenUsage::from_claude_response(&json_body) {
+                record_tokens(AppType::Claude, &model, &usage);
                 let latency_ms = start_time.elapsed().as_millis() as u64;
                 let _ = log_usage(
                     &state.db,
-                    "default",
-                    None,
+                    &upstream.id,
+                    upstream.name.as_deref(),
+                    Some(upstream.served_by()),
                     AppType::Claude,
                     &model,
                     usage,
                     latency_ms,
+                    None,
+                    false,
                     status_code.as_u16(),
                 );
             }
@@ -148,56 +157,39 @@ pub async fn handle_codex(
     let is_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
     let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
 
-    // 获取 API Key 和 Base URL
-    let (api_key, base_url) = get_openai_config(&headers);
+    // 获取上游服务商及备用链
+    let upstreams = get_openai_config(&state, &headers);
     
-    if api_key.is_empty() {
+    if upstreams.iter().all(|u| u.api_key.is_empty()) {
         return Err((StatusCode::UNAUTHORIZED, "缺少 API Key".to_string()));
     }
 
-    // 构建转发请求
-    let client = reqwest::Client::new();
-    let target_url = format!("{}/chat/completions", base_url);
-    
-    let mut req_builder = client.post(&target_url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&body);
-
-    // 复制其他头部
-    for (key, value) in headers.iter() {
-        let key_str = key.as_str().to_lowercase();
-        if !["host", "content-length", "authorization"].contains(&key_str.as_str()) {
-            if let Ok(v) = value.to_str() {
-                req_builder = req_builder.header(key.as_str(), v);
-            }
-        }
-    }
+    // 构建并发送转发请求，上游不可用时切换到下一跳
+    let (response, upstream) = send_with_failover(&state, AppType::Codex, &upstreams, response_timeout(is_stream), |client, upstream| {
+        let req_builder = client.post(format!("{}/chat/completions", upstream.base_url))
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", upstream.api_key))
+            .json(&body);
 
-    // 发送请求
-    let response = req_builder.send().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
+        // 复制其他头部
+        forward_headers(req_builder, &headers, &["host", "content-length", "authorization", "x-base-url"])
+    }).await?;
 
     let status_code = response.status();
     
     // 更新统计
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::Codex, &model, status_code).await;
 
     if is_stream {
-        let stream = response.bytes_stream();
-        let mapped_stream = stream.map(move |chunk| {
-            chunk.map(|bytes| bytes.to_vec())
-        });
-
-        let body = Body::from_stream(mapped_stream);
+        let body = Body::from_stream(relay_stream(
+            &state,
+            response,
+            AppType::Codex,
+            &model,
+            upstream,
+            start_time,
+            TokenUsage::from_openai_stream_events,
+        ));
         let mut response_headers = HeaderMap::new();
         response_headers.insert("Content-Type", HeaderValue::from_static("text/event-stream"));
         response_headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
@@ -210,15 +202,19 @@ pub async fn handle_codex(
         // 解析并记录使用量
         if let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) {
             if let Some(usage) = TokenUsage::from_openai_response(&json_body) {
+                record_tokens(AppType::Codex, &model, &usage);
                 let latency_ms = start_time.elapsed().as_millis() as u64;
                 let _ = log_usage(
                     &state.db,
-                    "default",
-                    None,
+                    &upstream.id,
+                    upstream.name.as_deref(),
+                    Some(upstream.served_by()),
                     AppType::Codex,
                     &model,
                     usage,
                     latency_ms,
+                    None,
+                    false,
                     status_code.as_u16(),
                 );
             }
@@ -241,51 +237,34 @@ pub async fn handle_codex_responses(
     let is_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
     let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
 
-    let (api_key, base_url) = get_openai_config(&headers);
+    let upstreams = get_openai_config(&state, &headers);
     
-    if api_key.is_empty() {
+    if upstreams.iter().all(|u| u.api_key.is_empty()) {
         return Err((StatusCode::UNAUTHORIZED, "缺少 API Key".to_string()));
     }
 
-    let client = reqwest::Client::new();
-    let target_url = format!("{}/responses", base_url);
-    
-    let mut req_builder = client.post(&target_url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&body);
-
-    for (key, value) in headers.iter() {
-        let key_str = key.as_str().to_lowercase();
-        if !["host", "content-length", "authorization"].contains(&key_str.as_str()) {
-            if let Ok(v) = value.to_str() {
-                req_builder = req_builder.header(key.as_str(), v);
-            }
-        }
-    }
-
-    let response = req_builder.send().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
+    let (response, upstream) = send_with_failover(&state, AppType::Codex, &upstreams, response_timeout(is_stream), |client, upstream| {
+        let req_builder = client.post(format!("{}/responses", upstream.base_url))
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", upstream.api_key))
+            .json(&body);
+        forward_headers(req_builder, &headers, &["host", "content-length", "authorization", "x-base-url"])
+    }).await?;
 
     let status_code = response.status();
     
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::Codex, &model, status_code).await;
 
     if is_stream {
-        let stream = response.bytes_stream();
-        let mapped_stream = stream.map(move |chunk| {
-            chunk.map(|bytes| bytes.to_vec())
-        });
-
-        let body = Body::from_stream(mapped_stream);
+        let body = Body::from_stream(relay_stream(
+            &state,
+            response,
+            AppType::Codex,
+            &model,
+            upstream,
+            start_time,
+            TokenUsage::from_codex_stream_events,
+        ));
         let mut response_headers = HeaderMap::new();
         response_headers.insert("Content-Type", HeaderValue::from_static("text/event-stream"));
         response_headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
@@ -297,15 +276,19 @@ pub async fn handle_codex_responses(
 
         if let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) {
             if let Some(usage) = TokenUsage::from_codex_response(&json_body) {
+                record_tokens(AppType::Codex, &model, &usage);
                 let latency_ms = start_time.elapsed().as_millis() as u64;
                 let _ = log_usage(
                     &state.db,
-                    "default",
-                    None,
+                    &upstream.id,
+                    upstream.name.as_deref(),
+                    Some(upstream.served_by()),
                     AppType::Codex,
                     &model,
                     usage,
                     latency_ms,
+                    None,
+                    false,
                     status_code.as_u16(),
                 );
             }
@@ -329,45 +312,45 @@ pub async fn handle_gemini(
     
     // 从路径提取模型名称
     let model = extract_gemini_model(&path).unwrap_or("unknown".to_string());
+    let is_stream = path.contains(":streamGenerateContent");
 
-    // 获取 API Key 和 Base URL
-    let (api_key, base_url) = get_gemini_config(&headers);
+    // 获取上游服务商及备用链
+    let upstreams = get_gemini_config(&state, &headers);
     
-    if api_key.is_empty() {
+    if upstreams.iter().all(|u| u.api_key.is_empty()) {
         return Err((StatusCode::UNAUTHORIZED, "缺少 API Key".to_string()));
     }
 
-    // 构建转发请求
-    let client = reqwest::Client::new();
-    let target_url = format!("{}/v1beta/{}", base_url, path);
-    
-    let mut req_builder = client.post(&target_url)
-        .header("Content-Type", "application/json")
-        .query(&[("key", &api_key)])
-        .json(&body);
-
-    for (key, value) in headers.iter() {
-        let key_str = key.as_str().to_lowercase();
-        if !["host", "content-length"].contains(&key_str.as_str()) {
-            if let Ok(v) = value.to_str() {
-                req_builder = req_builder.header(key.as_str(), v);
-            }
+    // 构建并发送转发请求，上游不可用时切换到下一跳
+    let (response, upstream) = send_with_failover(&state, AppType::Gemini, &upstreams, response_timeout(is_stream), |client, upstream| {
+        let mut req_builder = client.post(format!("{}/v1beta/{}", upstream.base_url, path))
+            .header("Content-Type", "application/json")
+            .query(&[("key", &upstream.api_key)])
+            .json(&body);
+        if is_stream {
+            req_builder = req_builder.query(&[("alt", "sse")]);
         }
-    }
-
-    let response = req_builder.send().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
+        forward_headers(req_builder, &headers, &["host", "content-length", "x-goog-api-key", "x-base-url"])
+    }).await?;
 
     let status_code = response.status();
     
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
+    record_request(&state, AppType::Gemini, &model, status_code).await;
+
+    if is_stream {
+        let body = Body::from_stream(relay_stream(
+            &state,
+            response,
+            AppType::Gemini,
+            &model,
+            upstream,
+            start_time,
+            TokenUsage::from_gemini_stream_chunks,
+        ));
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert("Content-Type", HeaderValue::from_static("text/event-stream"));
+        response_headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
+        return Ok((status_code, response_headers, body).into_response());
     }
 
     let response_body = response.bytes().await
@@ -376,15 +359,19 @@ pub async fn handle_gemini(
     // 解析并记录使用量
     if let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) {
         if let Some(usage) = TokenUsage::from_gemini_response(&json_body) {
+            record_tokens(AppType::Gemini, &model, &usage);
             let latency_ms = start_time.elapsed().as_millis() as u64;
             let _ = log_usage(
                 &state.db,
-                "default",
-                None,
+                &upstream.id,
+                upstream.name.as_deref(),
+                Some(upstream.served_by()),
                 AppType::Gemini,
                 &model,
                 usage,
                 latency_ms,
+                None,
+                false,
                 status_code.as_u16(),
             );
         }
@@ -400,8 +387,235 @@ pub async fn handle_gemini(
 // 辅助函数
 // ============================================================================
 
-/// 从请求头获取 Claude 配置
-fn get_claude_config(headers: &HeaderMap) -> (String, String) {
+/// 更新服务状态中的请求计数，并计入 `/metrics`
+async fn record_request(state: &ProxyState, app: AppType, model: &str, status_code: StatusCode) {
+    {
+        let mut s = state.status.write().await;
+        s.total_requests += 1;
+        if status_code.is_success() {
+            s.success_requests += 1;
+        } else {
+            s.failed_requests += 1;
+        }
+    }
+    metrics::proxy().inc_requests(vec![
+        ("status", status_code.as_u16().to_string()),
+        ("model", model.to_string()),
+        ("app", app.to_string()),
+    ]);
+}
+
+fn record_tokens(app: AppType, model: &str, usage: &TokenUsage) {
+    for (kind, tokens) in [
+        ("input", usage.input_tokens),
+        ("output", usage.output_tokens),
+        ("cache_read", usage.cache_read_tokens),
+        ("cache_creation", usage.cache_creation_tokens),
+    ] {
+        metrics::proxy().add_tokens(
+            vec![("model", model.to_string()), ("app", app.to_string()), ("type", kind.to_string())],
+            tokens as u64,
+        );
+    }
+}
+
+/// 原样转发流式响应，同时增量解析 SSE 事件；流结束或客户端断开时记录首 token 延迟与使用量
+fn relay_stream(
+    state: &ProxyState,
+    response: reqwest::Response,
+    app: AppType,
+    model: &str,
+    upstream: &UpstreamProvider,
+    start_time: Instant,
+    parse_usage: fn(&[Value]) -> Option<TokenUsage>,
+) -> ByteStream {
+    let db = state.db.clone();
+    let model = model.to_string();
+    let provider_id = upstream.id.clone();
+    let provider_name = upstream.name.clone();
+    let upstream_url = upstream.base_url.clone();
+    let hop = upstream.hop;
+    let status_code = response.status().as_u16();
+
+    let inner: ByteStream = Box::pin(response.bytes_stream());
+    let relay = RelayStream::new(inner, move |outcome| {
+        let first_token = outcome.first_chunk_at.map(|at| at.duration_since(start_time));
+        if let Some(first_token) = first_token {
+            metrics::proxy().observe_ttft(
+                vec![("model", model.clone()), ("app", app.to_string())],
+                first_token,
+            );
+        }
+        let Some(usage) = parse_usage(&outcome.usage_events) else {
+            return;
+        };
+        record_tokens(app, &model, &usage);
+        if let Err(e) = log_usage(
+            &db,
+            &provider_id,
+            provider_name.as_deref(),
+            Some((upstream_url.as_str(), hop)),
+            app,
+            &model,
+            usage,
+            start_time.elapsed().as_millis() as u64,
+            first_token.map(|d| d.as_millis() as u64),
+            true,
+            status_code,
+        ) {
+            tracing::warn!("[Proxy] 记录流式请求使用量失败: {}", e);
+        }
+    });
+    Box::pin(relay)
+}
+
+/// 记录流式响应首个数据块的到达时间
+fn time_first_chunk<S: Stream>(
+    stream: S,
+    app: AppType,
+    model: &str,
+    start_time: Instant,
+) -> impl Stream<Item = S::Item> {
+    let mut labels = Some(vec![("model", model.to_string()), ("app", app.to_string())]);
+    stream.inspect(move |_| {
+        if let Some(labels) = labels.take() {
+            metrics::proxy().observe_ttft(labels, start_time.elapsed());
+        }
+    })
+}
+
+/// 代理转发共用的上游客户端；只限制连接时间，响应头的等待时限按跳设置，
+/// 不限制整体耗时，避免截断长时间的流式响应
+pub fn upstream_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(UPSTREAM_CONNECT_TIMEOUT)
+        .build()
+        .expect("创建 HTTP 客户端失败")
+}
+
+fn response_timeout(is_stream: bool) -> Duration {
+    if is_stream {
+        STREAM_RESPONSE_TIMEOUT
+    } else {
+        BUFFERED_RESPONSE_TIMEOUT
+    }
+}
+
+/// 依次尝试上游链，连接失败、超时未响应、5xx 或 429 时切换到下一跳，熔断中或已超出消费预算硬上限的上游直接跳过。
+/// 此时响应体尚未转发给客户端，切换对客户端无感知；全部失败时返回最后收到的上游响应
+async fn send_with_failover<'a>(
+    state: &ProxyState,
+    app: AppType,
+    upstreams: &'a [UpstreamProvider],
+    response_timeout: Duration,
+    build_request: impl Fn(&reqwest::Client, &UpstreamProvider) -> reqwest::RequestBuilder,
+) -> Result<(reqwest::Response, &'a UpstreamProvider), (StatusCode, String)> {
+    let mut last_response = None;
+    let mut last_error = String::from("未配置上游");
+    let mut attempted = false;
+    let mut budget_exceeded = None;
+
+    for (index, upstream) in upstreams.iter().enumerate() {
+        if let Some(reason) = budget::hard_cap_reason(&state.db, app.as_str(), &upstream.id) {
+            tracing::debug!("[Proxy] {} 上游 {} {}，已跳过", app, upstream.id, reason);
+            budget_exceeded = Some(reason);
+            continue;
+        }
+
+        // 全部上游都在熔断时仍尝试最后一跳，避免请求直接失败
+        let is_last = index + 1 == upstreams.len();
+        if !state.circuits.try_acquire(upstream) && (attempted || !is_last) {
+            tracing::debug!(
+                "[Proxy] {} 上游 {} ({}) 熔断中，已跳过",
+                app, upstream.id, upstream.base_url
+            );
+            continue;
+        }
+        attempted = true;
+
+        let started = Instant::now();
+        let request = build_request(&state.client, upstream).send();
+        let sent = match tokio::time::timeout(response_timeout, request).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            // 接受连接却迟迟不响应的上游按失败处理，计入熔断并切换到下一跳
+            Err(_) => Err(format!("{} 秒内未收到响应", response_timeout.as_secs())),
+        };
+        match sent {
+            Ok(response) => {
+                let healthy = !should_failover(response.status());
+                state.circuits.record(&state.db, upstream, healthy, started.elapsed());
+                if healthy {
+                    return Ok((response, upstream));
+                }
+                tracing::warn!(
+                    "[Proxy] {} 上游 {} ({}) 返回 HTTP {}，切换到下一跳",
+                    app, upstream.id, upstream.base_url, response.status()
+                );
+                last_response = Some((response, upstream));
+            }
+            Err(e) => {
+                state.circuits.record(&state.db, upstream, false, started.elapsed());
+                tracing::warn!(
+                    "[Proxy] {} 上游 {} ({}) 请求失败: {}",
+                    app, upstream.id, upstream.base_url, e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    // 所有上游都因预算被跳过时直接拒绝，不视为上游故障
+    if let Some(reason) = budget_exceeded.filter(|_| !attempted) {
+        return Err((StatusCode::PAYMENT_REQUIRED, reason));
+    }
+    last_response.ok_or_else(|| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {last_error}")))
+}
+
+fn should_failover(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// 复制客户端请求头，跳过由代理重新设置的头部
+fn forward_headers(
+    mut req_builder: reqwest::RequestBuilder,
+    headers: &HeaderMap,
+    excluded: &[&str],
+) -> reqwest::RequestBuilder {
+    for (key, value) in headers.iter() {
+        let key_str = key.as_str().to_lowercase();
+        if !excluded.contains(&key_str.as_str()) {
+            if let Ok(v) = value.to_str() {
+                req_builder = req_builder.header(key.as_str(), v);
+            }
+        }
+    }
+    req_builder
+}
+
+/// 读取代理配置中该应用的备用服务商
+fn failover_ids(state: &ProxyState, app: AppType) -> Vec<String> {
+    let config = match state.db.get_proxy_config() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("[Proxy] 读取故障转移配置失败: {}", e);
+            return Vec::new();
+        }
+    };
+    match app {
+        AppType::Claude => config.failover_claude,
+        AppType::Codex => config.failover_codex,
+        AppType::Gemini => config.failover_gemini,
+        _ => Vec::new(),
+    }
+}
+
+/// 获取 Claude 上游链：当前激活的服务商及其备用服务商，均未配置时读取请求头
+fn get_claude_config(state: &ProxyState, headers: &HeaderMap) -> Vec<UpstreamProvider> {
+    let upstreams = provider::resolve_chain(AppType::Claude, &failover_ids(state, AppType::Claude));
+    if !upstreams.is_empty() {
+        return upstreams;
+    }
+
     let api_key = headers
         .get("x-api-key")
         .and_then(|v| v.to_str().ok())
@@ -414,11 +628,16 @@ fn get_claude_config(headers: &HeaderMap) -> (String, String) {
         .unwrap_or("https://api.anthropic.com")
         .to_string();
     
-    (api_key, base_url)
+    vec![UpstreamProvider::from_headers(api_key, base_url)]
 }
 
-/// 从请求头获取 OpenAI 配置
-fn get_openai_config(headers: &HeaderMap) -> (String, String) {
+/// 获取 OpenAI 上游链：当前激活的服务商及其备用服务商，均未配置时读取请求头
+fn get_openai_config(state: &ProxyState, headers: &HeaderMap) -> Vec<UpstreamProvider> {
+    let upstreams = provider::resolve_chain(AppType::Codex, &failover_ids(state, AppType::Codex));
+    if !upstreams.is_empty() {
+        return upstreams;
+    }
+
     let api_key = headers
         .get("authorization")
         .and_then(|v| v.to_str().ok())
@@ -431,11 +650,16 @@ fn get_openai_config(headers: &HeaderMap) -> (String, String) {
         .unwrap_or("https://api.openai.com/v1")
         .to_string();
     
-    (api_key, base_url)
+    vec![UpstreamProvider::from_headers(api_key, base_url)]
 }
 
-/// 从请求头获取 Gemini 配置
-fn get_gemini_config(headers: &HeaderMap) -> (String, String) {
+/// 获取 Gemini 上游链：当前激活的服务商及其备用服务商，均未配置时读取请求头
+fn get_gemini_config(state: &ProxyState, headers: &HeaderMap) -> Vec<UpstreamProvider> {
+    let upstreams = provider::resolve_chain(AppType::Gemini, &failover_ids(state, AppType::Gemini));
+    if !upstreams.is_empty() {
+        return upstreams;
+    }
+
     let api_key = headers
         .get("x-goog-api-key")
         .and_then(|v| v.to_str().ok())
@@ -448,7 +672,7 @@ fn get_gemini_config(headers: &HeaderMap) -> (String, String) {
         .unwrap_or("https://generativelanguage.googleapis.com")
         .to_string();
     
-    (api_key, base_url)
+    vec![UpstreamProvider::from_headers(api_key, base_url)]
 }
 
 /// 处理 Cursor Welfare 请求（OpenAI chat/completions 转发到 cursor2api-go）
@@ -482,18 +706,10 @@ pub async fn handle_cursor_welfare(
 
     let status_code = response.status();
 
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::CursorWelfare, &model, status_code).await;
 
     if is_stream {
-        let stream = response.bytes_stream();
+        let stream = time_first_chunk(response.bytes_stream(), AppType::CursorWelfare, &model, start_time);
         let mapped_stream = stream.map(move |chunk| chunk.map(|bytes| bytes.to_vec()));
 
         let body = Body::from_stream(mapped_stream);
@@ -509,15 +725,19 @@ pub async fn handle_cursor_welfare(
 
         if let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) {
             if let Some(usage) = TokenUsage::from_openai_response(&json_body) {
+                record_tokens(AppType::CursorWelfare, &model, &usage);
                 let latency_ms = start_time.elapsed().as_millis() as u64;
                 let _ = log_usage(
                     &state.db,
                     "cursor_welfare",
                     Some("Cursor Welfare"),
+                    None,
                     AppType::CursorWelfare,
                     &model,
                     usage,
                     latency_ms,
+                    None,
+                    false,
                     status_code.as_u16(),
                 );
             }
@@ -558,7 +778,7 @@ pub async fn handle_cursor_welfare_claude_compat(
     }
     let upstream = format!("http://localhost:{}", cursor_welfare_port);
 
-    let openai_body = anthropic_to_openai(&body, &model);
+    let openai_body = claude_openai::convert_request(&body, &model);
 
     let client = reqwest::Client::new();
     let response = client
@@ -571,15 +791,7 @@ pub async fn handle_cursor_welfare_claude_compat(
         .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
 
     let status_code = response.status();
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::CursorWelfare, &model, status_code).await;
 
     let response_body = response
         .bytes()
@@ -588,19 +800,23 @@ pub async fn handle_cursor_welfare_claude_compat(
 
     if let Ok(openai_resp) = serde_json::from_slice::<Value>(&response_body) {
         if let Some(usage) = TokenUsage::from_openai_response(&openai_resp) {
+            record_tokens(AppType::CursorWelfare, &model, &usage);
             let latency_ms = start_time.elapsed().as_millis() as u64;
             let _ = log_usage(
                 &state.db,
                 "cursor_welfare",
                 Some("Cursor Welfare"),
+                None,
                 AppType::CursorWelfare,
                 &model,
                 usage,
                 latency_ms,
+                None,
+                false,
                 status_code.as_u16(),
             );
         }
-        let anthropic_resp = openai_to_anthropic(&openai_resp, &model);
+        let anthropic_resp = claude_openai::convert_response(&openai_resp, &model);
         let resp_bytes = serde_json::to_vec(&anthropic_resp).unwrap_or_default();
 
         let mut response_headers = HeaderMap::new();
@@ -638,7 +854,7 @@ pub async fn handle_cursor_welfare_gemini_compat(
     }
     let upstream = format!("http://localhost:{}", cursor_welfare_port);
 
-    let openai_body = gemini_to_openai(&body, &model);
+    let openai_body = gemini_openai::convert_request(&body, &model);
     let cw_api_key = if api_key.is_empty() {
         "cursor-welfare".to_string()
     } else {
@@ -656,15 +872,7 @@ pub async fn handle_cursor_welfare_gemini_compat(
         .map_err(|e| (StatusCode::BAD_GATEWAY, format!("转发请求失败: {e}")))?;
 
     let status_code = response.status();
-    {
-        let mut s = state.status.write().await;
-        s.total_requests += 1;
-        if status_code.is_success() {
-            s.success_requests += 1;
-        } else {
-            s.failed_requests += 1;
-        }
-    }
+    record_request(&state, AppType::CursorWelfare, &model, status_code).await;
 
     let response_body = response
         .bytes()
@@ -673,19 +881,23 @@ pub async fn handle_cursor_welfare_gemini_compat(
 
     if let Ok(openai_resp) = serde_json::from_slice::<Value>(&response_body) {
         if let Some(usage) = TokenUsage::from_openai_response(&openai_resp) {
+            record_tokens(AppType::CursorWelfare, &model, &usage);
             let latency_ms = start_time.elapsed().as_millis() as u64;
             let _ = log_usage(
                 &state.db,
                 "cursor_welfare",
                 Some("Cursor Welfare"),
+                None,
                 AppType::CursorWelfare,
                 &model,
                 usage,
                 latency_ms,
+                None,
+                false,
                 status_code.as_u16(),
             );
         }
-        let gemini_resp = openai_to_gemini(&openai_resp, &model);
+        let gemini_resp = gemini_openai::convert_response(&openai_resp, &model);
         let resp_bytes = serde_json::to_vec(&gemini_resp).unwrap_or_default();
 
         let mut response_headers = HeaderMap::new();
@@ -699,192 +911,301 @@ pub async fn handle_cursor_welfare_gemini_compat(
 }
 
 // ============================================================================
-// 协议转换辅助函数
+// 协议转换
 // ============================================================================
 
-/// Anthropic Messages -> OpenAI chat/completions 请求
-fn anthropic_to_openai(body: &Value, model: &str) -> Value {
-    let mut messages = Vec::new();
-
-    if let Some(system) = body.get("system") {
-        if let Some(s) = system.as_str() {
-            messages.push(json!({"role": "system", "content": s}));
-        } else if let Some(arr) = system.as_array() {
-            let text: Vec<String> = arr
-                .iter()
-                .filter_map(|item| item.get("text").and_then(|t| t.as_str()))
-                .map(|s| s.to_string())
-                .collect();
-            if !text.is_empty() {
-                messages.push(json!({"role": "system", "content": text.join("\n")}));
-            }
+/// 协议转换路由的转发方向
+#[derive(Clone, Copy)]
+enum Conversion {
+    ClaudeToOpenAi,
+    ClaudeToGemini,
+    GeminiToOpenAi,
+}
+
+impl Conversion {
+    /// 客户端所属应用，请求统计与使用量按该应用记录
+    fn app(self) -> AppType {
+        match self {
+            Conversion::ClaudeToOpenAi | Conversion::ClaudeToGemini => AppType::Claude,
+            Conversion::GeminiToOpenAi => AppType::Gemini,
         }
     }
 
-    if let Some(msgs) = body.get("messages").and_then(|v| v.as_array()) {
-        for msg in msgs {
-            let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
-            let openai_role = match role {
-                "assistant" => "assistant",
-                _ => "user",
-            };
-            if let Some(content) = msg.get("content") {
-                if content.is_string() {
-                    messages.push(json!({"role": openai_role, "content": content}));
-                } else if let Some(arr) = content.as_array() {
-                    let text: Vec<String> = arr
-                        .iter()
-                        .filter_map(|item| item.get("text").and_then(|t| t.as_str()))
-                        .map(|s| s.to_string())
-                        .collect();
-                    messages.push(json!({"role": openai_role, "content": text.join("\n")}));
-                }
+    /// 上游协议对应的应用，决定服务商的解析与地址规范化
+    fn upstream_app(self) -> AppType {
+        match self {
+            Conversion::ClaudeToOpenAi | Conversion::GeminiToOpenAi => AppType::Codex,
+            Conversion::ClaudeToGemini => AppType::Gemini,
+        }
+    }
+
+    fn convert_response(self, body: &Value, model: &str) -> Value {
+        match self {
+            Conversion::ClaudeToOpenAi => claude_openai::convert_response(body, model),
+            Conversion::ClaudeToGemini => claude_gemini::convert_response(body, model),
+            Conversion::GeminiToOpenAi => gemini_openai::convert_response(body, model),
+        }
+    }
+
+    fn convert_error(self, status: u16, body: &[u8]) -> Vec<u8> {
+        match self {
+            Conversion::ClaudeToOpenAi | Conversion::ClaudeToGemini => {
+                anthropic_adapter::convert_error(status, body)
             }
+            Conversion::GeminiToOpenAi => gemini_openai::convert_error(status, body),
         }
     }
 
-    let max_tokens = body
-        .get("max_tokens")
-        .and_then(|v| v.as_u64())
-        .unwrap_or(8192);
+    /// 用量按上游协议解析
+    fn parse_usage(self, body: &Value) -> Option<TokenUsage> {
+        match self.upstream_app() {
+            AppType::Gemini => TokenUsage::from_gemini_response(body),
+            _ => TokenUsage::from_openai_response(body),
+        }
+    }
 
-    json!({
-        "model": model,
-        "messages": messages,
-        "max_tokens": max_tokens,
-    })
-}
+    fn parse_stream_usage(self) -> fn(&[Value]) -> Option<TokenUsage> {
+        match self.upstream_app() {
+            AppType::Gemini => TokenUsage::from_gemini_stream_chunks,
+            _ => TokenUsage::from_openai_stream_events,
+        }
+    }
 
-/// OpenAI chat/completions 响应 -> Anthropic Messages 响应
-fn openai_to_anthropic(resp: &Value, model: &str) -> Value {
-    let content_text = resp
-        .get("choices")
-        .and_then(|c| c.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|choice| choice.get("message"))
-        .and_then(|msg| msg.get("content"))
-        .and_then(|c| c.as_str())
-        .unwrap_or("");
-
-    let stop_reason = resp
-        .get("choices")
-        .and_then(|c| c.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|choice| choice.get("finish_reason"))
-        .and_then(|r| r.as_str())
-        .map(|r| match r {
-            "stop" => "end_turn",
-            "length" => "max_tokens",
-            _ => "end_turn",
-        })
-        .unwrap_or("end_turn");
-
-    let usage = resp.get("usage");
-    let input_tokens = usage
-        .and_then(|u| u.get("prompt_tokens"))
-        .and_then(|v| v.as_u64())
-        .unwrap_or(0);
-    let output_tokens = usage
-        .and_then(|u| u.get("completion_tokens"))
-        .and_then(|v| v.as_u64())
-        .unwrap_or(0);
-
-    json!({
-        "id": resp.get("id").and_then(|v| v.as_str()).unwrap_or("msg_cursor_welfare"),
-        "type": "message",
-        "role": "assistant",
-        "model": model,
-        "content": [{"type": "text", "text": content_text}],
-        "stop_reason": stop_reason,
-        "stop_sequence": null,
-        "usage": {
-            "input_tokens": input_tokens,
-            "output_tokens": output_tokens
+    fn translate_stream(self, upstream: ByteStream, model: &str) -> ByteStream {
+        match self {
+            Conversion::ClaudeToOpenAi => Box::pin(TranslatedStream::new(
+                upstream,
+                claude_openai::ChatToAnthropicStream::new(model),
+            )),
+            Conversion::ClaudeToGemini => Box::pin(TranslatedStream::new(
+                upstream,
+                claude_gemini::GeminiToAnthropicStream::new(model),
+            )),
+            Conversion::GeminiToOpenAi => Box::pin(TranslatedStream::new(
+                upstream,
+                gemini_openai::ChatToGeminiStream::new(model),
+            )),
         }
-    })
+    }
 }
 
-/// Gemini generateContent 请求 -> OpenAI chat/completions 请求
-fn gemini_to_openai(body: &Value, model: &str) -> Value {
-    let mut messages = Vec::new();
-
-    if let Some(contents) = body.get("contents").and_then(|v| v.as_array()) {
-        for content in contents {
-            let role = content.get("role").and_then(|r| r.as_str()).unwrap_or("user");
-            let openai_role = match role {
-                "model" => "assistant",
-                _ => "user",
-            };
-            if let Some(parts) = content.get("parts").and_then(|p| p.as_array()) {
-                let text: Vec<String> = parts
-                    .iter()
-                    .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
-                    .map(|s| s.to_string())
-                    .collect();
-                if !text.is_empty() {
-                    messages.push(json!({"role": openai_role, "content": text.join("\n")}));
-                }
-            }
+/// Claude Code 经 OpenAI 兼容服务商处理（Anthropic Messages -> Chat Completions）
+pub async fn handle_claude_to_openai(
+    State(state): State<ProxyState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let conversion = Conversion::ClaudeToOpenAi;
+    let client_model = body.get("model").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+    let is_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let target = takeover_target(&state, conversion.app());
+    let model = upstream_model(target.as_ref(), &client_model);
+    let upstreams = conversion_upstreams(&state, conversion, target.as_ref())?;
+
+    let mut request = claude_openai::convert_request(&body, &model);
+    if is_stream {
+        request["stream"] = json!(true);
+        request["stream_options"] = json!({"include_usage": true});
+    }
+
+    forward_converted(&state, conversion, &upstreams, &client_model, &model, is_stream, |client, upstream| {
+        client.post(format!("{}/chat/completions", upstream.base_url))
+            .header("Content-Type", "application/json")
+            .bearer_auth(&upstream.api_key)
+            .json(&request)
+    }).await
+}
+
+/// Claude Code 经 Gemini 服务商处理（Anthropic Messages -> generateContent）
+pub async fn handle_claude_to_gemini(
+    State(state): State<ProxyState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let conversion = Conversion::ClaudeToGemini;
+    let client_model = body.get("model").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+    let is_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let target = takeover_target(&state, conversion.app());
+    let model = upstream_model(target.as_ref(), &client_model);
+    let upstreams = conversion_upstreams(&state, conversion, target.as_ref())?;
+
+    let request = claude_gemini::convert_request(&body);
+    let method = if is_stream { "streamGenerateContent" } else { "generateContent" };
+
+    forward_converted(&state, conversion, &upstreams, &client_model, &model, is_stream, |client, upstream| {
+        let mut req_builder = client.post(format!("{}/v1beta/models/{}:{}", upstream.base_url, model, method))
+            .header("Content-Type", "application/json")
+            .query(&[("key", &upstream.api_key)])
+            .json(&request);
+        if is_stream {
+            req_builder = req_builder.query(&[("alt", "sse")]);
         }
+        req_builder
+    }).await
+}
+
+/// Gemini CLI 经 OpenAI 兼容服务商处理（generateContent -> Chat Completions）
+pub async fn handle_gemini_to_openai(
+    State(state): State<ProxyState>,
+    Path(path): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let conversion = Conversion::GeminiToOpenAi;
+    let client_model = extract_gemini_model(&path).unwrap_or("unknown".to_string());
+
+    if path.ends_with(":countTokens") {
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        let estimate = serde_json::to_vec(&gemini_openai::estimate_tokens(&body)).unwrap_or_default();
+        return Ok((StatusCode::OK, response_headers, estimate).into_response());
+    }
+    let is_stream = path.ends_with(":streamGenerateContent");
+    if !is_stream && !path.ends_with(":generateContent") {
+        return Err((StatusCode::NOT_FOUND, format!("协议转换不支持该接口: {path}")));
     }
 
-    if let Some(sys_inst) = body.get("systemInstruction") {
-        if let Some(parts) = sys_inst.get("parts").and_then(|p| p.as_array()) {
-            let text: Vec<String> = parts
-                .iter()
-                .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
-                .map(|s| s.to_string())
-                .collect();
-            if !text.is_empty() {
-                messages.insert(0, json!({"role": "system", "content": text.join("\n")}));
-            }
+    let target = takeover_target(&state, conversion.app());
+    let model = upstream_model(target.as_ref(), &client_model);
+    let upstreams = conversion_upstreams(&state, conversion, target.as_ref())?;
+
+    let mut request = gemini_openai::convert_request(&body, &model);
+    if is_stream {
+        request["stream"] = json!(true);
+        request["stream_options"] = json!({"include_usage": true});
+    }
+
+    forward_converted(&state, conversion, &upstreams, &client_model, &model, is_stream, |client, upstream| {
+        client.post(format!("{}/chat/completions", upstream.base_url))
+            .header("Content-Type", "application/json")
+            .bearer_auth(&upstream.api_key)
+            .json(&request)
+    }).await
+}
+
+/// 发送转换后的请求，并将响应、流式事件与错误转换回客户端协议。
+/// 统计与使用量按上游实际使用的模型记录，返回给客户端的模型名保持不变
+async fn forward_converted(
+    state: &ProxyState,
+    conversion: Conversion,
+    upstreams: &[UpstreamProvider],
+    client_model: &str,
+    model: &str,
+    is_stream: bool,
+    build_request: impl Fn(&reqwest::Client, &UpstreamProvider) -> reqwest::RequestBuilder,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let start_time = Instant::now();
+    let app = conversion.app();
+
+    let (response, upstream) = send_with_failover(
+        state,
+        app,
+        upstreams,
+        response_timeout(is_stream),
+        build_request,
+    )
+    .await?;
+    let status_code = response.status();
+    record_request(state, app, model, status_code).await;
+
+    let mut response_headers = HeaderMap::new();
+    if !status_code.is_success() {
+        let response_body = response.bytes().await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("读取响应失败: {e}")))?;
+        response_headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        let error = conversion.convert_error(status_code.as_u16(), &response_body);
+        return Ok((status_code, response_headers, error).into_response());
+    }
+
+    if is_stream {
+        let relayed = relay_stream(
+            state,
+            response,
+            app,
+            model,
+            upstream,
+            start_time,
+            conversion.parse_stream_usage(),
+        );
+        let body = Body::from_stream(conversion.translate_stream(relayed, client_model));
+        response_headers.insert("Content-Type", HeaderValue::from_static("text/event-stream"));
+        response_headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
+        return Ok((status_code, response_headers, body).into_response());
+    }
+
+    let response_body = response.bytes().await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("读取响应失败: {e}")))?;
+    response_headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+    let Ok(json_body) = serde_json::from_slice::<Value>(&response_body) else {
+        return Ok((status_code, response_headers, response_body.to_vec()).into_response());
+    };
+
+    if let Some(usage) = conversion.parse_usage(&json_body) {
+        record_tokens(app, model, &usage);
+        let latency_ms = start_time.elapsed().as_millis() as u64;
+        let _ = log_usage(
+            &state.db,
+            &upstream.id,
+            upstream.name.as_deref(),
+            Some(upstream.served_by()),
+            app,
+            model,
+            usage,
+            latency_ms,
+            None,
+            false,
+            status_code.as_u16(),
+        );
+    }
+
+    let converted = conversion.convert_response(&json_body, client_model);
+    let resp_bytes = serde_json::to_vec(&converted).unwrap_or_default();
+    Ok((status_code, response_headers, resp_bytes).into_response())
+}
+
+/// 读取接管时为该应用指定的协议转换目标
+fn takeover_target(state: &ProxyState, app: AppType) -> Option<TakeoverTarget> {
+    let config = match state.db.get_proxy_config() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("[Proxy] 读取协议转换配置失败: {}", e);
+            return None;
         }
+    };
+    match app {
+        AppType::Claude => config.target_claude,
+        AppType::Gemini => config.target_gemini,
+        _ => None,
     }
+}
 
-    json!({
-        "model": model,
-        "messages": messages,
-        "max_tokens": 8192,
-    })
+/// 接管目标指定了模型时替换客户端请求的模型，例如 Claude Code 的 claude-* 模型名在其他服务商上并不存在
+fn upstream_model(target: Option<&TakeoverTarget>, client_model: &str) -> String {
+    target
+        .and_then(|t| t.model.as_deref())
+        .filter(|m| !m.is_empty())
+        .unwrap_or(client_model)
+        .to_string()
 }
 
-/// OpenAI chat/completions 响应 -> Gemini generateContent 响应
-fn openai_to_gemini(resp: &Value, model: &str) -> Value {
-    let content_text = resp
-        .get("choices")
-        .and_then(|c| c.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|choice| choice.get("message"))
-        .and_then(|msg| msg.get("content"))
-        .and_then(|c| c.as_str())
-        .unwrap_or("");
-
-    let usage = resp.get("usage");
-    let prompt_tokens = usage
-        .and_then(|u| u.get("prompt_tokens"))
-        .and_then(|v| v.as_u64())
-        .unwrap_or(0);
-    let completion_tokens = usage
-        .and_then(|u| u.get("completion_tokens"))
-        .and_then(|v| v.as_u64())
-        .unwrap_or(0);
-
-    json!({
-        "candidates": [{
-            "content": {
-                "parts": [{"text": content_text}],
-                "role": "model"
-            },
-            "finishReason": "STOP",
-            "index": 0
-        }],
-        "usageMetadata": {
-            "promptTokenCount": prompt_tokens,
-            "candidatesTokenCount": completion_tokens,
-            "totalTokenCount": prompt_tokens + completion_tokens
-        },
-        "modelVersion": model
-    })
+/// 协议转换路由的上游链：接管时指定了服务商则使用该服务商，否则使用上游协议对应应用的当前服务商及备用服务商。
+/// 客户端请求头中只有占位符，不回退到请求头
+fn conversion_upstreams(
+    state: &ProxyState,
+    conversion: Conversion,
+    target: Option<&TakeoverTarget>,
+) -> Result<Vec<UpstreamProvider>, (StatusCode, String)> {
+    let app = conversion.upstream_app();
+    let upstreams = match target.and_then(|t| t.provider_id.as_deref()).filter(|id| !id.is_empty()) {
+        Some(id) => provider::resolve_provider(app, id),
+        None => provider::resolve_chain(app, &failover_ids(state, app)),
+    };
+    if upstreams.is_empty() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("未找到可用的 {} 服务商", app),
+        ));
+    }
+    Ok(upstreams)
 }
 
 /// 从 Gemini API 路径提取模型名称
@@ -920,3 +1241,161 @@ fn get_cursor_welfare_config(headers: &HeaderMap) -> (String, String) {
 
     (api_key, base_url)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::opencode_db::Database;
+    use crate::modules::proxy::circuit::CircuitBreakers;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    fn test_state() -> ProxyState {
+        let db = Arc::new(Database::memory().unwrap());
+        ProxyState {
+            circuits: Arc::new(CircuitBreakers::load(&db)),
+            db,
+            config: Arc::new(RwLock::new(ProxyConfig::default())),
+            status: Arc::new(RwLock::new(ProxyStatus::default())),
+            start_time: Arc::new(RwLock::new(None)),
+            client: upstream_client(),
+        }
+    }
+
+    /// 本地上游：请求 /{status} 时返回对应的 HTTP 状态，请求 /hang 时接受连接但不响应
+    async fn spawn_upstream() -> String {
+        let app = axum::Router::new()
+            .route(
+                "/hang",
+                axum::routing::get(|| async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    StatusCode::OK
+                }),
+            )
+            .route(
+                "/{status}",
+                axum::routing::get(|Path(status): Path<u16>| async move {
+                    StatusCode::from_u16(status).unwrap()
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    /// 已释放的端口，连接会被拒绝
+    async fn closed_upstream() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn chain(urls: &[String]) -> Vec<UpstreamProvider> {
+        urls.iter()
+            .enumerate()
+            .map(|(hop, url)| UpstreamProvider {
+                id: format!("p{}", hop),
+                name: None,
+                base_url: url.clone(),
+                api_key: "key".to_string(),
+                hop: hop as u32,
+            })
+            .collect()
+    }
+
+    fn open_circuit(state: &ProxyState, upstream: &UpstreamProvider) {
+        while state.circuits.try_acquire(upstream) {
+            state
+                .circuits
+                .record(&state.db, upstream, false, Duration::ZERO);
+        }
+    }
+
+    /// 返回 (响应状态, 响应的跳数)
+    async fn send(
+        state: &ProxyState,
+        upstreams: &[UpstreamProvider],
+    ) -> Result<(u16, u32), StatusCode> {
+        let timeout = Duration::from_millis(200);
+        send_with_failover(
+            state,
+            AppType::Claude,
+            upstreams,
+            timeout,
+            |client, upstream| client.get(&upstream.base_url),
+        )
+        .await
+        .map(|(response, upstream)| (response.status().as_u16(), upstream.hop))
+        .map_err(|(status, _)| status)
+    }
+
+    #[tokio::test]
+    async fn test_failover_tries_hops_in_order() {
+        let state = test_state();
+        let base = spawn_upstream().await;
+        let upstreams = chain(&[
+            format!("{}/500", base),
+            closed_upstream().await,
+            format!("{}/200", base),
+            format!("{}/204", base),
+        ]);
+        assert_eq!(send(&state, &upstreams).await, Ok((200, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_moves_on_when_upstream_never_responds() {
+        let state = test_state();
+        let base = spawn_upstream().await;
+        let upstreams = chain(&[format!("{}/hang", base), format!("{}/200", base)]);
+        assert_eq!(send(&state, &upstreams).await, Ok((200, 1)));
+
+        let hung = state
+            .circuits
+            .snapshot()
+            .into_iter()
+            .find(|status| status.provider_id == "p0")
+            .unwrap();
+        assert_eq!(hung.consecutive_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_failover_skips_open_circuit() {
+        let state = test_state();
+        let base = spawn_upstream().await;
+        let upstreams = chain(&[format!("{}/201", base), format!("{}/200", base)]);
+        open_circuit(&state, &upstreams[0]);
+        assert_eq!(send(&state, &upstreams).await, Ok((200, 1)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_still_tries_last_hop_when_all_circuits_open() {
+        let state = test_state();
+        let base = spawn_upstream().await;
+        let upstreams = chain(&[format!("{}/201", base), format!("{}/200", base)]);
+        for upstream in &upstreams {
+            open_circuit(&state, upstream);
+        }
+        assert_eq!(send(&state, &upstreams).await, Ok((200, 1)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_stops_at_end_of_chain() {
+        let state = test_state();
+        let base = spawn_upstream().await;
+        // 全部失败时返回最后收到的上游响应
+        let upstreams = chain(&[
+            format!("{}/500", base),
+            format!("{}/429", base),
+            closed_upstream().await,
+        ]);
+        assert_eq!(send(&state, &upstreams).await, Ok((429, 1)));
+
+        let unreachable = chain(&[closed_upstream().await, closed_upstream().await]);
+        assert_eq!(
+            send(&state, &unreachable).await,
+            Err(StatusCode::BAD_GATEWAY)
+        );
+        assert_eq!(send(&state, &[]).await, Err(StatusCode::BAD_GATEWAY));
+    }
+}
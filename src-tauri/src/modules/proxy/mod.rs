@@ -2,7 +2,10 @@
 //!
 //! 提供本地 HTTP 代理服务，拦截 CLI 工具的 API 请求并记录使用量
 
+pub mod circuit;
+pub mod convert;
 pub mod handlers;
+pub mod provider;
 pub mod server;
 pub mod service;
 pub mod types;
@@ -0,0 +1,387 @@
+//! 上游服务商解析
+//!
+//! 接管模式下 CLI 配置中的 Token 已被替换为占位符，转发时按 Ai Switch 中当前激活的服务商
+//! 确定上游地址与密钥；未激活时回退到 OpenCode 中同厂家的已启用 Provider。
+//! 首选上游连接失败或返回 5xx、429 时，依次尝试代理配置中的备用服务商
+
+use super::types::AppType;
+use crate::modules::opencode_config::models::{OpenCodeProvider, OpenCodeProviderOptions};
+use crate::modules::opencode_config::open_switch_manager::{
+    OpenSwitchConfig, OpenSwitchConfigManager,
+};
+use crate::modules::opencode_config::opencode_manager::OpenCodeConfigManager;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// 解析上游链所需的服务商配置
+#[derive(Default)]
+struct ProviderSources {
+    open_switch: Option<OpenSwitchConfig>,
+    opencode: HashMap<String, OpenCodeProvider>,
+}
+
+/// 各配置文件的 (修改时间, 大小)，任一变化时重新读取
+type ConfigStamp = Vec<Option<(SystemTime, u64)>>;
+
+/// 上次读取的服务商配置，配置文件未变化时每个请求直接复用
+static SOURCES: Mutex<Option<(ConfigStamp, Arc<ProviderSources>)>> = Mutex::new(None);
+
+/// 本次请求实际使用的上游
+#[derive(Debug, Clone)]
+pub struct UpstreamProvider {
+    /// 写入使用量记录的服务商 ID，来自请求头时为 "default"
+    pub id: String,
+    pub name: Option<String>,
+    pub base_url: String,
+    pub api_key: String,
+    /// 在故障转移链中的位置，0 为首选
+    pub hop: u32,
+}
+
+impl UpstreamProvider {
+    /// 未配置服务商时沿用客户端请求头中的地址与密钥
+    pub fn from_headers(api_key: String, base_url: String) -> Self {
+        Self {
+            id: "default".to_string(),
+            name: None,
+            base_url,
+            api_key,
+            hop: 0,
+        }
+    }
+
+    /// 写入请求日志的实际上游地址与跳数
+    pub fn served_by(&self) -> (&str, u32) {
+        (self.base_url.as_str(), self.hop)
+    }
+}
+
+/// 按尝试顺序返回应用的上游链：当前激活的服务商在前，其后依次为代理配置中的备用服务商。
+/// 配置了多个地址的 OpenCode Provider 按测速结果展开为多跳，地址已按各应用的转发路径规范化
+pub fn resolve_chain(app: AppType, failover_ids: &[String]) -> Vec<UpstreamProvider> {
+    chain_from_sources(app, failover_ids, &load_sources())
+}
+
+/// 协议转换接管指定的服务商，地址按目标协议对应应用的转发路径规范化
+pub fn resolve_provider(app: AppType, provider_id: &str) -> Vec<UpstreamProvider> {
+    let sources = load_sources();
+    build_chain(
+        app,
+        &[provider_id],
+        sources.open_switch.as_ref(),
+        &sources.opencode,
+    )
+}
+
+fn chain_from_sources(
+    app: AppType,
+    failover_ids: &[String],
+    sources: &ProviderSources,
+) -> Vec<UpstreamProvider> {
+    let primary = sources
+        .open_switch
+        .as_ref()
+        .and_then(|config| {
+            current_provider_id(config, app).filter(|id| config.providers.contains_key(id))
+        })
+        .or_else(|| first_opencode_provider_id(&sources.opencode, app));
+
+    let ids: Vec<&str> = primary
+        .iter()
+        .chain(failover_ids)
+        .map(|id| id.as_str())
+        .collect();
+    build_chain(app, &ids, sources.open_switch.as_ref(), &sources.opencode)
+}
+
+/// 依次展开服务商，去掉地址与密钥都相同的重复上游并编号
+fn build_chain(
+    app: AppType,
+    ids: &[&str],
+    open_switch: Option<&OpenSwitchConfig>,
+    opencode: &HashMap<String, OpenCodeProvider>,
+) -> Vec<UpstreamProvider> {
+    let mut chain: Vec<UpstreamProvider> = Vec::new();
+    for id in ids {
+        for upstream in expand_provider(app, id, open_switch, opencode) {
+            let duplicate = chain
+                .iter()
+                .any(|hop| hop.base_url == upstream.base_url && hop.api_key == upstream.api_key);
+            if !duplicate {
+                chain.push(UpstreamProvider {
+                    hop: chain.len() as u32,
+                    ..upstream
+                });
+            }
+        }
+    }
+    chain
+}
+
+/// 配置文件未变化时返回缓存，否则重新读取 Ai Switch 与 OpenCode 的服务商配置
+fn load_sources() -> Arc<ProviderSources> {
+    let open_switch_manager = OpenSwitchConfigManager::new().ok();
+    let opencode_manager = OpenCodeConfigManager::new(PathBuf::new()).ok();
+    let stamp: ConfigStamp = open_switch_manager
+        .iter()
+        .map(|manager| manager.config_path().as_path())
+        .chain(
+            opencode_manager
+                .iter()
+                .flat_map(|manager| manager.config_paths()),
+        )
+        .map(file_stamp)
+        .collect();
+
+    let mut guard = SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_stamp, sources)) = guard.as_ref() {
+        if *cached_stamp == stamp {
+            return sources.clone();
+        }
+    }
+
+    let sources = Arc::new(ProviderSources {
+        open_switch: open_switch_manager
+            .as_ref()
+            .and_then(read_open_switch_config),
+        opencode: opencode_manager
+            .as_ref()
+            .map(read_opencode_providers)
+            .unwrap_or_default(),
+    });
+    *guard = Some((stamp, sources.clone()));
+    sources
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn read_open_switch_config(manager: &OpenSwitchConfigManager) -> Option<OpenSwitchConfig> {
+    match manager.read_config() {
+        Ok(config) => Some(config),
+        Err(e) => {
+            tracing::debug!("[Proxy] 读取 Ai Switch 配置失败: {}", e);
+            None
+        }
+    }
+}
+
+fn read_opencode_providers(manager: &OpenCodeConfigManager) -> HashMap<String, OpenCodeProvider> {
+    manager.get_all_providers().unwrap_or_default()
+}
+
+fn current_provider_id(config: &OpenSwitchConfig, app: AppType) -> Option<String> {
+    match app {
+        AppType::Claude => config.current.claude.clone(),
+        AppType::Codex => config.current.codex.clone(),
+        AppType::Gemini => config.current.gemini.clone(),
+        _ => None,
+    }
+}
+
+fn first_opencode_provider_id(
+    providers: &HashMap<String, OpenCodeProvider>,
+    app: AppType,
+) -> Option<String> {
+    // HashMap 顺序不固定，按名称取第一个保证结果稳定
+    providers
+        .iter()
+        .filter(|(_, p)| p.enabled && p.model_type.as_deref() == Some(app.as_str()))
+        .map(|(id, _)| id)
+        .min()
+        .cloned()
+}
+
+/// 将服务商展开为上游列表，优先匹配 Ai Switch 中的服务商，其次是已启用的 OpenCode Provider
+fn expand_provider(
+    app: AppType,
+    id: &str,
+    open_switch: Option<&OpenSwitchConfig>,
+    opencode: &HashMap<String, OpenCodeProvider>,
+) -> Vec<UpstreamProvider> {
+    let (name, api_key, urls) =
+        if let Some(provider) = open_switch.and_then(|config| config.providers.get(id)) {
+            (
+                provider.name.clone(),
+                provider.api_key.clone(),
+                vec![provider.base_url.clone()],
+            )
+        } else if let Some(provider) = opencode.get(id).filter(|p| p.enabled) {
+            (
+                provider.name.clone(),
+                provider.options.api_key.clone(),
+                ordered_urls(&provider.options),
+            )
+        } else {
+            tracing::debug!("[Proxy] 未找到服务商 {}，已跳过", id);
+            return Vec::new();
+        };
+    if api_key.is_empty() {
+        return Vec::new();
+    }
+
+    urls.iter()
+        .filter(|url| !url.is_empty())
+        .map(|url| UpstreamProvider {
+            id: id.to_string(),
+            name: Some(name.clone()),
+            base_url: normalize_base_url(app, url),
+            api_key: api_key.clone(),
+            hop: 0,
+        })
+        .collect()
+}
+
+/// 测速最快的地址在前，其次是当前激活地址，其余按延迟升序，未测速的排在最后
+fn ordered_urls(options: &OpenCodeProviderOptions) -> Vec<String> {
+    let mut rest: Vec<_> = options.base_urls.iter().collect();
+    rest.sort_by_key(|u| u.latency_ms.unwrap_or(u64::MAX));
+
+    let mut urls: Vec<String> = Vec::new();
+    let preferred = [options.get_fastest_url(), Some(options.base_url.as_str())];
+    for url in preferred
+        .into_iter()
+        .flatten()
+        .chain(rest.into_iter().map(|u| u.url.as_str()))
+    {
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// Claude、Gemini 的转发路径自带版本前缀，Codex 的地址需要以 /v1 结尾
+fn normalize_base_url(app: AppType, base_url: &str) -> String {
+    let base = base_url.trim_end_matches('/');
+    match app {
+        AppType::Claude => base.trim_end_matches("/v1").to_string(),
+        AppType::Gemini => base.trim_end_matches("/v1beta").to_string(),
+        AppType::Codex => {
+            let origin_only = match base.split_once("://") {
+                Some((_, rest)) => !rest.contains('/'),
+                None => !base.contains('/'),
+            };
+            if origin_only {
+                format!("{}/v1", base)
+            } else {
+                base.to_string()
+            }
+        }
+        _ => base.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sources(open_switch: serde_json::Value, opencode: serde_json::Value) -> ProviderSources {
+        ProviderSources {
+            open_switch: Some(serde_json::from_value(open_switch).unwrap()),
+            opencode: serde_json::from_value(opencode).unwrap(),
+        }
+    }
+
+    fn unified(id: &str, base_url: &str, api_key: &str) -> serde_json::Value {
+        json!({ "id": id, "name": id, "baseUrl": base_url, "apiKey": api_key, "apps": {} })
+    }
+
+    fn opencode(model_type: &str, base_url: &str, urls: serde_json::Value) -> serde_json::Value {
+        json!({
+            "name": model_type,
+            "model_type": model_type,
+            "options": { "baseURL": base_url, "apiKey": "oc-key", "baseUrls": urls },
+            "models": {}
+        })
+    }
+
+    fn hops(chain: &[UpstreamProvider]) -> Vec<(&str, &str, u32)> {
+        chain
+            .iter()
+            .map(|u| (u.id.as_str(), u.base_url.as_str(), u.hop))
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_puts_current_provider_before_failovers() {
+        let sources = sources(
+            json!({
+                "version": "1.0",
+                "providers": {
+                    "main": unified("main", "https://main.example.com/v1", "k1"),
+                    "backup": unified("backup", "https://backup.example.com", "k2")
+                },
+                "current": { "claude": "main" }
+            }),
+            json!({}),
+        );
+        let chain = chain_from_sources(
+            AppType::Claude,
+            &[
+                "backup".to_string(),
+                "missing".to_string(),
+                "main".to_string(),
+            ],
+            &sources,
+        );
+        // 未找到的服务商被跳过，重复出现的上游只保留第一次
+        assert_eq!(
+            hops(&chain),
+            vec![
+                ("main", "https://main.example.com", 0),
+                ("backup", "https://backup.example.com", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chain_falls_back_to_first_enabled_opencode_provider() {
+        let sources = sources(
+            json!({ "version": "1.0" }),
+            json!({
+                "b-relay": opencode("claude", "https://b.example.com", json!([])),
+                "a-relay": opencode("claude", "https://a.example.com", json!([])),
+                "codex-relay": opencode("codex", "https://c.example.com", json!([]))
+            }),
+        );
+        let chain = chain_from_sources(AppType::Claude, &[], &sources);
+        assert_eq!(hops(&chain), vec![("a-relay", "https://a.example.com", 0)]);
+    }
+
+    #[test]
+    fn test_build_chain_expands_urls_by_latency_and_skips_empty_keys() {
+        let sources = sources(
+            json!({
+                "version": "1.0",
+                "providers": { "no-key": unified("no-key", "https://nokey.example.com", "") }
+            }),
+            json!({
+                "relay": opencode("codex", "https://active.example.com", json!([
+                    { "url": "https://slow.example.com", "latency_ms": 900 },
+                    { "url": "https://active.example.com" },
+                    { "url": "https://fast.example.com/v1", "latency_ms": 100 }
+                ]))
+            }),
+        );
+        let chain = build_chain(
+            AppType::Codex,
+            &["no-key", "relay"],
+            sources.open_switch.as_ref(),
+            &sources.opencode,
+        );
+        assert_eq!(
+            hops(&chain),
+            vec![
+                ("relay", "https://fast.example.com/v1", 0),
+                ("relay", "https://active.example.com/v1", 1),
+                ("relay", "https://slow.example.com/v1", 2),
+            ]
+        );
+    }
+}
@@ -2,14 +2,19 @@
 //!
 //! 基于 Axum 的 HTTP 服务器，处理代理请求
 
-use super::{handlers, types::*, ProxyConfig};
+use super::{circuit::CircuitBreakers, handlers, types::*, ProxyConfig};
+use crate::modules::metrics::{self, InFlightGuard};
 use crate::modules::opencode_db::Database;
 use crate::opencode_error::AppError;
 use axum::{
-    extract::DefaultBodyLimit,
+    body::{Body, HttpBody},
+    extract::{DefaultBodyLimit, Request},
+    middleware::{self, Next},
+    response::Response,
     routing::{get, post},
     Router,
 };
+use futures::StreamExt;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
@@ -22,6 +27,9 @@ pub struct ProxyState {
     pub config: Arc<RwLock<ProxyConfig>>,
     pub status: Arc<RwLock<ProxyStatus>>,
     pub start_time: Arc<RwLock<Option<Instant>>>,
+    pub circuits: Arc<CircuitBreakers>,
+    /// 转发上游请求共用的客户端，复用连接池
+    pub client: reqwest::Client,
 }
 
 /// 代理 HTTP 服务器
@@ -35,10 +43,12 @@ impl ProxyServer {
     /// 创建新的代理服务器
     pub fn new(config: ProxyConfig, db: Arc<Database>) -> Self {
         let state = ProxyState {
+            circuits: Arc::new(CircuitBreakers::load(&db)),
             db,
             config: Arc::new(RwLock::new(config.clone())),
             status: Arc::new(RwLock::new(ProxyStatus::default())),
             start_time: Arc::new(RwLock::new(None)),
+            client: handlers::upstream_client(),
         };
 
         Self {
@@ -124,6 +134,7 @@ impl ProxyServer {
         if let Some(start) = *self.state.start_time.read().await {
             status.uptime_seconds = start.elapsed().as_secs();
         }
+        status.upstreams = self.state.circuits.snapshot();
 
         status
     }
@@ -146,6 +157,7 @@ impl ProxyServer {
             // 健康检查
             .route("/health", get(handlers::health_check))
             .route("/status", get(handlers::get_status))
+            .route("/metrics", get(handlers::get_metrics))
             // Claude API
             .route("/v1/messages", post(handlers::handle_claude))
             .route("/claude/v1/messages", post(handlers::handle_claude))
@@ -164,9 +176,65 @@ impl ProxyServer {
             .route("/cursor-welfare/v1/messages", post(handlers::handle_cursor_welfare_claude_compat))
             // Cursor Welfare Gemini 协议适配（Gemini -> OpenAI -> cursor2api-go）
             .route("/cursor-welfare/v1beta/*path", post(handlers::handle_cursor_welfare_gemini_compat))
+            // 协议转换：Claude Code / Gemini CLI 使用其他协议的服务商
+            .route("/claude-openai/v1/messages", post(handlers::handle_claude_to_openai))
+            .route("/claude-gemini/v1/messages", post(handlers::handle_claude_to_gemini))
+            .route("/gemini-openai/v1beta/*path", post(handlers::handle_gemini_to_openai))
             // 提高请求体大小限制
             .layer(DefaultBodyLimit::max(200 * 1024 * 1024))
             .layer(cors)
+            .layer(middleware::from_fn(track_request))
             .with_state(self.state.clone())
     }
 }
+
+/// 在途请求与请求耗时，流式响应计到最后一个数据块发送完毕
+struct RequestTimer {
+    app: AppType,
+    start: Instant,
+    _in_flight: InFlightGuard,
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        metrics::proxy()
+            .observe_duration(vec![("app", self.app.to_string())], self.start.elapsed());
+    }
+}
+
+async fn track_request(req: Request, next: Next) -> Response {
+    let Some(app) = app_type_for_path(req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let timer = RequestTimer {
+        app,
+        start: Instant::now(),
+        _in_flight: metrics::proxy().track_in_flight(),
+    };
+    let response = next.run(req).await;
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _timer = &timer;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 按路由判断请求所属的应用，健康检查等内部端点不计入
+fn app_type_for_path(path: &str) -> Option<AppType> {
+    if path.starts_with("/cursor-welfare/") {
+        Some(AppType::CursorWelfare)
+    } else if path.contains("/v1beta/") {
+        Some(AppType::Gemini)
+    } else if path.ends_with("/messages") {
+        Some(AppType::Claude)
+    } else if path.ends_with("/chat/completions") || path.ends_with("/responses") {
+        Some(AppType::Codex)
+    } else {
+        None
+    }
+}
@@ -2,16 +2,31 @@
 //!
 //! 提供代理服务器的启动、停止和配置接管管理
 
-use super::{ProxyConfig, ProxyServer, ProxyServerInfo, ProxyStatus, ProxyTakeoverStatus};
+use super::circuit::CircuitBreakers;
+use super::convert;
+use super::{
+    OrphanedTakeover, ProxyConfig, ProxyServer, ProxyServerInfo, ProxyStatus, ProxyTakeoverStatus,
+    TakeoverRecoveryReport,
+};
+use crate::modules::opencode_db::schema::{ProxyConfigDb, TakeoverTarget};
 use crate::modules::opencode_db::Database;
 use crate::opencode_error::AppError;
 use serde_json::{json, Value};
 use std::sync::Arc;
+use tauri::Emitter;
 use tokio::sync::RwLock;
 
 /// 代理接管模式下的占位符 Token
 const PROXY_TOKEN_PLACEHOLDER: &str = "PROXY_MANAGED";
 
+/// 支持接管的应用
+const TAKEOVER_APPS: [&str; 3] = ["claude", "codex", "gemini"];
+
+/// 残留接管的处理方式
+pub const RECOVERY_ASK: &str = "ask";
+pub const RECOVERY_RESTORE: &str = "restore";
+pub const RECOVERY_RELAUNCH: &str = "relaunch";
+
 /// 代理服务
 pub struct ProxyService {
     db: Arc<Database>,
@@ -29,6 +44,15 @@ impl ProxyService {
 
     /// 启动代理服务器
     pub async fn start(&self) -> Result<ProxyServerInfo, AppError> {
+        let result = self.start_server().await;
+        // 端口被占用等原因启动失败时，已接管的 CLI 仍指向不可用的代理
+        if result.is_err() && !self.is_running().await {
+            self.check_takeover_after_bind_failure();
+        }
+        result
+    }
+
+    async fn start_server(&self) -> Result<ProxyServerInfo, AppError> {
         let config_db = self.db.get_proxy_config()?;
         
         let config = ProxyConfig {
@@ -64,12 +88,15 @@ impl ProxyService {
         Ok(())
     }
 
-    /// 获取代理状态
+    /// 获取代理状态；未运行时熔断状态取自数据库中上次保存的记录
     pub async fn get_status(&self) -> ProxyStatus {
         if let Some(server) = self.server.read().await.as_ref() {
             server.get_status().await
         } else {
-            ProxyStatus::default()
+            ProxyStatus {
+                upstreams: CircuitBreakers::load(&self.db).snapshot(),
+                ..ProxyStatus::default()
+            }
         }
     }
 
@@ -105,7 +132,7 @@ impl ProxyService {
             }
         }
 
-        // 4. 更新接管状态
+        // 4. 更新接管状态，批量接管均按原生协议转发
         let mut config = self.db.get_proxy_config()?;
         for app in apps {
             match *app {
@@ -114,6 +141,7 @@ impl ProxyService {
                 "gemini" => config.takeover_gemini = true,
                 _ => {}
             }
+            set_target(&mut config, app, None);
         }
         self.db.update_proxy_config(&config)?;
 
@@ -143,6 +171,8 @@ impl ProxyService {
         updated_config.takeover_claude = false;
         updated_config.takeover_codex = false;
         updated_config.takeover_gemini = false;
+        updated_config.target_claude = None;
+        updated_config.target_gemini = None;
         self.db.update_proxy_config(&updated_config)?;
 
         // 删除备份
@@ -160,25 +190,49 @@ impl ProxyService {
             claude: config.takeover_claude,
             codex: config.takeover_codex,
             gemini: config.takeover_gemini,
+            claude_target: config.target_claude,
+            gemini_target: config.target_gemini,
         })
     }
 
-    /// 为指定应用开启/关闭接管
-    pub async fn set_takeover_for_app(&self, app_type: &str, enabled: bool) -> Result<(), AppError> {
+    /// 为指定应用开启/关闭接管。指定 `target` 时 CLI 指向协议转换路由，
+    /// 请求转换为目标协议后发往目标服务商
+    pub async fn set_takeover_for_app(
+        &self,
+        app_type: &str,
+        enabled: bool,
+        target: Option<TakeoverTarget>,
+    ) -> Result<(), AppError> {
         let mut config = self.db.get_proxy_config()?;
 
         if enabled {
+            let route_prefix = match &target {
+                Some(t) => convert::route_prefix(app_type, &t.protocol).ok_or_else(|| {
+                    AppError::Proxy(format!("{app_type} 不支持转换为 {} 协议", t.protocol))
+                })?,
+                None => "",
+            };
+
             // 确保代理服务器正在运行
             if !self.is_running().await {
                 self.start().await?;
             }
 
-            // 备份并接管配置
-            self.backup_live_config(app_type)?;
+            // 备份并接管配置；已接管时只是切换转发目标，不能用已改写的配置覆盖备份
+            let already_taken_over = match app_type {
+                "claude" => config.takeover_claude,
+                "codex" => config.takeover_codex,
+                "gemini" => config.takeover_gemini,
+                _ => false,
+            };
+            if !already_taken_over {
+                self.backup_live_config(app_type)?;
+            }
             
             let status = self.get_status().await;
-            let proxy_url = format!("http://{}:{}", status.address, status.port);
+            let proxy_url = format!("http://{}:{}{}", status.address, status.port, route_prefix);
             self.takeover_live_config(app_type, &proxy_url)?;
+            set_target(&mut config, app_type, target);
 
             // 更新接管状态
             match app_type {
@@ -199,6 +253,7 @@ impl ProxyService {
                 "gemini" => config.takeover_gemini = false,
                 _ => {}
             }
+            set_target(&mut config, app_type, None);
 
             // 如果没有任何应用被接管，停止代理
             if !config.takeover_claude && !config.takeover_codex && !config.takeover_gemini {
@@ -214,12 +269,7 @@ impl ProxyService {
 
     /// 备份应用的配置
     fn backup_live_config(&self, app_type: &str) -> Result<(), AppError> {
-        let config = match app_type {
-            "claude" => self.read_claude_live()?,
-            "codex" => self.read_codex_live()?,
-            "gemini" => self.read_gemini_live()?,
-            _ => return Err(AppError::Proxy(format!("未知的应用类型: {app_type}"))),
-        };
+        let config = self.read_live_config(app_type)?;
 
         let json_str = serde_json::to_string(&config)
             .map_err(|e| AppError::Proxy(format!("序列化配置失败: {e}")))?;
@@ -228,6 +278,16 @@ impl ProxyService {
         Ok(())
     }
 
+    /// 读取应用当前的配置
+    fn read_live_config(&self, app_type: &str) -> Result<Value, AppError> {
+        match app_type {
+            "claude" => self.read_claude_live(),
+            "codex" => self.read_codex_live(),
+            "gemini" => self.read_gemini_live(),
+            _ => Err(AppError::Proxy(format!("未知的应用类型: {app_type}"))),
+        }
+    }
+
     /// 接管应用的配置
     fn takeover_live_config(&self, app_type: &str, proxy_url: &str) -> Result<(), AppError> {
         match app_type {
@@ -257,6 +317,198 @@ impl ProxyService {
         Ok(())
     }
 
+    // ==================== 残留接管恢复 ====================
+
+    /// 获取残留接管；代理在本进程运行时不存在残留
+    pub async fn get_orphaned_takeover(&self) -> Result<Vec<OrphanedTakeover>, AppError> {
+        if self.is_running().await {
+            return Ok(Vec::new());
+        }
+        self.scan_takeover()
+    }
+
+    /// 处理残留接管：restore 用备份恢复 CLI 配置，relaunch 重新启动代理并重新接管
+    pub async fn recover_takeover(&self, action: &str) -> Result<(), AppError> {
+        let orphaned = self.get_orphaned_takeover().await?;
+        if orphaned.is_empty() {
+            return Ok(());
+        }
+        match action {
+            RECOVERY_RESTORE => self.restore_orphaned(&orphaned),
+            RECOVERY_RELAUNCH => self.relaunch_orphaned(&orphaned).await,
+            _ => Err(AppError::Proxy(format!("未知的处理方式: {action}"))),
+        }
+    }
+
+    /// 启动时检查上次崩溃或被强制结束残留的接管，按 `takeover_recovery` 自动处理，
+    /// 为 ask 或自动处理失败时由前端提示用户选择
+    pub async fn reconcile_takeover(&self) -> Result<TakeoverRecoveryReport, AppError> {
+        let mut report = TakeoverRecoveryReport {
+            reason: "startup".to_string(),
+            orphaned: self.get_orphaned_takeover().await?,
+            action: None,
+            error: None,
+        };
+        if report.orphaned.is_empty() {
+            return Ok(report);
+        }
+
+        let policy = self.db.get_proxy_config()?.takeover_recovery;
+        let result = match policy.as_str() {
+            RECOVERY_RESTORE => Some(self.restore_orphaned(&report.orphaned)),
+            RECOVERY_RELAUNCH => Some(self.relaunch_orphaned(&report.orphaned).await),
+            _ => None,
+        };
+        if let Some(result) = result {
+            report.action = Some(policy);
+            report.error = result.err().map(|e| e.to_string());
+        }
+
+        notify_orphaned(&report);
+        Ok(report)
+    }
+
+    /// 代理启动失败后检查残留接管；此时无法重新启动代理，只按配置自动恢复
+    fn check_takeover_after_bind_failure(&self) {
+        let orphaned = match self.scan_takeover() {
+            Ok(orphaned) => orphaned,
+            Err(e) => {
+                tracing::warn!("[Proxy] 检查残留接管失败: {}", e);
+                return;
+            }
+        };
+        if orphaned.is_empty() {
+            return;
+        }
+
+        let mut report = TakeoverRecoveryReport {
+            reason: "bind_failed".to_string(),
+            orphaned,
+            action: None,
+            error: None,
+        };
+        let auto_restore = self
+            .db
+            .get_proxy_config()
+            .is_ok_and(|config| config.takeover_recovery == RECOVERY_RESTORE);
+        if auto_restore {
+            report.action = Some(RECOVERY_RESTORE.to_string());
+            report.error = self
+                .restore_orphaned(&report.orphaned)
+                .err()
+                .map(|e| e.to_string());
+        }
+
+        notify_orphaned(&report);
+    }
+
+    /// 对比 CLI 配置、备份与接管状态：仍指向本地代理的应用为残留接管；
+    /// 配置已被改回但仍有接管记录的，说明备份已过期，直接清除记录，避免恢复时覆盖用户的修改
+    fn scan_takeover(&self) -> Result<Vec<OrphanedTakeover>, AppError> {
+        let mut config = self.db.get_proxy_config()?;
+        let proxy_origin = format!("http://{}:{}", config.listen_address, config.listen_port);
+        let mut orphaned = Vec::new();
+        let mut stale = false;
+
+        for app_type in TAKEOVER_APPS {
+            // 单个 CLI 配置损坏或无法读取时只跳过该应用，不影响其余应用的检查
+            let live = match self.read_live_config(app_type) {
+                Ok(live) => live,
+                Err(e) => {
+                    tracing::warn!(
+                        "[Proxy] 读取 {} 配置失败，跳过残留接管检查: {}",
+                        app_type,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let proxy_url = live_base_url(app_type, &live);
+            let has_backup = self.db.get_live_backup(app_type)?.is_some();
+            let taken_over = proxy_url
+                .as_deref()
+                .is_some_and(|url| url.starts_with(&proxy_origin))
+                || live.to_string().contains(PROXY_TOKEN_PLACEHOLDER);
+
+            if taken_over {
+                orphaned.push(OrphanedTakeover {
+                    app_type: app_type.to_string(),
+                    proxy_url,
+                    has_backup,
+                });
+            } else if has_backup || takeover_flag(&config, app_type) {
+                tracing::info!(
+                    "[Proxy] {} 配置已不再指向代理，清除过期的接管记录",
+                    app_type
+                );
+                let _ = self.db.delete_live_backup(app_type);
+                set_takeover_flag(&mut config, app_type, false);
+                set_target(&mut config, app_type, None);
+                stale = true;
+            }
+        }
+
+        if stale {
+            self.db.update_proxy_config(&config)?;
+        }
+        Ok(orphaned)
+    }
+
+    /// 用备份恢复残留接管的 CLI 配置并清除接管记录；没有备份的应用保持原样并返回错误
+    fn restore_orphaned(&self, orphaned: &[OrphanedTakeover]) -> Result<(), AppError> {
+        let mut config = self.db.get_proxy_config()?;
+        let mut missing = Vec::new();
+
+        for item in orphaned {
+            if !item.has_backup {
+                missing.push(item.app_type.as_str());
+                continue;
+            }
+            self.restore_live_config(&item.app_type)?;
+            let _ = self.db.delete_live_backup(&item.app_type);
+            set_takeover_flag(&mut config, &item.app_type, false);
+            set_target(&mut config, &item.app_type, None);
+        }
+
+        if TAKEOVER_APPS
+            .iter()
+            .all(|app_type| !takeover_flag(&config, app_type))
+        {
+            config.proxy_enabled = false;
+        }
+        self.db.update_proxy_config(&config)?;
+
+        if !missing.is_empty() {
+            return Err(AppError::Proxy(format!(
+                "{} 没有配置备份，无法自动恢复，请重新启动代理或手动修改配置",
+                missing.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// 重新启动代理，并让残留接管的 CLI 指向新的代理地址（监听端口可能已修改）
+    async fn relaunch_orphaned(&self, orphaned: &[OrphanedTakeover]) -> Result<(), AppError> {
+        let info = self.start_server().await?;
+        let mut config = self.db.get_proxy_config()?;
+
+        for item in orphaned {
+            let target = match item.app_type.as_str() {
+                "claude" => config.target_claude.as_ref(),
+                "gemini" => config.target_gemini.as_ref(),
+                _ => None,
+            };
+            let route_prefix = target
+                .and_then(|t| convert::route_prefix(&item.app_type, &t.protocol))
+                .unwrap_or("");
+            let proxy_url = format!("http://{}:{}{}", info.address, info.port, route_prefix);
+            self.takeover_live_config(&item.app_type, &proxy_url)?;
+            set_takeover_flag(&mut config, &item.app_type, true);
+        }
+
+        self.db.update_proxy_config(&config)
+    }
+
     // ==================== Claude 配置处理 ====================
 
     fn read_claude_live(&self) -> Result<Value, AppError> {
@@ -475,3 +727,63 @@ impl ProxyService {
         self.write_gemini_live(&config)
     }
 }
+
+/// 仅 Claude、Gemini 支持协议转换接管
+fn set_target(config: &mut ProxyConfigDb, app_type: &str, target: Option<TakeoverTarget>) {
+    match app_type {
+        "claude" => config.target_claude = target,
+        "gemini" => config.target_gemini = target,
+        _ => {}
+    }
+}
+
+fn takeover_flag(config: &ProxyConfigDb, app_type: &str) -> bool {
+    match app_type {
+        "claude" => config.takeover_claude,
+        "codex" => config.takeover_codex,
+        "gemini" => config.takeover_gemini,
+        _ => false,
+    }
+}
+
+fn set_takeover_flag(config: &mut ProxyConfigDb, app_type: &str, enabled: bool) {
+    match app_type {
+        "claude" => config.takeover_claude = enabled,
+        "codex" => config.takeover_codex = enabled,
+        "gemini" => config.takeover_gemini = enabled,
+        _ => {}
+    }
+}
+
+/// CLI 配置中的上游地址
+fn live_base_url(app_type: &str, live: &Value) -> Option<String> {
+    let url = match app_type {
+        "claude" => live.pointer("/env/ANTHROPIC_BASE_URL")?.as_str()?,
+        "codex" => live.get("config")?.as_str()?.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "base_url").then_some(value.trim().trim_matches('"'))
+        })?,
+        "gemini" => live.pointer("/env/GOOGLE_GEMINI_BASE_URL")?.as_str()?,
+        _ => return None,
+    };
+    Some(url.to_string())
+}
+
+/// 通知前端存在残留接管，由用户选择恢复配置或重新启动代理
+fn notify_orphaned(report: &TakeoverRecoveryReport) {
+    let apps: Vec<&str> = report
+        .orphaned
+        .iter()
+        .map(|o| o.app_type.as_str())
+        .collect();
+    tracing::warn!(
+        "[Proxy] 发现残留接管 ({}): {}，自动处理: {:?}，错误: {:?}",
+        report.reason,
+        apps.join(", "),
+        report.action,
+        report.error
+    );
+    if let Some(app_handle) = crate::get_app_handle() {
+        let _ = app_handle.emit("proxy:takeover_orphaned", report);
+    }
+}
@@ -1,5 +1,6 @@
 //! 代理服务器类型定义
 
+use crate::modules::opencode_db::schema::TakeoverTarget;
 use serde::{Deserialize, Serialize};
 
 /// 代理服务器配置
@@ -41,6 +42,25 @@ pub struct ProxyStatus {
     pub failed_requests: u64,
     /// 运行时间（秒）
     pub uptime_seconds: u64,
+    /// 各上游的健康统计与熔断状态
+    pub upstreams: Vec<UpstreamCircuitStatus>,
+}
+
+/// 单个上游的健康统计与熔断状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamCircuitStatus {
+    pub provider_id: String,
+    pub base_url: String,
+    /// closed / open / half_open
+    pub state: String,
+    /// 统计窗口内的请求数与错误率
+    pub window_requests: u32,
+    pub error_rate: f64,
+    pub consecutive_failures: u32,
+    pub avg_latency_ms: u64,
+    /// 熔断截止时间（Unix 秒），到期后放行探测请求
+    pub open_until: Option<i64>,
 }
 
 /// 代理服务器信息
@@ -59,6 +79,33 @@ pub struct ProxyTakeoverStatus {
     pub claude: bool,
     pub codex: bool,
     pub gemini: bool,
+    /// 协议转换接管的目标，原生协议接管时为空
+    pub claude_target: Option<TakeoverTarget>,
+    pub gemini_target: Option<TakeoverTarget>,
+}
+
+/// 残留接管：代理未运行，但 CLI 配置仍指向本地代理（通常是应用崩溃或被强制结束）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedTakeover {
+    pub app_type: String,
+    /// CLI 配置中当前的代理地址
+    pub proxy_url: Option<String>,
+    /// 没有备份时无法恢复，只能重新启动代理
+    pub has_backup: bool,
+}
+
+/// 残留接管的检查结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoverRecoveryReport {
+    /// 触发检查的原因：startup / bind_failed
+    pub reason: String,
+    pub orphaned: Vec<OrphanedTakeover>,
+    /// 已自动执行的处理（restore / relaunch），为空时等待用户选择
+    pub action: Option<String>,
+    /// 自动处理失败的原因
+    pub error: Option<String>,
 }
 
 /// 应用类型
@@ -68,6 +115,7 @@ pub enum AppType {
     Codex,
     Gemini,
     CursorWelfare,
+    Gateway,
 }
 
 impl AppType {
@@ -77,6 +125,7 @@ impl AppType {
             AppType::Codex => "codex",
             AppType::Gemini => "gemini",
             AppType::CursorWelfare => "cursor_welfare",
+            AppType::Gateway => "gateway",
         }
     }
 }
@@ -0,0 +1,366 @@
+//! 消费预算
+//!
+//! 按应用、服务商或项目设置每日/每周/每月的消费上限。代理记录使用量或导入本地日志后评估相关预算，
+//! 消费在每个周期内达到 80%、100% 时各提醒一次；设置了硬上限的预算超限后，本地代理拒绝对应范围的请求。
+//! 项目只能从使用记录中识别，代理转发时无法判断，因此项目预算只提醒、不能设置硬上限
+
+use crate::modules::opencode_db::schema::UsageBudgetDb;
+use crate::modules::opencode_db::Database;
+use crate::modules::{account, i18n};
+use crate::opencode_error::AppError;
+use chrono::{DateTime, Datelike, Days, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// 预算范围
+pub const SCOPE_APP_TYPE: &str = "app_type";
+pub const SCOPE_PROVIDER: &str = "provider";
+pub const SCOPE_PROJECT: &str = "project";
+
+/// 统计周期
+pub const PERIOD_DAILY: &str = "daily";
+pub const PERIOD_WEEKLY: &str = "weekly";
+pub const PERIOD_MONTHLY: &str = "monthly";
+
+/// 提醒阈值（百分比），100 即达到上限
+const ALERT_THRESHOLDS: [u32; 2] = [80, 100];
+const HARD_CAP_THRESHOLD: u32 = 100;
+
+/// 刚写入的使用量记录所属的范围，只评估与之相关的预算
+pub struct UsageScope<'a> {
+    pub app_type: &'a str,
+    pub provider_id: &'a str,
+    pub project_name: Option<&'a str>,
+}
+
+/// 预算在当前周期的消费情况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub budget: UsageBudgetDb,
+    /// 当前周期起点，Unix 秒
+    pub period_start: i64,
+    pub spent_usd: f64,
+    pub percent: f64,
+    /// 已超出硬上限，本地代理正在拒绝该范围的请求
+    pub capped: bool,
+}
+
+/// 预算提醒，通过 `usage:budget_alert` 事件与系统通知发出
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetAlertPayload {
+    pub budget_id: String,
+    pub scope: String,
+    pub scope_value: String,
+    pub period: String,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    pub threshold: u32,
+    pub hard_cap: bool,
+}
+
+/// 校验预算配置
+pub fn validate_budget(budget: &UsageBudgetDb) -> Result<(), AppError> {
+    if ![SCOPE_APP_TYPE, SCOPE_PROVIDER, SCOPE_PROJECT].contains(&budget.scope.as_str()) {
+        return Err(AppError::Proxy(format!("未知的预算范围: {}", budget.scope)));
+    }
+    if ![PERIOD_DAILY, PERIOD_WEEKLY, PERIOD_MONTHLY].contains(&budget.period.as_str()) {
+        return Err(AppError::Proxy(format!(
+            "未知的预算周期: {}",
+            budget.period
+        )));
+    }
+    if budget.scope_value.trim().is_empty() {
+        return Err(AppError::Proxy("预算范围取值不能为空".to_string()));
+    }
+    if !budget.limit_usd.is_finite() || budget.limit_usd <= 0.0 {
+        return Err(AppError::Proxy("预算金额必须大于 0".to_string()));
+    }
+    if budget.hard_cap && budget.scope == SCOPE_PROJECT {
+        return Err(AppError::Proxy(
+            "项目预算无法在代理转发时识别，不支持硬上限".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 周期起点（所在时区的零点），周从周一开始
+pub fn period_start<Tz: TimeZone>(period: &str, now: &DateTime<Tz>) -> Option<i64> {
+    let today = now.date_naive();
+    let start = match period {
+        PERIOD_DAILY => today,
+        PERIOD_WEEKLY => {
+            today.checked_sub_days(Days::new(today.weekday().num_days_from_monday() as u64))?
+        }
+        PERIOD_MONTHLY => today.with_day(1)?,
+        _ => return None,
+    };
+    now.timezone()
+        .from_local_datetime(&start.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|start| start.timestamp())
+}
+
+/// 获取所有预算在当前周期的消费情况
+pub fn list_budget_status(db: &Database) -> Result<Vec<BudgetStatus>, AppError> {
+    let now = Local::now();
+    db.list_usage_budgets()?
+        .into_iter()
+        .map(|budget| budget_status(db, budget, &now))
+        .collect()
+}
+
+/// 评估与本次写入相关的预算，`scope` 为空时评估全部预算（如批量导入本地日志后）
+pub fn evaluate_budgets(db: &Database, scope: Option<&UsageScope>) {
+    let budgets = match db.list_usage_budgets() {
+        Ok(budgets) => budgets,
+        Err(e) => {
+            tracing::warn!("[Budget] 读取消费预算失败: {}", e);
+            return;
+        }
+    };
+
+    let now = Local::now();
+    for budget in budgets {
+        if !budget.enabled || scope.is_some_and(|scope| !matches_scope(&budget, scope)) {
+            continue;
+        }
+        if let Err(e) = evaluate_budget(db, budget, &now) {
+            tracing::warn!("[Budget] 评估消费预算失败: {}", e);
+        }
+    }
+}
+
+/// 本地代理转发前检查硬上限，服务商或其所属应用已超限时返回拒绝原因
+pub fn hard_cap_reason(db: &Database, app_type: &str, provider_id: &str) -> Option<String> {
+    let budgets = db.list_usage_budgets().ok()?;
+    let now = Local::now();
+    budgets
+        .iter()
+        .find(|budget| {
+            let in_scope = match budget.scope.as_str() {
+                SCOPE_APP_TYPE => budget.scope_value == app_type,
+                SCOPE_PROVIDER => budget.scope_value == provider_id,
+                _ => false,
+            };
+            in_scope && is_capped(budget, &now)
+        })
+        .map(|budget| {
+            format!(
+                "已超出{} {} 的{}预算上限 ${}",
+                scope_label(&budget.scope),
+                budget.scope_value,
+                period_label(&budget.period),
+                budget.limit_usd
+            )
+        })
+}
+
+pub fn dispatch_budget_alert(payload: &BudgetAlertPayload) {
+    tracing::warn!(
+        "[Budget] 触发消费预算提醒: {}={}, period={}, spent=${:.4}, limit=${}, threshold={}%",
+        payload.scope,
+        payload.scope_value,
+        payload.period,
+        payload.spent_usd,
+        payload.limit_usd,
+        payload.threshold
+    );
+
+    if let Some(app_handle) = crate::get_app_handle() {
+        use tauri::Emitter;
+        let _ = app_handle.emit("usage:budget_alert", payload);
+    }
+    let (title, body) = build_budget_alert_notification_text(payload);
+    account::send_native_notification(title, body);
+}
+
+fn budget_status<Tz: TimeZone>(
+    db: &Database,
+    budget: UsageBudgetDb,
+    now: &DateTime<Tz>,
+) -> Result<BudgetStatus, AppError> {
+    let period_start = period_start(&budget.period, now)
+        .ok_or_else(|| AppError::Proxy(format!("未知的预算周期: {}", budget.period)))?;
+    let spent_usd = db.sum_usage_cost(&budget.scope, &budget.scope_value, period_start)?;
+    let percent = if budget.limit_usd > 0.0 {
+        spent_usd / budget.limit_usd * 100.0
+    } else {
+        0.0
+    };
+    let capped = is_capped(&budget, now);
+    Ok(BudgetStatus {
+        budget,
+        period_start,
+        spent_usd,
+        percent,
+        capped,
+    })
+}
+
+fn evaluate_budget<Tz: TimeZone>(
+    db: &Database,
+    budget: UsageBudgetDb,
+    now: &DateTime<Tz>,
+) -> Result<(), AppError> {
+    let status = budget_status(db, budget, now)?;
+    let budget = &status.budget;
+    // 进入新周期后之前的提醒不再算数
+    let alerted = if budget.alerted_period_start == Some(status.period_start) {
+        budget.alerted_threshold
+    } else {
+        0
+    };
+    let Some(threshold) = next_alert(status.percent, alerted) else {
+        return Ok(());
+    };
+
+    db.update_budget_alert(&budget.id, threshold, status.period_start)?;
+    dispatch_budget_alert(&BudgetAlertPayload {
+        budget_id: budget.id.clone(),
+        scope: budget.scope.clone(),
+        scope_value: budget.scope_value.clone(),
+        period: budget.period.clone(),
+        limit_usd: budget.limit_usd,
+        spent_usd: status.spent_usd,
+        threshold,
+        hard_cap: budget.hard_cap,
+    });
+    Ok(())
+}
+
+/// 本周期内尚未提醒过的最高阈值
+fn next_alert(percent: f64, alerted: u32) -> Option<u32> {
+    ALERT_THRESHOLDS
+        .iter()
+        .rev()
+        .copied()
+        .find(|threshold| percent >= *threshold as f64)
+        .filter(|threshold| *threshold > alerted)
+}
+
+fn matches_scope(budget: &UsageBudgetDb, scope: &UsageScope) -> bool {
+    match budget.scope.as_str() {
+        SCOPE_APP_TYPE => budget.scope_value == scope.app_type,
+        SCOPE_PROVIDER => budget.scope_value == scope.provider_id,
+        SCOPE_PROJECT => scope.project_name == Some(budget.scope_value.as_str()),
+        _ => false,
+    }
+}
+
+/// 硬上限以本周期已记录的 100% 提醒为准，进入新周期后自动解除
+fn is_capped<Tz: TimeZone>(budget: &UsageBudgetDb, now: &DateTime<Tz>) -> bool {
+    budget.enabled
+        && budget.hard_cap
+        && budget.alerted_threshold >= HARD_CAP_THRESHOLD
+        && budget.alerted_period_start.is_some()
+        && budget.alerted_period_start == period_start(&budget.period, now)
+}
+
+fn scope_label(scope: &str) -> &'static str {
+    match scope {
+        SCOPE_APP_TYPE => "应用",
+        SCOPE_PROVIDER => "服务商",
+        _ => "项目",
+    }
+}
+
+fn period_label(period: &str) -> &'static str {
+    match period {
+        PERIOD_DAILY => "每日",
+        PERIOD_WEEKLY => "每周",
+        _ => "每月",
+    }
+}
+
+fn build_budget_alert_notification_text(payload: &BudgetAlertPayload) -> (String, String) {
+    let locale = crate::modules::config::get_user_config().language;
+    let scope = format!(
+        "{} {}",
+        i18n::translate(
+            &locale,
+            &format!("budgetAlert.scope.{}", payload.scope),
+            &[]
+        ),
+        payload.scope_value
+    );
+    let period = i18n::translate(
+        &locale,
+        &format!("budgetAlert.period.{}", payload.period),
+        &[],
+    );
+    let spent = format!("{:.2}", payload.spent_usd);
+    let limit = format!("{:.2}", payload.limit_usd);
+    let threshold = payload.threshold.to_string();
+
+    let title = i18n::translate(&locale, "budgetAlert.title", &[]);
+    let mut body = i18n::translate(
+        &locale,
+        "budgetAlert.body",
+        &[
+            ("scope", scope.as_str()),
+            ("period", period.as_str()),
+            ("spent", spent.as_str()),
+            ("limit", limit.as_str()),
+            ("threshold", threshold.as_str()),
+        ],
+    );
+    if payload.hard_cap && payload.threshold >= HARD_CAP_THRESHOLD {
+        body.push_str(" · ");
+        body.push_str(&i18n::translate(&locale, "budgetAlert.hardCap", &[]));
+    }
+    (title, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_period_start() {
+        // 2026-10-15 是周四
+        let now = Utc.with_ymd_and_hms(2026, 10, 15, 13, 30, 0).unwrap();
+        let day = |d: u32| {
+            Utc.with_ymd_and_hms(2026, 10, d, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(period_start(PERIOD_DAILY, &now), Some(day(15)));
+        assert_eq!(period_start(PERIOD_WEEKLY, &now), Some(day(12)));
+        assert_eq!(period_start(PERIOD_MONTHLY, &now), Some(day(1)));
+        assert_eq!(period_start("yearly", &now), None);
+    }
+
+    #[test]
+    fn test_next_alert_only_once_per_threshold() {
+        assert_eq!(next_alert(50.0, 0), None);
+        assert_eq!(next_alert(85.0, 0), Some(80));
+        assert_eq!(next_alert(90.0, 80), None);
+        assert_eq!(next_alert(120.0, 80), Some(100));
+        // 一次写入跨过两个阈值时只提醒最高的
+        assert_eq!(next_alert(120.0, 0), Some(100));
+        assert_eq!(next_alert(150.0, 100), None);
+    }
+
+    #[test]
+    fn test_validate_budget_rejects_project_hard_cap() {
+        let budget = |scope: &str, hard_cap: bool| UsageBudgetDb {
+            id: String::new(),
+            scope: scope.to_string(),
+            scope_value: "demo".to_string(),
+            period: PERIOD_DAILY.to_string(),
+            limit_usd: 5.0,
+            hard_cap,
+            enabled: true,
+            alerted_threshold: 0,
+            alerted_period_start: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+        assert!(validate_budget(&budget(SCOPE_PROJECT, false)).is_ok());
+        assert!(validate_budget(&budget(SCOPE_PROJECT, true)).is_err());
+        assert!(validate_budget(&budget(SCOPE_PROVIDER, true)).is_ok());
+        assert!(validate_budget(&budget(SCOPE_APP_TYPE, true)).is_ok());
+    }
+}
@@ -1,5 +1,6 @@
 //! Usage Logger - 记录 API 请求使用情况
 
+use super::budget::{self, UsageScope};
 use super::parser::TokenUsage;
 use crate::modules::opencode_db::{lock_conn, Database};
 use crate::opencode_error::AppError;
@@ -27,15 +28,21 @@ pub struct CostBreakdown {
     pub total_cost: Decimal,
 }
 
-/// 记录使用量到数据库
+/// 记录使用量到数据库；流式请求额外记录首个数据块的到达时间。
+/// `served_by` 为实际响应的上游地址及其在故障转移链中的位置（0 为首选）。
+/// 写入后评估该应用与服务商的消费预算
+#[allow(clippy::too_many_arguments)]
 pub fn log_usage(
     db: &Database,
     provider_id: &str,
     provider_name: Option<&str>,
+    served_by: Option<(&str, u32)>,
     app_type: AppType,
     model: &str,
     usage: TokenUsage,
     latency_ms: u64,
+    first_token_ms: Option<u64>,
+    is_streaming: bool,
     status_code: u16,
 ) -> Result<(), AppError> {
     let conn = lock_conn!(db.conn);
@@ -57,8 +64,8 @@ pub fn log_usage(
             request_id, provider_id, provider_name, app_type, model,
             input_tokens, output_tokens, cache_read_tokens, cache_creation_tokens,
             input_cost_usd, output_cost_usd, cache_read_cost_usd, cache_creation_cost_usd, total_cost_usd,
-            latency_ms, status_code, is_streaming, created_at
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            latency_ms, first_token_ms, status_code, is_streaming, upstream_url, failover_hop, created_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         rusqlite::params![
             request_id,
             provider_id,
@@ -75,16 +82,36 @@ pub fn log_usage(
             cost.cache_creation_cost.to_string(),
             cost.total_cost.to_string(),
             latency_ms as i64,
+            first_token_ms.map(|ms| ms as i64),
             status_code as i64,
-            0, // is_streaming
+            is_streaming as i64,
+            served_by.map(|(url, _)| url),
+            served_by.map_or(0, |(_, hop)| hop as i64),
             created_at,
         ],
     )
     .map_err(|e| AppError::Database(format!("记录使用量失败: {e}")))?;
+    drop(conn);
+
+    budget::evaluate_budgets(
+        db,
+        Some(&UsageScope {
+            app_type: app_type.as_str(),
+            provider_id,
+            project_name: None,
+        }),
+    );
 
     Ok(())
 }
 
+/// 按模型定价估算一次请求的成本（不写入数据库）
+pub fn estimate_cost(db: &Database, model: &str, usage: &TokenUsage) -> Result<CostBreakdown, AppError> {
+    let conn = lock_conn!(db.conn);
+    let pricing = get_model_pricing(&conn, model)?;
+    Ok(calculate_cost(usage, pricing.as_ref()))
+}
+
 /// 获取模型定价
 fn get_model_pricing(conn: &rusqlite::Connection, model_id: &str) -> Result<Option<ModelPricing>, AppError> {
     // 清洗模型名称
@@ -1,7 +1,8 @@
 ﻿//! 使用量解析与记录模块
 
+pub mod budget;
 mod parser;
 mod logger;
 
 pub use parser::TokenUsage;
-pub use logger::log_usage;
+pub use logger::{estimate_cost, log_usage};
@@ -42,7 +42,6 @@ impl TokenUsage {
     }
 
     /// 从 Claude API 流式响应事件解析
-    #[allow(dead_code)]
     pub fn from_claude_stream_events(events: &[Value]) -> Option<Self> {
         let mut usage = Self::default();
         let mut model: Option<String> = None;
@@ -152,7 +151,6 @@ impl TokenUsage {
     }
 
     /// 从 OpenAI 流式响应事件解析
-    #[allow(dead_code)]
     pub fn from_openai_stream_events(events: &[Value]) -> Option<Self> {
         // OpenAI 流式响应在最后一个 chunk 中包含 usage
         for event in events.iter().rev() {
@@ -165,6 +163,19 @@ impl TokenUsage {
         None
     }
 
+    /// 从 Codex Responses API 流式响应事件解析（usage 位于 response.completed 事件中）
+    pub fn from_codex_stream_events(events: &[Value]) -> Option<Self> {
+        for event in events.iter().rev() {
+            let event_type = event.get("type").and_then(|v| v.as_str()).unwrap_or("");
+            if event_type == "response.completed" || event_type == "response.done" {
+                if let Some(response) = event.get("response") {
+                    return Self::from_codex_response(response);
+                }
+            }
+        }
+        None
+    }
+
     /// 从 Gemini API 响应解析
     pub fn from_gemini_response(body: &Value) -> Option<Self> {
         let usage = body.get("usageMetadata")?;
@@ -189,7 +200,6 @@ impl TokenUsage {
     }
 
     /// 从 Gemini 流式响应 chunks 解析
-    #[allow(dead_code)]
     pub fn from_gemini_stream_chunks(chunks: &[Value]) -> Option<Self> {
         let mut total_input = 0u32;
         let mut total_tokens = 0u32;
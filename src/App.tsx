@@ -12,6 +12,7 @@ import { GlobalModal } from './components/GlobalModal';
 import { ErrorBoundary } from './components/ErrorBoundary';
 import type { QuickSettingsType } from './components/QuickSettingsPopover';
 import { Page } from './types/navigation';
+import { OrphanedTakeover, TakeoverRecoveryReport } from './types/proxy';
 import { useAutoRefresh } from './hooks/useAutoRefresh';
 import { useEasterEggTrigger } from './hooks/useEasterEggTrigger';
 import { useGlobalModal } from './hooks/useGlobalModal';
@@ -1580,6 +1581,112 @@ function App() {
     };
   }, [closeModal, openQuickSettingsForPlatform, showModal, t]);
 
+  useEffect(() => {
+    let unlisten: UnlistenFn | undefined;
+    let disposed = false;
+
+    const appLabels: Record<string, string> = {
+      claude: 'Claude Code',
+      codex: 'Codex',
+      gemini: 'Gemini CLI',
+    };
+
+    const showTakeoverModal = (orphaned: OrphanedTakeover[], autoError?: string | null) => {
+      const recover = async (action: 'restore' | 'relaunch') => {
+        await invoke('recover_proxy_takeover', { action });
+      };
+
+      showModal({
+        title: t('proxyTakeover.modal.title', '代理接管未正常结束'),
+        description: t(
+          'proxyTakeover.modal.desc',
+          '代理已不在运行，但以下 CLI 的配置仍指向本地代理，请求会一直失败。请恢复原配置或重新启动代理。'
+        ),
+        width: 'md',
+        closeOnOverlay: false,
+        content: (
+          <div className="quota-alert-modal-content">
+            {orphaned.map((item) => (
+              <div key={item.appType} className="quota-alert-modal-row quota-alert-modal-row--stack">
+                <span>{appLabels[item.appType] || item.appType}</span>
+                <strong>
+                  {item.proxyUrl || '-'}
+                  {!item.hasBackup && ` (${t('proxyTakeover.modal.noBackup', '无备份，只能重新启动代理')})`}
+                </strong>
+              </div>
+            ))}
+            {autoError && (
+              <div className="quota-alert-modal-row quota-alert-modal-row--stack">
+                <span>{t('proxyTakeover.modal.autoFailed', '自动处理失败')}</span>
+                <strong>{autoError}</strong>
+              </div>
+            )}
+          </div>
+        ),
+        actions: [
+          {
+            id: 'proxy-takeover-later',
+            label: t('proxyTakeover.modal.later', '稍后处理'),
+            variant: 'secondary',
+          },
+          {
+            id: 'proxy-takeover-settings',
+            label: t('proxyTakeover.modal.openSettings', '修改默认处理方式'),
+            variant: 'secondary',
+            onClick: () => setPage('settings'),
+          },
+          {
+            id: 'proxy-takeover-restore',
+            label: t('proxyTakeover.modal.restore', '恢复原配置'),
+            variant: 'secondary',
+            disabled: !orphaned.some((item) => item.hasBackup),
+            onClick: () => recover('restore'),
+          },
+          {
+            id: 'proxy-takeover-relaunch',
+            label: t('proxyTakeover.modal.relaunch', '重新启动代理'),
+            variant: 'primary',
+            onClick: () => recover('relaunch'),
+          },
+        ],
+      });
+    };
+
+    // 启动时的检查可能早于监听注册完成，挂载时主动查询一次
+    invoke<OrphanedTakeover[]>('get_orphaned_takeover')
+      .then((orphaned) => {
+        if (!disposed && orphaned.length > 0) {
+          showTakeoverModal(orphaned);
+        }
+      })
+      .catch(() => { /* 代理服务尚未初始化时由随后的事件通知 */ });
+
+    listen<TakeoverRecoveryReport>('proxy:takeover_orphaned', (event) => {
+      const report = event.payload;
+      if (!report || report.orphaned.length === 0) {
+        return;
+      }
+      // 已按设置自动处理成功，无需打扰用户
+      if (report.action && !report.error) {
+        return;
+      }
+      showTakeoverModal(report.orphaned, report.error);
+    }).then((fn) => {
+      if (disposed) {
+        fn();
+        return;
+      }
+      unlisten = fn;
+    });
+
+    return () => {
+      disposed = true;
+      if (unlisten) {
+        unlisten();
+      }
+    };
+  }, [showModal, t]);
+
   useEffect(() => {
     let unlisten: UnlistenFn | undefined;
 
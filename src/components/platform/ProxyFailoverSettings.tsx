@@ -0,0 +1,124 @@
+import { useState, useEffect, useMemo } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { useTranslation } from 'react-i18next';
+import { ArrowUp, ArrowDown, Trash2, Plus } from 'lucide-react';
+import { useProviderStore } from '../../stores/useProviderStore';
+import { useToast } from '../../hooks/useToast';
+import { ToastContainer } from '../Toast';
+import { FAILOVER_FIELDS, FailoverModelType, ProxyConfig } from '../../types/proxy';
+
+interface Props {
+  modelType: FailoverModelType;
+}
+
+/** 代理接管时的备用 Provider 链：当前 Provider 失败后按顺序尝试 */
+export function ProxyFailoverSettings({ modelType }: Props) {
+  const { t } = useTranslation();
+  const toast = useToast();
+  const { providers, loadProviders } = useProviderStore();
+  const [config, setConfig] = useState<ProxyConfig | null>(null);
+  const [saving, setSaving] = useState(false);
+  const [candidate, setCandidate] = useState('');
+
+  const field = FAILOVER_FIELDS[modelType];
+  const chain = config?.[field] ?? [];
+
+  useEffect(() => {
+    invoke<ProxyConfig>('get_proxy_config')
+      .then(setConfig)
+      .catch(e => toast.error(String(e)));
+    if (providers.length === 0) loadProviders();
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
+  const candidates = useMemo(
+    () => providers.filter(p => p.model_type === modelType && p.enabled && !chain.includes(p.name)),
+    [providers, modelType, chain],
+  );
+
+  const saveChain = async (next: string[]) => {
+    if (!config) return;
+    const updated = { ...config, [field]: next };
+    setSaving(true);
+    try {
+      await invoke('update_proxy_config', { config: updated });
+      setConfig(updated);
+    } catch (e) {
+      toast.error(t('providers.failoverSaveFailed', '保存备用链失败: ') + String(e));
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  const move = (index: number, offset: number) => {
+    const target = index + offset;
+    if (target < 0 || target >= chain.length) return;
+    const next = [...chain];
+    [next[index], next[target]] = [next[target], next[index]];
+    saveChain(next);
+  };
+
+  const add = () => {
+    if (!candidate) return;
+    saveChain([...chain, candidate]);
+    setCandidate('');
+  };
+
+  if (!config) return null;
+
+  return (
+    <div className="p-3 border-b border-base-content/10 flex flex-col gap-2">
+      <div>
+        <div className="font-semibold text-sm">{t('providers.failoverTitle', '代理备用链')}</div>
+        <div className="text-xs opacity-50 mt-0.5">
+          {t('providers.failoverHint', '代理接管时，当前 Provider 请求失败或熔断后按以下顺序切换')}
+        </div>
+      </div>
+
+      {chain.length === 0 ? (
+        <div className="text-xs opacity-50">{t('providers.failoverEmpty', '未配置备用 Provider')}</div>
+      ) : (
+        chain.map((name, index) => {
+          const exists = providers.some(p => p.name === name);
+          return (
+            <div key={name} className="flex items-center justify-between">
+              <span className="font-mono text-sm">
+                {index + 1}. {name}
+                {!exists && (
+                  <span className="text-xs opacity-50 ml-2">{t('providers.failoverMissing', '（Provider 不存在，将被跳过）')}</span>
+                )}
+              </span>
+              <span>
+                <button className="btn btn-xs btn-ghost" disabled={saving || index === 0} onClick={() => move(index, -1)}>
+                  <ArrowUp size={14} />
+                </button>
+                <button className="btn btn-xs btn-ghost" disabled={saving || index === chain.length - 1} onClick={() => move(index, 1)}>
+                  <ArrowDown size={14} />
+                </button>
+                <button className="btn btn-xs btn-ghost" disabled={saving} onClick={() => saveChain(chain.filter(n => n !== name))}>
+                  <Trash2 size={14} />
+                </button>
+              </span>
+            </div>
+          );
+        })
+      )}
+
+      {candidates.length > 0 && (
+        <div className="flex gap-2">
+          <select className="select select-sm select-bordered flex-1" value={candidate} onChange={e => setCandidate(e.target.value)}>
+            <option value="">{t('providers.failoverSelect', '选择备用 Provider')}</option>
+            {candidates.map(p => (
+              <option key={p.name} value={p.name}>{p.name}</option>
+            ))}
+          </select>
+          <button className="btn btn-sm btn-ghost" disabled={saving || !candidate} onClick={add}>
+            <Plus size={14} /> {t('providers.failoverAdd', '添加')}
+          </button>
+        </div>
+      )}
+
+      <ToastContainer toasts={toast.toasts} />
+    </div>
+  );
+}
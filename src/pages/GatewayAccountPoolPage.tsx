@@ -25,6 +25,15 @@ interface GatewayAccount {
   source: string | null;
 }
 
+interface AccountEvent {
+  id: number;
+  account_id: string;
+  timestamp: number;
+  from_status: string | null;
+  to_status: string;
+  reason: string | null;
+}
+
 const AVATAR_COLORS = [
   'linear-gradient(135deg, #1d4ed8, #0ea5a5)',
   'linear-gradient(135deg, #6366f1, #8b5cf6)',
@@ -62,6 +71,7 @@ export function GatewayAccountPoolPage({ embedded }: { embedded?: boolean } = {}
   const [expandedId, setExpandedId] = useState<string | null>(null);
   const [addTab, setAddTab] = useState<'manual' | 'sync'>('manual');
   const [syncing, setSyncing] = useState(false);
+  const [events, setEvents] = useState<AccountEvent[]>([]);
 
   const fetchAccounts = useCallback(async () => {
     try {
@@ -78,6 +88,14 @@ export function GatewayAccountPoolPage({ embedded }: { embedded?: boolean } = {}
     fetchAccounts();
   }, [fetchAccounts]);
 
+  useEffect(() => {
+    setEvents([]);
+    if (!expandedId) return;
+    invoke<AccountEvent[]>('list_account_events', { accountId: expandedId, limit: 10 })
+      .then(setEvents)
+      .catch(error => console.error('Failed to list account events:', error));
+  }, [expandedId]);
+
   const filteredAccounts = useMemo(() => {
     if (!searchQuery.trim()) return accounts;
     const q = searchQuery.toLowerCase();
@@ -461,6 +479,17 @@ export function GatewayAccountPoolPage({ embedded }: { embedded?: boolean } = {}
                           {account.error_count}
                         </span>
                       </div>
+                      {events.length > 0 && (
+                        <div className="gw-log-detail-item" style={{ gridColumn: '1 / -1' }}>
+                          <span className="gw-log-detail-label">{t('gateway.statusEvents', '状态变化')}</span>
+                          {events.map(event => (
+                            <span key={event.id} className="gw-log-detail-value">
+                              {formatTime(event.timestamp)} · {event.from_status || '-'} → {event.to_status}
+                              {event.reason ? ` · ${event.reason}` : ''}
+                            </span>
+                          ))}
+                        </div>
+                      )}
                     </div>
                   )}
                 </div>
@@ -1,9 +1,9 @@
-import { useEffect, useState, useCallback, useMemo } from 'react';
+import { Fragment, useEffect, useState, useCallback, useMemo } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { useTranslation } from 'react-i18next';
 import {
   Plus, Trash2, RefreshCw, Copy, Eye, EyeOff, Key,
-  Search, ToggleLeft, ToggleRight, CheckCircle, BarChart3,
+  Search, ToggleLeft, ToggleRight, CheckCircle, BarChart3, RotateCw, Settings2,
 } from 'lucide-react';
 import { useToast } from '../hooks/useToast';
 import { ToastContainer } from '../components/Toast';
@@ -18,6 +18,38 @@ interface GatewayApiKey {
   created_at: number;
   last_used_at: number | null;
   usage_count: number;
+  rpm_limit: number | null;
+  daily_token_limit: number | null;
+  monthly_budget_usd: number | null;
+  expires_at: number | null;
+  tokens_last_24h: number;
+  cost_this_month_usd: number;
+  group_name: string | null;
+  allowed_endpoints: string | null;
+  last_used_ip: string | null;
+  previous_key_expires_at: number | null;
+}
+
+interface ApiKeyAuditEntry {
+  id: number;
+  key_id: string;
+  key_name: string;
+  key_prefix: string;
+  timestamp: number;
+  action: string;
+  detail: string | null;
+}
+
+const ROTATION_OVERLAP_OPTIONS = [0, 3600, 86400, 7 * 86400];
+
+function splitList(value: string): string[] | null {
+  const items = value.split(',').map(m => m.trim()).filter(Boolean);
+  return items.length > 0 ? items : null;
+}
+
+function parseOptionalNumber(value: string): number | null {
+  const n = Number(value.trim());
+  return value.trim() && Number.isFinite(n) && n > 0 ? n : null;
 }
 
 export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
@@ -28,9 +60,21 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
   const [showCreateForm, setShowCreateForm] = useState(false);
   const [newKeyName, setNewKeyName] = useState('');
   const [newKeyModels, setNewKeyModels] = useState('');
+  const [newKeyRpm, setNewKeyRpm] = useState('');
+  const [newKeyDailyTokens, setNewKeyDailyTokens] = useState('');
+  const [newKeyBudget, setNewKeyBudget] = useState('');
+  const [newKeyExpires, setNewKeyExpires] = useState('');
+  const [newKeyGroup, setNewKeyGroup] = useState('');
+  const [groupNames, setGroupNames] = useState<string[]>([]);
   const [createdKey, setCreatedKey] = useState<string | null>(null);
   const [showKey, setShowKey] = useState(false);
   const [searchQuery, setSearchQuery] = useState('');
+  const [newKeyEndpoints, setNewKeyEndpoints] = useState('');
+  const [expandedKeyId, setExpandedKeyId] = useState<string | null>(null);
+  const [scopeModels, setScopeModels] = useState('');
+  const [scopeEndpoints, setScopeEndpoints] = useState('');
+  const [rotationOverlap, setRotationOverlap] = useState(86400);
+  const [auditEntries, setAuditEntries] = useState<ApiKeyAuditEntry[]>([]);
 
   const fetchKeys = useCallback(async () => {
     try {
@@ -45,8 +89,28 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
 
   useEffect(() => {
     fetchKeys();
+    invoke<{ account_groups?: { name: string }[] }>('get_gateway_config')
+      .then(c => setGroupNames((c.account_groups ?? []).map(g => g.name).filter(Boolean)))
+      .catch(() => setGroupNames([]));
   }, [fetchKeys]);
 
+  const fetchAudit = useCallback((keyId: string) => {
+    invoke<ApiKeyAuditEntry[]>('list_api_key_audit', { keyId, limit: 20 })
+      .then(setAuditEntries)
+      .catch(error => console.error('Failed to list api key audit:', error));
+  }, []);
+
+  useEffect(() => {
+    setAuditEntries([]);
+    if (!expandedKeyId) return;
+    const key = apiKeys.find(k => k.id === expandedKeyId);
+    setScopeModels(parseModels(key?.allowed_models ?? null).join(', '));
+    setScopeEndpoints(parseModels(key?.allowed_endpoints ?? null).join(', '));
+    fetchAudit(expandedKeyId);
+    // 仅在展开的 Key 变化时重置编辑内容
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [expandedKeyId, fetchAudit]);
+
   const filteredKeys = useMemo(() => {
     if (!searchQuery.trim()) return apiKeys;
     const q = searchQuery.toLowerCase();
@@ -65,16 +129,35 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
   const handleCreateKey = async () => {
     if (!newKeyName.trim()) return;
     try {
-      const allowedModels = newKeyModels.trim()
-        ? newKeyModels.split(',').map(m => m.trim()).filter(Boolean)
+      const allowedModels = splitList(newKeyModels);
+
+      const rpmLimit = parseOptionalNumber(newKeyRpm);
+      const dailyTokenLimit = parseOptionalNumber(newKeyDailyTokens);
+      const expiresAt = newKeyExpires
+        ? Math.floor(new Date(`${newKeyExpires}T23:59:59`).getTime() / 1000)
         : null;
 
       const [rawKey] = await invoke<[string, GatewayApiKey]>('create_api_key', {
-        payload: { name: newKeyName.trim(), allowed_models: allowedModels },
+        payload: {
+          name: newKeyName.trim(),
+          allowed_models: allowedModels,
+          rpm_limit: rpmLimit !== null ? Math.floor(rpmLimit) : null,
+          daily_token_limit: dailyTokenLimit !== null ? Math.floor(dailyTokenLimit) : null,
+          monthly_budget_usd: parseOptionalNumber(newKeyBudget),
+          expires_at: expiresAt,
+          group_name: newKeyGroup || null,
+          allowed_endpoints: splitList(newKeyEndpoints),
+        },
       });
       setCreatedKey(rawKey);
       setNewKeyName('');
       setNewKeyModels('');
+      setNewKeyRpm('');
+      setNewKeyDailyTokens('');
+      setNewKeyBudget('');
+      setNewKeyExpires('');
+      setNewKeyGroup('');
+      setNewKeyEndpoints('');
       setShowCreateForm(false);
       await fetchKeys();
       toast.success(t('gateway.keyCreatedMsg', 'API Key 已创建'));
@@ -103,6 +186,52 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
     }
   };
 
+  const handleChangeKeyGroup = async (id: string, groupName: string) => {
+    try {
+      await invoke('update_api_key_group', { id, groupName: groupName || null });
+      await fetchKeys();
+    } catch (error) {
+      toast.error(String(error));
+    }
+  };
+
+  const handleSaveScopes = async (id: string) => {
+    try {
+      await invoke('update_api_key_scopes', {
+        id,
+        allowedModels: splitList(scopeModels),
+        allowedEndpoints: splitList(scopeEndpoints),
+      });
+      await fetchKeys();
+      fetchAudit(id);
+      toast.success(t('gateway.scopesSaved', '权限已更新'));
+    } catch (error) {
+      toast.error(String(error));
+    }
+  };
+
+  const handleRotateKey = async (id: string) => {
+    try {
+      const [rawKey] = await invoke<[string, GatewayApiKey]>('rotate_api_key', {
+        id,
+        overlapSeconds: rotationOverlap,
+      });
+      setCreatedKey(rawKey);
+      setShowKey(false);
+      await fetchKeys();
+      fetchAudit(id);
+      toast.success(t('gateway.keyRotated', 'Key 已轮换'));
+    } catch (error) {
+      toast.error(String(error));
+    }
+  };
+
+  const overlapLabel = (seconds: number) => {
+    if (seconds === 0) return t('gateway.overlapNone', '旧 Key 立即失效');
+    if (seconds < 86400) return t('gateway.overlapHours', '旧 Key 保留 {{count}} 小时', { count: seconds / 3600 });
+    return t('gateway.overlapDays', '旧 Key 保留 {{count}} 天', { count: seconds / 86400 });
+  };
+
   const copyToClipboard = (text: string) => {
     navigator.clipboard.writeText(text);
     toast.success(t('gateway.copied', '已复制'));
@@ -211,7 +340,60 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
               value={newKeyModels}
               onChange={e => setNewKeyModels(e.target.value)}
             />
+            <input
+              type="text"
+              className="input input-bordered input-sm"
+              placeholder={t('gateway.allowedEndpoints', '允许的端点（逗号分隔，如 /v1/responses、/v1/models，留空为全部）')}
+              value={newKeyEndpoints}
+              onChange={e => setNewKeyEndpoints(e.target.value)}
+            />
           </div>
+          <div className="gw-form-row">
+            <input
+              type="number"
+              className="input input-bordered input-sm"
+              placeholder={t('gateway.rpmLimit', '每分钟请求上限（留空不限）')}
+              value={newKeyRpm}
+              min={0}
+              onChange={e => setNewKeyRpm(e.target.value)}
+            />
+            <input
+              type="number"
+              className="input input-bordered input-sm"
+              placeholder={t('gateway.dailyTokenLimit', '24 小时 token 上限（留空不限）')}
+              value={newKeyDailyTokens}
+              min={0}
+              onChange={e => setNewKeyDailyTokens(e.target.value)}
+            />
+            <input
+              type="number"
+              className="input input-bordered input-sm"
+              placeholder={t('gateway.monthlyBudget', '每月预算 USD（留空不限）')}
+              value={newKeyBudget}
+              min={0}
+              step="0.01"
+              onChange={e => setNewKeyBudget(e.target.value)}
+            />
+            <input
+              type="date"
+              className="input input-bordered input-sm"
+              title={t('gateway.expiresAt', '过期日期（留空永不过期）')}
+              value={newKeyExpires}
+              onChange={e => setNewKeyExpires(e.target.value)}
+            />
+          </div>
+          {groupNames.length > 0 && (
+            <div className="gw-form-row">
+              <select
+                className="select select-bordered select-sm"
+                value={newKeyGroup}
+                onChange={e => setNewKeyGroup(e.target.value)}
+              >
+                <option value="">{t('gateway.noGroup', '不绑定分组（可用 X-Gateway-Group 请求头指定）')}</option>
+                {groupNames.map(g => <option key={g} value={g}>{g}</option>)}
+              </select>
+            </div>
+          )}
           <div className="gw-form-actions">
             <button className="btn btn-sm btn-ghost" onClick={() => setShowCreateForm(false)}>{t('common.cancel', '取消')}</button>
             <button className="btn btn-sm btn-primary" onClick={handleCreateKey}>{t('common.create', '创建')}</button>
@@ -230,47 +412,156 @@ export function GatewayApiKeysPage({ embedded }: { embedded?: boolean } = {}) {
         <div className="gw-list">
           {filteredKeys.map((key, i) => {
             const models = parseModels(key.allowed_models);
+            const endpoints = parseModels(key.allowed_endpoints);
+            const expanded = expandedKeyId === key.id;
             return (
-              <div
-                key={key.id}
-                className={`gw-key-card ${key.enabled ? '' : 'is-disabled'}`}
-                style={{ animationDelay: `${i * 40}ms` }}
-              >
-                <div className="gw-key-icon"><Key size={18} /></div>
-                <div className="gw-key-info">
-                  <div className="gw-key-name">{key.name}</div>
-                  <div className="gw-key-meta">
-                    <span className="gw-key-prefix">{key.key_prefix}•••</span>
-                    <span className={`gw-badge ${key.enabled ? 'gw-badge--enabled' : 'gw-badge--disabled'}`}>
-                      {key.enabled ? t('gateway.enabled', '启用') : t('gateway.disabled', '禁用')}
-                    </span>
-                    <span className="gw-key-usage">
-                      <BarChart3 size={10} />
-                      {key.usage_count.toLocaleString()} {t('gateway.calls', '次调用')}
-                    </span>
+              <Fragment key={key.id}>
+                <div
+                  className={`gw-key-card ${key.enabled ? '' : 'is-disabled'}`}
+                  style={{ animationDelay: `${i * 40}ms` }}
+                >
+                  <div className="gw-key-icon"><Key size={18} /></div>
+                  <div className="gw-key-info">
+                    <div className="gw-key-name">{key.name}</div>
+                    <div className="gw-key-meta">
+                      <span className="gw-key-prefix">{key.key_prefix}•••</span>
+                      <span className={`gw-badge ${key.enabled ? 'gw-badge--enabled' : 'gw-badge--disabled'}`}>
+                        {key.enabled ? t('gateway.enabled', '启用') : t('gateway.disabled', '禁用')}
+                      </span>
+                      <span className="gw-key-usage">
+                        <BarChart3 size={10} />
+                        {key.usage_count.toLocaleString()} {t('gateway.calls', '次调用')}
+                      </span>
+                      {key.rpm_limit != null && (
+                        <span className="gw-key-usage">{key.rpm_limit} RPM</span>
+                      )}
+                      {key.daily_token_limit != null && (
+                        <span className="gw-key-usage">
+                          {t('gateway.tokensLast24h', '近 24 小时 token')} {key.tokens_last_24h.toLocaleString()} / {key.daily_token_limit.toLocaleString()}
+                        </span>
+                      )}
+                      {key.monthly_budget_usd != null && (
+                        <span className="gw-key-usage">
+                          {t('gateway.costThisMonth', '本月费用')} ${key.cost_this_month_usd.toFixed(2)} / ${key.monthly_budget_usd.toFixed(2)}
+                        </span>
+                      )}
+                      {key.expires_at != null && (
+                        <span className={`gw-badge ${key.expires_at * 1000 < Date.now() ? 'gw-badge--disabled' : ''}`}>
+                          {key.expires_at * 1000 < Date.now()
+                            ? t('gateway.keyExpired', '已过期')
+                            : `${t('gateway.expiresOn', '有效期至')} ${new Date(key.expires_at * 1000).toLocaleDateString()}`}
+                        </span>
+                      )}
+                      {key.previous_key_expires_at != null && key.previous_key_expires_at * 1000 > Date.now() && (
+                        <span className="gw-badge">
+                          {t('gateway.previousKeyValidUntil', '旧 Key 有效至')} {new Date(key.previous_key_expires_at * 1000).toLocaleString()}
+                        </span>
+                      )}
+                      {key.last_used_ip && (
+                        <span className="gw-key-usage">{key.last_used_ip}</span>
+                      )}
+                    </div>
+                    {models.length > 0 && (
+                      <div className="gw-key-models" style={{ marginTop: 6 }}>
+                        {models.map(m => (
+                          <span key={m} className="gw-key-model-tag">{m}</span>
+                        ))}
+                      </div>
+                    )}
+                    {!models.length && (
+                      <div style={{ fontSize: '0.65rem', color: 'var(--text-muted)', marginTop: 4 }}>
+                        {t('gateway.allModels', '全部模型')}
+                      </div>
+                    )}
+                    {endpoints.length > 0 && (
+                      <div className="gw-key-models" style={{ marginTop: 6 }}>
+                        {endpoints.map(e => (
+                          <span key={e} className="gw-key-model-tag">{e}</span>
+                        ))}
+                      </div>
+                    )}
+                  </div>
+                  <div className="gw-key-actions">
+                    {(groupNames.length > 0 || key.group_name) && (
+                      <select
+                        className="select select-bordered select-xs"
+                        value={key.group_name ?? ''}
+                        title={t('gateway.keyGroup', '账号分组')}
+                        onChange={e => handleChangeKeyGroup(key.id, e.target.value)}
+                      >
+                        <option value="">{t('gateway.anyGroup', '全部账号')}</option>
+                        {groupNames.map(g => <option key={g} value={g}>{g}</option>)}
+                        {key.group_name && !groupNames.includes(key.group_name) && (
+                          <option value={key.group_name}>{key.group_name}</option>
+                        )}
+                      </select>
+                    )}
+                    <button className="btn btn-ghost btn-xs" onClick={() => handleToggleKey(key.id, key.enabled)} title={key.enabled ? t('gateway.disable', '禁用') : t('gateway.enable', '启用')}>
+                      {key.enabled ? <ToggleRight size={16} style={{ color: 'var(--success)' }} /> : <ToggleLeft size={16} />}
+                    </button>
+                    <button
+                      className="btn btn-ghost btn-xs"
+                      onClick={() => setExpandedKeyId(expanded ? null : key.id)}
+                      title={t('gateway.keySettings', '权限、轮换与审计')}
+                    >
+                      <Settings2 size={12} />
+                    </button>
+                    <button className="btn btn-ghost btn-xs" style={{ color: 'var(--danger)' }} onClick={() => handleDeleteKey(key.id)}>
+                      <Trash2 size={12} />
+                    </button>
                   </div>
-                  {models.length > 0 && (
-                    <div className="gw-key-models" style={{ marginTop: 6 }}>
-                      {models.map(m => (
-                        <span key={m} className="gw-key-model-tag">{m}</span>
-                      ))}
+                </div>
+                {expanded && (
+                  <div className="gw-form-panel">
+                    <div className="gw-form-row">
+                      <input
+                        type="text"
+                        className="input input-bordered input-sm"
+                        placeholder={t('gateway.allowedModels', '允许的模型（逗号分隔，留空为全部）')}
+                        value={scopeModels}
+                        onChange={e => setScopeModels(e.target.value)}
+                      />
+                      <input
+                        type="text"
+                        className="input input-bordered input-sm"
+                        placeholder={t('gateway.allowedEndpoints', '允许的端点（逗号分隔，如 /v1/responses、/v1/models，留空为全部）')}
+                        value={scopeEndpoints}
+                        onChange={e => setScopeEndpoints(e.target.value)}
+                      />
+                      <button className="btn btn-sm btn-primary" onClick={() => handleSaveScopes(key.id)}>
+                        {t('common.save', '保存')}
+                      </button>
                     </div>
-                  )}
-                  {!models.length && (
-                    <div style={{ fontSize: '0.65rem', color: 'var(--text-muted)', marginTop: 4 }}>
-                      {t('gateway.allModels', '全部模型')}
+                    <div className="gw-form-row">
+                      <select
+                        className="select select-bordered select-sm"
+                        value={rotationOverlap}
+                        onChange={e => setRotationOverlap(Number(e.target.value))}
+                      >
+                        {ROTATION_OVERLAP_OPTIONS.map(seconds => (
+                          <option key={seconds} value={seconds}>{overlapLabel(seconds)}</option>
+                        ))}
+                      </select>
+                      <button className="btn btn-sm btn-ghost" onClick={() => handleRotateKey(key.id)}>
+                        <RotateCw size={12} /> {t('gateway.rotateKey', '轮换 Key')}
+                      </button>
                     </div>
-                  )}
-                </div>
-                <div className="gw-key-actions">
-                  <button className="btn btn-ghost btn-xs" onClick={() => handleToggleKey(key.id, key.enabled)} title={key.enabled ? t('gateway.disable', '禁用') : t('gateway.enable', '启用')}>
-                    {key.enabled ? <ToggleRight size={16} style={{ color: 'var(--success)' }} /> : <ToggleLeft size={16} />}
-                  </button>
-                  <button className="btn btn-ghost btn-xs" style={{ color: 'var(--danger)' }} onClick={() => handleDeleteKey(key.id)}>
-                    <Trash2 size={12} />
-                  </button>
-                </div>
-              </div>
+                    {auditEntries.length > 0 && (
+                      <div className="gw-log-detail">
+                        <div className="gw-log-detail-item" style={{ gridColumn: '1 / -1' }}>
+                          <span className="gw-log-detail-label">{t('gateway.keyAudit', '操作记录')}</span>
+                          {auditEntries.map(entry => (
+                            <span key={entry.id} className="gw-log-detail-value">
+                              {new Date(entry.timestamp * 1000).toLocaleString()} · {entry.action}
+                              {entry.detail ? ` · ${entry.detail}` : ''}
+                            </span>
+                          ))}
+                        </div>
+                      </div>
+                    )}
+                  </div>
+                )}
+              </Fragment>
             );
           })}
         </div>
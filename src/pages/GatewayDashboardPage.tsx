@@ -3,7 +3,7 @@ import { invoke } from '@tauri-apps/api/core';
 import { useTranslation } from 'react-i18next';
 import {
   Power, PowerOff, RefreshCw, Activity, Users, Key, FileText,
-  Shield, Clock, Copy, CheckCircle, Settings2, Wifi,
+  Shield, Clock, Copy, CheckCircle, Settings2, Wifi, Plus, Trash2,
 } from 'lucide-react';
 import { useToast } from '../hooks/useToast';
 import { ToastContainer } from '../components/Toast';
@@ -16,6 +16,9 @@ interface GatewayStatus {
   total_api_keys: number;
   total_requests: number;
   uptime_seconds: number | null;
+  in_flight_requests?: number;
+  account_in_flight?: Record<string, number>;
+  draining?: boolean;
 }
 
 interface GatewayConfig {
@@ -28,6 +31,58 @@ interface GatewayConfig {
   cors_enabled: boolean;
   max_concurrent_per_account: number;
   cooldown_seconds: number;
+  quota_threshold_percent?: number;
+  queue_timeout_seconds?: number;
+  responses_only_upstreams?: string[];
+  models_cache_seconds?: number;
+  drain_timeout_seconds?: number;
+  health_check_interval_seconds?: number;
+  capture_enabled?: boolean;
+  capture_max_entries?: number;
+  capture_max_body_kb?: number;
+  capture_retention_hours?: number;
+  model_mappings?: ModelMapping[];
+  platform_upstreams?: Record<string, string> | null;
+  account_groups?: AccountGroup[];
+  retry_policy?: RetryPolicy;
+}
+
+interface RetryPolicy {
+  max_attempts: number;
+  retryable_status_codes: number[];
+  backoff_base_ms: number;
+  backoff_max_ms: number;
+}
+
+const DEFAULT_RETRY_POLICY: RetryPolicy = {
+  max_attempts: 3,
+  retryable_status_codes: [429, 500, 502, 503, 504],
+  backoff_base_ms: 500,
+  backoff_max_ms: 5000,
+};
+
+interface AccountGroup {
+  name: string;
+  route_strategy: string | null;
+  members: { account_id: string; weight: number }[];
+}
+
+interface GatewayAccountOption {
+  id: string;
+  email: string;
+  platform: string | null;
+}
+
+const UPSTREAM_PLATFORMS = [
+  'codex', 'antigravity', 'github_copilot', 'gemini', 'windsurf', 'kiro',
+  'cursor', 'codebuddy', 'codebuddy_cn', 'workbuddy', 'qoder', 'trae',
+];
+
+interface ModelMapping {
+  pattern: string;
+  target_model: string | null;
+  target_platform: string | null;
+  enabled: boolean;
 }
 
 interface RequestLogSummary {
@@ -44,6 +99,8 @@ const ROUTE_STRATEGIES = [
   { value: 'least_used', labelKey: 'gateway.leastUsed', fallback: '最少使用' },
   { value: 'random', labelKey: 'gateway.random', fallback: '随机' },
   { value: 'priority', labelKey: 'gateway.priority', fallback: '优先级' },
+  { value: 'quota_aware', labelKey: 'gateway.quotaAware', fallback: '按剩余配额' },
+  { value: 'weighted', labelKey: 'gateway.weighted', fallback: '按权重' },
 ];
 
 function formatUptime(seconds: number | null | undefined): string {
@@ -68,6 +125,7 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
   const [actionLoading, setActionLoading] = useState(false);
   const [showConfig, setShowConfig] = useState(false);
   const [copiedEndpoint, setCopiedEndpoint] = useState<string | null>(null);
+  const [accounts, setAccounts] = useState<GatewayAccountOption[]>([]);
 
   const refreshStatus = useCallback(async () => {
     try {
@@ -91,6 +149,13 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
     return () => clearInterval(interval);
   }, [refreshStatus]);
 
+  useEffect(() => {
+    if (!showConfig) return;
+    invoke<GatewayAccountOption[]>('list_gateway_accounts')
+      .then(setAccounts)
+      .catch(error => console.error('Failed to list gateway accounts:', error));
+  }, [showConfig]);
+
   const handleToggleGateway = async () => {
     setActionLoading(true);
     try {
@@ -121,6 +186,26 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
     }
   };
 
+  const updateRetryPolicy = (updates: Partial<RetryPolicy>) => {
+    handleSaveConfig({ retry_policy: { ...(config?.retry_policy ?? DEFAULT_RETRY_POLICY), ...updates } });
+  };
+
+  const updateModelMapping = (index: number, updates: Partial<ModelMapping>) => {
+    const mappings = [...(config?.model_mappings ?? [])];
+    mappings[index] = { ...mappings[index], ...updates };
+    handleSaveConfig({ model_mappings: mappings });
+  };
+
+  const savePlatformUpstreams = (entries: [string, string][]) => {
+    handleSaveConfig({ platform_upstreams: entries.length > 0 ? Object.fromEntries(entries) : null });
+  };
+
+  const updateAccountGroup = (index: number, updates: Partial<AccountGroup>) => {
+    const groups = [...(config?.account_groups ?? [])];
+    groups[index] = { ...groups[index], ...updates };
+    handleSaveConfig({ account_groups: groups });
+  };
+
   const copyEndpoint = (endpoint: string) => {
     const url = `http://localhost:${config?.port ?? 48760}${endpoint}`;
     navigator.clipboard.writeText(url);
@@ -235,7 +320,11 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
           <div className="gw-stat-content">
             <div className="gw-stat-value">
               <span className={`gw-health-dot gw-health-dot--${healthDot}`} />
-              {status?.running ? t('gateway.running', '运行中') : t('gateway.stopped', '已停止')}
+              {status?.running
+                ? t('gateway.running', '运行中')
+                : status?.draining
+                  ? t('gateway.draining', '正在停止')
+                  : t('gateway.stopped', '已停止')}
             </div>
             <div className="gw-stat-label">{t('gateway.status', '服务状态')}</div>
             {status?.running && <div className="gw-stat-sub">:{status.port}</div>}
@@ -249,6 +338,11 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
           <div className="gw-stat-content">
             <div className="gw-stat-value">{status?.active_accounts ?? 0} / {status?.total_accounts ?? 0}</div>
             <div className="gw-stat-label">{t('gateway.accounts', '活跃 / 总账号')}</div>
+            {(status?.running || status?.draining) && (
+              <div className="gw-stat-sub">
+                {t('gateway.inFlight', '进行中')}: {status.in_flight_requests ?? 0}
+              </div>
+            )}
           </div>
         </div>
 
@@ -338,11 +432,14 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
               <input
                 type="number"
                 className="input input-bordered input-sm"
-                value={config.port}
-                onChange={(e) => handleSaveConfig({ port: parseInt(e.target.value) || 48760 })}
-                disabled={status?.running}
+                key={config.port}
+                defaultValue={config.port}
+                onBlur={(e) => {
+                  const port = parseInt(e.target.value) || 48760;
+                  if (port !== config.port) handleSaveConfig({ port });
+                }}
               />
-              <span className="gw-config-hint">{t('gateway.portHint', '运行中无法修改端口')}</span>
+              <span className="gw-config-hint">{t('gateway.portHint', '运行中修改端口会平滑切换，进行中的请求不受影响')}</span>
             </div>
             <div className="gw-config-field">
               <label className="gw-config-label">{t('gateway.upstreamUrl', '上游地址')}</label>
@@ -352,6 +449,20 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
                 value={config.upstream_base_url}
                 onChange={(e) => handleSaveConfig({ upstream_base_url: e.target.value })}
               />
+              <label className="gw-config-hint" style={{ display: 'flex', alignItems: 'center', gap: 6 }}>
+                <input
+                  type="checkbox"
+                  className="checkbox checkbox-xs"
+                  checked={(config.responses_only_upstreams ?? []).includes(config.upstream_base_url)}
+                  onChange={(e) => {
+                    const others = (config.responses_only_upstreams ?? []).filter(u => u !== config.upstream_base_url);
+                    handleSaveConfig({
+                      responses_only_upstreams: e.target.checked ? [...others, config.upstream_base_url] : others,
+                    });
+                  }}
+                />
+                {t('gateway.responsesOnlyUpstream', '上游仅支持 Responses API（自动转换 Chat Completions 请求）')}
+              </label>
             </div>
             <div className="gw-config-field">
               <label className="gw-config-label">{t('gateway.proxyUrl', '代理地址')}</label>
@@ -396,6 +507,102 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
                 onChange={(e) => handleSaveConfig({ cooldown_seconds: parseInt(e.target.value) || 0 })}
               />
             </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.queueTimeout', '并发排队超时 (秒)')}</label>
+              <input
+                type="number"
+                className="input input-bordered input-sm"
+                value={config.queue_timeout_seconds ?? 30}
+                min={0}
+                onChange={(e) => handleSaveConfig({ queue_timeout_seconds: parseInt(e.target.value) || 0 })}
+              />
+            </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.retryMaxAttempts', '最大尝试次数')}</label>
+              <input
+                type="number"
+                className="input input-bordered input-sm"
+                value={(config.retry_policy ?? DEFAULT_RETRY_POLICY).max_attempts}
+                min={1}
+                max={10}
+                onChange={(e) => updateRetryPolicy({ max_attempts: parseInt(e.target.value) || 1 })}
+              />
+              <span className="gw-config-hint">{t('gateway.retryMaxAttemptsHint', '失败后换用其他账号重试，1 为不重试')}</span>
+            </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.retryStatusCodes', '触发重试的状态码')}</label>
+              <input
+                type="text"
+                className="input input-bordered input-sm"
+                key={(config.retry_policy ?? DEFAULT_RETRY_POLICY).retryable_status_codes.join(',')}
+                defaultValue={(config.retry_policy ?? DEFAULT_RETRY_POLICY).retryable_status_codes.join(', ')}
+                placeholder="429, 500, 502, 503, 504"
+                onBlur={(e) => {
+                  const codes = e.target.value
+                    .split(/[\s,]+/)
+                    .map(code => parseInt(code))
+                    .filter(code => code >= 100 && code <= 599);
+                  updateRetryPolicy({ retryable_status_codes: Array.from(new Set(codes)) });
+                }}
+              />
+            </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.retryBackoff', '重试退避 (毫秒)')}</label>
+              <div style={{ display: 'flex', gap: 6 }}>
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={(config.retry_policy ?? DEFAULT_RETRY_POLICY).backoff_base_ms}
+                  min={0}
+                  title={t('gateway.retryBackoffBase', '初始等待')}
+                  onChange={(e) => updateRetryPolicy({ backoff_base_ms: parseInt(e.target.value) || 0 })}
+                />
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={(config.retry_policy ?? DEFAULT_RETRY_POLICY).backoff_max_ms}
+                  min={0}
+                  title={t('gateway.retryBackoffMax', '最长等待')}
+                  onChange={(e) => updateRetryPolicy({ backoff_max_ms: parseInt(e.target.value) || 0 })}
+                />
+              </div>
+              <span className="gw-config-hint">{t('gateway.retryBackoffHint', '每次重试前等待时间翻倍，不超过最长等待')}</span>
+            </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.drainTimeout', '停止等待超时 (秒)')}</label>
+              <input
+                type="number"
+                className="input input-bordered input-sm"
+                value={config.drain_timeout_seconds ?? 30}
+                min={0}
+                onChange={(e) => handleSaveConfig({ drain_timeout_seconds: parseInt(e.target.value) || 0 })}
+              />
+              <span className="gw-config-hint">{t('gateway.drainTimeoutHint', '停止网关时等待进行中的请求完成，超时后强制断开')}</span>
+            </div>
+            <div className="gw-config-field">
+              <label className="gw-config-label">{t('gateway.healthCheckInterval', '健康检查间隔 (秒)')}</label>
+              <input
+                type="number"
+                className="input input-bordered input-sm"
+                value={config.health_check_interval_seconds ?? 60}
+                min={0}
+                onChange={(e) => handleSaveConfig({ health_check_interval_seconds: parseInt(e.target.value) || 0 })}
+              />
+              <span className="gw-config-hint">{t('gateway.healthCheckHint', '定期解除到期冷却、刷新即将过期的 Token 并探测异常账号，0 为关闭')}</span>
+            </div>
+            {config.route_strategy === 'quota_aware' && (
+              <div className="gw-config-field">
+                <label className="gw-config-label">{t('gateway.quotaThreshold', '最低剩余配额 (%)')}</label>
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={config.quota_threshold_percent ?? 5}
+                  min={0}
+                  max={100}
+                  onChange={(e) => handleSaveConfig({ quota_threshold_percent: parseInt(e.target.value) || 0 })}
+                />
+              </div>
+            )}
           </div>
           <div style={{ display: 'flex', gap: 16, marginTop: 14 }}>
             <div className="gw-toggle-row" style={{ flex: 1 }}>
@@ -416,6 +623,268 @@ export function GatewayDashboardPage({ embedded }: { embedded?: boolean } = {})
                 onClick={() => handleSaveConfig({ cors_enabled: !config.cors_enabled })}
               />
             </div>
+            <div className="gw-toggle-row" style={{ flex: 1 }}>
+              <span className="gw-toggle-row-label">{t('gateway.capture', '请求捕获')}</span>
+              <span
+                className={`gw-toggle ${config.capture_enabled ? 'is-on' : ''}`}
+                role="switch"
+                aria-checked={!!config.capture_enabled}
+                onClick={() => handleSaveConfig({ capture_enabled: !config.capture_enabled })}
+              />
+            </div>
+          </div>
+          {config.capture_enabled && (
+            <div className="gw-config-grid" style={{ marginTop: 14 }}>
+              <div className="gw-config-field">
+                <label className="gw-config-label">{t('gateway.captureMaxEntries', '最多保留捕获条数')}</label>
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={config.capture_max_entries ?? 200}
+                  min={1}
+                  onChange={(e) => handleSaveConfig({ capture_max_entries: parseInt(e.target.value) || 1 })}
+                />
+              </div>
+              <div className="gw-config-field">
+                <label className="gw-config-label">{t('gateway.captureMaxBodyKb', '单个请求体上限 (KB)')}</label>
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={config.capture_max_body_kb ?? 1024}
+                  min={1}
+                  onChange={(e) => handleSaveConfig({ capture_max_body_kb: parseInt(e.target.value) || 1 })}
+                />
+                <span className="gw-config-hint">{t('gateway.captureHint', '捕获内容保存在本地，鉴权头已脱敏；超出上限的请求会被截断且无法重放')}</span>
+              </div>
+              <div className="gw-config-field">
+                <label className="gw-config-label">{t('gateway.captureRetentionHours', '捕获保留时长 (小时)')}</label>
+                <input
+                  type="number"
+                  className="input input-bordered input-sm"
+                  value={config.capture_retention_hours ?? 24}
+                  min={0}
+                  onChange={(e) => handleSaveConfig({ capture_retention_hours: Math.max(0, parseInt(e.target.value) || 0) })}
+                />
+                <span className="gw-config-hint">{t('gateway.captureRetentionHint', '超过时长的捕获会被删除，0 表示只按条数清理')}</span>
+              </div>
+            </div>
+          )}
+          <div className="gw-config-field" style={{ marginTop: 14 }}>
+            <label className="gw-config-label">{t('gateway.platformUpstreams', '平台上游')}</label>
+            {Object.entries(config.platform_upstreams ?? {}).map(([platform, url], index, entries) => (
+              <div key={platform} style={{ display: 'flex', gap: 8, alignItems: 'center', marginBottom: 6 }}>
+                <select
+                  className="select select-bordered select-sm"
+                  style={{ width: 160 }}
+                  value={platform}
+                  onChange={(e) => savePlatformUpstreams(
+                    entries.map(([p, u], i): [string, string] => (i === index ? [e.target.value, u] : [p, u])),
+                  )}
+                >
+                  {UPSTREAM_PLATFORMS.filter(p => p === platform || !(p in (config.platform_upstreams ?? {}))).map(p => (
+                    <option key={p} value={p}>{p}</option>
+                  ))}
+                </select>
+                <input
+                  type="text"
+                  className="input input-bordered input-sm"
+                  style={{ flex: 1 }}
+                  value={url}
+                  placeholder="https://api.openai.com"
+                  onChange={(e) => savePlatformUpstreams(
+                    entries.map(([p, u], i): [string, string] => (i === index ? [p, e.target.value] : [p, u])),
+                  )}
+                />
+                <button
+                  className="btn btn-ghost btn-sm"
+                  title={t('common.delete', '删除')}
+                  onClick={() => savePlatformUpstreams(entries.filter((_, i) => i !== index))}
+                >
+                  <Trash2 size={14} />
+                </button>
+              </div>
+            ))}
+            <div>
+              <button
+                className="btn btn-ghost btn-sm"
+                disabled={UPSTREAM_PLATFORMS.every(p => p in (config.platform_upstreams ?? {}))}
+                onClick={() => {
+                  const entries = Object.entries(config.platform_upstreams ?? {});
+                  const next = UPSTREAM_PLATFORMS.find(p => !(p in (config.platform_upstreams ?? {})));
+                  if (next) savePlatformUpstreams([...entries, [next, config.upstream_base_url]]);
+                }}
+              >
+                <Plus size={14} />
+                {t('gateway.addPlatformUpstream', '添加平台上游')}
+              </button>
+            </div>
+            <span className="gw-config-hint">
+              {t('gateway.platformUpstreamsHint', '按账号所属平台选择上游，未配置的平台使用上方的上游地址；账号单独配置的代理优先于全局代理')}
+            </span>
+          </div>
+          <div className="gw-config-field" style={{ marginTop: 14 }}>
+            <label className="gw-config-label">{t('gateway.accountGroups', '账号分组')}</label>
+            {(config.account_groups ?? []).map((group, index) => (
+              <div key={index} style={{ marginBottom: 10 }}>
+                <div style={{ display: 'flex', gap: 8, alignItems: 'center', marginBottom: 6 }}>
+                  <input
+                    type="text"
+                    className="input input-bordered input-sm"
+                    style={{ flex: 1 }}
+                    value={group.name}
+                    placeholder={t('gateway.groupName', '分组名称，如 premium')}
+                    onChange={(e) => updateAccountGroup(index, { name: e.target.value })}
+                  />
+                  <select
+                    className="select select-bordered select-sm"
+                    value={group.route_strategy ?? ''}
+                    onChange={(e) => updateAccountGroup(index, { route_strategy: e.target.value || null })}
+                  >
+                    <option value="">{t('gateway.followGlobalStrategy', '沿用全局策略')}</option>
+                    {ROUTE_STRATEGIES.map(s => (
+                      <option key={s.value} value={s.value}>{t(s.labelKey, s.fallback)}</option>
+                    ))}
+                  </select>
+                  <button
+                    className="btn btn-ghost btn-sm"
+                    title={t('common.delete', '删除')}
+                    onClick={() => handleSaveConfig({
+                      account_groups: (config.account_groups ?? []).filter((_, i) => i !== index),
+                    })}
+                  >
+                    <Trash2 size={14} />
+                  </button>
+                </div>
+                {group.members.map((member, memberIndex) => (
+                  <div key={member.account_id} style={{ display: 'flex', gap: 8, alignItems: 'center', marginBottom: 6, paddingLeft: 16 }}>
+                    <span className="gw-config-hint" style={{ width: 20 }}>{memberIndex + 1}</span>
+                    <span style={{ flex: 1, fontSize: '0.8rem' }}>
+                      {accounts.find(a => a.id === member.account_id)?.email ?? member.account_id}
+                    </span>
+                    <input
+                      type="number"
+                      className="input input-bordered input-sm"
+                      style={{ width: 90 }}
+                      value={member.weight}
+                      min={0}
+                      title={t('gateway.memberWeight', '权重')}
+                      onChange={(e) => updateAccountGroup(index, {
+                        members: group.members.map((m, i) => (
+                          i === memberIndex ? { ...m, weight: Math.max(0, parseInt(e.target.value) || 0) } : m
+                        )),
+                      })}
+                    />
+                    <button
+                      className="btn btn-ghost btn-sm"
+                      title={t('common.delete', '删除')}
+                      onClick={() => updateAccountGroup(index, {
+                        members: group.members.filter((_, i) => i !== memberIndex),
+                      })}
+                    >
+                      <Trash2 size={14} />
+                    </button>
+                  </div>
+                ))}
+                <div style={{ paddingLeft: 16 }}>
+                  <select
+                    className="select select-bordered select-sm"
+                    value=""
+                    onChange={(e) => e.target.value && updateAccountGroup(index, {
+                      members: [...group.members, { account_id: e.target.value, weight: 1 }],
+                    })}
+                  >
+                    <option value="">{t('gateway.addGroupMember', '添加账号...')}</option>
+                    {accounts
+                      .filter(a => !group.members.some(m => m.account_id === a.id))
+                      .map(a => (
+                        <option key={a.id} value={a.id}>
+                          {a.email}{a.platform ? ` (${a.platform})` : ''}
+                        </option>
+                      ))}
+                  </select>
+                </div>
+              </div>
+            ))}
+            <div>
+              <button
+                className="btn btn-ghost btn-sm"
+                onClick={() => handleSaveConfig({
+                  account_groups: [
+                    ...(config.account_groups ?? []),
+                    { name: '', route_strategy: null, members: [] },
+                  ],
+                })}
+              >
+                <Plus size={14} />
+                {t('gateway.addAccountGroup', '添加分组')}
+              </button>
+            </div>
+            <span className="gw-config-hint">
+              {t('gateway.accountGroupsHint', '分组名称与账号的分组或标签相同时自动归入；列出的账号按顺序决定优先级，权重用于按权重策略。API Key 可绑定分组，未绑定时可用请求头 X-Gateway-Group 指定')}
+            </span>
+          </div>
+          <div className="gw-config-field" style={{ marginTop: 14 }}>
+            <label className="gw-config-label">{t('gateway.modelMappings', '模型映射')}</label>
+            {(config.model_mappings ?? []).map((mapping, index) => (
+              <div key={index} style={{ display: 'flex', gap: 8, alignItems: 'center', marginBottom: 6 }}>
+                <input
+                  type="checkbox"
+                  className="checkbox checkbox-xs"
+                  checked={mapping.enabled}
+                  onChange={(e) => updateModelMapping(index, { enabled: e.target.checked })}
+                />
+                <input
+                  type="text"
+                  className="input input-bordered input-sm"
+                  style={{ flex: 1 }}
+                  value={mapping.pattern}
+                  placeholder={t('gateway.mappingPattern', '请求模型，如 claude-*')}
+                  onChange={(e) => updateModelMapping(index, { pattern: e.target.value })}
+                />
+                <input
+                  type="text"
+                  className="input input-bordered input-sm"
+                  style={{ flex: 1 }}
+                  value={mapping.target_model ?? ''}
+                  placeholder={t('gateway.mappingTargetModel', '目标模型（留空不改写）')}
+                  onChange={(e) => updateModelMapping(index, { target_model: e.target.value || null })}
+                />
+                <input
+                  type="text"
+                  className="input input-bordered input-sm"
+                  style={{ width: 140 }}
+                  value={mapping.target_platform ?? ''}
+                  placeholder={t('gateway.mappingTargetPlatform', '目标平台（可选）')}
+                  onChange={(e) => updateModelMapping(index, { target_platform: e.target.value || null })}
+                />
+                <button
+                  className="btn btn-ghost btn-sm"
+                  title={t('common.delete', '删除')}
+                  onClick={() => handleSaveConfig({
+                    model_mappings: (config.model_mappings ?? []).filter((_, i) => i !== index),
+                  })}
+                >
+                  <Trash2 size={14} />
+                </button>
+              </div>
+            ))}
+            <div>
+              <button
+                className="btn btn-ghost btn-sm"
+                onClick={() => handleSaveConfig({
+                  model_mappings: [
+                    ...(config.model_mappings ?? []),
+                    { pattern: '', target_model: null, target_platform: null, enabled: true },
+                  ],
+                })}
+              >
+                <Plus size={14} />
+                {t('gateway.addModelMapping', '添加映射')}
+              </button>
+            </div>
+            <span className="gw-config-hint">
+              {t('gateway.modelMappingsHint', '按顺序匹配，支持 * 和 ? 通配符，第一条命中的规则生效')}
+            </span>
           </div>
         </div>
       )}
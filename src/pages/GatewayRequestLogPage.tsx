@@ -1,9 +1,9 @@
-import { useEffect, useState, useCallback, useRef } from 'react';
+import { useEffect, useState, useCallback, useRef, type CSSProperties } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { useTranslation } from 'react-i18next';
 import {
   RefreshCw, Trash2, Filter, FileText, ChevronDown, ChevronRight,
-  Download, Clock, Zap,
+  Download, Clock, Zap, Play,
 } from 'lucide-react';
 import { useToast } from '../hooks/useToast';
 import { ToastContainer } from '../components/Toast';
@@ -24,6 +24,45 @@ interface RequestLogEntry {
   api_key_prefix: string | null;
 }
 
+interface RequestCapture {
+  trace_id: string;
+  account_id: string | null;
+  upstream_url: string | null;
+  request_headers: [string, string][];
+  request_body: string;
+  upstream_request_body: string | null;
+  status_code: number;
+  response_body: string;
+  truncated: boolean;
+}
+
+interface ReplayResult {
+  status_code: number;
+  upstream_url: string;
+  duration_ms: number;
+  response_body: string;
+  client_body: string;
+}
+
+const capturePreStyle: CSSProperties = {
+  maxHeight: 240,
+  overflow: 'auto',
+  whiteSpace: 'pre-wrap',
+  wordBreak: 'break-all',
+  fontSize: 11,
+  margin: 0,
+};
+
+function CaptureBlock({ label, content }: { label: string; content: string | null }) {
+  if (!content) return null;
+  return (
+    <div className="gw-log-detail-item" style={{ gridColumn: '1 / -1' }}>
+      <span className="gw-log-detail-label">{label}</span>
+      <pre className="gw-log-detail-value" style={capturePreStyle}>{content}</pre>
+    </div>
+  );
+}
+
 interface RequestLogQuery {
   limit: number | null;
   offset: number | null;
@@ -43,6 +82,12 @@ export function GatewayRequestLogPage({ embedded }: { embedded?: boolean } = {})
   const [page, setCurrentPage] = useState(0);
   const [expandedId, setExpandedId] = useState<number | null>(null);
   const [autoRefresh, setAutoRefresh] = useState(false);
+  const [capture, setCapture] = useState<RequestCapture | null>(null);
+  const [accounts, setAccounts] = useState<{ id: string; email: string }[]>([]);
+  const [replayAccountId, setReplayAccountId] = useState('');
+  const [replayUpstream, setReplayUpstream] = useState('');
+  const [replayResult, setReplayResult] = useState<ReplayResult | null>(null);
+  const [replaying, setReplaying] = useState(false);
   const autoRefreshRef = useRef<ReturnType<typeof setInterval> | null>(null);
   const pageSize = 50;
 
@@ -78,9 +123,47 @@ export function GatewayRequestLogPage({ embedded }: { embedded?: boolean } = {})
     };
   }, [autoRefresh, fetchLogs]);
 
+  const expandedTraceId = logs.find(l => l.id === expandedId)?.trace_id;
+
+  useEffect(() => {
+    setCapture(null);
+    setReplayResult(null);
+    if (!expandedTraceId) return;
+    invoke<RequestCapture | null>('get_request_capture', { traceId: expandedTraceId })
+      .then(data => {
+        setCapture(data);
+        setReplayAccountId(data?.account_id || '');
+        setReplayUpstream('');
+        if (data) {
+          invoke<{ id: string; email: string }[]>('list_gateway_accounts')
+            .then(setAccounts)
+            .catch(() => {});
+        }
+      })
+      .catch(error => console.error('Failed to load request capture:', error));
+  }, [expandedTraceId]);
+
+  const handleReplay = async () => {
+    if (!capture) return;
+    setReplaying(true);
+    try {
+      const result = await invoke<ReplayResult>('replay_request_capture', {
+        traceId: capture.trace_id,
+        accountId: replayAccountId || null,
+        upstreamBaseUrl: replayUpstream || null,
+      });
+      setReplayResult(result);
+    } catch (error) {
+      toast.error(String(error));
+    } finally {
+      setReplaying(false);
+    }
+  };
+
   const handleClearLogs = async () => {
     try {
       await invoke('clear_request_logs');
+      await invoke('clear_request_captures');
       await fetchLogs();
       toast.success(t('gateway.logsCleared', '日志已清除'));
     } catch (error) {
@@ -276,6 +359,62 @@ export function GatewayRequestLogPage({ embedded }: { embedded?: boolean } = {})
                           <span className="gw-log-detail-value" style={{ color: 'var(--danger)' }}>{log.error_message}</span>
                         </div>
                       )}
+                      {capture && capture.trace_id === log.trace_id && (
+                        <>
+                          {capture.upstream_url && (
+                            <div className="gw-log-detail-item" style={{ gridColumn: '1 / -1' }}>
+                              <span className="gw-log-detail-label">{t('gateway.upstreamUrl', '上游地址')}</span>
+                              <span className="gw-log-detail-value">{capture.upstream_url}</span>
+                            </div>
+                          )}
+                          <CaptureBlock
+                            label={t('gateway.captureRequestHeaders', '请求头')}
+                            content={capture.request_headers.map(([k, v]) => `${k}: ${v}`).join('\n')}
+                          />
+                          <CaptureBlock label={t('gateway.captureRequestBody', '请求体')} content={capture.request_body} />
+                          <CaptureBlock label={t('gateway.captureUpstreamBody', '转换后的上游请求')} content={capture.upstream_request_body} />
+                          <CaptureBlock
+                            label={`${t('gateway.captureResponseBody', '上游响应')} (${capture.status_code})${capture.truncated ? ` · ${t('gateway.captureTruncated', '已截断')}` : ''}`}
+                            content={capture.response_body}
+                          />
+                          <div className="gw-log-detail-item" style={{ gridColumn: '1 / -1' }}>
+                            <span className="gw-log-detail-label">{t('gateway.replay', '重放')}</span>
+                            <div style={{ display: 'flex', gap: 8, alignItems: 'center', flexWrap: 'wrap' }}>
+                              <select
+                                className="select select-bordered select-sm"
+                                value={replayAccountId}
+                                onChange={e => setReplayAccountId(e.target.value)}
+                              >
+                                {accounts.map(a => (
+                                  <option key={a.id} value={a.id}>{a.email}</option>
+                                ))}
+                              </select>
+                              <input
+                                className="input input-bordered input-sm"
+                                placeholder={t('gateway.replayUpstreamPlaceholder', '上游地址（留空按账号平台解析）')}
+                                value={replayUpstream}
+                                onChange={e => setReplayUpstream(e.target.value)}
+                              />
+                              <button
+                                className="btn btn-primary btn-xs"
+                                disabled={replaying || capture.truncated}
+                                onClick={handleReplay}
+                              >
+                                <Play size={12} /> {replaying ? t('gateway.replaying', '重放中...') : t('gateway.replay', '重放')}
+                              </button>
+                            </div>
+                          </div>
+                          {replayResult && (
+                            <>
+                              <CaptureBlock
+                                label={`${t('gateway.replayUpstreamResponse', '重放上游响应')} (${replayResult.status_code} · ${replayResult.duration_ms}ms)`}
+                                content={replayResult.response_body}
+                              />
+                              <CaptureBlock label={t('gateway.replayClientResponse', '转换后的客户端响应')} content={replayResult.client_body} />
+                            </>
+                          )}
+                        </>
+                      )}
                     </div>
                   )}
                 </div>
@@ -2,6 +2,7 @@ import { useState } from 'react';
 import { useTranslation } from 'react-i18next';
 import { Layers } from 'lucide-react';
 import { PlatformProviderPanel, ProviderModelType } from '../components/platform/PlatformProviderPanel';
+import { ProxyFailoverSettings } from '../components/platform/ProxyFailoverSettings';
 
 const MODEL_TYPES: { id: ProviderModelType; name: string; color: string; gradient: string }[] = [
   { id: 'claude', name: 'Claude', color: '#D97757', gradient: 'linear-gradient(135deg, #D97757, #c2603e)' },
@@ -40,6 +41,10 @@ export function ProvidersPage() {
         ))}
       </div>
 
+      {(selectedModelType === 'claude' || selectedModelType === 'codex' || selectedModelType === 'gemini') && (
+        <ProxyFailoverSettings key={`failover-${selectedModelType}`} modelType={selectedModelType} />
+      )}
+
       <div className="flex-1 min-h-0">
         <PlatformProviderPanel key={selectedModelType} modelType={selectedModelType} />
       </div>
@@ -11,6 +11,7 @@ import { usePlatformRuntimeSupport } from '../hooks/usePlatformRuntimeSupport';
 import { usePlatformLayoutStore } from '../stores/usePlatformLayoutStore';
 import { ALL_PLATFORM_IDS, PlatformId } from '../types/platform';
 import { SettingsAccountTransferSection } from '../components/SettingsAccountTransferSection';
+import { ProxyConfig, TakeoverRecovery } from '../types/proxy';
 import './settings/Settings.css';
 import { 
   Github, User, Rocket, Save, FolderOpen,
@@ -188,6 +189,7 @@ export function SettingsPage() {
   const [cursorAutoRefresh, setCursorAutoRefresh] = useState('10');
   const [geminiAutoRefresh, setGeminiAutoRefresh] = useState('10');
   const [closeBehavior, setCloseBehavior] = useState<'ask' | 'minimize' | 'quit'>('ask');
+  const [proxyConfig, setProxyConfig] = useState<ProxyConfig | null>(null);
   const [minimizeBehavior, setMinimizeBehavior] = useState<'dock_and_tray' | 'tray_only'>('dock_and_tray');
   const [hideDockIcon, setHideDockIcon] = useState(false);
   const [opencodeAppPath, setOpencodeAppPath] = useState('');
@@ -359,6 +361,7 @@ export function SettingsPage() {
   useEffect(() => {
     loadGeneralConfig();
     loadNetworkConfig();
+    loadProxyConfig();
   }, []);
   
   useEffect(() => {
@@ -842,6 +845,26 @@ export function SettingsPage() {
     }
   };
   
+  const loadProxyConfig = async () => {
+    try {
+      setProxyConfig(await invoke<ProxyConfig>('get_proxy_config'));
+    } catch (err) {
+      console.error('加载代理配置失败:', err);
+    }
+  };
+
+  // 残留接管处理方式属于代理配置，修改后立即保存
+  const handleTakeoverRecoveryChange = async (takeoverRecovery: TakeoverRecovery) => {
+    if (!proxyConfig) return;
+    const updated = { ...proxyConfig, takeoverRecovery };
+    try {
+      await invoke('update_proxy_config', { config: updated });
+      setProxyConfig(updated);
+    } catch (err) {
+      console.error('保存代理配置失败:', err);
+    }
+  };
+
   // 保存网络配置
   const handleSaveNetworkConfig = async () => {
     setNetworkSaving(true);
@@ -1126,6 +1149,28 @@ export function SettingsPage() {
                 </div>
               </div>
 
+              {proxyConfig && (
+                <div className="settings-row">
+                  <div className="row-label">
+                    <div className="row-title">{t('settings.general.takeoverRecovery', '代理残留接管')}</div>
+                    <div className="row-desc">
+                      {t('settings.general.takeoverRecoveryDesc', '代理异常退出后 CLI 配置仍指向本地代理时的处理方式')}
+                    </div>
+                  </div>
+                  <div className="row-control">
+                    <select
+                      className="settings-select"
+                      value={proxyConfig.takeoverRecovery}
+                      onChange={(e) => handleTakeoverRecoveryChange(e.target.value as TakeoverRecovery)}
+                    >
+                      <option value="ask">{t('settings.general.takeoverRecoveryAsk', '每次询问')}</option>
+                      <option value="restore">{t('settings.general.takeoverRecoveryRestore', '恢复原配置')}</option>
+                      <option value="relaunch">{t('settings.general.takeoverRecoveryRelaunch', '重新启动代理')}</option>
+                    </select>
+                  </div>
+                </div>
+              )}
+
               <div className="settings-row">
                 <div className="row-label">
                   <div className="row-title">{t('settings.general.autoUpdate')}</div>
@@ -10,6 +10,9 @@ interface GatewayStatus {
   total_requests: number;
   synced_accounts?: number;
   platform_stats?: Record<string, number>;
+  in_flight_requests?: number;
+  account_in_flight?: Record<string, number>;
+  draining?: boolean;
 }
 
 interface GatewayConfig {
@@ -24,6 +27,39 @@ interface GatewayConfig {
   cooldown_seconds: number;
   platform_upstreams: Record<string, string> | null;
   enable_account_bridge: boolean;
+  retry_policy?: RetryPolicy;
+  quota_threshold_percent?: number;
+  queue_timeout_seconds?: number;
+  responses_only_upstreams?: string[];
+  models_cache_seconds?: number;
+  drain_timeout_seconds?: number;
+  health_check_interval_seconds?: number;
+  capture_enabled?: boolean;
+  capture_max_entries?: number;
+  capture_max_body_kb?: number;
+  capture_retention_hours?: number;
+  model_mappings?: ModelMapping[];
+  account_groups?: AccountGroup[];
+}
+
+interface AccountGroup {
+  name: string;
+  route_strategy: string | null;
+  members: { account_id: string; weight: number }[];
+}
+
+interface ModelMapping {
+  pattern: string;
+  target_model: string | null;
+  target_platform: string | null;
+  enabled: boolean;
+}
+
+interface RetryPolicy {
+  max_attempts: number;
+  retryable_status_codes: number[];
+  backoff_base_ms: number;
+  backoff_max_ms: number;
 }
 
 interface SyncResult {
@@ -0,0 +1,43 @@
+export type TakeoverRecovery = 'ask' | 'restore' | 'relaunch';
+
+export interface TakeoverTarget {
+  protocol: string;
+  providerId?: string | null;
+  model?: string | null;
+}
+
+export interface ProxyConfig {
+  proxyEnabled: boolean;
+  listenAddress: string;
+  listenPort: number;
+  takeoverClaude: boolean;
+  takeoverCodex: boolean;
+  takeoverGemini: boolean;
+  failoverClaude: string[];
+  failoverCodex: string[];
+  failoverGemini: string[];
+  targetClaude?: TakeoverTarget | null;
+  targetGemini?: TakeoverTarget | null;
+  takeoverRecovery: TakeoverRecovery;
+}
+
+export type FailoverModelType = 'claude' | 'codex' | 'gemini';
+
+export const FAILOVER_FIELDS: Record<FailoverModelType, 'failoverClaude' | 'failoverCodex' | 'failoverGemini'> = {
+  claude: 'failoverClaude',
+  codex: 'failoverCodex',
+  gemini: 'failoverGemini',
+};
+
+export interface OrphanedTakeover {
+  appType: string;
+  proxyUrl?: string | null;
+  hasBackup: boolean;
+}
+
+export interface TakeoverRecoveryReport {
+  reason: 'startup' | 'bind_failed';
+  orphaned: OrphanedTakeover[];
+  action?: TakeoverRecovery | null;
+  error?: string | null;
+}